@@ -0,0 +1,176 @@
+//! Common interface for packed and unpacked file containers
+
+use std::borrow::{Cow};
+use std::collections::{HashMap};
+use std::fs::{File, create_dir_all, remove_file};
+use std::io::{Read, Write, Cursor, Error};
+use std::path::{Path, PathBuf};
+
+use crate::*;
+use crate::error::*;
+use crate::pbo::{PBO, list_files, parse_prefix};
+use crate::paths::{from_fs_path, to_fs_path};
+
+/// Container of named files, like a PBO or its unpacked folder
+///
+/// Entry names are relative paths with backslashes as separators, like in PBOs. The metadata
+/// are the header extensions of a PBO or the content of the `$PBOPREFIX$` file of a folder.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::PathBuf;
+/// # use armake2::archive::{Archive, Directory};
+/// # use armake2::pbo::PBO;
+/// let directory = Directory::new(PathBuf::from("src"));
+/// let pbo = PBO::from_directory(PathBuf::from("src"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+///
+/// let reread = PBO::read(&mut pbo.to_cursor().unwrap()).unwrap();
+///
+/// assert_eq!(directory.entries().unwrap(), reread.entries().unwrap());
+/// assert_eq!(directory.read_entry("lib.rs").unwrap(), pbo.read_entry("lib.rs").unwrap());
+/// ```
+pub trait Archive: Send + Sync {
+    /// Returns the names of all entries.
+    fn entries(&self) -> Result<Vec<String>, Error>;
+    /// Returns the content of the entry, `None` if there is no entry with that name.
+    fn read_entry(&self, name: &str) -> Result<Option<Cow<'_, [u8]>>, Error>;
+    /// Returns the size of the content of the entry, `None` if there is no entry with that name.
+    fn entry_size(&self, name: &str) -> Result<Option<u64>, Error> {
+        Ok(self.read_entry(name)?.map(|data| data.len() as u64))
+    }
+    /// Adds the entry or replaces its content.
+    fn write_entry(&mut self, name: &str, data: &[u8]) -> Result<(), Error>;
+    /// Removes the entry, if it exists.
+    fn remove_entry(&mut self, name: &str) -> Result<(), Error>;
+    /// Returns the metadata of the archive.
+    fn metadata(&self) -> Result<HashMap<String, String>, Error>;
+}
+
+/// Unpacked archive in a folder
+#[derive(Debug, Clone)]
+pub struct Directory {
+    pub root: PathBuf,
+}
+
+impl Directory {
+    /// Creates an archive for the folder at `root`.
+    pub fn new(root: PathBuf) -> Directory {
+        Directory { root }
+    }
+
+    /// Returns the path of the entry, failing for names that point outside of the folder.
+    fn path(&self, name: &str) -> Result<PathBuf, Error> {
+        if name.starts_with(['\\', '/']) || name.split(['\\', '/']).any(|part| part == ".." || part.contains(':')) {
+            return Err(error!("Invalid entry name \"{}\".", name));
+        }
+
+        Ok(self.root.join(to_fs_path(name)))
+    }
+}
+
+impl Archive for Directory {
+    fn entries(&self) -> Result<Vec<String>, Error> {
+        let mut entries: Vec<String> = list_files(&self.root)?.iter()
+            .map(|p| from_fs_path(p.strip_prefix(&self.root).unwrap()))
+            .filter(|name| name != "$PBOPREFIX$")
+            .collect();
+        // same order as in written PBOs
+        entries.sort_by_key(|name| name.to_lowercase());
+
+        Ok(entries)
+    }
+
+    fn read_entry(&self, name: &str) -> Result<Option<Cow<'_, [u8]>>, Error> {
+        let path = self.path(name)?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let mut buffer: Vec<u8> = Vec::new();
+        File::open(path)?.read_to_end(&mut buffer)?;
+        Ok(Some(Cow::Owned(buffer)))
+    }
+
+    fn entry_size(&self, name: &str) -> Result<Option<u64>, Error> {
+        let path = self.path(name)?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        Ok(Some(path.metadata()?.len()))
+    }
+
+    fn write_entry(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        let path = self.path(name)?;
+        create_dir_all(path.parent().unwrap())?;
+        File::create(path)?.write_all(data)
+    }
+
+    fn remove_entry(&mut self, name: &str) -> Result<(), Error> {
+        let path = self.path(name)?;
+        if path.is_file() {
+            remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    fn metadata(&self) -> Result<HashMap<String, String>, Error> {
+        let path = self.root.join("$PBOPREFIX$");
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+        Ok(parse_prefix(&content))
+    }
+}
+
+impl Archive for PBO {
+    fn entries(&self) -> Result<Vec<String>, Error> {
+        Ok(self.files.keys().cloned().collect())
+    }
+
+    fn read_entry(&self, name: &str) -> Result<Option<Cow<'_, [u8]>>, Error> {
+        Ok(self.files.get(name).map(|cursor| Cow::Borrowed(&cursor.get_ref()[..])))
+    }
+
+    fn entry_size(&self, name: &str) -> Result<Option<u64>, Error> {
+        Ok(self.files.get(name).map(|cursor| cursor.get_ref().len() as u64))
+    }
+
+    fn write_entry(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        match self.files.get_mut(name) {
+            Some(cursor) => { *cursor = Cursor::new(data.to_vec().into_boxed_slice()); },
+            None => { self.files.insert(name.to_string(), Cursor::new(data.to_vec().into_boxed_slice())); }
+        }
+        // the checksum of the read PBO doesn't match the content anymore
+        self.checksum = None;
+
+        Ok(())
+    }
+
+    fn remove_entry(&mut self, name: &str) -> Result<(), Error> {
+        if self.files.remove(name).is_some() {
+            self.checksum = None;
+        }
+
+        Ok(())
+    }
+
+    fn metadata(&self) -> Result<HashMap<String, String>, Error> {
+        Ok(self.header_extensions.clone())
+    }
+}
+
+/// Opens a PBO file or a folder as an archive.
+pub fn open_archive(path: &Path) -> Result<Box<dyn Archive>, Error> {
+    if path.is_dir() {
+        return Ok(Box::new(Directory::new(path.to_path_buf())));
+    }
+
+    let mut file = File::open(path).prepend_error(format!("Failed to open {}:", path.display()))?;
+    Ok(Box::new(PBO::read(&mut file).prepend_error("Failed to read PBO:")?))
+}
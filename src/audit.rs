@@ -0,0 +1,224 @@
+//! Reporting where third-party PBOs come from, for server admins vetting uploads
+
+use std::fs::{File};
+use std::io::{Read, Seek, SeekFrom, Error};
+use std::path::{Path, PathBuf};
+
+use crate::error::*;
+use crate::install::pbo_signatures;
+use crate::pbo::{ContainerType, FileType, PBO, PBOReader, detect_container};
+use crate::sign::{BIPublicKey, BISign};
+
+/// Signature of an audited PBO
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureAudit {
+    /// path of the .bisign
+    pub path: PathBuf,
+    /// name of the key the PBO was signed with, `None` if the signature can't be read
+    pub authority: Option<String>,
+    /// version of the signature, 2 or 3
+    pub version: u32,
+    /// key the signature was checked with, from the `keys` folder of the mod or next to the PBO
+    pub key: Option<PathBuf>,
+    /// whether the signature matches the PBO and the key, `None` if no key was found
+    pub valid: Option<bool>,
+}
+
+/// Provenance of a PBO, see `audit`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Audit {
+    /// type of the container, obfuscated PBOs aren't read any further
+    pub container: ContainerType,
+    /// header extensions in the order they are stored in
+    pub header_extensions: Vec<(String, String)>,
+    /// tools that likely produced the PBO, empty if none could be identified
+    pub tools: Vec<String>,
+    /// number of files in the PBO
+    pub files: usize,
+    /// numbers of binarized files by type: rapified configs, ODOL models, binarized animations
+    /// and terrains
+    pub binarized: Vec<(FileType, usize)>,
+    /// whether the checksum in the footer is correct, `None` if the PBO has no footer
+    pub checksum: Option<bool>,
+    pub signatures: Vec<SignatureAudit>,
+}
+
+/// Returns the tools that likely produced a PBO with the header extensions, from the extensions
+/// that only they write.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::audit::identify_tools;
+/// let extensions = vec![("prefix".to_string(), "x\\abc\\addons\\main".to_string()), ("hemtt".to_string(), "1.5.0".to_string())];
+/// assert_eq!(vec!["HEMTT 1.5.0"], identify_tools(&extensions));
+/// assert!(identify_tools(&extensions[..1]).is_empty());
+/// ```
+pub fn identify_tools(header_extensions: &[(String, String)]) -> Vec<String> {
+    let get = |key: &str| header_extensions.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str());
+    let mut tools: Vec<String> = Vec::new();
+
+    if let Some(version) = get("hemtt") {
+        tools.push(format!("HEMTT {}", version));
+    }
+    if let Some(version) = get("mikero") {
+        tools.push(format!("Mikero's tools ({})", version));
+    }
+    if get("git_hash").is_some() && tools.is_empty() {
+        tools.push("armake2 with --git".to_string());
+    }
+
+    tools
+}
+
+/// Returns the key the signature can be checked with: `<authority>.bikey` in the `keys` folder
+/// of the mod the PBO is in, or next to the PBO.
+fn find_key(pbo: &Path, authority: &str) -> Option<PathBuf> {
+    let folder = match pbo.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = format!("{}.bikey", authority);
+
+    let mut candidates = vec![folder.join(&name)];
+    if let Some(modfolder) = folder.parent() {
+        candidates.insert(0, modfolder.join("keys").join(&name));
+    }
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+fn audit_signature(path: PathBuf, pbo_path: &Path, pbo: Option<&PBO>) -> SignatureAudit {
+    let signature = match File::open(&path).and_then(|mut file| BISign::read(&mut file)) {
+        Ok(signature) => signature,
+        Err(_) => { return SignatureAudit { path, authority: None, version: 0, key: None, valid: None }; }
+    };
+
+    let key = find_key(pbo_path, signature.name());
+    let valid = match (&key, pbo) {
+        (Some(key), Some(pbo)) => Some(File::open(key).and_then(|mut file| BIPublicKey::read(&mut file))
+            .and_then(|key| key.verify(pbo, &signature)).is_ok()),
+        _ => None,
+    };
+
+    SignatureAudit {
+        path,
+        authority: Some(signature.name().to_string()),
+        version: signature.version().into(),
+        key,
+        valid,
+    }
+}
+
+/// Audits the PBO at `path`: the type of container, the tools that likely produced it, its
+/// binarized files, its checksum and its signatures with the keys that signed them. Signatures
+/// are checked with the keys of the same name in the `keys` folder of the mod or next to the
+/// PBO, if there are any.
+pub fn audit(path: &Path) -> Result<Audit, Error> {
+    let mut file = File::open(path).prepend_error(format!("Failed to open {}:", path.display()))?;
+    let container = detect_container(&mut file);
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut audit = Audit {
+        container: container.clone(),
+        header_extensions: Vec::new(),
+        tools: Vec::new(),
+        files: 0,
+        binarized: Vec::new(),
+        checksum: None,
+        signatures: Vec::new(),
+    };
+
+    let pbo = if container == ContainerType::PBO {
+        let mut reader = PBOReader::new(&mut file).prepend_error("Failed to read PBO:")?;
+        audit.tools = identify_tools(&reader.header_extensions);
+        audit.header_extensions = reader.header_extensions.clone();
+        audit.files = reader.entries.len();
+
+        for index in 0..reader.entries.len() {
+            let mut magic: Vec<u8> = Vec::new();
+            reader.entry_at(index)?.take(16).read_to_end(&mut magic)?;
+            let file_type = FileType::detect(&magic);
+            let binarized = match file_type {
+                FileType::Config | FileType::ODOL | FileType::WRP => true,
+                FileType::RTM => magic.starts_with(b"BMTR"),
+                _ => false,
+            };
+            if !binarized { continue; }
+            match audit.binarized.iter_mut().find(|(t, _)| *t == file_type) {
+                Some((_, count)) => { *count += 1; },
+                None => { audit.binarized.push((file_type, 1)); }
+            }
+        }
+
+        if let Some(checksum) = reader.checksum()? {
+            audit.checksum = Some(reader.compute_checksum()? == checksum);
+        }
+
+        file.seek(SeekFrom::Start(0))?;
+        Some(PBO::read(&mut file).prepend_error("Failed to read PBO:")?)
+    } else {
+        None
+    };
+
+    for signature in pbo_signatures(path)? {
+        audit.signatures.push(audit_signature(signature, path, pbo.as_ref()));
+    }
+
+    Ok(audit)
+}
+
+/// Audits the PBO at `path` (see `audit`) and prints the report.
+pub fn cmd_audit(path: PathBuf) -> Result<(), Error> {
+    let audit = audit(&path)?;
+
+    println!("File:       {}", path.display());
+    println!("Container:  {}", audit.container);
+    if audit.container != ContainerType::PBO {
+        println!("            The content can't be inspected any further.");
+    } else {
+        let tools = if audit.tools.is_empty() {
+            "unknown, no header extension identifies it (Addon Builder and armake write none)".to_string()
+        } else {
+            audit.tools.join(", ")
+        };
+        println!("Tool:       {}", tools);
+        for (key, value) in &audit.header_extensions {
+            println!("            {}={}", key, value);
+        }
+
+        let binarized: Vec<String> = audit.binarized.iter().map(|(file_type, count)| format!("{} {}", count, file_type)).collect();
+        if binarized.is_empty() {
+            println!("Files:      {}, none binarized", audit.files);
+        } else {
+            println!("Files:      {}, binarized: {}", audit.files, binarized.join(", "));
+        }
+        println!("Checksum:   {}", match audit.checksum {
+            Some(true) => "valid",
+            Some(false) => "INVALID, the PBO was modified after packing",
+            None => "missing",
+        });
+    }
+
+    if audit.signatures.is_empty() {
+        println!("Signatures: none");
+    }
+    for (i, signature) in audit.signatures.iter().enumerate() {
+        let label = if i == 0 { "Signatures:" } else { "" };
+        let name = signature.path.file_name().unwrap().to_string_lossy();
+        let authority = match &signature.authority {
+            Some(authority) => authority,
+            None => {
+                println!("{:11} {}: unreadable", label, name);
+                continue;
+            }
+        };
+        let status = match (&signature.key, signature.valid) {
+            (Some(key), Some(true)) => format!("valid with {}", key.display()),
+            (Some(key), _) => format!("INVALID with {}", key.display()),
+            (None, _) => "not checked, no key with that name found".to_string(),
+        };
+        println!("{:11} {}: signed by {} (v{}), {}", label, name, authority, signature.version, status);
+    }
+
+    Ok(())
+}
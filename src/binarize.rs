@@ -1,10 +1,13 @@
 //! Functions for calling BI's binarize.exe (on Windows)
 
-use std::env::{var, temp_dir};
-use std::fs::{File, create_dir_all, remove_dir_all};
+use std::env::{var};
+use std::fs::{File, read_to_string};
 use std::io::{Read, Write, Cursor, Error};
-use std::path::{PathBuf};
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Command};
+
+use once_cell::sync::Lazy;
+use regex::{Regex};
 
 #[cfg(windows)]
 use winreg::RegKey;
@@ -13,6 +16,79 @@ use winreg::enums::*;
 
 use crate::*;
 use crate::error::*;
+use crate::io::AtomicFile;
+use crate::preprocess::{preprocess};
+use crate::temp::{TempDir};
+
+/// Known lines of binarize.exe's log: pattern, whether it is an error and the warning name.
+/// Patterns capture the message and optionally the file and line it is about.
+static LOG_PATTERNS: Lazy<Vec<(Regex, bool, &'static str)>> = Lazy::new(|| vec![
+    (Regex::new(r"^File (?P<file>.+?), line (?P<line>\d+): (?P<msg>.+)$").unwrap(), true, "binarize"),
+    (Regex::new(r"(?i)^(?:Error|ErrorMessage):\s*(?P<msg>.+)$").unwrap(), true, "binarize"),
+    (Regex::new(r"(?i)^Cannot (?:open|load|find) (?:object |file |texture )?(?P<msg>\S+?)\.?$").unwrap(), false, "binarize-missing-file"),
+    (Regex::new(r"(?i)^Missing file (?P<msg>\S+?)\.?$").unwrap(), false, "binarize-missing-file"),
+    (Regex::new(r"(?i)^(?:Warning|Warning Message):\s*(?P<msg>.+)$").unwrap(), false, "binarize"),
+]);
+
+/// Problem reported in the log of binarize.exe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinarizeDiagnostic {
+    pub error: bool,
+    /// warning name, to disable it with -w
+    pub name: &'static str,
+    pub message: String,
+    /// file the problem is in, the binarized file if the log doesn't name one
+    pub file: String,
+    pub line: Option<u32>,
+}
+
+/// Parses the log of binarize.exe for `input` into diagnostics. Lines that don't match a known
+/// pattern are skipped and repeated messages are only reported once.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use armake2::binarize::parse_log;
+/// let log = "Binarize 1.0\nFile P:\\addon\\config.cpp, line 4: /CfgPatches/: Missing ';'\nCannot load texture addon\\data\\x_co.paa.\n";
+/// let diagnostics = parse_log(log, Path::new("addon/model.p3d"));
+///
+/// assert_eq!(2, diagnostics.len());
+/// assert!(diagnostics[0].error);
+/// assert_eq!("P:\\addon\\config.cpp", diagnostics[0].file);
+/// assert_eq!(Some(4), diagnostics[0].line);
+/// assert_eq!("binarize-missing-file", diagnostics[1].name);
+/// assert_eq!("addon/model.p3d", diagnostics[1].file);
+/// ```
+pub fn parse_log(log: &str, input: &Path) -> Vec<BinarizeDiagnostic> {
+    let mut diagnostics: Vec<BinarizeDiagnostic> = Vec::new();
+
+    for line in log.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        let (captures, error, name) = match LOG_PATTERNS.iter().find_map(|(r, e, n)| r.captures(line).map(|c| (c, *e, *n))) {
+            Some(found) => found,
+            None => { continue; }
+        };
+
+        let message = if name == "binarize-missing-file" {
+            format!("Referenced file {} can't be loaded.", &captures["msg"])
+        } else {
+            captures["msg"].to_string()
+        };
+        let diagnostic = BinarizeDiagnostic {
+            error,
+            name,
+            message,
+            file: captures.name("file").map(|f| f.as_str().to_string()).unwrap_or_else(|| input.display().to_string()),
+            line: captures.name("line").and_then(|l| l.as_str().parse().ok()),
+        };
+
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    diagnostics
+}
 
 #[cfg(windows)]
 fn find_binarize_exe() -> Result<PathBuf, Error> {
@@ -28,21 +104,32 @@ fn find_binarize_exe() -> Result<PathBuf, Error> {
     unreachable!();
 }
 
-fn create_temp_directory(name: &str) -> Result<PathBuf, Error> {
-    let dir = temp_dir();
-    let mut i = 0;
+/// Returns whether BI's binarize.exe is installed and can be used.
+pub fn binarize_available() -> bool {
+    cfg!(windows) && find_binarize_exe().map(|path| path.exists()).unwrap_or(false)
+}
 
-    let mut path;
-    loop {
-        path = dir.join(format!("armake_{}_{}", name, i));
-        if !path.exists() { break; }
+/// Returns the model.cfg files of the folder of the input and all its parent folders, which
+/// binarize.exe reads for the skeletons and animations of models.
+pub fn model_configs(input: &Path) -> Vec<PathBuf> {
+    input.ancestors().skip(1).map(|folder| folder.join("model.cfg")).filter(|path| path.is_file()).collect()
+}
 
-        i += 1;
+/// Returns the files besides the input that binarize.exe reads when binarizing it: the model.cfg
+/// files (see `model_configs`) and the files they include.
+pub fn dependencies(input: &Path, includefolders: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dependencies: Vec<PathBuf> = Vec::new();
+    for model_cfg in model_configs(input) {
+        // model.cfg files that can't be preprocessed fail in binarize.exe
+        if let Ok(content) = read_to_string(&model_cfg) {
+            if let Ok((_, info)) = preprocess(content, Some(model_cfg.clone()), includefolders) {
+                dependencies.extend(info.includes);
+            }
+        }
+        dependencies.push(model_cfg);
     }
 
-    create_dir_all(&path)?;
-
-    Ok(path)
+    dependencies
 }
 
 /// Binarizes the given path with BI's binarize.exe (Only available on Windows).
@@ -58,27 +145,39 @@ pub fn binarize(input: &PathBuf) -> Result<Cursor<Box<[u8]>>, Error> {
 
     let input_dir = PathBuf::from(input.parent().unwrap());
     let name = input.file_name().unwrap().to_str().unwrap().to_string();
-    let tempdir = create_temp_directory(&name).prepend_error("Failed to create tempfolder:")?;
+    let tempdir = TempDir::new(&name).prepend_error("Failed to create tempfolder:")?;
 
     let piped = var("BIOUTPUT").unwrap_or_else(|_| "0".to_string()) == "1";
 
     let binarize_output = Command::new(binarize_exe)
-        .args(&["-norecurse", "-always", "-silent", "-maxProcesses=0", input_dir.to_str().unwrap(), tempdir.to_str().unwrap(), input.file_name().unwrap().to_str().unwrap()])
-        .stdout(if piped { Stdio::inherit() } else { Stdio::null() })
-        .stderr(if piped { Stdio::inherit() } else { Stdio::null() })
-        .output().unwrap();
+        .args(["-norecurse", "-always", "-silent", "-maxProcesses=0", input_dir.to_str().unwrap(), tempdir.path().to_str().unwrap(), input.file_name().unwrap().to_str().unwrap()])
+        .output().prepend_error("Failed to run binarize.exe:")?;
+
+    let log = format!("{}{}", String::from_utf8_lossy(&binarize_output.stdout), String::from_utf8_lossy(&binarize_output.stderr));
+    if piped {
+        print!("{}", log);
+    }
+
+    let diagnostics = parse_log(&log, input);
+    for diagnostic in diagnostics.iter().filter(|d| !d.error) {
+        warning(diagnostic.message.clone(), Some(diagnostic.name), (Some(diagnostic.file.clone()), diagnostic.line));
+    }
 
     if !binarize_output.status.success() {
         let msg = match binarize_output.status.code() {
             Some(code) => format!("binarize.exe terminated with exit code: {}", code),
             None => "binarize.exe terminated by signal.".to_string()
         };
+        let errors: Vec<String> = diagnostics.iter().filter(|d| d.error).map(|d| match d.line {
+            Some(line) => format!("\n    {}:{}: {}", d.file, line, d.message),
+            None => format!("\n    {}: {}", d.file, d.message),
+        }).collect();
         let outputhint = if !piped { "\nUse BIOUTPUT=1 to see binarize.exe's output." } else { "" };
 
-        return Err(error!("{}{}", msg, outputhint));
+        return Err(error!("{}{}{}", msg, errors.concat(), outputhint));
     }
 
-    let result_path = tempdir.join(input.strip_prefix(&input_dir).unwrap());
+    let result_path = tempdir.path().join(input.strip_prefix(&input_dir).unwrap());
     let mut buffer: Vec<u8> = Vec::new();
 
     {
@@ -86,8 +185,6 @@ pub fn binarize(input: &PathBuf) -> Result<Cursor<Box<[u8]>>, Error> {
         file.read_to_end(&mut buffer).prepend_error("Failed to read binarize.exe output:")?;
     }
 
-    remove_dir_all(&tempdir).prepend_error("Failed to remove temp directory:")?;
-
     Ok(Cursor::new(buffer.into_boxed_slice()))
 }
 
@@ -102,8 +199,9 @@ pub fn cmd_binarize(input: PathBuf, output: PathBuf, force: bool) -> Result<(),
     }
 
     let cursor = binarize(&input)?;
-    let mut file = File::create(&output).prepend_error("Failed to open output:")?;
+    let mut file = AtomicFile::create(&output).prepend_error("Failed to open output:")?;
     file.write_all(cursor.get_ref()).prepend_error("Failed to write result to file:")?;
+    file.commit().prepend_error("Failed to write result to file:")?;
 
     Ok(())
 }
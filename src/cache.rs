@@ -0,0 +1,387 @@
+//! Functions for caching build results by content hash, locally or on a remote server
+
+use std::fs::{File, create_dir_all};
+use std::io::{Read, Write, Error};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+use openssl::hash::{Hasher, MessageDigest, hash};
+
+use crate::error::*;
+use crate::io::AtomicFile;
+
+static BUILD_CACHE: Lazy<Mutex<Option<Arc<dyn CacheBackend>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Storage for build results, keyed by a hash of everything the result depends on. Backends are
+/// used by several threads at once.
+pub trait CacheBackend: Send + Sync {
+    /// Returns the cached data for the key, `None` if the key is not cached.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+    /// Stores data for the key.
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error>;
+}
+
+/// Cache in a local folder, storing every entry in a file named like its key
+pub struct LocalCache {
+    pub directory: PathBuf,
+}
+
+impl CacheBackend for LocalCache {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let path = self.directory.join(key);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut buffer: Vec<u8> = Vec::new();
+        File::open(path)?.read_to_end(&mut buffer)?;
+        Ok(Some(buffer))
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        create_dir_all(&self.directory)?;
+
+        // write to a temporary file first so concurrent builds never read partial entries
+        let mut file = AtomicFile::create(&self.directory.join(key))?;
+        file.write_all(data)?;
+        file.commit()
+    }
+}
+
+#[cfg(feature = "remote-cache")]
+pub use self::remote::HttpCache;
+
+#[cfg(feature = "remote-cache")]
+mod remote {
+    use std::io::{Read, Write, Error, BufRead, BufReader};
+    use std::net::{TcpStream};
+
+    use openssl::ssl::{SslConnector, SslMethod};
+
+    use crate::*;
+    use super::CacheBackend;
+
+    pub fn open(url: &str) -> Result<Box<dyn CacheBackend>, Error> {
+        Ok(Box::new(HttpCache::new(url)?))
+    }
+
+    /// Cache on an HTTP(S) server storing entries with `PUT <url>/<key>` and retrieving them with
+    /// `GET <url>/<key>`, e.g. a WebDAV folder, nginx with the dav module or an S3-compatible
+    /// bucket allowing these requests.
+    ///
+    /// If the environment variable `ARMAKE2_CACHE_AUTH` is set, its value is sent as the
+    /// `Authorization` header.
+    pub struct HttpCache {
+        tls: bool,
+        host: String,
+        port: u16,
+        path: String,
+    }
+
+    /// Largest response body accepted from a cache server, so a broken server can't make the
+    /// build allocate arbitrary amounts of memory
+    const MAX_RESPONSE_SIZE: usize = 1024 * 1024 * 1024;
+
+    trait Stream: Read + Write {}
+    impl<T: Read + Write> Stream for T {}
+
+    impl HttpCache {
+        /// Creates a cache for a URL like `https://cache.example.com/armake2`.
+        pub fn new(url: &str) -> Result<HttpCache, Error> {
+            let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+                (true, rest)
+            } else if let Some(rest) = url.strip_prefix("http://") {
+                (false, rest)
+            } else {
+                return Err(error!("Cache URL \"{}\" doesn't start with http:// or https://.", url));
+            };
+
+            let (authority, path) = match rest.find('/') {
+                Some(index) => (&rest[..index], rest[index..].trim_end_matches('/')),
+                None => (rest, ""),
+            };
+            let (host, port) = match authority.rfind(':') {
+                Some(index) => (&authority[..index], authority[index + 1..].parse::<u16>().map_err(|_| error!("Invalid port in cache URL \"{}\".", url))?),
+                None => (authority, if tls { 443 } else { 80 }),
+            };
+
+            Ok(HttpCache {
+                tls,
+                host: host.to_string(),
+                port,
+                path: path.to_string(),
+            })
+        }
+
+        fn request(&self, method: &str, key: &str, body: &[u8]) -> Result<(u16, Vec<u8>), Error> {
+            let tcp = TcpStream::connect((self.host.as_str(), self.port))?;
+            let mut stream: Box<dyn Stream> = if self.tls {
+                let connector = SslConnector::builder(SslMethod::tls()).map_err(|e| error!("{}", e))?.build();
+                Box::new(connector.connect(&self.host, tcp).map_err(|e| error!("TLS handshake failed: {}", e))?)
+            } else {
+                Box::new(tcp)
+            };
+
+            let mut request = format!("{} {}/{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+                method, self.path, key, self.host, body.len());
+            if let Ok(auth) = std::env::var("ARMAKE2_CACHE_AUTH") {
+                request += &format!("Authorization: {}\r\n", auth);
+            }
+            request += "\r\n";
+
+            stream.write_all(request.as_bytes())?;
+            stream.write_all(body)?;
+            stream.flush()?;
+
+            let mut reader = BufReader::new(stream);
+            let mut status_line = String::new();
+            reader.read_line(&mut status_line)?;
+            let status: u16 = status_line.split_whitespace().nth(1).and_then(|s| s.parse().ok())
+                .ok_or_else(|| error!("Invalid HTTP response: {}", status_line.trim()))?;
+
+            let mut content_length: Option<usize> = None;
+            let mut transfer_encoding: Option<String> = None;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 || line.trim().is_empty() { break; }
+                if let Some(index) = line.find(':') {
+                    if line[..index].eq_ignore_ascii_case("content-length") {
+                        content_length = line[index + 1..].trim().parse().ok();
+                    } else if line[..index].eq_ignore_ascii_case("transfer-encoding") {
+                        transfer_encoding = Some(line[index + 1..].trim().to_lowercase());
+                    }
+                }
+            }
+
+            let mut data: Vec<u8> = Vec::new();
+            match (transfer_encoding.as_deref(), content_length) {
+                (Some("chunked"), _) => { data = read_chunked(&mut reader)?; },
+                (Some(encoding), _) if encoding != "identity" => {
+                    return Err(error!("Unsupported transfer encoding \"{}\" in cache server response.", encoding));
+                },
+                (_, Some(length)) => { read_body(&mut reader, length, &mut data)?; },
+                // the body ends when the server closes the connection
+                (_, None) => {
+                    reader.take(MAX_RESPONSE_SIZE as u64 + 1).read_to_end(&mut data)?;
+                    if data.len() > MAX_RESPONSE_SIZE {
+                        return Err(error!("Cache server response is larger than {} bytes.", MAX_RESPONSE_SIZE));
+                    }
+                }
+            }
+
+            Ok((status, data))
+        }
+    }
+
+    /// Reads `length` more bytes of the body into `data`. The data is read as it arrives instead
+    /// of allocating the size the server claims up front.
+    fn read_body<R: Read>(reader: &mut R, length: usize, data: &mut Vec<u8>) -> Result<(), Error> {
+        if length > MAX_RESPONSE_SIZE - data.len() {
+            return Err(error!("Cache server response is larger than {} bytes.", MAX_RESPONSE_SIZE));
+        }
+        if reader.take(length as u64).read_to_end(data)? < length {
+            return Err(error!("Cache server response ended early."));
+        }
+
+        Ok(())
+    }
+
+    /// Reads a body with `Transfer-Encoding: chunked`, skipping chunk extensions and trailers.
+    fn read_chunked<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, Error> {
+        let mut data: Vec<u8> = Vec::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(error!("Cache server response ended before the last chunk."));
+            }
+            let size = line.split(';').next().unwrap().trim();
+            let size = usize::from_str_radix(size, 16).map_err(|_| error!("Invalid chunk size \"{}\" in cache server response.", size))?;
+            if size == 0 { break; }
+
+            read_body(reader, size, &mut data)?;
+
+            let mut end = [0; 2];
+            reader.read_exact(&mut end)?;
+            if &end != b"\r\n" {
+                return Err(error!("Chunk in cache server response is longer than its size."));
+            }
+        }
+
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() { break; }
+        }
+
+        Ok(data)
+    }
+
+    impl CacheBackend for HttpCache {
+        fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+            match self.request("GET", key, &[])? {
+                (200, data) => Ok(Some(data)),
+                (404, _) => Ok(None),
+                (status, _) => Err(error!("Cache server responded with status {}.", status)),
+            }
+        }
+
+        fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+            match self.request("PUT", key, data)? {
+                (200..=299, _) => Ok(()),
+                (status, _) => Err(error!("Cache server responded with status {}.", status)),
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "remote-cache"))]
+mod remote {
+    use std::io::{Error};
+
+    use crate::*;
+    use super::CacheBackend;
+
+    pub fn open(_url: &str) -> Result<Box<dyn CacheBackend>, Error> {
+        Err(error!("Remote caches are not supported by this build of armake2."))
+    }
+}
+
+/// Creates a cache backend for a local folder or an `http(s)://` URL.
+pub fn open_cache(location: &str) -> Result<Box<dyn CacheBackend>, Error> {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        return remote::open(location);
+    }
+
+    Ok(Box::new(LocalCache { directory: PathBuf::from(location) }))
+}
+
+/// Sets the cache used for build results, `None` disables caching.
+pub fn init_cache(cache: Option<Box<dyn CacheBackend>>) {
+    *BUILD_CACHE.lock().unwrap() = cache.map(Arc::from);
+}
+
+/// Returns the cache key for the given kind of result and the data it is computed from. The
+/// version of armake2 is part of the key so results of older versions are never reused.
+pub fn cache_key(kind: &str, parts: &[&[u8]]) -> String {
+    let mut hasher = Hasher::new(MessageDigest::sha1()).unwrap();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes()).unwrap();
+    hasher.update(kind.as_bytes()).unwrap();
+    for part in parts {
+        hasher.update(&(part.len() as u64).to_le_bytes()).unwrap();
+        hasher.update(part).unwrap();
+    }
+
+    let hash: String = hasher.finish().unwrap().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}", kind, hash)
+}
+
+/// Returns whether a build cache is set.
+fn cache_enabled() -> bool {
+    BUILD_CACHE.lock().unwrap().is_some()
+}
+
+/// Returns the build cache. The lock is only held to clone it, so requests of several threads,
+/// e.g. to a remote cache, run at the same time.
+fn build_cache() -> Option<Arc<dyn CacheBackend>> {
+    BUILD_CACHE.lock().unwrap().clone()
+}
+
+/// Returns the cached data for the key, `None` if it isn't cached or there is no cache.
+fn cache_get(key: &str) -> Option<Vec<u8>> {
+    match build_cache()?.get(key) {
+        Ok(data) => data,
+        Err(e) => {
+            warning(format!("Failed to read from build cache: {}", e), Some("cache"), (None, None));
+            None
+        }
+    }
+}
+
+/// Stores the data for the key, if there is a cache.
+fn cache_put(key: &str, data: &[u8]) {
+    if let Some(backend) = build_cache() {
+        if let Err(e) = backend.put(key, data) {
+            warning(format!("Failed to write to build cache: {}", e), Some("cache"), (None, None));
+        }
+    }
+}
+
+/// Returns the cached result for the key or computes and caches it. Failures of the cache only
+/// cause warnings, the result is computed as if nothing was cached.
+pub fn cached<F: FnOnce() -> Result<Vec<u8>, Error>>(key: &str, compute: F) -> Result<Vec<u8>, Error> {
+    if !cache_enabled() {
+        return compute();
+    }
+
+    if let Some(data) = cache_get(key) {
+        return Ok(data);
+    }
+
+    // the lock isn't held while computing so other threads can use the cache
+    let data = compute()?;
+    cache_put(key, &data);
+
+    Ok(data)
+}
+
+fn file_hash(path: &Path) -> Option<String> {
+    let mut buffer: Vec<u8> = Vec::new();
+    File::open(path).ok()?.read_to_end(&mut buffer).ok()?;
+    let digest = hash(MessageDigest::sha1(), &buffer).ok()?;
+    Some(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Returns whether all included files listed in a manifest are unchanged.
+fn includes_unchanged(manifest: &[u8]) -> bool {
+    let manifest = match std::str::from_utf8(manifest) {
+        Ok(manifest) => manifest,
+        Err(_) => { return false; }
+    };
+
+    manifest.lines().all(|line| match line.split_once(' ') {
+        Some((hash, path)) => file_hash(Path::new(path)).as_deref() == Some(hash),
+        None => false,
+    })
+}
+
+/// Like `cached`, for results that also depend on the files the input includes, like rapified
+/// configs. This way configs are only preprocessed again if they or one of their includes
+/// changed.
+///
+/// `key` only needs to cover the input itself. `compute` returns the result and the included
+/// files. A manifest with the hashes of the included files is cached under `key` and the result
+/// under a key that also covers the manifest, so the result is only reused while all included
+/// files are unchanged.
+pub fn cached_with_includes<F: FnOnce() -> Result<(Vec<u8>, Vec<PathBuf>), Error>>(key: &str, compute: F) -> Result<Vec<u8>, Error> {
+    if !cache_enabled() {
+        return compute().map(|(data, _)| data);
+    }
+
+    if let Some(manifest) = cache_get(key) {
+        if includes_unchanged(&manifest) {
+            if let Some(data) = cache_get(&cache_key("included", &[key.as_bytes(), &manifest])) {
+                let includes: Vec<PathBuf> = String::from_utf8_lossy(&manifest).lines()
+                    .filter_map(|line| line.split_once(' ').map(|(_, path)| PathBuf::from(path)))
+                    .collect();
+                crate::deps::track(&includes);
+                return Ok(data);
+            }
+        }
+    }
+
+    let (data, includes) = compute()?;
+
+    let mut manifest = String::new();
+    for include in &includes {
+        // includes that can't be hashed are not cached
+        match file_hash(include) {
+            Some(hash) => { manifest += &format!("{} {}\n", hash, include.display()); },
+            None => { return Ok(data); }
+        }
+    }
+
+    cache_put(key, manifest.as_bytes());
+    cache_put(&cache_key("included", &[key.as_bytes(), manifest.as_bytes()]), &data);
+
+    Ok(data)
+}
@@ -1,20 +1,40 @@
 //! Functions for rapifying and derapifying Arma configs
 
 use std::cmp::{min};
-use std::io::{Read, Seek, Write, SeekFrom, Error, Cursor, BufReader, BufWriter};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, Write, SeekFrom, Error, Cursor, BufWriter};
 use std::iter::{Sum};
-use std::path::PathBuf;
+use std::ops::{Range};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use rayon::prelude::*;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer, SerializeMap, SerializeSeq};
 
 use crate::*;
 use crate::io::*;
 use crate::error::*;
+use crate::lint::*;
+use crate::paths::EntryAddress;
+use crate::pbo::read_address;
 use crate::preprocess::*;
+use crate::target::target;
+
+/// Warnings of the grammar that are errors in strict mode
+const STRICT_WARNINGS: &[&str] = &["unquoted-string", "missing-semicolon"];
+
+/// Number of entries from which the entries of a class are written in parallel
+const PARALLEL_WRITE_ENTRIES: usize = 4096;
+
+/// Warnings collected by the grammar with their position, message and name
+type GrammarWarnings = Vec<(usize, String, Option<&'static str>)>;
 
 peg::parser! {
-    pub grammar config_grammar(warnings: &mut Vec<(usize, String, Option<&'static str>)>) for str {
-        rule whitespace() = quiet!{ [' ' | '\r' | '\n' | '\t']+ }
+    pub grammar config_grammar(warnings: &mut GrammarWarnings, interner: &mut Interner, recover: bool) for str {
+        // pragmas the preprocessor doesn't know are passed through and ignored here
+        rule whitespace() = quiet!{ ([' ' | '\r' | '\n' | '\t'] / "#pragma" (!['\n'] [_])*)+ }
 
         rule float() -> f32 = f:$((['-' | '+'])? ['0'..='9']* "." ['0'..='9']+) {
             if f.chars().filter(|c| c == &'.').count() == 0 {
@@ -31,36 +51,49 @@ peg::parser! {
             }
         }
 
-        rule doublequoted_string() -> String = "\"" s:$(("\"\"" / !['"'] [_])*) "\"" {
-            s.to_string().replace("\"\"", "\"")
+        rule doublequoted_string() -> Arc<str> = "\"" s:$(("\"\"" / !['"'] [_])*) "\"" {
+            if s.contains("\"\"") { interner.intern(&s.replace("\"\"", "\"")) } else { interner.intern(s) }
         }
 
-        rule singlequoted_string() -> String = "'" s:$(("''" / !['\''] [_])*) "'" {
-            s.to_string().replace("''", "'")
+        rule singlequoted_string() -> Arc<str> = "'" s:$(("''" / !['\''] [_])*) "'" {
+            if s.contains("''") { interner.intern(&s.replace("''", "'")) } else { interner.intern(s) }
         }
 
-        rule unquoted_string() -> String = pos:position!() s:$(![';' | '}'] [_]*) {
-            let result = s.to_string().trim().to_string();
+        rule unquoted_string() -> Arc<str> = pos:position!() s:$((![';' | '}'] [_])+) {
+            let result = interner.intern(s.trim());
             warnings.push((pos, format!("String value \"{}\" is not quoted properly.", result), Some("unquoted-string")));
             result
         }
 
-        rule unquoted_string_array() -> String = pos:position!() s:$( (![',' | '}' | ' ' | '\t'] [_]) (![',' | '}'] [_])* ) {
-            let result = s.to_string().trim().to_string();
+        rule unquoted_string_array() -> Arc<str> = pos:position!() s:$( (![',' | '}' | ' ' | '\t'] [_]) (![',' | '}'] [_])* ) {
+            let result = interner.intern(s.trim());
             warnings.push((pos, format!("String array element \"{}\" is not quoted properly.", result), Some("unquoted-string")));
             result
         }
 
-        rule string() -> String = doublequoted_string() / singlequoted_string()
+        rule string() -> Arc<str> = pos:position!() s:(doublequoted_string() / singlequoted_string()) {
+            if let Err(msg) = check_procedural_texture(&s) {
+                warnings.push((pos, format!("Procedural texture \"{}\" is invalid: {}.", s, msg), Some("procedural-texture")));
+            }
+            s
+        }
+
+        rule array_element() -> (ConfigArrayElement, Option<Span>) = start:position!() e:array_element_value() end:position!() {
+            (e, Some(Span::new(start..end)))
+        }
 
-        rule array_element() -> ConfigArrayElement =
+        rule array_element_value() -> ConfigArrayElement =
             f:float()   &(whitespace()? [',' | '}']) { ConfigArrayElement::FloatElement(f) } /
             i:integer() &(whitespace()? [',' | '}']) { ConfigArrayElement::IntElement(i) } /
             a:array()   &(whitespace()? [',' | '}']) { ConfigArrayElement::ArrayElement(a) } /
             s:string()  &(whitespace()? [',' | '}']) { ConfigArrayElement::StringElement(s) } /
             s:unquoted_string_array() &(whitespace()? [',' | '}']) { ConfigArrayElement::StringElement(s) }
 
-        rule array_elements() -> Vec<ConfigArrayElement> = array_element() ** (whitespace()? "," whitespace()?)
+        rule array_elements() -> Vec<(ConfigArrayElement, Option<Span>)> = e:(array_element() ** (whitespace()? "," whitespace()?)) {
+            let mut e = e;
+            e.shrink_to_fit();
+            e
+        }
 
         rule array() -> ConfigArray = "{" whitespace()? elems:array_elements() whitespace()? ","? whitespace()? "}" {
             ConfigArray {
@@ -74,41 +107,64 @@ peg::parser! {
             i:integer() { ConfigEntry::IntEntry(i) } /
             s:string()  { ConfigEntry::StringEntry(s) }
 
-        rule var_entry() -> (String, ConfigEntry) = n:name() whitespace()? "=" whitespace()? ce:var() {
+        rule var_entry() -> (Arc<str>, ConfigEntry) = n:name() whitespace()? "=" whitespace()? ce:var() {
             (n, ce)
         }
 
-        rule unquoted_string_entry() -> (String, ConfigEntry) = n:name() whitespace()? "=" whitespace()? s:unquoted_string() {
+        rule unquoted_string_entry() -> (Arc<str>, ConfigEntry) = n:name() whitespace()? "=" whitespace()? s:unquoted_string() {
             (n, ConfigEntry::StringEntry(s))
         }
 
-        rule array_entry() -> (String, ConfigEntry) = n:name() whitespace()? "[" whitespace()? "]" whitespace()? "=" whitespace()? a:array() {
+        rule array_entry() -> (Arc<str>, ConfigEntry) = n:name() whitespace()? "[" whitespace()? "]" whitespace()? "=" whitespace()? a:array() {
             (n, ConfigEntry::ArrayEntry(a))
         }
 
-        rule array_expansion_entry() -> (String, ConfigEntry) = n:name() whitespace()? "[" whitespace()? "]" whitespace()? "+=" whitespace()? a:array() {
+        rule array_expansion_entry() -> (Arc<str>, ConfigEntry) = n:name() whitespace()? "[" whitespace()? "]" whitespace()? "+=" whitespace()? a:array() {
             (n, ConfigEntry::ArrayEntry(ConfigArray {
                 is_expansion: true,
                 ..a
             }))
         }
 
-        rule entry() -> (String, ConfigEntry) =
-            e:(class() / array_entry() / array_expansion_entry() / var_entry()) whitespace()? (";" / &"}") { e } /
-            e:unquoted_string_entry() whitespace()? (";" / &"}") { e }
+        rule entry_end() = ";" / pos:position!() &"}" {
+            warnings.push((pos, "Missing semicolon before \"}\".".to_string(), Some("missing-semicolon")));
+        }
+
+        rule entry() -> NamedEntry = start:position!() e:entry_value() end:position!() {
+            (e.0, e.1, Some(Span::new(start..end)))
+        }
+
+        rule entry_value() -> (Arc<str>, ConfigEntry) =
+            e:(class() / array_entry() / array_expansion_entry() / var_entry()) whitespace()? entry_end() { e } /
+            e:unquoted_string_entry() whitespace()? entry_end() { e }
+
+        rule recovering() = "" {? if recover { Ok(()) } else { Err("recovery") } }
 
-        rule entries() -> Vec<(String, ConfigEntry)> = entry() ** (whitespace()?)
+        rule braced() = "{" ((!['{' | '}'] [_]) / braced())* "}"
 
-        rule name() -> String = n:$(['a'..='z' | 'A'..='Z' | '0'..='9' | '_']+) {
-            n.to_string()
+        // skips everything up to the next semicolon or the end of the class in recovery mode
+        rule invalid_entry() = quiet!{ recovering() pos:position!() s:$((braced() / (!['{' | '}' | ';'] [_]))+ ";"? / ";") {
+            warnings.push((pos, s.to_string(), Some("syntax-error")));
+        } }
+
+        rule entry_or_invalid() -> Option<NamedEntry> = e:entry() { Some(e) } / invalid_entry() { None }
+
+        rule entries() -> Vec<NamedEntry> = e:(entry_or_invalid() ** (whitespace()?)) {
+            let mut e: Vec<_> = e.into_iter().flatten().collect();
+            e.shrink_to_fit();
+            e
+        }
+
+        rule name() -> Arc<str> = n:$(['a'..='z' | 'A'..='Z' | '0'..='9' | '_']+) {
+            interner.intern(n)
         }
 
-        rule parent() -> String = whitespace()? ":" whitespace()? n:name() {
+        rule parent() -> Arc<str> = whitespace()? ":" whitespace()? n:name() {
             n
         }
 
-        rule regular_class() -> (String, ConfigEntry) = "class" whitespace()+ n:name() p:parent()? whitespace()? "{" whitespace()? e:entries() whitespace()? "}" {
-            let parent = p.unwrap_or_default();
+        rule regular_class() -> (Arc<str>, ConfigEntry) = "class" whitespace()+ n:name() p:parent()? whitespace()? "{" whitespace()? e:entries() whitespace()? "}" {
+            let parent = p.unwrap_or_else(|| interner.intern(""));
             (n, ConfigEntry::ClassEntry(ConfigClass {
                 parent,
                 is_external: false,
@@ -117,30 +173,50 @@ peg::parser! {
             }))
         }
 
-        rule external_class() -> (String, ConfigEntry) = "class" whitespace()+ n:name() {
+        rule external_class() -> (Arc<str>, ConfigEntry) = "class" whitespace()+ n:name() {
             (n, ConfigEntry::ClassEntry(ConfigClass {
-                parent: String::from(""),
+                parent: interner.intern(""),
                 is_external: true,
                 is_deletion: false,
                 entries: None
             }))
         }
 
-        rule deleted_class() -> (String, ConfigEntry) = "delete" whitespace()+ n:name() {
+        rule deleted_class() -> (Arc<str>, ConfigEntry) = "delete" whitespace()+ n:name() {
             (n, ConfigEntry::ClassEntry(ConfigClass {
-                parent: String::from(""),
+                parent: interner.intern(""),
                 is_external: false,
                 is_deletion: true,
                 entries: None
             }))
         }
 
-        rule class() -> (String, ConfigEntry) = regular_class() / external_class() / deleted_class()
+        rule class() -> (Arc<str>, ConfigEntry) = regular_class() / external_class() / deleted_class()
+
+        rule name_char() = ['a'..='z' | 'A'..='Z' | '0'..='9' | '_']
+
+        rule token_kind() -> TokenKind =
+            whitespace() { TokenKind::Whitespace } /
+            ("//" (!['\n'] [_])* / "/*" (!"*/" [_])* "*/") { TokenKind::Comment } /
+            "#" (("\\" "\r"? "\n") / !['\n'] [_])* { TokenKind::Directive } /
+            ("class" / "delete") !name_char() { TokenKind::Keyword } /
+            float() !name_char() { TokenKind::Float } /
+            integer() !name_char() { TokenKind::Integer } /
+            name() { TokenKind::Name } /
+            (doublequoted_string() / singlequoted_string()) { TokenKind::String } /
+            ("+=" / ['{' | '}' | '[' | ']' | ';' | ',' | ':' | '=']) { TokenKind::Punctuation } /
+            [_] { TokenKind::Other }
+
+        rule token() -> Token = start:position!() kind:token_kind() end:position!() {
+            Token { kind, span: start..end }
+        }
+
+        pub rule tokens() -> Vec<Token> = t:token()* { t }
 
         pub rule config() -> Config = whitespace()? e:entries() whitespace()? ![_] {
             Config {
                 root_body: ConfigClass {
-                    parent: String::from(""),
+                    parent: interner.intern(""),
                     is_external: false,
                     is_deletion: false,
                     entries: Some(e)
@@ -150,6 +226,142 @@ peg::parser! {
     }
 }
 
+/// Kind of a token in a config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Whitespace,
+    /// line or block comment
+    Comment,
+    /// preprocessor directive including its continuation lines
+    Directive,
+    /// `class` or `delete`
+    Keyword,
+    /// class, property or macro name
+    Name,
+    Integer,
+    Float,
+    /// quoted string including the quotes
+    String,
+    /// one of `{ } [ ] ; , : = +=`
+    Punctuation,
+    /// any other character, e.g. part of an unquoted string
+    Other,
+}
+
+/// Token of a config with its byte range in the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+}
+
+impl Token {
+    /// Returns the text of the token in the input it was read from.
+    pub fn text<'a>(&self, input: &'a str) -> &'a str {
+        &input[self.span.clone()]
+    }
+}
+
+/// Splits a config into tokens with the same rules the config parser uses for numbers, names and
+/// strings. Comments and preprocessor directives are kept as single tokens, so unpreprocessed
+/// files can be tokenized, e.g. for syntax highlighting. The tokens cover the whole input.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::{tokenize, TokenKind};
+/// let input = "class A: B { x = 1.5; y[] += {\"a\"}; };";
+/// let tokens = tokenize(input);
+///
+/// assert_eq!(TokenKind::Keyword, tokens[0].kind);
+/// assert_eq!("1.5", tokens.iter().find(|t| t.kind == TokenKind::Float).unwrap().text(input));
+/// assert_eq!(input, tokens.iter().map(|t| t.text(input)).collect::<String>());
+/// ```
+pub fn tokenize(input: &str) -> Vec<Token> {
+    let mut warnings: Vec<(usize, String, Option<&'static str>)> = Vec::new();
+
+    // every character is at least a token of kind other, so this never fails
+    config_grammar::tokens(input, &mut warnings, &mut Interner::default(), false).unwrap_or_default()
+}
+
+/// Location of a config entry or array element
+///
+/// Spans are only known for configs parsed from text, not for rapified ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    /// byte range in the preprocessed config, including the semicolon of entries
+    pub range: Range<usize>,
+    /// file the node starts in, `None` if it was in the input itself and no path was given. Spans
+    /// in the same file share the path.
+    pub file: Option<Arc<Path>>,
+    /// line in that file the node starts at, starting at 1
+    pub line: u32,
+}
+
+impl Span {
+    fn new(range: Range<usize>) -> Span {
+        Span {
+            range,
+            file: None,
+            line: 0,
+        }
+    }
+}
+
+/// Finds the files and lines of spans in a preprocessed config
+struct SpanLocator<'a> {
+    line_starts: Vec<usize>,
+    info: &'a PreprocessInfo,
+    files: HashMap<&'a Path, Arc<Path>>,
+}
+
+impl<'a> SpanLocator<'a> {
+    fn new(preprocessed: &str, info: &'a PreprocessInfo) -> SpanLocator<'a> {
+        SpanLocator {
+            line_starts: line_starts(preprocessed),
+            info,
+            files: HashMap::new(),
+        }
+    }
+
+    fn locate(&mut self, span: &mut Span) {
+        let line = self.line_starts.partition_point(|start| *start <= span.range.start);
+        let origin = &self.info.line_origins[min(line, self.info.line_origins.len()) - 1];
+
+        span.file = origin.1.as_deref().map(|path| self.files.entry(path).or_insert_with(|| Arc::from(path)).clone());
+        span.line = origin.0;
+    }
+}
+
+/// Deduplicates the names and strings of a config while it is read, generated configs repeat
+/// the same few names and values many times.
+#[derive(Default)]
+struct Interner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(interned) = self.strings.get(s) {
+            return interned.clone();
+        }
+
+        let interned: Arc<str> = Arc::from(s);
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    /// Reads a null-terminated string and interns it without copying it first.
+    fn read_cstring(&mut self, input: &mut Cursor<&[u8]>) -> Result<Arc<str>, Error> {
+        let data = &input.get_ref()[min(input.position() as usize, input.get_ref().len())..];
+        let len = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+        let s = std::str::from_utf8(&data[..len]).map_err(|e| Error::new(std::io::ErrorKind::InvalidData, e))?;
+        input.set_position(input.position() + min(len + 1, data.len()) as u64);
+
+        Ok(self.intern(s))
+    }
+}
+
 /// Config
 ///
 /// # Examples
@@ -175,17 +387,20 @@ pub struct Config {
 /// Config class
 #[derive(Debug)]
 pub struct ConfigClass {
-    parent: String,
+    parent: Arc<str>,
     is_external: bool,
     is_deletion: bool,
-    entries: Option<Vec<(String, ConfigEntry)>>,
+    entries: Option<Vec<NamedEntry>>,
 }
 
+/// Entry of a class with its name and location
+type NamedEntry = (Arc<str>, ConfigEntry, Option<Span>);
+
 /// Config entry
 #[derive(Debug)]
 pub enum ConfigEntry {
     /// String entry
-    StringEntry(String),
+    StringEntry(Arc<str>),
     /// Float entry
     FloatEntry(f32),
     /// Int entry
@@ -200,14 +415,14 @@ pub enum ConfigEntry {
 #[derive(Debug)]
 pub struct ConfigArray {
     is_expansion: bool,
-    elements: Vec<ConfigArrayElement>,
+    elements: Vec<(ConfigArrayElement, Option<Span>)>,
 }
 
 /// Config array element
 #[derive(Debug)]
 pub enum ConfigArrayElement {
     /// String element
-    StringElement(String),
+    StringElement(Arc<str>),
     /// Float element
     FloatElement(f32),
     /// Int element
@@ -216,6 +431,25 @@ pub enum ConfigArrayElement {
     ArrayElement(ConfigArray),
 }
 
+/// Function declared in `CfgFunctions`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigFunction {
+    /// tag from the `tag` property of the tag class, or its name
+    pub tag: String,
+    pub category: String,
+    pub name: String,
+    /// path of the script from the `file` property of the function or category, or the default
+    /// `functions\<category>\fn_<name>.sqf`
+    pub path: String,
+}
+
+impl ConfigFunction {
+    /// Returns the name the function is called by, `<tag>_fnc_<name>`.
+    pub fn full_name(&self) -> String {
+        format!("{}_fnc_{}", self.tag, self.name)
+    }
+}
+
 impl ConfigArrayElement {
     fn rapified_length(&self) -> usize {
         match self {
@@ -223,27 +457,50 @@ impl ConfigArrayElement {
             ConfigArrayElement::FloatElement(_f) => 5,
             ConfigArrayElement::IntElement(_i) => 5,
             ConfigArrayElement::ArrayElement(a) => 1 + compressed_int_len(a.elements.len() as u32) +
-                usize::sum(a.elements.iter().map(|e| e.rapified_length()))
+                usize::sum(a.elements.iter().map(|(e, _)| e.rapified_length()))
         }
     }
 }
 
 impl ConfigArray {
+    /// Returns the elements of the array.
+    pub fn elements(&self) -> impl Iterator<Item = &ConfigArrayElement> {
+        self.elements.iter().map(|(e, _)| e)
+    }
+
+    fn replace_strings<F: FnMut(&str) -> Option<String>>(&mut self, replace: &mut F) -> usize {
+        let mut replaced = 0;
+        for (element, _) in self.elements.iter_mut() {
+            match element {
+                ConfigArrayElement::StringElement(s) => {
+                    if let Some(new) = replace(s) {
+                        *s = Arc::from(new);
+                        replaced += 1;
+                    }
+                },
+                ConfigArrayElement::ArrayElement(a) => { replaced += a.replace_strings(replace); },
+                _ => {}
+            }
+        }
+
+        replaced
+    }
+
     fn write<O: Write>(&self, output: &mut O) -> Result<(), Error> {
         output.write_all(b"{")?;
-        for (key, value) in self.elements.iter().enumerate() {
+        for (key, (value, _)) in self.elements.iter().enumerate() {
             match value {
                 ConfigArrayElement::ArrayElement(ref a) => {
                     a.write(output)?;
                 },
                 ConfigArrayElement::StringElement(s) => {
-                    output.write_all(format!("\"{}\"", s.replace("\r", "\\r").replace("\n", "\\n").replace("\"", "\"\"")).as_bytes())?;
+                    write_quoted(output, s)?;
                 },
                 ConfigArrayElement::FloatElement(f) => {
-                    output.write_all(format!("{:?}", f).as_bytes())?;
+                    write!(output, "{:?}", f)?;
                 },
                 ConfigArrayElement::IntElement(i) => {
-                    output.write_all(format!("{}", i).as_bytes())?;
+                    write!(output, "{}", i)?;
                 }
             }
             if key < self.elements.len() - 1 {
@@ -257,11 +514,11 @@ impl ConfigArray {
     fn write_rapified<O: Write>(&self, output: &mut O) -> Result<usize, Error> {
         let mut written = output.write_compressed_int(self.elements.len() as u32)?;
 
-        for element in &self.elements {
+        for (element, _) in &self.elements {
             match element {
                 ConfigArrayElement::StringElement(s) => {
                     output.write_all(&[0])?;
-                    output.write_cstring(s)?;
+                    output.write_cstring(&**s)?;
                     written += s.len() + 2;
                 },
                 ConfigArrayElement::FloatElement(f) => {
@@ -284,21 +541,57 @@ impl ConfigArray {
         Ok(written)
     }
 
-    fn read_rapified<I: Read + Seek>(input: &mut I) -> Result<ConfigArray, Error> {
+    fn locate_spans(&mut self, locator: &mut SpanLocator) {
+        for (element, span) in &mut self.elements {
+            if let Some(span) = span {
+                locator.locate(span);
+            }
+            if let ConfigArrayElement::ArrayElement(a) = element {
+                a.locate_spans(locator);
+            }
+        }
+    }
+
+    fn spans<'a>(&'a self, path: &str, spans: &mut Vec<(String, &'a Span)>) {
+        for (i, (element, span)) in self.elements.iter().enumerate() {
+            let element_path = format!("{}[{}]", path, i);
+            if let Some(span) = span {
+                spans.push((element_path.clone(), span));
+            }
+            if let ConfigArrayElement::ArrayElement(a) = element {
+                a.spans(&element_path, spans);
+            }
+        }
+    }
+
+    fn fixes(&self, preprocessed: &str, fixes: &mut Vec<Fix>) {
+        for (element, span) in &self.elements {
+            match (element, span) {
+                (ConfigArrayElement::StringElement(s), Some(span)) => {
+                    let text = preprocessed[span.range.clone()].trim_end();
+                    string_fixes(text, s, span.range.start, fixes);
+                },
+                (ConfigArrayElement::ArrayElement(a), _) => a.fixes(preprocessed, fixes),
+                _ => {}
+            }
+        }
+    }
+
+    fn read_rapified(input: &mut Cursor<&[u8]>, interner: &mut Interner) -> Result<ConfigArray, Error> {
         let num_elements: u32 = input.read_compressed_int()?;
-        let mut elements: Vec<ConfigArrayElement> = Vec::with_capacity(num_elements as usize);
+        let mut elements: Vec<(ConfigArrayElement, Option<Span>)> = Vec::with_capacity(num_elements as usize);
 
         for _i in 0..num_elements {
-            let element_type: u8 = input.bytes().next().unwrap()?;
+            let element_type: u8 = input.read_u8()?;
 
             if element_type == 0 {
-                elements.push(ConfigArrayElement::StringElement(input.read_cstring()?));
+                elements.push((ConfigArrayElement::StringElement(interner.read_cstring(input)?), None));
             } else if element_type == 1 {
-                elements.push(ConfigArrayElement::FloatElement(input.read_f32::<LittleEndian>()?));
+                elements.push((ConfigArrayElement::FloatElement(input.read_f32::<LittleEndian>()?), None));
             } else if element_type == 2 {
-                elements.push(ConfigArrayElement::IntElement(input.read_i32::<LittleEndian>()?));
+                elements.push((ConfigArrayElement::IntElement(input.read_i32::<LittleEndian>()?), None));
             } else if element_type == 3 {
-                elements.push(ConfigArrayElement::ArrayElement(ConfigArray::read_rapified(input)?));
+                elements.push((ConfigArrayElement::ArrayElement(ConfigArray::read_rapified(input, interner)?), None));
             } else {
                 return Err(error!("Unrecognized array element type: {}", element_type));
             }
@@ -320,7 +613,7 @@ impl ConfigEntry {
             ConfigEntry::IntEntry(_i) => 6,
             ConfigEntry::ArrayEntry(a) => {
                 let len = 1 + compressed_int_len(a.elements.len() as u32) +
-                    usize::sum(a.elements.iter().map(|e| e.rapified_length()));
+                    usize::sum(a.elements.iter().map(|(e, _)| e.rapified_length()));
                 if a.is_expansion { len + 4 } else { len }
             },
             ConfigEntry::ClassEntry(c) => {
@@ -330,63 +623,330 @@ impl ConfigEntry {
     }
 }
 
+/// Writes the string quoted like in configs, with quotes doubled and line breaks escaped.
+fn write_quoted<O: Write>(output: &mut O, s: &str) -> Result<(), Error> {
+    output.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, b) in s.bytes().enumerate() {
+        let escaped: &[u8] = match b {
+            b'\r' => b"\\r",
+            b'\n' => b"\\n",
+            b'"' => b"\"\"",
+            _ => { continue; }
+        };
+        output.write_all(&s.as_bytes()[start..i])?;
+        output.write_all(escaped)?;
+        start = i + 1;
+    }
+    output.write_all(&s.as_bytes()[start..])?;
+    output.write_all(b"\"")
+}
+
+/// Float as written in configs, so e.g. 0.1 isn't written as 0.10000000149011612
+fn float_value(f: f32) -> f64 {
+    format!("{:?}", f).parse().unwrap_or(f64::NAN)
+}
+
+/// Key of an entry in JSON and YAML, `<name>+=` for array expansions
+fn structured_key(name: &str, entry: &ConfigEntry) -> String {
+    match entry {
+        ConfigEntry::ArrayEntry(a) if a.is_expansion => format!("{}+=", name),
+        _ => name.to_string(),
+    }
+}
+
+impl Serialize for ConfigArray {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.elements.len()))?;
+        for (element, _) in &self.elements {
+            match element {
+                ConfigArrayElement::StringElement(s) => seq.serialize_element(&**s)?,
+                ConfigArrayElement::FloatElement(f) => seq.serialize_element(&float_value(*f))?,
+                ConfigArrayElement::IntElement(i) => seq.serialize_element(i)?,
+                ConfigArrayElement::ArrayElement(a) => seq.serialize_element(a)?,
+            }
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for ConfigClass {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        if self.is_deletion {
+            map.serialize_entry("$delete", &true)?;
+        } else if self.is_external {
+            map.serialize_entry("$external", &true)?;
+        }
+        if !self.parent.is_empty() {
+            map.serialize_entry("$parent", &*self.parent)?;
+        }
+
+        for (name, entry, _) in self.entries.iter().flatten() {
+            let key = structured_key(name, entry);
+            match entry {
+                ConfigEntry::StringEntry(s) => map.serialize_entry(&key, &**s)?,
+                ConfigEntry::FloatEntry(f) => map.serialize_entry(&key, &float_value(*f))?,
+                ConfigEntry::IntEntry(i) => map.serialize_entry(&key, i)?,
+                ConfigEntry::ArrayEntry(a) => map.serialize_entry(&key, a)?,
+                ConfigEntry::ClassEntry(c) => map.serialize_entry(&key, c)?,
+            }
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Config {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.root_body.serialize(serializer)
+    }
+}
+
+/// Entry or array element read from JSON
+enum StructuredValue {
+    String(Arc<str>),
+    Float(f32),
+    Int(i32),
+    Array(ConfigArray),
+    Class(ConfigClass),
+}
+
+struct StructuredVisitor;
+
+impl<'de> Visitor<'de> for StructuredVisitor {
+    type Value = StructuredValue;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a string, number, array or object")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<StructuredValue, E> {
+        Ok(i32::try_from(v).map(StructuredValue::Int).unwrap_or(StructuredValue::Float(v as f32)))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<StructuredValue, E> {
+        Ok(i32::try_from(v).map(StructuredValue::Int).unwrap_or(StructuredValue::Float(v as f32)))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<StructuredValue, E> {
+        Ok(StructuredValue::Float(v as f32))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<StructuredValue, E> {
+        Ok(StructuredValue::String(Arc::from(v)))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<StructuredValue, A::Error> {
+        let mut elements: Vec<(ConfigArrayElement, Option<Span>)> = Vec::new();
+        while let Some(value) = seq.next_element::<StructuredValue>()? {
+            let element = match value {
+                StructuredValue::String(s) => ConfigArrayElement::StringElement(s),
+                StructuredValue::Float(f) => ConfigArrayElement::FloatElement(f),
+                StructuredValue::Int(i) => ConfigArrayElement::IntElement(i),
+                StructuredValue::Array(a) => ConfigArrayElement::ArrayElement(a),
+                StructuredValue::Class(_) => { return Err(de::Error::custom("arrays can't contain classes")); }
+            };
+            elements.push((element, None));
+        }
+
+        Ok(StructuredValue::Array(ConfigArray { is_expansion: false, elements }))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<StructuredValue, A::Error> {
+        let mut class = ConfigClass {
+            parent: Arc::from(""),
+            is_external: false,
+            is_deletion: false,
+            entries: None,
+        };
+        let mut entries: Vec<NamedEntry> = Vec::new();
+
+        // entries are read in the order of the document, so parents stay defined before children
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "$parent" => { class.parent = Arc::from(map.next_value::<String>()?); },
+                "$external" => { class.is_external = map.next_value()?; },
+                "$delete" => { class.is_deletion = map.next_value()?; },
+                _ => {
+                    let (name, expansion) = match key.strip_suffix("+=") {
+                        Some(name) => (Arc::from(name), true),
+                        None => (Arc::from(key.as_str()), false),
+                    };
+                    let entry = match map.next_value::<StructuredValue>()? {
+                        StructuredValue::Array(mut a) => {
+                            a.is_expansion = expansion;
+                            ConfigEntry::ArrayEntry(a)
+                        },
+                        _ if expansion => { return Err(de::Error::custom(format!("{} is not an array", key))); },
+                        StructuredValue::String(s) => ConfigEntry::StringEntry(s),
+                        StructuredValue::Float(f) => ConfigEntry::FloatEntry(f),
+                        StructuredValue::Int(i) => ConfigEntry::IntEntry(i),
+                        StructuredValue::Class(c) => ConfigEntry::ClassEntry(c),
+                    };
+                    entries.push((name, entry, None));
+                }
+            }
+        }
+
+        if !class.is_external && !class.is_deletion {
+            class.entries = Some(entries);
+        }
+
+        Ok(StructuredValue::Class(class))
+    }
+}
+
+impl<'de> Deserialize<'de> for StructuredValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StructuredValue, D::Error> {
+        deserializer.deserialize_any(StructuredVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Config {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Config, D::Error> {
+        match deserializer.deserialize_map(StructuredVisitor)? {
+            StructuredValue::Class(root_body) => Ok(Config { root_body }),
+            _ => Err(de::Error::custom("expected an object")),
+        }
+    }
+}
+
+fn yaml_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        key.to_string()
+    } else {
+        serde_json::to_string(key).unwrap()
+    }
+}
+
+fn yaml_float(f: f32) -> String {
+    if f.is_nan() {
+        ".nan".to_string()
+    } else if f.is_infinite() {
+        if f > 0.0 { ".inf".to_string() } else { "-.inf".to_string() }
+    } else {
+        format!("{:?}", f)
+    }
+}
+
+impl ConfigArray {
+    /// Writes the elements as YAML sequence items, the first one without indentation.
+    fn write_yaml<O: Write>(&self, output: &mut O, indent: usize) -> Result<(), Error> {
+        for (i, (element, _)) in self.elements.iter().enumerate() {
+            if i > 0 {
+                output.write_all(" ".repeat(indent).as_bytes())?;
+            }
+            output.write_all(b"- ")?;
+            match element {
+                ConfigArrayElement::StringElement(s) => { writeln!(output, "{}", serde_json::to_string(&**s).unwrap())?; },
+                ConfigArrayElement::FloatElement(f) => { writeln!(output, "{}", yaml_float(*f))?; },
+                ConfigArrayElement::IntElement(i) => { writeln!(output, "{}", i)?; },
+                ConfigArrayElement::ArrayElement(a) if a.elements.is_empty() => { output.write_all(b"[]\n")?; },
+                ConfigArrayElement::ArrayElement(a) => { a.write_yaml(output, indent + 2)?; },
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl ConfigClass {
-    fn write<O: Write>(&self, mut output: &mut O, level: i32) -> Result<(), Error> {
-        match &self.entries {
-            Some(entries) => {
-                if level > 0 && !entries.is_empty() {
+    fn write_yaml<O: Write>(&self, output: &mut O, indent: usize) -> Result<(), Error> {
+        let prefix = " ".repeat(indent);
+        if self.is_deletion {
+            writeln!(output, "{}\"$delete\": true", prefix)?;
+        } else if self.is_external {
+            writeln!(output, "{}\"$external\": true", prefix)?;
+        }
+        if !self.parent.is_empty() {
+            writeln!(output, "{}\"$parent\": {}", prefix, serde_json::to_string(&*self.parent).unwrap())?;
+        }
+
+        for (name, entry, _) in self.entries.iter().flatten() {
+            write!(output, "{}{}:", prefix, yaml_key(&structured_key(name, entry)))?;
+            match entry {
+                ConfigEntry::StringEntry(s) => { writeln!(output, " {}", serde_json::to_string(&**s).unwrap())?; },
+                ConfigEntry::FloatEntry(f) => { writeln!(output, " {}", yaml_float(*f))?; },
+                ConfigEntry::IntEntry(i) => { writeln!(output, " {}", i)?; },
+                ConfigEntry::ArrayEntry(a) if a.elements.is_empty() => { output.write_all(b" []\n")?; },
+                ConfigEntry::ArrayEntry(a) => {
+                    write!(output, "\n{}  ", prefix)?;
+                    a.write_yaml(output, indent + 2)?;
+                },
+                ConfigEntry::ClassEntry(c) if !c.is_deletion && !c.is_external && c.parent.is_empty() && c.entries.as_ref().map(|e| e.is_empty()).unwrap_or(true) => {
+                    output.write_all(b" {}\n")?;
+                },
+                ConfigEntry::ClassEntry(c) => {
                     output.write_all(b"\n")?;
+                    c.write_yaml(output, indent + 2)?;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write<O: Write>(&self, output: &mut O, level: i32) -> Result<(), Error> {
+        let entries = match &self.entries {
+            Some(entries) if !entries.is_empty() => entries,
+            _ => { return Ok(()); }
+        };
+        if level > 0 {
+            output.write_all(b"\n")?;
+        }
+
+        // large classes like the entities of a mission are rendered in parallel
+        if entries.len() >= PARALLEL_WRITE_ENTRIES {
+            let chunks: Vec<Vec<u8>> = entries.par_chunks(PARALLEL_WRITE_ENTRIES / 4).map(|chunk| {
+                let mut buffer: Vec<u8> = Vec::new();
+                for (key, value, _) in chunk {
+                    Self::write_entry(&mut buffer, key, value, level)?;
                 }
-                for (key, value) in entries {
-                    output.write_all(String::from("    ").repeat(level as usize).as_bytes())?;
-
-                    match value {
-                        ConfigEntry::ClassEntry(ref c) => {
-                            if c.is_deletion {
-                                output.write_all(format!("delete {};\n", key).as_bytes())?;
-                            } else if c.is_external {
-                                output.write_all(format!("class {};\n", key).as_bytes())?;
-                            } else {
-                        let parent = if c.parent.is_empty() { String::from("") } else { format!(": {}", c.parent) };
-                                match &c.entries {
-                                    Some(entries) => {
-                                        if !entries.is_empty() {
-                                            output.write_all(format!("class {}{} {{", key, parent).as_bytes())?;
-                                            c.write(output, level + 1)?;
-                                            output.write_all(String::from("    ").repeat(level as usize).as_bytes())?;
-                                            output.write_all(b"};\n")?;
-                                        } else {
-                                            output.write_all(format!("class {}{} {{}};\n", key, parent).as_bytes())?;
-                                        }
-                                    },
-                                    None => {
-                                        output.write_all(format!("class {}{} {{}};\n", key, parent).as_bytes())?;
-                                    },
-                                }
-                            }
-                        },
-                        ConfigEntry::StringEntry(s) => {
-                            output.write_all(format!("{} = \"{}\";\n", key, s.replace("\r", "\\r").replace("\n", "\\n").replace("\"", "\"\"")).as_bytes())?;
-                        },
-                        ConfigEntry::FloatEntry(f) => {
-                            output.write_all(format!("{} = {:?};\n", key, f).as_bytes())?;
-                        },
-                        ConfigEntry::IntEntry(i) => {
-                            output.write_all(format!("{} = {};\n", key, i).as_bytes())?;
-                        },
-                        ConfigEntry::ArrayEntry(ref a) => {
-                            if a.is_expansion {
-                                output.write_all(format!("{}[] += ", key).as_bytes())?;
-                            } else {
-                                output.write_all(format!("{}[] = ", key).as_bytes())?;
-                            }
-                            a.write(&mut output)?;
-                            output.write_all(b";\n")?;
-                        },
-                    }
+                Ok(buffer)
+            }).collect::<Result<Vec<Vec<u8>>, Error>>()?;
+
+            return chunks.iter().try_for_each(|chunk| output.write_all(chunk));
+        }
+
+        for (key, value, _) in entries {
+            Self::write_entry(output, key, value, level)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_entry<O: Write>(output: &mut O, key: &str, value: &ConfigEntry, level: i32) -> Result<(), Error> {
+        let indent = 4 * level as usize;
+        write!(output, "{:1$}", "", indent)?;
+
+        match value {
+            ConfigEntry::ClassEntry(c) if c.is_deletion => writeln!(output, "delete {};", key)?,
+            ConfigEntry::ClassEntry(c) if c.is_external => writeln!(output, "class {};", key)?,
+            ConfigEntry::ClassEntry(c) => {
+                write!(output, "class {}", key)?;
+                if !c.parent.is_empty() {
+                    write!(output, ": {}", c.parent)?;
+                }
+                if c.entries.as_ref().map(|e| e.is_empty()).unwrap_or(true) {
+                    output.write_all(b" {};\n")?;
+                } else {
+                    output.write_all(b" {")?;
+                    c.write(output, level + 1)?;
+                    writeln!(output, "{:1$}}};", "", indent)?;
                 }
             },
-            None => {}
+            ConfigEntry::StringEntry(s) => {
+                write!(output, "{} = ", key)?;
+                write_quoted(output, s)?;
+                output.write_all(b";\n")?;
+            },
+            ConfigEntry::FloatEntry(f) => writeln!(output, "{} = {:?};", key, f)?,
+            ConfigEntry::IntEntry(i) => writeln!(output, "{} = {};", key, i)?,
+            ConfigEntry::ArrayEntry(a) => {
+                write!(output, "{}[] {} ", key, if a.is_expansion { "+=" } else { "=" })?;
+                a.write(output)?;
+                output.write_all(b";\n")?;
+            },
         }
 
         Ok(())
@@ -396,7 +956,7 @@ impl ConfigClass {
         match &self.entries {
             Some(entries) => self.parent.len() + 1 +
                 compressed_int_len(entries.len() as u32) +
-                usize::sum(entries.iter().map(|(k,v)| {
+                usize::sum(entries.iter().map(|(k, v, _)| {
                     k.len() + 1 + v.rapified_length() + match v {
                         ConfigEntry::ClassEntry(c) => c.rapified_length(),
                         _ => 0
@@ -411,62 +971,67 @@ impl ConfigClass {
 
         match &self.entries {
             Some(entries) => {
-                output.write_cstring(&self.parent)?;
+                output.write_cstring(&*self.parent)?;
                 written += self.parent.len() + 1;
 
                 written += output.write_compressed_int(entries.len() as u32)?;
 
-                let entries_len = usize::sum(entries.iter().map(|(k,v)| k.len() + 1 + v.rapified_length()));
+                let entries_len = usize::sum(entries.iter().map(|(k, v, _)| k.len() + 1 + v.rapified_length()));
                 let mut class_offset = offset + written + entries_len;
-                let mut class_bodies: Vec<Cursor<Box<[u8]>>> = Vec::new();
+                // bodies of subclasses follow the entries in the same order, their offsets are
+                // known from their lengths before they are written
+                let mut class_bodies: Vec<(&str, &ConfigClass)> = Vec::new();
                 let pre_entries = written;
 
-                for (name, entry) in entries {
+                for (name, entry, _) in entries {
                     let pre_write = written;
                     match entry {
                         ConfigEntry::StringEntry(s) => {
                             output.write_all(&[1, 0])?;
-                            output.write_cstring(name)?;
-                            output.write_cstring(s)?;
+                            output.write_cstring(&**name)?;
+                            output.write_cstring(&**s)?;
                             written += name.len() + s.len() + 4;
                         },
                         ConfigEntry::FloatEntry(f) => {
                             output.write_all(&[1, 1])?;
-                            output.write_cstring(name)?;
+                            output.write_cstring(&**name)?;
                             output.write_f32::<LittleEndian>(*f)?;
                             written += name.len() + 7;
                         },
                         ConfigEntry::IntEntry(i) => {
                             output.write_all(&[1, 2])?;
-                            output.write_cstring(name)?;
+                            output.write_cstring(&**name)?;
                             output.write_i32::<LittleEndian>(*i)?;
                             written += name.len() + 7;
                         },
                         ConfigEntry::ArrayEntry(a) => {
+                            if a.is_expansion && !target().supports_config_extensions() {
+                                warning(format!("Array expansion {}[] += is not supported by {}.", name, target().name()), Some("target-feature"), (None, None));
+                            }
                             output.write_all(if a.is_expansion { &[5] } else { &[2] })?;
                             if a.is_expansion {
                                 output.write_all(&[1,0,0,0])?;
                                 written += 4;
                             }
-                            output.write_cstring(name)?;
+                            output.write_cstring(&**name)?;
                             written += name.len() + 2 + a.write_rapified(output)?;
                         },
                         ConfigEntry::ClassEntry(c) => {
+                            if c.is_deletion && !target().supports_config_extensions() {
+                                warning(format!("Class deletion delete {}; is not supported by {}.", name, target().name()), Some("target-feature"), (None, None));
+                            }
                             if c.is_external || c.is_deletion {
                                 output.write_all(if c.is_deletion { &[4] } else { &[3] })?;
-                                output.write_cstring(name)?;
+                                output.write_cstring(&**name)?;
                                 written += name.len() + 2;
                             } else {
                                 output.write_all(&[0])?;
-                                output.write_cstring(name)?;
+                                output.write_cstring(&**name)?;
                                 output.write_u32::<LittleEndian>(class_offset as u32)?;
                                 written += name.len() + 6;
 
-                                let buffer: Box<[u8]> = vec![0; c.rapified_length()].into_boxed_slice();
-                                let mut cursor: Cursor<Box<[u8]>> = Cursor::new(buffer);
-                                class_offset += c.write_rapified(&mut cursor, class_offset).prepend_error(format!("Failed to rapify {}:",name))?;
-
-                                class_bodies.push(cursor);
+                                class_offset += c.rapified_length();
+                                class_bodies.push((name, c));
                             }
                         }
                     }
@@ -475,10 +1040,11 @@ impl ConfigClass {
 
                 assert_eq!(written - pre_entries, entries_len);
 
-                for cursor in class_bodies {
-                    output.write_all(cursor.get_ref())?;
-                    written += cursor.get_ref().len();
+                for (name, c) in class_bodies {
+                    written += c.write_rapified(output, offset + written).prepend_error(format!("Failed to rapify {}:", name))?;
                 }
+
+                assert_eq!(offset + written, class_offset);
             },
             None => { unreachable!() }
         }
@@ -486,7 +1052,190 @@ impl ConfigClass {
         Ok(written)
     }
 
-    fn read_rapified<I: Read + Seek>(input: &mut I, level: u32) -> Result<ConfigClass, Error> {
+    /// Removes properties that are defined again later in the same class and string properties
+    /// that only contain whitespace in classes without a parent. Returns the number of removed
+    /// properties.
+    fn optimize(&mut self) -> usize {
+        let entries = match &mut self.entries {
+            Some(entries) => entries,
+            None => { return 0; }
+        };
+        let has_parent = !self.parent.is_empty();
+        let before = entries.len();
+
+        let mut kept: Vec<NamedEntry> = Vec::with_capacity(entries.len());
+        for (name, entry, span) in entries.drain(..).rev() {
+            let redundant = match &entry {
+                ConfigEntry::ClassEntry(_) => false,
+                ConfigEntry::ArrayEntry(a) if a.is_expansion => false,
+                ConfigEntry::StringEntry(s) if !has_parent && s.trim().is_empty() => true,
+                // a later definition of the same property replaces this one
+                _ => kept.iter().any(|(k, e, _)| k.eq_ignore_ascii_case(&name) && match e {
+                    ConfigEntry::ClassEntry(_) => false,
+                    ConfigEntry::ArrayEntry(a) => !a.is_expansion,
+                    _ => true,
+                }),
+            };
+            if !redundant {
+                kept.push((name, entry, span));
+            }
+        }
+        kept.reverse();
+        *entries = kept;
+
+        let mut removed = before - entries.len();
+        for (_, entry, _) in entries.iter_mut() {
+            if let ConfigEntry::ClassEntry(c) = entry {
+                removed += c.optimize();
+            }
+        }
+
+        removed
+    }
+
+    fn replace_strings<F: FnMut(&str) -> Option<String>>(&mut self, replace: &mut F) -> usize {
+        let mut replaced = 0;
+        for (_, entry, _) in self.entries.iter_mut().flatten() {
+            match entry {
+                ConfigEntry::StringEntry(s) => {
+                    if let Some(new) = replace(s) {
+                        *s = Arc::from(new);
+                        replaced += 1;
+                    }
+                },
+                ConfigEntry::ArrayEntry(a) => { replaced += a.replace_strings(replace); },
+                ConfigEntry::ClassEntry(c) => { replaced += c.replace_strings(replace); },
+                _ => {}
+            }
+        }
+
+        replaced
+    }
+
+    /// Adds the path of every entry defined more than once in the class or its child classes.
+    fn duplicates(&self, path: &str, duplicates: &mut Vec<String>) {
+        let entries = match &self.entries {
+            Some(entries) => entries,
+            None => { return; }
+        };
+
+        let mut seen: Vec<String> = Vec::new();
+        for (name, entry, _) in entries {
+            let counts = match entry {
+                ConfigEntry::ClassEntry(c) => !c.is_external && !c.is_deletion,
+                ConfigEntry::ArrayEntry(a) => !a.is_expansion,
+                _ => true,
+            };
+            if counts {
+                if seen.contains(&name.to_lowercase()) {
+                    duplicates.push(format!("{}{}", path, name));
+                } else {
+                    seen.push(name.to_lowercase());
+                }
+            }
+
+            if let ConfigEntry::ClassEntry(c) = entry {
+                c.duplicates(&format!("{}{}.", path, name), duplicates);
+            }
+        }
+    }
+
+    fn locate_spans(&mut self, locator: &mut SpanLocator) {
+        for (_, entry, span) in self.entries.iter_mut().flatten() {
+            if let Some(span) = span {
+                locator.locate(span);
+            }
+            match entry {
+                ConfigEntry::ClassEntry(c) => c.locate_spans(locator),
+                ConfigEntry::ArrayEntry(a) => a.locate_spans(locator),
+                _ => {}
+            }
+        }
+    }
+
+    fn fixes(&self, preprocessed: &str, fixes: &mut Vec<Fix>) {
+        for (name, entry, span) in self.entries.iter().flatten() {
+            if let Some(span) = span {
+                let text = &preprocessed[span.range.clone()];
+                let trimmed = text.trim_end();
+                if !trimmed.ends_with(';') {
+                    let end = span.range.start + trimmed.len();
+                    fixes.push(Fix {
+                        range: end..end,
+                        replacement: ";".to_string(),
+                        message: format!("Missing semicolon after {}.", name),
+                        name: "missing-semicolon",
+                    });
+                }
+
+                if let ConfigEntry::StringEntry(s) = entry {
+                    // the value is everything between the equals sign and the semicolon
+                    let after_equals = text.find('=').map(|i| i + 1).unwrap_or(0);
+                    let value = text[after_equals..].trim_end().trim_end_matches(';').trim_end();
+                    let value_start = after_equals + value.len() - value.trim_start().len();
+                    string_fixes(value.trim_start(), s, span.range.start + value_start, fixes);
+                }
+            }
+
+            match entry {
+                ConfigEntry::ClassEntry(c) => c.fixes(preprocessed, fixes),
+                ConfigEntry::ArrayEntry(a) => a.fixes(preprocessed, fixes),
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the entry with the name, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&ConfigEntry> {
+        self.entries.iter().flatten().find(|(n, _, _)| n.eq_ignore_ascii_case(name)).map(|(_, entry, _)| entry)
+    }
+
+    /// Returns the name of the class this class inherits from, `None` if it has no parent.
+    pub fn parent(&self) -> Option<&str> {
+        if self.parent.is_empty() { None } else { Some(&self.parent) }
+    }
+
+    /// Returns whether the class is only declared, like `class Car;`, to inherit from it.
+    pub fn is_external(&self) -> bool {
+        self.is_external
+    }
+
+    /// Returns all entries with their names.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &ConfigEntry)> {
+        self.entries.iter().flatten().map(|(name, entry, _)| (&**name, entry))
+    }
+
+    /// Returns the subclasses with their names.
+    pub fn classes(&self) -> impl Iterator<Item = (&str, &ConfigClass)> {
+        self.entries.iter().flatten().filter_map(|(name, entry, _)| match entry {
+            ConfigEntry::ClassEntry(c) => Some((&**name, c)),
+            _ => None,
+        })
+    }
+
+    /// Returns the value of the string entry, ignoring the case of the name.
+    pub fn string(&self, name: &str) -> Option<&str> {
+        self.entries.iter().flatten().find_map(|(n, entry, _)| match entry {
+            ConfigEntry::StringEntry(s) if n.eq_ignore_ascii_case(name) => Some(&**s),
+            _ => None,
+        })
+    }
+
+    fn spans<'a>(&'a self, path: &str, spans: &mut Vec<(String, &'a Span)>) {
+        for (name, entry, span) in self.entries.iter().flatten() {
+            let entry_path = format!("{}{}", path, name);
+            if let Some(span) = span {
+                spans.push((entry_path.clone(), span));
+            }
+            match entry {
+                ConfigEntry::ClassEntry(c) => c.spans(&format!("{}.", entry_path), spans),
+                ConfigEntry::ArrayEntry(a) => a.spans(&entry_path, spans),
+                _ => {}
+            }
+        }
+    }
+
+    fn read_rapified(input: &mut Cursor<&[u8]>, level: u32, interner: &mut Interner) -> Result<ConfigClass, Error> {
         let mut fp = 0;
         if level == 0 {
             input.seek(SeekFrom::Start(16))?;
@@ -497,29 +1246,29 @@ impl ConfigClass {
             input.seek(SeekFrom::Start(classbody_fp.into()))?;
         }
 
-        let parent = input.read_cstring()?;
+        let parent = interner.read_cstring(input)?;
         let num_entries: u32 = input.read_compressed_int()?;
-        let mut entries: Vec<(String, ConfigEntry)> = Vec::with_capacity(num_entries as usize);
+        let mut entries: Vec<NamedEntry> = Vec::with_capacity(num_entries as usize);
 
         for _i in 0..num_entries {
-            let entry_type: u8 = input.bytes().next().unwrap()?;
+            let entry_type: u8 = input.read_u8()?;
 
             if entry_type == 0 {
-                let name = input.read_cstring()?;
+                let name = interner.read_cstring(input)?;
 
-                let class_entry = ConfigClass::read_rapified(input, level + 1)
+                let class_entry = ConfigClass::read_rapified(input, level + 1, interner)
                     .prepend_error(format!("Failed to read rapified class \"{}\":", name))?;
-                entries.push((name, ConfigEntry::ClassEntry(class_entry)));
+                entries.push((name, ConfigEntry::ClassEntry(class_entry), None));
             } else if entry_type == 1 {
-                let subtype: u8 = input.bytes().next().unwrap()?;
-                let name = input.read_cstring()?;
+                let subtype: u8 = input.read_u8()?;
+                let name = interner.read_cstring(input)?;
 
                 if subtype == 0 {
-                    entries.push((name, ConfigEntry::StringEntry(input.read_cstring()?)));
+                    entries.push((name, ConfigEntry::StringEntry(interner.read_cstring(input)?), None));
                 } else if subtype == 1 {
-                    entries.push((name, ConfigEntry::FloatEntry(input.read_f32::<LittleEndian>()?)));
+                    entries.push((name, ConfigEntry::FloatEntry(input.read_f32::<LittleEndian>()?), None));
                 } else if subtype == 2 {
-                    entries.push((name, ConfigEntry::IntEntry(input.read_i32::<LittleEndian>()?)));
+                    entries.push((name, ConfigEntry::IntEntry(input.read_i32::<LittleEndian>()?), None));
                 } else {
                     return Err(error!("Unrecognized variable entry subtype: {}.", subtype));
                 }
@@ -528,21 +1277,21 @@ impl ConfigClass {
                     input.seek(SeekFrom::Current(4))?;
                 }
 
-                let name = input.read_cstring()?;
-                let mut array = ConfigArray::read_rapified(input).prepend_error("Failed to read rapified array:")?;
+                let name = interner.read_cstring(input)?;
+                let mut array = ConfigArray::read_rapified(input, interner).prepend_error("Failed to read rapified array:")?;
                 array.is_expansion = entry_type == 5;
 
-                entries.push((name.clone(), ConfigEntry::ArrayEntry(array)));
+                entries.push((name, ConfigEntry::ArrayEntry(array), None));
             } else if entry_type == 3 || entry_type == 4 {
-                let name = input.read_cstring()?;
+                let name = interner.read_cstring(input)?;
                 let class_entry = ConfigClass {
-                    parent: String::from(""),
+                    parent: interner.intern(""),
                     is_external: entry_type == 3,
-                    is_deletion: entry_type == 5,
+                    is_deletion: entry_type == 4,
                     entries: None
                 };
 
-                entries.push((name.clone(), ConfigEntry::ClassEntry(class_entry)));
+                entries.push((name, ConfigEntry::ClassEntry(class_entry), None));
             } else {
                 return Err(error!("Unrecognized class entry type: {}.", entry_type));
             }
@@ -562,9 +1311,18 @@ impl ConfigClass {
 }
 
 impl Config {
+    /// Returns the root class containing all entries.
+    pub fn root(&self) -> &ConfigClass {
+        &self.root_body
+    }
+
     /// Writes the config (unrapified) to the output.
     pub fn write<O: Write>(&self, output: &mut O) -> Result<(), Error> {
-        self.root_body.write(output, 0)
+        let mut writer = BufWriter::new(output);
+        self.root_body.write(&mut writer, 0)?;
+        writer.flush()?;
+
+        Ok(())
     }
 
     /// Returns the unrapified config as a string.
@@ -576,30 +1334,275 @@ impl Config {
         Ok(String::from_utf8(cursor.into_inner()).unwrap())
     }
 
-    /// Writes the rapified config to the output.
+    /// Writes the config as JSON to the output. Classes are objects with their entries in the
+    /// order they are defined in, arrays are JSON arrays. The parent of a class is stored as
+    /// `$parent`, external classes (`class A;`) have `$external` and deleted classes `$delete`
+    /// set to true. Array expansions (`a[] += {...}`) are stored as `a+=`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::Config;
+    /// let input = String::from("class A: B { x = 1; y[] = {0.1, \"z\"}; };");
+    /// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+    ///
+    /// let mut json: Vec<u8> = Vec::new();
+    /// config.write_json(&mut json).unwrap();
+    /// assert_eq!("{\"A\":{\"$parent\":\"B\",\"x\":1,\"y\":[0.1,\"z\"]}}", String::from_utf8(json).unwrap());
+    /// ```
+    pub fn write_json<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        serde_json::to_writer(output, self).map_err(|e| error!("{}", e))
+    }
+
+    /// Reads a config from JSON structured like the output of `write_json`. Numbers with a
+    /// fraction or exponent are read as floats, all other numbers as integers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::Cursor;
+    /// # use armake2::config::Config;
+    /// let input = "{\"B\": {\"$external\": true}, \"A\": {\"$parent\": \"B\", \"x\": 1, \"y\": 0.5, \"z+=\": [\"a\"]}}";
+    /// let config = Config::read_json(&mut Cursor::new(input)).unwrap();
+    ///
+    /// assert_eq!("class B;\nclass A: B {\n    x = 1;\n    y = 0.5;\n    z[] += {\"a\"};\n};\n", config.to_string().unwrap());
+    /// ```
+    pub fn read_json<I: Read>(input: &mut I) -> Result<Config, Error> {
+        serde_json::from_reader(input).map_err(|e| error!("{}", e))
+    }
+
+    /// Writes the config as YAML to the output, structured like in `write_json`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::Config;
+    /// let input = String::from("class A: B { x = 1; y[] = {0.1, {}}; class C {}; };");
+    /// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+    ///
+    /// let mut yaml: Vec<u8> = Vec::new();
+    /// config.write_yaml(&mut yaml).unwrap();
+    /// assert_eq!("A:\n  \"$parent\": \"B\"\n  x: 1\n  y:\n    - 0.1\n    - []\n  C: {}\n", String::from_utf8(yaml).unwrap());
+    /// ```
+    pub fn write_yaml<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        self.root_body.write_yaml(output, 0)
+    }
+
+    /// Writes the rapified config to the output as it is serialized, so only the parsed config
+    /// and not its rapified form has to fit into memory.
     pub fn write_rapified<O: Write>(&self, output: &mut O) -> Result<(), Error> {
         let mut writer = BufWriter::new(output);
 
         writer.write_all(b"\0raP")?;
         writer.write_all(b"\0\0\0\0\x08\0\0\0")?; // always_0, always_8
 
-        let buffer: Box<[u8]> = vec![0; self.root_body.rapified_length()].into_boxed_slice();
-        let mut cursor: Cursor<Box<[u8]>> = Cursor::new(buffer);
-        self.root_body.write_rapified(&mut cursor, 16).prepend_error("Failed to rapify root class:")?;
-
-        let enum_offset: u32 = 16 + cursor.get_ref().len() as u32;
+        let enum_offset: u32 = 16 + self.root_body.rapified_length() as u32;
         writer.write_u32::<LittleEndian>(enum_offset)?;
 
-        writer.write_all(cursor.get_ref())?;
+        self.root_body.write_rapified(&mut writer, 16).prepend_error("Failed to rapify root class:")?;
 
         writer.write_all(b"\0\0\0\0")?;
 
         Ok(())
     }
 
+    /// Returns the size of the rapified config in bytes.
+    pub fn rapified_length(&self) -> usize {
+        self.root_body.rapified_length() + 20
+    }
+
+    /// Removes properties that don't change the config when it is loaded on its own: properties
+    /// defined again later in the same class and string properties containing only whitespace in
+    /// classes without a parent. Returns the number of removed properties.
+    ///
+    /// Whitespace-only strings can still override a value from a class of the same name in
+    /// another addon, so this is only applied on request.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::Config;
+    /// let input = String::from("foo = 1; bar = \"  \"; foo = 2;");
+    /// let mut config = Config::from_string(input, None, &Vec::new()).unwrap();
+    ///
+    /// assert_eq!(2, config.optimize());
+    /// assert_eq!("foo = 2;\n", config.to_string().unwrap());
+    /// ```
+    pub fn optimize(&mut self) -> usize {
+        self.root_body.optimize()
+    }
+
+    /// Returns the paths and spans of all entries and array elements in the order they appear in
+    /// the config. Paths are class and entry names separated by dots, array elements are
+    /// addressed by their index, e.g. `CfgPatches.foo.units[0]`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::Config;
+    /// let input = String::from("class A {\n    x = 1;\n    y[] = {2, {3}};\n};\n");
+    /// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+    ///
+    /// let paths: Vec<String> = config.spans().into_iter().map(|(path, _)| path).collect();
+    /// assert_eq!(vec!["A", "A.x", "A.y", "A.y[0]", "A.y[1]", "A.y[1][0]"], paths);
+    ///
+    /// let span = config.span("a.Y[1]").unwrap();
+    /// assert_eq!(3, span.line);
+    /// assert_eq!(3, span.range.len());
+    /// ```
+    pub fn spans(&self) -> Vec<(String, &Span)> {
+        let mut spans: Vec<(String, &Span)> = Vec::new();
+        self.root_body.spans("", &mut spans);
+
+        spans
+    }
+
+    /// Returns the paths of the entries defined more than once in their class, ignoring case.
+    /// Declarations of external classes, deletions and array expansions don't count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::Config;
+    /// let input = String::from("class A { x = 1; X = 2; y[] = {}; y[] += {1}; }; class B; class B {};");
+    /// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+    ///
+    /// assert_eq!(vec!["A.X"], config.duplicates());
+    /// ```
+    pub fn duplicates(&self) -> Vec<String> {
+        let mut duplicates: Vec<String> = Vec::new();
+        self.root_body.duplicates("", &mut duplicates);
+        duplicates
+    }
+
+    /// Returns the entry at the path of class names and the entry name separated by slashes or
+    /// dots, like `CfgPatches/my_addon/requiredVersion`, ignoring case. `None` if there is no such
+    /// entry or one of the classes on the way is missing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::{Config, ConfigEntry};
+    /// let input = String::from("class CfgPatches { class my_addon { requiredVersion = 1.5; }; };");
+    /// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+    ///
+    /// assert!(matches!(config.get("CfgPatches/my_addon/requiredVersion"), Some(ConfigEntry::FloatEntry(f)) if *f == 1.5));
+    /// assert!(matches!(config.get("cfgpatches.MY_ADDON"), Some(ConfigEntry::ClassEntry(_))));
+    /// assert!(config.get("CfgPatches/other_addon/requiredVersion").is_none());
+    /// ```
+    pub fn get(&self, path: &str) -> Option<&ConfigEntry> {
+        self.get_named(path).map(|(_, entry)| entry)
+    }
+
+    fn get_named(&self, path: &str) -> Option<(&str, &ConfigEntry)> {
+        let mut class = &self.root_body;
+        let mut names = path.split(['/', '.']).filter(|n| !n.is_empty()).peekable();
+        while let Some(name) = names.next() {
+            let (name, entry) = class.entries().find(|(n, _)| n.eq_ignore_ascii_case(name))?;
+            if names.peek().is_none() {
+                return Some((name, entry));
+            }
+            class = match entry {
+                ConfigEntry::ClassEntry(c) => c,
+                _ => { return None; }
+            };
+        }
+
+        None
+    }
+
+    /// Returns the span of the entry or array element at the path (see `spans`), ignoring case.
+    /// `None` if there is no such node or the config was read rapified.
+    pub fn span(&self, path: &str) -> Option<&Span> {
+        self.spans().into_iter().find(|(p, _)| p.eq_ignore_ascii_case(path)).map(|(_, span)| span)
+    }
+
+    /// Returns the automatic fixes for unquoted strings, missing semicolons and paths with
+    /// forward slashes, ordered by their position. `preprocessed` has to be the text the config
+    /// was parsed from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::Config;
+    /// # use armake2::preprocess::preprocess;
+    /// let (preprocessed, info) = preprocess(String::from("class A { x = foo; y = 1 };"), None, &Vec::new()).unwrap();
+    /// let config = Config::from_preprocessed(&preprocessed, &info).unwrap();
+    ///
+    /// let fixes: Vec<&str> = config.fixes(&preprocessed).iter().map(|f| f.name).collect();
+    /// assert_eq!(vec!["unquoted-string", "missing-semicolon"], fixes);
+    /// ```
+    pub fn fixes(&self, preprocessed: &str) -> Vec<Fix> {
+        let mut fixes: Vec<Fix> = Vec::new();
+        self.root_body.fixes(preprocessed, &mut fixes);
+        fixes.sort_by_key(|f| (f.range.start, f.range.end));
+
+        fixes
+    }
+
+    /// Replaces string entries and array elements for which `replace` returns a new value.
+    /// Returns the number of replaced strings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::Config;
+    /// let input = String::from("class A { model = \"a.p3d\"; textures[] = {\"a.paa\", 1}; };");
+    /// let mut config = Config::from_string(input, None, &Vec::new()).unwrap();
+    ///
+    /// assert_eq!(2, config.replace_strings(|s| if s.starts_with("a.") { Some(format!("x\\{}", s)) } else { None }));
+    /// assert_eq!(Some("x\\a.p3d"), config.root().classes().next().unwrap().1.string("model"));
+    /// ```
+    pub fn replace_strings<F: FnMut(&str) -> Option<String>>(&mut self, mut replace: F) -> usize {
+        self.root_body.replace_strings(&mut replace)
+    }
+
+    /// Returns the functions declared in `CfgFunctions`, in the order they appear in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::Config;
+    /// let input = String::from("class CfgFunctions { class ABC { tag = \"XYZ\"; class Misc { file = \"x\\abc\\functions\"; class init {}; }; }; };");
+    /// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+    ///
+    /// let functions = config.functions();
+    /// assert_eq!("XYZ_fnc_init", functions[0].full_name());
+    /// assert_eq!("x\\abc\\functions\\fn_init.sqf", functions[0].path);
+    /// ```
+    pub fn functions(&self) -> Vec<ConfigFunction> {
+        let mut functions: Vec<ConfigFunction> = Vec::new();
+        let cfg_functions = match self.root_body.classes().find(|(name, _)| name.eq_ignore_ascii_case("CfgFunctions")) {
+            Some((_, c)) => c,
+            None => { return functions; }
+        };
+
+        for (tag_name, tag_class) in cfg_functions.classes() {
+            let tag = tag_class.string("tag").unwrap_or(tag_name);
+            for (category, category_class) in tag_class.classes() {
+                let folder = category_class.string("file").map(|f| f.trim_end_matches('\\').to_string())
+                    .unwrap_or_else(|| format!("functions\\{}", category));
+                for (name, function_class) in category_class.classes() {
+                    let path = match function_class.string("file") {
+                        Some(file) => file.to_string(),
+                        None => format!("{}\\fn_{}{}", folder, name, function_class.string("ext").unwrap_or(".sqf")),
+                    };
+                    functions.push(ConfigFunction {
+                        tag: tag.to_string(),
+                        category: category.to_string(),
+                        name: name.to_string(),
+                        path,
+                    });
+                }
+            }
+        }
+
+        functions
+    }
+
     /// Returns the rapified config as a `Cursor`.
     pub fn to_cursor(&self) -> Result<Cursor<Box<[u8]>>, Error> {
-        let len = self.root_body.rapified_length() + 20;
+        let len = self.rapified_length();
 
         let buffer: Box<[u8]> = vec![0; len].into_boxed_slice();
         let mut cursor: Cursor<Box<[u8]>> = Cursor::new(buffer);
@@ -614,23 +1617,98 @@ impl Config {
     /// messages. `includefolders` are the folders searched for absolute includes and should usually at
     /// least include the current working directory.
     pub fn read<I: Read>(input: &mut I, path: Option<PathBuf>, includefolders: &[PathBuf]) -> Result<Config, Error> {
+        Self::read_with(input, path, includefolders, &PreprocessOptions::default())
+    }
+
+    /// Reads the unrapified config from input, preprocessing it with the include paths and defines
+    /// of `options`, see `preprocess_with`.
+    pub fn read_with<I: Read>(input: &mut I, path: Option<PathBuf>, includefolders: &[PathBuf], options: &PreprocessOptions) -> Result<Config, Error> {
         let mut buffer = String::new();
         input.read_to_string(&mut buffer).prepend_error("Failed to read input file:")?;
 
-        let (preprocessed, info) = preprocess(buffer, path, includefolders).prepend_error("Failed to preprocess config:")?;
+        let (preprocessed, info) = preprocess_with(buffer, path, includefolders, options).prepend_error("Failed to preprocess config:")?;
+
+        Self::from_preprocessed(&preprocessed, &info)
+    }
+
+    /// Parses an already preprocessed config. `info` is used for the locations of errors and
+    /// warnings.
+    pub fn from_preprocessed(preprocessed: &str, info: &PreprocessInfo) -> Result<Config, Error> {
+        Self::parse(preprocessed, info, false, false)
+    }
+
+    /// Parses an already preprocessed config like `from_preprocessed`, but fails for constructs
+    /// that are otherwise tolerated: unquoted strings, missing semicolons before the end of a
+    /// class and entries that are defined multiple times in the same class.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::Config;
+    /// # use armake2::preprocess::preprocess;
+    /// let (preprocessed, info) = preprocess(String::from("class A { x = 1; x = 2; y = 3 };"), None, &Vec::new()).unwrap();
+    ///
+    /// assert!(Config::from_preprocessed(&preprocessed, &info).is_ok());
+    /// assert!(Config::from_preprocessed_strict(&preprocessed, &info).is_err());
+    /// ```
+    pub fn from_preprocessed_strict(preprocessed: &str, info: &PreprocessInfo) -> Result<Config, Error> {
+        Self::parse(preprocessed, info, true, false)
+    }
 
+    /// Parses an already preprocessed config like `from_preprocessed`, but skips invalid entries
+    /// up to the next semicolon or the end of their class and continues parsing. Fails with all
+    /// syntax errors found in the config instead of only the first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::Config;
+    /// # use armake2::preprocess::preprocess;
+    /// let (preprocessed, info) = preprocess(String::from("class A {\n    x = ;\n    class B { = 1; };\n};\nz = 3;\n"), None, &Vec::new()).unwrap();
+    ///
+    /// let error = Config::from_preprocessed_recover(&preprocessed, &info).unwrap_err().to_string();
+    /// assert!(error.starts_with("2 syntax errors:"));
+    /// ```
+    pub fn from_preprocessed_recover(preprocessed: &str, info: &PreprocessInfo) -> Result<Config, Error> {
+        Self::parse(preprocessed, info, false, true)
+    }
+
+    fn parse(preprocessed: &str, info: &PreprocessInfo, strict: bool, recover: bool) -> Result<Config, Error> {
         let mut warnings: Vec<(usize, String, Option<&'static str>)> = Vec::new();
 
-        let result = ConfigParseErrorExt::format_error(config_grammar::config(&preprocessed, &mut warnings), &info, &preprocessed);
+        let result = ConfigParseErrorExt::format_error(config_grammar::config(preprocessed, &mut warnings, &mut Interner::default(), recover), info, preprocessed);
+
+        // entries skipped in recovery mode, skipped again when the parser backtracks
+        let mut errors: Vec<(usize, String)> = warnings.iter().filter(|w| w.2 == Some("syntax-error")).map(|w| (w.0, w.1.clone())).collect();
+        errors.sort();
+        errors.dedup();
+        warnings.retain(|w| w.2 != Some("syntax-error"));
 
+        let line_of = |pos: usize| preprocessed[..pos].chars().filter(|c| c == &'\n').count() + 1;
+        let location = |pos: usize| -> (Option<String>, Option<u32>) {
+            let line = line_of(pos);
+            let origin = &info.line_origins[min(line, info.line_origins.len()) - 1];
+
+            (origin.1.as_ref().map(|p| p.to_str().unwrap().to_string()), Some(origin.0))
+        };
+
+        let mut problems: Vec<String> = Vec::new();
         for w in warnings {
+            if strict && STRICT_WARNINGS.contains(&w.2.unwrap_or("")) {
+                let (file, line) = location(w.0);
+                problems.push(format!("In file {}:{}: {} [{}]", file.unwrap_or_default(), line.unwrap(), w.1, w.2.unwrap()));
+                continue;
+            }
+            // missing semicolons are only reported in strict mode
+            if w.2 == Some("missing-semicolon") {
+                continue;
+            }
+            if w.2.map(|name| info.is_muted(name, line_of(w.0))).unwrap_or(false) {
+                continue;
+            }
 
             let location = if !warning_suppressed(w.2) {
-                let mut line = preprocessed[..w.0].chars().filter(|c| c == &'\n').count();
-                let file = info.line_origins[min(line, info.line_origins.len()) - 1].1.as_ref().map(|p| p.to_str().unwrap().to_string());
-                line = info.line_origins[min(line, info.line_origins.len()) - 1].0 as usize + 1;
-
-                (file, Some(line as u32))
+                location(w.0)
             } else {
                 (None, None)
             };
@@ -638,7 +1716,29 @@ impl Config {
             warning(w.1, w.2, location);
         }
 
-        result
+        if !errors.is_empty() {
+            let mut messages: Vec<String> = errors.iter().map(|(pos, skipped)| {
+                let (file, line) = location(*pos);
+                let snippet = skipped.lines().next().unwrap_or("").trim();
+                format!("In file {}:{}: Invalid entry \"{}\"", file.unwrap_or_default(), line.unwrap(), snippet)
+            }).collect();
+            if let Err(e) = result {
+                messages.push(e.to_string());
+            }
+            return Err(error!("{} syntax errors:\n{}", messages.len(), messages.join("\n")));
+        }
+
+        let mut config = result?;
+        config.root_body.locate_spans(&mut SpanLocator::new(preprocessed, info));
+
+        if strict {
+            problems.extend(config.duplicates().into_iter().map(|path| format!("{} is defined multiple times.", path)));
+        }
+        if !problems.is_empty() {
+            return Err(error!("{} problems in strict mode:\n{}", problems.len(), problems.join("\n")));
+        }
+
+        Ok(config)
     }
 
     /// Preprocesses and parses input string.
@@ -651,41 +1751,170 @@ impl Config {
         Self::read(&mut cursor, path, includefolders)
     }
 
-    /// Reads the rapified config from input.
-    pub fn read_rapified<I: Read + Seek>(input: &mut I) -> Result<Config, Error> {
-        let mut reader = BufReader::new(input);
-
-        let mut buffer = [0; 4];
-        reader.read_exact(&mut buffer)?;
+    /// Writes the rapified config compressed with LZSS to the output.
+    pub fn write_rapified_compressed<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        let rapified = self.to_cursor()?.into_inner();
+        output.write_all(&lzss_compress(&rapified))
+    }
 
-        if &buffer != b"\0raP" {
-            return Err(error!("File doesn't seem to be a rapified config."));
+    /// Reads the rapified config from input. LZSS compressed configs are detected and
+    /// decompressed.
+    ///
+    /// The input is read into memory first, classes are read by seeking back and forth.
+    pub fn read_rapified<I: Read>(input: &mut I) -> Result<Config, Error> {
+        let mut data: Vec<u8> = Vec::new();
+        input.read_to_end(&mut data)?;
+
+        if !data.starts_with(b"\0raP") {
+            data = match lzss_decompress(&data) {
+                Ok(data) if data.starts_with(b"\0raP") => data,
+                _ => { return Err(error!("File doesn't seem to be a rapified config.")); }
+            };
         }
 
         Ok(Config {
-            root_body: ConfigClass::read_rapified(&mut reader, 0)?
+            root_body: ConfigClass::read_rapified(&mut Cursor::new(&data), 0, &mut Interner::default())?
         })
     }
 }
 
+/// Options for `cmd_rapify`
+#[derive(Debug, Clone, Default)]
+pub struct RapifyOptions {
+    /// remove redundant properties
+    pub optimize: bool,
+    /// compress the rapified config with LZSS
+    pub compress: bool,
+    /// fail for tolerated oddities instead of warning
+    pub strict: bool,
+    /// report all syntax errors instead of only the first
+    pub recover: bool,
+    /// read the input as JSON structured like the output of `Config::write_json` instead of a
+    /// config, without preprocessing
+    pub json: bool,
+}
+
 /// Reads input, preprocesses and rapifies it and writes to output.
 ///
 /// `path` is the path to the input if it is known and is used for relative includes and error
 /// messages. `includefolders` are the folders searched for absolute includes and should usually at
-/// least include the current working directory.
-pub fn cmd_rapify<I: Read, O: Write>(input: &mut I, output: &mut O, path: Option<PathBuf>, includefolders: &[PathBuf]) -> Result<(), Error> {
-    let config = Config::read(input, path, includefolders)?;
+/// least include the current working directory. `defines` are macros defined before the input is
+/// read.
+///
+/// With `options.optimize`, redundant properties are removed (see `Config::optimize`) and the
+/// size savings are printed. With `options.compress`, the rapified config is compressed with LZSS.
+/// With `options.strict`, tolerated oddities are errors (see `Config::from_preprocessed_strict`).
+/// With `options.recover`, all syntax errors are reported (see `Config::from_preprocessed_recover`).
+/// With `options.json`, the input is JSON instead (see `Config::read_json`).
+pub fn cmd_rapify<I: Read, O: Write>(input: &mut I, output: &mut O, path: Option<PathBuf>, includefolders: &[PathBuf], defines: &[(String, String)], options: &RapifyOptions) -> Result<(), Error> {
+    let mut config = if options.json {
+        Config::read_json(input).prepend_error("Failed to read JSON config:")?
+    } else if options.strict || options.recover {
+        let mut buffer = String::new();
+        input.read_to_string(&mut buffer).prepend_error("Failed to read input file:")?;
+        let (preprocessed, info) = preprocess_with(buffer, path, includefolders, &PreprocessOptions::new(defines)).prepend_error("Failed to preprocess config:")?;
+        Config::parse(&preprocessed, &info, options.strict, options.recover)?
+    } else {
+        Config::read_with(input, path, includefolders, &PreprocessOptions::new(defines))?
+    };
+
+    if options.optimize {
+        let before = config.rapified_length();
+        let removed = config.optimize();
+        let after = config.rapified_length();
+        eprintln!("Removed {} redundant properties, {} -> {} bytes ({:.1}% smaller).",
+            removed, before, after, 100.0 * (before - after) as f64 / before as f64);
+    }
 
-    config.write_rapified(output).prepend_error("Failed to write rapified config:")?;
+    if options.compress {
+        config.write_rapified_compressed(output).prepend_error("Failed to write rapified config:")?;
+    } else {
+        config.write_rapified(output).prepend_error("Failed to write rapified config:")?;
+    }
 
     Ok(())
 }
 
-/// Reads input, derapifies it and writes to output.
-pub fn cmd_derapify<I: Read + Seek, O: Write>(input: &mut I, output: &mut O) -> Result<(), Error> {
+/// Writes a single entry for `cmd_config_get`. Classes are listed by the names of their entries
+/// unless `full` is set.
+fn write_entry<O: Write>(output: &mut O, name: &str, entry: &ConfigEntry, format: &str, full: bool) -> Result<(), Error> {
+    match (format, entry) {
+        ("cpp", ConfigEntry::StringEntry(s)) => writeln!(output, "{}", s),
+        ("cpp", ConfigEntry::FloatEntry(f)) => writeln!(output, "{:?}", f),
+        ("cpp", ConfigEntry::IntEntry(i)) => writeln!(output, "{}", i),
+        ("cpp", ConfigEntry::ArrayEntry(a)) => a.write(output).and_then(|_| output.write_all(b"\n")),
+        ("cpp", ConfigEntry::ClassEntry(c)) if !full => {
+            c.entries().try_for_each(|(n, _)| writeln!(output, "{}", n))
+        },
+        ("cpp", ConfigEntry::ClassEntry(c)) if c.is_deletion => writeln!(output, "delete {};", name),
+        ("cpp", ConfigEntry::ClassEntry(c)) if c.is_external => writeln!(output, "class {};", name),
+        ("cpp", ConfigEntry::ClassEntry(c)) => {
+            let parent = if c.parent.is_empty() { String::new() } else { format!(": {}", c.parent) };
+            if c.entries().next().is_none() {
+                return writeln!(output, "class {}{} {{}};", name, parent);
+            }
+            write!(output, "class {}{} {{", name, parent)?;
+            c.write(output, 1)?;
+            output.write_all(b"};\n")
+        },
+        ("json" | "yaml", ConfigEntry::StringEntry(s)) => writeln!(output, "{}", serde_json::to_string(&**s).unwrap()),
+        ("json", ConfigEntry::FloatEntry(f)) => writeln!(output, "{}", serde_json::to_string(&float_value(*f)).unwrap()),
+        ("yaml", ConfigEntry::FloatEntry(f)) => writeln!(output, "{}", yaml_float(*f)),
+        ("json" | "yaml", ConfigEntry::IntEntry(i)) => writeln!(output, "{}", i),
+        ("json", ConfigEntry::ArrayEntry(a)) => writeln!(output, "{}", serde_json::to_string(a).unwrap()),
+        ("yaml", ConfigEntry::ArrayEntry(a)) if a.elements.is_empty() => output.write_all(b"[]\n"),
+        ("yaml", ConfigEntry::ArrayEntry(a)) => a.write_yaml(output, 0),
+        ("json", ConfigEntry::ClassEntry(c)) if !full => {
+            writeln!(output, "{}", serde_json::to_string(&c.entries().map(|(n, _)| n).collect::<Vec<&str>>()).unwrap())
+        },
+        ("yaml", ConfigEntry::ClassEntry(c)) if !full => {
+            c.entries().try_for_each(|(n, _)| writeln!(output, "- {}", serde_json::to_string(n).unwrap()))
+        },
+        ("json", ConfigEntry::ClassEntry(c)) => writeln!(output, "{}", serde_json::to_string(c).unwrap()),
+        ("yaml", ConfigEntry::ClassEntry(c)) => {
+            let mut yaml: Vec<u8> = Vec::new();
+            c.write_yaml(&mut yaml, 0)?;
+            output.write_all(if yaml.is_empty() { b"{}\n" } else { &yaml })
+        },
+        _ => Err(error!("Unknown format \"{}\", expected cpp, json or yaml.", format)),
+    }
+}
+
+/// Reads the config at `source`, rapified or not, and writes the entry at `path` (see
+/// `Config::get`) to output. Strings are written without quotes, arrays like in configs and
+/// classes as the names of their entries, one per line, or with `full` as the whole class.
+/// `format` `json` or `yaml` writes the entry as JSON or YAML instead, see `Config::write_json`.
+///
+/// Configs that aren't rapified are preprocessed first, `includefolders` are the folders searched
+/// for absolute includes.
+pub fn cmd_config_get<O: Write>(output: &mut O, source: PathBuf, includefolders: &[PathBuf], path: &str, format: &str, full: bool) -> Result<(), Error> {
+    let content = if EntryAddress::is_address(&source.to_string_lossy()) {
+        read_address(&source.to_string_lossy())?.into_vec()
+    } else {
+        std::fs::read(&source).prepend_error("Failed to read config:")?
+    };
+    let is_rapified = content.starts_with(b"\0raP") || source.extension().map(|e| e.eq_ignore_ascii_case("bin")).unwrap_or(false);
+    let config = if is_rapified {
+        Config::read_rapified(&mut Cursor::new(content)).prepend_error("Failed to read rapified config:")?
+    } else {
+        Config::read_with(&mut Cursor::new(content), Some(source.clone()), includefolders, &PreprocessOptions::new(&[]))?
+    };
+
+    let (name, entry) = config.get_named(path).ok_or_else(|| error!("{} has no entry {}.", source.display(), path))?;
+    write_entry(output, name, entry, format, full)
+}
+
+/// Reads input, derapifies it and writes to output as a config (`format` is `cpp`), JSON (`json`)
+/// or YAML (`yaml`), see `Config::write_json`.
+pub fn cmd_derapify<I: Read + Seek, O: Write>(input: &mut I, output: &mut O, format: &str) -> Result<(), Error> {
     let config = Config::read_rapified(input).prepend_error("Failed to read rapified config:")?;
 
-    config.write(output).prepend_error("Failed to derapify config:")?;
+    match format {
+        "cpp" => config.write(output),
+        "json" => config.write_json(output).and_then(|_| output.write_all(b"\n")),
+        "yaml" => config.write_yaml(output),
+        _ => { return Err(error!("Unknown format \"{}\", expected cpp, json or yaml.", format)); }
+    }.prepend_error("Failed to derapify config:")?;
 
     Ok(())
 }
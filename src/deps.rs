@@ -0,0 +1,97 @@
+//! Tracking the files read by rapify and build, for dependency files of external build systems
+
+use std::collections::{BTreeSet};
+use std::fs::{File};
+use std::io::{BufWriter, Error, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex};
+
+use once_cell::sync::Lazy;
+use serde::{Serialize};
+
+use crate::*;
+use crate::error::*;
+use crate::pbo::{absolute_path};
+
+/// Files read since `start_tracking`, `None` while not tracking
+static DEPENDENCIES: Lazy<Mutex<Option<BTreeSet<PathBuf>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Starts recording the files read by the preprocessor and the files packed into PBOs.
+pub fn start_tracking() {
+    *DEPENDENCIES.lock().unwrap() = Some(BTreeSet::new());
+}
+
+/// Records files as read, if tracking. Paths are made absolute, so files read through different
+/// relative paths are only listed once.
+pub fn track<'a, I: IntoIterator<Item = &'a PathBuf>>(paths: I) {
+    if let Some(dependencies) = DEPENDENCIES.lock().unwrap().as_mut() {
+        for path in paths {
+            dependencies.insert(absolute_path(path).unwrap_or_else(|| path.clone()));
+        }
+    }
+}
+
+/// Stops tracking and returns the files read since `start_tracking`, sorted.
+pub fn finish_tracking() -> Vec<PathBuf> {
+    DEPENDENCIES.lock().unwrap().take().map(|d| d.into_iter().collect()).unwrap_or_default()
+}
+
+/// Escapes a path for a make rule: spaces and `#` with a backslash, `$` as `$$`.
+fn escape_make(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' | '#' => { escaped.push('\\'); escaped.push(c); },
+            '$' => { escaped.push_str("$$"); },
+            _ => { escaped.push(c); }
+        }
+    }
+    escaped
+}
+
+/// Writes a make rule of `target` depending on `dependencies`, as written by `gcc -MD` and read
+/// by make and ninja.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::{Path, PathBuf};
+/// # use armake2::deps::write_make;
+/// let mut output = Vec::new();
+/// write_make(&mut output, Path::new("addons/main.pbo"), &[PathBuf::from("main/config.cpp"), PathBuf::from("my headers/macros.hpp")]).unwrap();
+///
+/// assert_eq!("addons/main.pbo: \\\n  main/config.cpp \\\n  my\\ headers/macros.hpp\n", String::from_utf8(output).unwrap());
+/// ```
+pub fn write_make<O: Write>(output: &mut O, target: &Path, dependencies: &[PathBuf]) -> Result<(), Error> {
+    write!(output, "{}:", escape_make(target))?;
+    for dependency in dependencies {
+        write!(output, " \\\n  {}", escape_make(dependency))?;
+    }
+    output.write_all(b"\n")
+}
+
+/// Target with the files it was built from, as written to JSON dependency files
+#[derive(Serialize)]
+struct DependencyList<'a> {
+    target: &'a Path,
+    dependencies: &'a [PathBuf],
+}
+
+/// Writes `target` and its `dependencies` as a JSON object.
+pub fn write_json<O: Write>(output: &mut O, target: &Path, dependencies: &[PathBuf]) -> Result<(), Error> {
+    let list = DependencyList { target, dependencies };
+    serde_json::to_writer_pretty(&mut *output, &list).map_err(|e| error!("{}", e))?;
+    output.write_all(b"\n")
+}
+
+/// Writes the dependency file for `target` to `path`, as JSON if its extension is `json` and as
+/// a make rule otherwise.
+pub fn write_file(path: &Path, target: &Path, dependencies: &[PathBuf]) -> Result<(), Error> {
+    let mut output = BufWriter::new(File::create(path).prepend_error(format!("Failed to create dependency file {}:", path.display()))?);
+    if path.extension().map(|e| e.eq_ignore_ascii_case("json")).unwrap_or(false) {
+        write_json(&mut output, target, dependencies)?;
+    } else {
+        write_make(&mut output, target, dependencies)?;
+    }
+    output.flush()
+}
@@ -3,38 +3,251 @@
 use std::cmp::{min};
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Display};
-use std::io::{Error};
-use std::path::{PathBuf};
+use std::fs::{File};
+use std::io::{Read, Write, Error};
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
 use colored::Colorize;
 use once_cell::sync::Lazy;
 use peg::error::ParseError;
 use peg::str::LineCol;
+use serde::{Serialize};
 
+use crate::paths::{matches_glob_suffix};
 use crate::preprocess::*;
 
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => (
+        std::io::Error::new(std::io::ErrorKind::Other, format!($($arg)*))
+    )
+}
+
+/// Name of the warning configuration file, read from the current folder
+pub const WARNING_CONFIG_FILE: &str = ".armake2-warnings.toml";
+
+/// Warnings muted or capped for files matching a glob
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathWarningRule {
+    /// glob like `legacy/**`, matched against the end of the path of the file raising a warning
+    pub glob: String,
+    pub muted: HashSet<String>,
+    /// maximum number of warnings of each name in the matching files
+    pub max: Option<u32>,
+}
+
+/// Warning configuration of a project, read from a `.armake2-warnings.toml` file
+///
+/// ```toml
+/// # muted everywhere
+/// mute = ["redefinition-wo-undef"]
+/// # maximum number of warnings of each name, 10 by default
+/// max = 20
+///
+/// # maximum number of warnings of specific names
+/// [caps]
+/// unquoted-string = 50
+///
+/// [[paths]]
+/// glob = "legacy/**"
+/// mute = ["unquoted-string"]
+/// max = 5
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::error::WarningConfig;
+/// let config = WarningConfig::from_string("mute = [\"a\", \"b\"]\n[caps]\nc = 3\n[[paths]]\nglob = \"legacy/**\"\nmute = [\"d\"]\n").unwrap();
+///
+/// assert!(config.muted.contains("b"));
+/// assert_eq!(Some(&3), config.caps.get("c"));
+/// assert_eq!("legacy/**", config.paths[0].glob);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarningConfig {
+    pub muted: HashSet<String>,
+    /// maximum number of warnings of each name
+    pub max: Option<u32>,
+    /// maximum number of warnings of specific names
+    pub caps: HashMap<String, u32>,
+    pub paths: Vec<PathWarningRule>,
+}
+
+enum TomlValue {
+    Integer(u32),
+    String(String),
+    Array(Vec<String>),
+}
+
+fn parse_toml_string(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.len() >= 2 && ((value.starts_with('"') && value.ends_with('"')) || (value.starts_with('\'') && value.ends_with('\''))) {
+        Some(value[1..value.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+fn parse_toml_value(value: &str) -> Option<TomlValue> {
+    let value = value.trim();
+    if let Some(inner) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        let items: Option<Vec<String>> = inner.split(',')
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+            .map(parse_toml_string)
+            .collect();
+        items.map(TomlValue::Array)
+    } else if let Some(string) = parse_toml_string(value) {
+        Some(TomlValue::String(string))
+    } else {
+        value.parse().ok().map(TomlValue::Integer)
+    }
+}
+
+/// Removes a comment from a line, ignoring `#` in strings.
+fn strip_toml_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        match (c, quote) {
+            ('"', None) | ('\'', None) => { quote = Some(c); },
+            (_, Some(q)) if c == q => { quote = None; },
+            ('#', None) => { return &line[..i]; },
+            _ => {}
+        }
+    }
+    line
+}
+
+impl WarningConfig {
+    /// Parses a warning configuration. Only the subset of TOML needed for it is supported: keys
+    /// with integers, strings and arrays of strings, the `[caps]` table and `[[paths]]` entries.
+    pub fn from_string(input: &str) -> Result<WarningConfig, Error> {
+        let mut config = WarningConfig::default();
+        let mut table = String::new();
+        // multi-line arrays are joined before parsing
+        let mut pending: Option<(usize, String)> = None;
+
+        for (i, line) in input.lines().enumerate() {
+            let mut line = strip_toml_comment(line).trim().to_string();
+            let mut line_number = i + 1;
+            if let Some((start, previous)) = pending.take() {
+                line = format!("{} {}", previous, line);
+                line_number = start;
+            }
+            if line.is_empty() { continue; }
+            if line.matches('[').count() > line.matches(']').count() {
+                pending = Some((line_number, line));
+                continue;
+            }
+
+            if line == "[[paths]]" {
+                table = "paths".to_string();
+                config.paths.push(PathWarningRule::default());
+                continue;
+            } else if line.starts_with('[') {
+                table = line.trim_matches(|c| c == '[' || c == ']').trim().to_string();
+                if table != "caps" {
+                    return Err(error!("In line {}: Unknown table \"{}\".", line_number, table));
+                }
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (parse_toml_string(key).unwrap_or_else(|| key.trim().to_string()), parse_toml_value(value)),
+                None => { return Err(error!("In line {}: Expected \"key = value\".", line_number)); }
+            };
+
+            match (table.as_str(), key.as_str(), value) {
+                ("", "mute", Some(TomlValue::Array(names))) => { config.muted.extend(names); },
+                ("", "max", Some(TomlValue::Integer(max))) => { config.max = Some(max); },
+                ("caps", _, Some(TomlValue::Integer(max))) => { config.caps.insert(key, max); },
+                ("paths", "glob", Some(TomlValue::String(glob))) => { config.paths.last_mut().unwrap().glob = glob; },
+                ("paths", "mute", Some(TomlValue::Array(names))) => { config.paths.last_mut().unwrap().muted.extend(names); },
+                ("paths", "max", Some(TomlValue::Integer(max))) => { config.paths.last_mut().unwrap().max = Some(max); },
+                _ => { return Err(error!("In line {}: Invalid value for \"{}\".", line_number, key)); }
+            }
+        }
+
+        if let Some((line_number, _)) = pending {
+            return Err(error!("In line {}: Unterminated array.", line_number));
+        }
+        if let Some(rule) = config.paths.iter().find(|rule| rule.glob.is_empty()) {
+            return Err(error!("Path rule muting {:?} has no glob.", rule.muted));
+        }
+
+        Ok(config)
+    }
+
+    /// Reads the warning configuration at `path`, an empty configuration if it doesn't exist.
+    pub fn read(path: &Path) -> Result<WarningConfig, Error> {
+        if !path.exists() {
+            return Ok(WarningConfig::default());
+        }
+
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+        WarningConfig::from_string(&content).prepend_error(format!("Failed to read {}:", path.display()))
+    }
+}
+
+/// Returns whether the glob matches the end of the path. `**` matches any number of folders,
+/// `*` any part of a single file or folder name. Matching is case-insensitive.
+///
+/// ```
+/// # use armake2::error::matches_path_glob;
+/// assert!(matches_path_glob("P:\\x\\mod\\addons\\main\\legacy\\macros.hpp", "legacy/**"));
+/// assert!(matches_path_glob("addons/main/config.cpp", "addons/*/config.cpp"));
+/// assert!(!matches_path_glob("addons/main/legacy.hpp", "legacy/**"));
+/// ```
+pub fn matches_path_glob(path: &str, glob: &str) -> bool {
+    matches_glob_suffix(path, glob)
+}
+
 struct WarningState {
     max: u32,
     muted: HashSet<String>,
+    caps: HashMap<String, u32>,
+    paths: Vec<PathWarningRule>,
     raised: HashMap<String, u32>,
+    /// warnings raised in the files matching a path rule by rule index and name
+    raised_in_paths: HashMap<(usize, String), u32>,
+    errors: u32,
+    warnings: u32,
+    suppressed: u32,
+    /// warnings by name, including suppressed ones
+    names: HashMap<String, u32>,
+    /// warnings by file, for the ones raised with a location
+    files: HashMap<String, u32>,
+    print_summary: bool,
+    summary_json: Option<PathBuf>,
+}
+
+impl WarningState {
+    fn max(&self, name: &str) -> u32 {
+        self.caps.get(name).cloned().unwrap_or(self.max)
+    }
 }
 
 static WARNING_STATE: Lazy<Mutex<WarningState>> = Lazy::new(|| {
     Mutex::new(WarningState {
         max: 10,
         muted: HashSet::new(),
+        caps: HashMap::new(),
+        paths: Vec::new(),
         raised: HashMap::new(),
+        raised_in_paths: HashMap::new(),
+        errors: 0,
+        warnings: 0,
+        suppressed: 0,
+        names: HashMap::new(),
+        files: HashMap::new(),
+        print_summary: false,
+        summary_json: None,
     })
 });
 
-#[macro_export]
-macro_rules! error {
-    ($($arg:tt)*) => (
-        std::io::Error::new(std::io::ErrorKind::Other, format!($($arg)*))
-    )
-}
-
 pub trait ErrorExt<T> {
     fn prepend_error<M: AsRef<[u8]> + Display>(self, msg: M) -> Result<T, Error>;
     fn print_error(self, exit: bool) -> ();
@@ -50,6 +263,7 @@ impl<T> ErrorExt<T> for Result<T, Error> {
     fn print_error(self, exit: bool) {
         if let Err(error) = self {
             eprintln!("{}: {}", "error".red().bold(), error);
+            WARNING_STATE.lock().unwrap().errors += 1;
 
             if exit {
                 print_warning_summary();
@@ -137,18 +351,47 @@ fn print_warning_message<M: AsRef<[u8]> + Display>(msg: M, name: Option<&'static
 
 pub fn warning<M: AsRef<[u8]> + Display>(msg: M, name: Option<&'static str>, location: (Option<M>,Option<u32>)) {
     let mut state = WARNING_STATE.lock().unwrap();
+    let file = location.0.as_ref().map(|f| f.to_string());
+    let mut suppressed = false;
 
     if let Some(name_str) = name {
         if state.muted.contains(name_str) {
             return;
         }
 
-        let max_warnings = state.max;
-        let raised_count = state.raised.entry(name_str.to_string()).or_insert(0);
-        if *raised_count >= max_warnings {
-            return;
+        if let Some(ref file) = file {
+            let matching: Vec<usize> = (0..state.paths.len()).filter(|i| matches_path_glob(file, &state.paths[*i].glob)).collect();
+            for i in matching {
+                if state.paths[i].muted.contains(name_str) {
+                    return;
+                }
+                if let Some(max) = state.paths[i].max {
+                    let raised_count = state.raised_in_paths.entry((i, name_str.to_string())).or_insert(0);
+                    *raised_count += 1;
+                    suppressed |= *raised_count > max;
+                }
+            }
+        }
+
+        // warnings capped for their path don't count towards the cap of their name
+        if !suppressed {
+            let max_warnings = state.max(name_str);
+            let raised_count = state.raised.entry(name_str.to_string()).or_insert(0);
+            *raised_count += 1;
+            suppressed = *raised_count > max_warnings;
         }
-        *raised_count += 1;
+    }
+
+    state.warnings += 1;
+    if let Some(name_str) = name {
+        *state.names.entry(name_str.to_string()).or_insert(0) += 1;
+    }
+    if let Some(file) = file {
+        *state.files.entry(file).or_insert(0) += 1;
+    }
+    if suppressed {
+        state.suppressed += 1;
+        return;
     }
 
     // Drop the lock before printing to avoid deadlocks if printing logic ever changes to call back into this module.
@@ -169,21 +412,86 @@ pub fn warning_suppressed(name: Option<&'static str>) -> bool {
     }
 
     if let Some(raised) = state.raised.get(name) {
-        raised >= &state.max
+        *raised >= state.max(name)
     } else {
         return false;
     }
 }
 
+/// Number of errors and warnings raised by a run, with the warning names and files raising the
+/// most warnings first
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WarningSummary {
+    pub errors: u32,
+    pub warnings: u32,
+    /// warnings not printed because of caps
+    pub suppressed: u32,
+    /// warnings by name, unnamed warnings are only part of the total
+    pub names: Vec<(String, u32)>,
+    /// warnings by file, for the ones raised with a location
+    pub files: Vec<(String, u32)>,
+}
+
+/// Number of warning names and files listed in the printed summary
+const SUMMARY_TOP: usize = 5;
+
+fn sorted_counts(counts: &HashMap<String, u32>) -> Vec<(String, u32)> {
+    let mut sorted: Vec<(String, u32)> = counts.iter().map(|(k, v)| (k.clone(), *v)).collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    sorted
+}
+
+impl WarningSummary {
+    /// Writes the summary as JSON.
+    pub fn write_json<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        serde_json::to_writer_pretty(&mut *output, self).map_err(|e| error!("{}", e))?;
+        output.write_all(b"\n")
+    }
+
+    fn print(&self) {
+        eprintln!("{} errors, {} warnings ({} suppressed).", self.errors, self.warnings, self.suppressed);
+        for (title, counts) in [("Most frequent warnings:", &self.names), ("Files with the most warnings:", &self.files)] {
+            if counts.is_empty() { continue; }
+            eprintln!("{}", title);
+            for (name, count) in counts.iter().take(SUMMARY_TOP) {
+                eprintln!("{:>8}  {}", count, name);
+            }
+        }
+    }
+}
+
+/// Returns the summary of the errors and warnings raised so far.
+pub fn warning_summary() -> WarningSummary {
+    let state = WARNING_STATE.lock().unwrap();
+
+    WarningSummary {
+        errors: state.errors,
+        warnings: state.warnings,
+        suppressed: state.suppressed,
+        names: sorted_counts(&state.names),
+        files: sorted_counts(&state.files),
+    }
+}
+
+/// Sets how the summary is reported at the end of the run: printed with the top warning names
+/// and files if `print` is true and written as JSON to `json`.
+pub fn init_summary(print: bool, json: Option<PathBuf>) {
+    let mut state = WARNING_STATE.lock().unwrap();
+    state.print_summary = print;
+    state.summary_json = json;
+}
+
 pub fn print_warning_summary() {
     let state = WARNING_STATE.lock().unwrap();
+    let print_summary = state.print_summary;
+    let summary_json = state.summary_json.clone();
     let mut summary_warnings = Vec::new();
 
     for (name, raised) in state.raised.iter() {
         if state.muted.contains(name) { continue; }
 
-        if *raised > state.max {
-            let excess = *raised - state.max;
+        if *raised > state.max(name) {
+            let excess = *raised - state.max(name);
             let msg = if excess > 1 {
                 format!("{} warnings of type \"{}\" were suppressed to prevent spam. Use \"-w {}\" to disable these warnings entirely.", excess, name, name)
             } else {
@@ -198,12 +506,33 @@ pub fn print_warning_summary() {
     for msg in summary_warnings {
         print_warning_message(msg, None, (None, None));
     }
+
+    let summary = warning_summary();
+    if print_summary {
+        summary.print();
+    }
+    if let Some(path) = summary_json {
+        if let Err(e) = File::create(&path).and_then(|mut f| summary.write_json(&mut f)) {
+            eprintln!("{}: Failed to write summary to {}: {}", "error".red().bold(), path.display(), e);
+        }
+    }
 }
 
-pub fn init_warnings(muted: HashSet<String>, verbose: bool) {
+/// Sets up warnings for the following commands. Warnings named in `muted` and the ones muted by
+/// the configuration are never printed, `verbose` prints all others without caps.
+pub fn init_warnings(muted: HashSet<String>, verbose: bool, config: WarningConfig) {
     let mut state = WARNING_STATE.lock().unwrap();
     state.muted = muted;
+    state.muted.extend(config.muted);
+    state.max = config.max.unwrap_or(10);
+    state.caps = config.caps;
+    state.paths = config.paths;
+    state.raised_in_paths.clear();
     if verbose {
         state.max = u32::MAX;
+        state.caps.clear();
+        for rule in state.paths.iter_mut() {
+            rule.max = None;
+        }
     }
 }
@@ -0,0 +1,236 @@
+//! Evaluating the expressions of `__EVAL` in configs, a small subset of SQF
+
+use std::cmp::{Ordering};
+use std::io::{Error};
+
+/// Value of an evaluated expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Number, all numbers are floats like in SQF
+    Number(f64),
+    /// String
+    String(String),
+    /// Boolean, the result of comparisons and logical operators
+    Bool(bool),
+}
+
+/// Formats a number as an integer if it is whole and fits one and as a float otherwise, always
+/// with a decimal point so configs read it as a float again.
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n >= f64::from(i32::MIN) && n <= f64::from(i32::MAX) {
+        format!("{}", n as i32)
+    } else if n.fract() == 0.0 {
+        format!("{:.1}", n as f32)
+    } else {
+        let formatted = format!("{}", n as f32);
+        if formatted.contains('.') { formatted } else { format!("{}.0", formatted) }
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Bool(_) => "boolean",
+        }
+    }
+
+    /// Returns the value as it is written into the config: numbers as integers if they are whole
+    /// and as floats otherwise, strings quoted and booleans as 1 and 0, as configs have no
+    /// booleans.
+    pub fn to_config(&self) -> String {
+        match self {
+            Value::Number(n) => format_number(*n),
+            Value::String(s) => quote(s),
+            Value::Bool(b) => String::from(if *b { "1" } else { "0" }),
+        }
+    }
+
+    fn number(&self, operator: &str) -> Result<f64, Error> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            _ => Err(error!("Operator {} expects a number, not a {}.", operator, self.type_name())),
+        }
+    }
+
+    fn bool(&self, operator: &str) -> Result<bool, Error> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(error!("Operator {} expects a boolean, not a {}.", operator, self.type_name())),
+        }
+    }
+}
+
+/// Applies a unary operator or command like `-`, `!` or `round`.
+fn unary(operator: &str, x: Result<Value, Error>) -> Result<Value, Error> {
+    let x = x?;
+    let n = || x.number(operator);
+
+    Ok(match operator {
+        "-" => Value::Number(-n()?),
+        "+" => Value::Number(n()?),
+        "!" => Value::Bool(!x.bool(operator)?),
+        "abs" => Value::Number(n()?.abs()),
+        "ceil" => Value::Number(n()?.ceil()),
+        "floor" => Value::Number(n()?.floor()),
+        // SQF rounds halves away from zero, like Rust
+        "round" => Value::Number(n()?.round()),
+        "sqrt" => Value::Number(n()?.sqrt()),
+        "str" => Value::String(match &x {
+            Value::Number(n) => format_number(*n),
+            Value::String(s) => quote(s),
+            Value::Bool(b) => b.to_string(),
+        }),
+        "count" | "toLower" | "toUpper" => {
+            let s = match &x {
+                Value::String(s) => s,
+                _ => { return Err(error!("Operator {} expects a string, not a {}.", operator, x.type_name())); }
+            };
+            match operator {
+                "count" => Value::Number(s.chars().count() as f64),
+                "toLower" => Value::String(s.to_lowercase()),
+                _ => Value::String(s.to_uppercase()),
+            }
+        },
+        _ => unreachable!(),
+    })
+}
+
+/// Applies a binary operator like `+`, `==` or `&&`.
+fn binary(operator: &str, x: Result<Value, Error>, y: Result<Value, Error>) -> Result<Value, Error> {
+    let (x, y) = (x?, y?);
+
+    Ok(match (operator, &x, &y) {
+        ("+", Value::String(a), Value::String(b)) => Value::String(format!("{}{}", a, b)),
+        ("&&", _, _) => Value::Bool(x.bool(operator)? && y.bool(operator)?),
+        ("||", _, _) => Value::Bool(x.bool(operator)? || y.bool(operator)?),
+        ("==", _, _) | ("!=", _, _) => {
+            // strings are compared case-insensitively like in SQF
+            let equal = match (&x, &y) {
+                (Value::String(a), Value::String(b)) => a.to_lowercase() == b.to_lowercase(),
+                (Value::Number(_), Value::Number(_)) | (Value::Bool(_), Value::Bool(_)) => x == y,
+                _ => { return Err(error!("Operator {} can't compare a {} with a {}.", operator, x.type_name(), y.type_name())); }
+            };
+            Value::Bool(equal == (operator == "=="))
+        },
+        ("<", _, _) | ("<=", _, _) | (">", _, _) | (">=", _, _) => {
+            let ordering = x.number(operator)?.partial_cmp(&y.number(operator)?);
+            Value::Bool(match operator {
+                "<" => ordering == Some(Ordering::Less),
+                "<=" => ordering == Some(Ordering::Less) || ordering == Some(Ordering::Equal),
+                ">" => ordering == Some(Ordering::Greater),
+                _ => ordering == Some(Ordering::Greater) || ordering == Some(Ordering::Equal),
+            })
+        },
+        _ => {
+            let (a, b) = (x.number(operator)?, y.number(operator)?);
+            Value::Number(match operator {
+                "+" => a + b,
+                "-" => a - b,
+                "*" => a * b,
+                "/" | "%" if b == 0.0 => { return Err(error!("Division by zero.")); },
+                "/" => a / b,
+                "%" => a % b,
+                "^" => a.powf(b),
+                "max" => a.max(b),
+                "min" => a.min(b),
+                _ => unreachable!(),
+            })
+        }
+    })
+}
+
+peg::parser! {
+    grammar eval_grammar() for str {
+        rule _ = quiet!{ [' ' | '\t' | '\r' | '\n']* }
+
+        // keywords of SQF are case-insensitive
+        rule kw(name: &'static str) = w:$(['a'..='z' | 'A'..='Z']+) !['a'..='z' | 'A'..='Z' | '0'..='9' | '_'] {?
+            if w.eq_ignore_ascii_case(name) { Ok(()) } else { Err(name) }
+        }
+
+        rule number() -> f64 =
+            ("0x" / "$") h:$(['0'..='9' | 'a'..='f' | 'A'..='F']+) {? i64::from_str_radix(h, 16).map(|i| i as f64).or(Err("hexadecimal number")) } /
+            n:$((['0'..='9']+ ("." ['0'..='9']*)? / "." ['0'..='9']+) (['e' | 'E'] ['-' | '+']? ['0'..='9']+)?) {? n.parse().or(Err("number")) }
+
+        rule string() -> String =
+            "\"" s:$(("\"\"" / !['"'] [_])*) "\"" { s.replace("\"\"", "\"") } /
+            "'" s:$(("''" / !['\''] [_])*) "'" { s.replace("''", "'") }
+
+        rule atom() -> Result<Value, Error> =
+            n:number() { Ok(Value::Number(n)) } /
+            s:string() { Ok(Value::String(s)) } /
+            kw("true") { Ok(Value::Bool(true)) } /
+            kw("false") { Ok(Value::Bool(false)) } /
+            kw("pi") { Ok(Value::Number(std::f64::consts::PI)) } /
+            "(" _ e:expression() _ ")" { e }
+
+        // from the lowest to the highest precedence, like in SQF
+        rule expression() -> Result<Value, Error> = precedence!{
+            x:(@) _ ("||" / kw("or")) _ y:@ { binary("||", x, y) }
+            --
+            x:(@) _ ("&&" / kw("and")) _ y:@ { binary("&&", x, y) }
+            --
+            x:(@) _ "==" _ y:@ { binary("==", x, y) }
+            x:(@) _ "!=" _ y:@ { binary("!=", x, y) }
+            x:(@) _ "<=" _ y:@ { binary("<=", x, y) }
+            x:(@) _ ">=" _ y:@ { binary(">=", x, y) }
+            x:(@) _ "<" _ y:@ { binary("<", x, y) }
+            x:(@) _ ">" _ y:@ { binary(">", x, y) }
+            --
+            x:(@) _ "+" _ y:@ { binary("+", x, y) }
+            x:(@) _ "-" _ y:@ { binary("-", x, y) }
+            x:(@) _ kw("max") _ y:@ { binary("max", x, y) }
+            x:(@) _ kw("min") _ y:@ { binary("min", x, y) }
+            --
+            x:(@) _ "*" _ y:@ { binary("*", x, y) }
+            x:(@) _ "/" _ y:@ { binary("/", x, y) }
+            x:(@) _ ("%" / kw("mod")) _ y:@ { binary("%", x, y) }
+            --
+            x:(@) _ "^" _ y:@ { binary("^", x, y) }
+            --
+            "-" _ x:@ { unary("-", x) }
+            "+" _ x:@ { unary("+", x) }
+            ("!" / kw("not")) _ x:@ { unary("!", x) }
+            kw("abs") _ x:@ { unary("abs", x) }
+            kw("ceil") _ x:@ { unary("ceil", x) }
+            kw("floor") _ x:@ { unary("floor", x) }
+            kw("round") _ x:@ { unary("round", x) }
+            kw("sqrt") _ x:@ { unary("sqrt", x) }
+            kw("str") _ x:@ { unary("str", x) }
+            kw("count") _ x:@ { unary("count", x) }
+            kw("toLower") _ x:@ { unary("toLower", x) }
+            kw("toUpper") _ x:@ { unary("toUpper", x) }
+            --
+            a:atom() { a }
+        }
+
+        pub rule evaluate() -> Result<Value, Error> = _ e:expression() _ ![_] { e }
+    }
+}
+
+/// Evaluates an expression as `__EVAL` in configs does.
+///
+/// Expressions are a subset of SQF: numbers (also hexadecimal), strings in double or single
+/// quotes, `true`, `false` and `pi`, the arithmetic operators `+ - * / % mod ^ min max`, the
+/// comparisons `== != < <= > >=`, the logical operators `&& and || or ! not`, string
+/// concatenation with `+` and the commands `abs ceil floor round sqrt str count toLower toUpper`.
+/// Like in SQF, operators are case-insensitive and so is comparing strings.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::eval::{evaluate, Value};
+/// assert_eq!(Value::Number(7.0), evaluate("1 + 2 * 3").unwrap());
+/// assert_eq!(Value::String("abc".to_string()), evaluate("\"a\" + 'bc'").unwrap());
+/// assert_eq!(Value::Bool(true), evaluate("round 2.5 == 3 && \"A\" == \"a\"").unwrap());
+/// assert!(evaluate("1 + \"a\"").is_err());
+/// ```
+pub fn evaluate(expression: &str) -> Result<Value, Error> {
+    eval_grammar::evaluate(expression).map_err(|e| error!("Invalid expression \"{}\": {}", expression.trim(), e))?
+}
@@ -0,0 +1,64 @@
+//! Functions for embedding git information into builds
+
+use std::io::{Error};
+use std::path::{Path};
+use std::process::{Command};
+
+use crate::error::*;
+
+/// State of the git repository a build is made from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitInfo {
+    /// hash of the checked out commit
+    pub hash: String,
+    /// whether there are uncommitted changes or untracked files
+    pub dirty: bool,
+}
+
+fn git(directory: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .current_dir(directory)
+        .args(args)
+        .output()
+        .prepend_error("Failed to run git:")?;
+
+    if !output.status.success() {
+        return Err(error!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+impl GitInfo {
+    /// Reads the state of the git repository containing `directory`.
+    pub fn read(directory: &Path) -> Result<GitInfo, Error> {
+        let hash = git(directory, &["rev-parse", "HEAD"])?.trim().to_string();
+        let dirty = !git(directory, &["status", "--porcelain"])?.trim().is_empty();
+
+        Ok(GitInfo {
+            hash,
+            dirty,
+        })
+    }
+}
+
+/// Adds the macros and header extensions for a build from `directory`: the commit hash as
+/// `__GIT_HASH__` and as the `git_hash` header extension.
+///
+/// Building from a tree with uncommitted changes raises a warning, or fails if `require_clean`
+/// is true, since the hash doesn't describe the built content then.
+pub fn add_git_build_info(directory: &Path, require_clean: bool, defines: &mut Vec<(String, String)>, headerext: &mut Vec<String>) -> Result<(), Error> {
+    let info = GitInfo::read(directory).prepend_error("Failed to read git repository:")?;
+
+    if info.dirty {
+        if require_clean {
+            return Err(error!("Building from a git tree with uncommitted changes ({}).", directory.display()));
+        }
+        warning(format!("Building from a git tree with uncommitted changes, {} doesn't describe the build.", info.hash), Some("dirty-tree"), (None, None));
+    }
+
+    defines.push(("__GIT_HASH__".to_string(), info.hash.clone()));
+    headerext.push(format!("git_hash={}", info.hash));
+
+    Ok(())
+}
@@ -0,0 +1,198 @@
+//! Functions for running external commands at fixed points of the build pipeline
+
+use std::fs::{File, create_dir_all, read_dir};
+use std::io::{Read, Write, Cursor, Error};
+use std::path::{Path, PathBuf};
+use std::process::{Command};
+
+use linked_hash_map::{LinkedHashMap};
+
+use crate::error::*;
+use crate::pbo::PBO;
+use crate::paths::{from_fs_path, to_fs_path};
+use crate::temp::{TempDir};
+
+/// Point of the build pipeline a hook runs at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    /// Before any file of the source folder is read, hooks may generate source files
+    PrePreprocess,
+    /// After configs were rapified, hooks may modify the in-flight files
+    PostRapify,
+    /// Right before the PBO is written, hooks may modify the in-flight files
+    PrePack,
+    /// After the PBO was written and signed
+    PostSign,
+}
+
+/// External command to run at a stage of the build
+///
+/// Hooks are run through the system shell with these environment variables set:
+///
+/// - `ARMAKE2_HOOK`: name of the stage, e.g. `pre-pack`
+/// - `ARMAKE2_SOURCE`: source folder of the PBO
+/// - `ARMAKE2_TARGET`: target PBO, if it is written to a file
+/// - `ARMAKE2_FILES`: folder containing the in-flight files (`post-rapify` and `pre-pack` only).
+///   Files changed, added or removed in this folder are changed, added or removed in the PBO.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::hooks::{Hook, HookStage};
+/// let hook = Hook::parse("pre-pack=python obfuscate.py").unwrap();
+///
+/// assert_eq!(HookStage::PrePack, hook.stage);
+/// assert_eq!("python obfuscate.py", hook.command);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub stage: HookStage,
+    pub command: String,
+}
+
+/// Hooks of a single build together with the paths passed to them
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pub hooks: Vec<Hook>,
+    pub source: PathBuf,
+    pub target: Option<PathBuf>,
+}
+
+impl HookStage {
+    /// Returns the name of the stage as used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            HookStage::PrePreprocess => "pre-preprocess",
+            HookStage::PostRapify => "post-rapify",
+            HookStage::PrePack => "pre-pack",
+            HookStage::PostSign => "post-sign",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<HookStage> {
+        [HookStage::PrePreprocess, HookStage::PostRapify, HookStage::PrePack, HookStage::PostSign]
+            .iter().cloned().find(|s| s.name() == name)
+    }
+}
+
+impl Hook {
+    /// Parses a hook given as `<stage>=<command>`.
+    pub fn parse(hook: &str) -> Result<Hook, Error> {
+        let (stage, command) = match hook.find('=') {
+            Some(index) => (&hook[..index], &hook[index + 1..]),
+            None => { return Err(error!("Hook \"{}\" is not given as <stage>=<command>.", hook)); }
+        };
+
+        let stage = HookStage::from_name(stage.trim()).ok_or_else(||
+            error!("Unknown hook stage \"{}\", expected pre-preprocess, post-rapify, pre-pack or post-sign.", stage))?;
+
+        Ok(Hook {
+            stage,
+            command: command.trim().to_string(),
+        })
+    }
+
+    fn run(&self, source: &Path, target: Option<&Path>, files: Option<&Path>) -> Result<(), Error> {
+        let mut command = if cfg!(windows) {
+            let mut c = Command::new("cmd");
+            c.arg("/C").arg(&self.command);
+            c
+        } else {
+            let mut c = Command::new("sh");
+            c.arg("-c").arg(&self.command);
+            c
+        };
+
+        command.env("ARMAKE2_HOOK", self.stage.name());
+        command.env("ARMAKE2_SOURCE", source);
+        if let Some(target) = target {
+            command.env("ARMAKE2_TARGET", target);
+        }
+        if let Some(files) = files {
+            command.env("ARMAKE2_FILES", files);
+        }
+
+        let status = command.status().prepend_error(format!("Failed to run {} hook \"{}\":", self.stage.name(), self.command))?;
+        if !status.success() {
+            return Err(match status.code() {
+                Some(code) => error!("{} hook \"{}\" terminated with exit code: {}", self.stage.name(), self.command, code),
+                None => error!("{} hook \"{}\" terminated by signal.", self.stage.name(), self.command),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn read_staged_files(directory: &Path, root: &Path, files: &mut LinkedHashMap<String, Cursor<Box<[u8]>>>) -> Result<(), Error> {
+    for entry in read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_staged_files(&path, root, files)?;
+        } else {
+            let name = from_fs_path(path.strip_prefix(root).unwrap());
+            let mut buffer: Vec<u8> = Vec::new();
+            File::open(&path)?.read_to_end(&mut buffer)?;
+            files.insert(name, Cursor::new(buffer.into_boxed_slice()));
+        }
+    }
+
+    Ok(())
+}
+
+impl Hooks {
+    /// Creates hooks for building `source` into `target` from hooks given as `<stage>=<command>`.
+    pub fn new(hooks: &[String], source: PathBuf, target: Option<PathBuf>) -> Result<Hooks, Error> {
+        Ok(Hooks {
+            hooks: hooks.iter().map(|h| Hook::parse(h)).collect::<Result<Vec<Hook>, Error>>()?,
+            source,
+            target,
+        })
+    }
+
+    /// Runs all hooks of the given stage in order.
+    pub fn run(&self, stage: HookStage) -> Result<(), Error> {
+        for hook in self.hooks.iter().filter(|h| h.stage == stage) {
+            hook.run(&self.source, self.target.as_deref(), None)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs all hooks of the given stage in order, giving them access to the files of the PBO.
+    ///
+    /// The files are written to a temporary folder before the first hook and read back after the
+    /// last one. Files that still exist keep their position in the PBO, new files are appended.
+    pub fn run_with_files(&self, stage: HookStage, pbo: &mut PBO) -> Result<(), Error> {
+        if !self.hooks.iter().any(|h| h.stage == stage) {
+            return Ok(());
+        }
+
+        let tempdir = TempDir::new(&format!("hook_{}", stage.name())).prepend_error("Failed to create tempfolder:")?;
+        let staging = tempdir.path();
+
+        for (name, cursor) in pbo.files.iter() {
+            let path = staging.join(to_fs_path(name));
+            create_dir_all(path.parent().unwrap())?;
+            File::create(&path)?.write_all(cursor.get_ref())?;
+        }
+
+        for hook in self.hooks.iter().filter(|h| h.stage == stage) {
+            hook.run(&self.source, self.target.as_deref(), Some(staging))?;
+        }
+
+        let mut staged: LinkedHashMap<String, Cursor<Box<[u8]>>> = LinkedHashMap::new();
+        read_staged_files(staging, staging, &mut staged).prepend_error("Failed to read files changed by hooks:")?;
+
+        let mut files: LinkedHashMap<String, Cursor<Box<[u8]>>> = LinkedHashMap::new();
+        for name in pbo.files.keys() {
+            if let Some(cursor) = staged.remove(name) {
+                files.insert(name.clone(), cursor);
+            }
+        }
+        files.extend(staged);
+        pbo.files = files;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+//! Deploying built PBOs together with their signatures
+
+use std::fs::{File, copy, read_dir, remove_file, rename};
+use std::io::{Error};
+use std::path::{Path, PathBuf};
+
+use crate::error::*;
+use crate::sign::BISign;
+
+/// Returns the signatures of the PBO in its folder, named `<pbo>.<key>.bisign`.
+pub fn pbo_signatures(pbo: &Path) -> Result<Vec<PathBuf>, Error> {
+    let folder = match pbo.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.", pbo.file_name().unwrap().to_string_lossy().to_lowercase());
+
+    let mut signatures: Vec<PathBuf> = Vec::new();
+    for entry in read_dir(folder)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy().to_lowercase();
+        if name.starts_with(&prefix) && name.ends_with(".bisign") && path.is_file() {
+            signatures.push(path);
+        }
+    }
+    signatures.sort();
+
+    Ok(signatures)
+}
+
+fn read_authority(path: &Path) -> Result<String, Error> {
+    let signature = BISign::read(&mut File::open(path)?).prepend_error(format!("Failed to read signature {}:", path.display()))?;
+
+    Ok(signature.name().to_string())
+}
+
+/// Copies the PBO and its signatures into the target folder. Returns the installed files.
+///
+/// All files are copied to temporary files in the target folder first and then renamed, so the
+/// target folder never contains partially written files and nothing is replaced if copying any
+/// file fails. Afterwards, signatures of the PBO in the target folder that were created with the
+/// key of an installed signature but are named differently are removed. Signatures of other keys
+/// don't match the new PBO anymore and raise warnings.
+pub fn install(pbo: &Path, target: &Path) -> Result<Vec<PathBuf>, Error> {
+    if !pbo.is_file() {
+        return Err(error!("{} doesn't exist.", pbo.display()));
+    }
+    if !target.is_dir() {
+        return Err(error!("Target folder {} doesn't exist.", target.display()));
+    }
+
+    let mut sources: Vec<PathBuf> = vec![pbo.to_path_buf()];
+    let mut authorities: Vec<String> = Vec::new();
+    for signature in pbo_signatures(pbo)? {
+        authorities.push(read_authority(&signature)?);
+        sources.push(signature);
+    }
+
+    let pbo_modified = pbo.metadata()?.modified()?;
+    for signature in &sources[1..] {
+        if signature.metadata()?.modified()? < pbo_modified {
+            warning(format!("Signature {} is older than the PBO.", signature.display()), Some("stale-signature"), (None, None));
+        }
+    }
+
+    let mut staged: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for source in &sources {
+        let name = source.file_name().unwrap().to_string_lossy().to_string();
+        let temp = target.join(format!(".{}.tmp{}", name, std::process::id()));
+        let destination = target.join(&name);
+
+        if let Err(e) = copy(source, &temp) {
+            for (temp, _) in staged.iter().chain(std::iter::once(&(temp.clone(), destination))) {
+                let _ = remove_file(temp);
+            }
+            return Err(e).prepend_error(format!("Failed to copy {}:", source.display()));
+        }
+        staged.push((temp, destination));
+    }
+
+    let installed: Vec<PathBuf> = staged.iter().map(|(_, destination)| destination.clone()).collect();
+    for (temp, destination) in staged {
+        rename(&temp, &destination).prepend_error(format!("Failed to replace {}:", destination.display()))?;
+    }
+
+    for existing in pbo_signatures(&target.join(pbo.file_name().unwrap()))? {
+        if installed.contains(&existing) {
+            continue;
+        }
+
+        match read_authority(&existing) {
+            Ok(authority) if authorities.contains(&authority) => {
+                remove_file(&existing).prepend_error(format!("Failed to remove {}:", existing.display()))?;
+                eprintln!("Removed stale signature {}.", existing.display());
+            },
+            _ => {
+                warning(format!("Signature {} doesn't match the installed PBO.", existing.display()), Some("stale-signature"), (None, None));
+            }
+        }
+    }
+
+    Ok(installed)
+}
+
+/// Copies the PBO and its signatures into the target folder, see `install`.
+pub fn cmd_install(pbo: PathBuf, target: PathBuf) -> Result<(), Error> {
+    let installed = install(&pbo, &target)?;
+
+    eprintln!("Installed {} with {} signatures to {}.", pbo.file_name().unwrap().to_string_lossy(), installed.len() - 1, target.display());
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Handling Ctrl-C, so interrupted commands don't leave half-written outputs behind
+
+use std::fs::{remove_file};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once};
+
+use once_cell::sync::Lazy;
+
+use crate::error::*;
+
+/// Outputs currently being written, removed when the process is interrupted
+static OUTPUTS: Lazy<Mutex<Vec<PathBuf>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static INSTALL: Once = Once::new();
+
+/// Exit code of processes terminated by Ctrl-C, like shells report them
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Registers the file as being written until `finish_output` is called for it, so it is removed
+/// if the process is interrupted in between. Outputs are written with `io::AtomicFile`, which
+/// registers its temporary file.
+pub fn track_output(path: &Path) {
+    OUTPUTS.lock().unwrap().push(path.to_path_buf());
+}
+
+/// Marks the output as completely written, so it is kept if the process is interrupted later.
+pub fn finish_output(path: &Path) {
+    OUTPUTS.lock().unwrap().retain(|p| p != path);
+}
+
+/// Removes the outputs being written, prints the warning summary and exits.
+fn interrupted() {
+    let outputs: Vec<PathBuf> = OUTPUTS.lock().unwrap().drain(..).collect();
+    for output in outputs {
+        if remove_file(&output).is_ok() {
+            eprintln!("Removed partially written {}.", output.display());
+        }
+    }
+
+    eprintln!("Interrupted.");
+    print_warning_summary();
+    std::process::exit(INTERRUPTED_EXIT_CODE);
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    /// Write end of the pipe the signal handler wakes the cleanup thread with
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn handle(_signal: libc::c_int) {
+        // only async-signal-safe functions may be called here, the cleanup happens in a thread
+        let byte = 1u8;
+        unsafe { libc::write(PIPE.load(Ordering::Relaxed), &byte as *const u8 as *const libc::c_void, 1); }
+    }
+
+    pub fn install() {
+        let mut fds: [libc::c_int; 2] = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return;
+        }
+        PIPE.store(fds[1], Ordering::Relaxed);
+
+        std::thread::spawn(move || {
+            let mut byte = 0u8;
+            while unsafe { libc::read(fds[0], &mut byte as *mut u8 as *mut libc::c_void, 1) } != 1 {}
+            super::interrupted();
+        });
+
+        unsafe {
+            libc::signal(libc::SIGINT, handle as extern "C" fn(libc::c_int) as libc::sighandler_t);
+            libc::signal(libc::SIGTERM, handle as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<unsafe extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    }
+
+    /// Called by Windows in a new thread, so the cleanup can happen here
+    unsafe extern "system" fn handle(_event: u32) -> i32 {
+        super::interrupted();
+        1
+    }
+
+    pub fn install() {
+        unsafe { SetConsoleCtrlHandler(Some(handle), 1); }
+    }
+}
+
+/// Installs the handler for Ctrl-C (and SIGTERM on Unix) that removes outputs being written and
+/// prints the warning summary before exiting. Installing it again does nothing.
+pub fn install_handler() {
+    INSTALL.call_once(platform::install);
+}
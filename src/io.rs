@@ -1,6 +1,10 @@
-use std::fs::{File};
+use std::fs::{File, remove_file, rename};
 use std::io;
 use std::io::{Read, Seek, Write, Stdout, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use colored::Colorize;
 
 pub enum Input {
     File(File),
@@ -8,10 +12,103 @@ pub enum Input {
 }
 
 pub enum Output {
-    File(File),
+    File(AtomicFile),
     Standard(Stdout),
 }
 
+impl Output {
+    /// Completes the output, moving files into place.
+    pub fn commit(self) -> io::Result<()> {
+        match self {
+            Output::File(f) => f.commit(),
+            Output::Standard(mut s) => s.flush(),
+        }
+    }
+}
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Output file that is written to a temporary file in the same folder and only renamed to its
+/// path by `commit`, so a crash, a full disk or an interrupt never leaves a truncated file behind
+/// that the game or other tools load. The temporary file is removed if the `AtomicFile` is
+/// dropped without being committed.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Write;
+/// # use armake2::io::AtomicFile;
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("addon.pbo");
+///
+/// let mut file = AtomicFile::create(&path).unwrap();
+/// file.write_all(b"data").unwrap();
+/// assert!(!path.exists());
+///
+/// file.commit().unwrap();
+/// assert_eq!(b"data", &std::fs::read(&path).unwrap()[..]);
+/// ```
+pub struct AtomicFile {
+    file: Option<File>,
+    temp: PathBuf,
+    path: PathBuf,
+}
+
+impl AtomicFile {
+    /// Creates the temporary file for `path`, named like it with a leading dot and a suffix.
+    pub fn create(path: &Path) -> io::Result<AtomicFile> {
+        let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a file path.", path.display())))?;
+        let temp = path.with_file_name(format!(".{}.tmp{}_{}", name.to_string_lossy(), std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+
+        let file = File::create(&temp)?;
+        crate::interrupt::track_output(&temp);
+
+        Ok(AtomicFile {
+            file: Some(file),
+            temp,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Returns the path the file is moved to when committed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the file to disk and renames it to its path, replacing an existing file.
+    pub fn commit(mut self) -> io::Result<()> {
+        let file = self.file.take().unwrap();
+        file.sync_all()?;
+        drop(file);
+
+        rename(&self.temp, &self.path)?;
+        crate::interrupt::finish_output(&self.temp);
+
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().unwrap().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // the file has to be closed before it can be removed on Windows
+        self.file.take();
+        if self.temp.exists() {
+            let _ = remove_file(&self.temp);
+            crate::interrupt::finish_output(&self.temp);
+        }
+    }
+}
+
 impl Read for Input {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match *self {
@@ -48,22 +145,31 @@ impl Write for Output {
 
 pub trait ReadExt: Read {
     fn read_cstring(&mut self) -> io::Result<String>;
+    /// Reads a null-terminated string of at most `max` bytes, failing on longer strings instead
+    /// of reading on.
+    fn read_cstring_bounded(&mut self, max: usize) -> io::Result<String>;
     fn read_compressed_int(&mut self) -> io::Result<u32>;
 }
 
 impl<T: Read> ReadExt for T {
     fn read_cstring(&mut self) -> io::Result<String> {
+        self.read_cstring_bounded(usize::MAX)
+    }
+
+    fn read_cstring_bounded(&mut self, max: usize) -> io::Result<String> {
         let mut bytes: Vec<u8> = Vec::new();
         for byte in self.bytes() {
             let b = byte?;
             if b == 0 {
                 break;
+            } else if bytes.len() == max {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("string is longer than {} bytes", max)));
             } else {
                 bytes.push(b);
             }
         }
 
-        Ok(String::from_utf8(bytes).unwrap())
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 
     fn read_compressed_int(&mut self) -> io::Result<u32> {
@@ -122,3 +228,255 @@ pub fn compressed_int_len(x: u32) -> usize {
 
     len + 1
 }
+
+const LZSS_WINDOW: usize = 4095;
+const LZSS_MIN_MATCH: usize = 3;
+const LZSS_MAX_MATCH: usize = 18;
+
+/// Decompresses BI-style LZSS data followed by the 32-bit checksum of the decompressed data.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::io::{lzss_compress, lzss_decompress};
+/// let data = b"class CfgPatches { class CfgPatches {}; };".to_vec();
+///
+/// assert_eq!(data, lzss_decompress(&lzss_compress(&data)).unwrap());
+/// ```
+pub fn lzss_decompress(input: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    if input.len() < 4 {
+        return Err(invalid("LZSS data is too short"));
+    }
+
+    let (data, checksum) = input.split_at(input.len() - 4);
+    let mut output: Vec<u8> = Vec::with_capacity(data.len() * 2);
+    let mut ip = 0;
+
+    while ip < data.len() {
+        let flags = data[ip];
+        ip += 1;
+
+        for bit in 0..8 {
+            if ip >= data.len() { break; }
+
+            if flags & (1 << bit) != 0 {
+                output.push(data[ip]);
+                ip += 1;
+            } else {
+                if ip + 1 >= data.len() {
+                    return Err(invalid("LZSS input overrun"));
+                }
+                let distance = data[ip] as usize | ((data[ip + 1] as usize & 0xf0) << 4);
+                let length = (data[ip + 1] as usize & 0x0f) + LZSS_MIN_MATCH;
+                ip += 2;
+
+                // references before the start of the data are filled with spaces
+                for _ in 0..length {
+                    let position = output.len() as isize - distance as isize;
+                    let b = if position < 0 || distance == 0 { b' ' } else { output[position as usize] };
+                    output.push(b);
+                }
+            }
+        }
+    }
+
+    let expected = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    if lzss_checksum(&output) != expected {
+        return Err(invalid("LZSS checksum mismatch"));
+    }
+
+    Ok(output)
+}
+
+/// Compresses data with BI-style LZSS and appends the checksum of the data.
+pub fn lzss_compress(input: &[u8]) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::with_capacity(input.len() / 2 + 16);
+    // last positions of every 3 byte sequence, searched from the most recent one
+    let mut positions: std::collections::HashMap<[u8; 3], Vec<usize>> = std::collections::HashMap::new();
+    let mut ip = 0;
+
+    while ip < input.len() {
+        let flags_index = output.len();
+        output.push(0);
+
+        for bit in 0..8 {
+            if ip >= input.len() { break; }
+
+            let mut best = (0, 0);
+            if ip + LZSS_MIN_MATCH <= input.len() {
+                let key = [input[ip], input[ip + 1], input[ip + 2]];
+                if let Some(candidates) = positions.get(&key) {
+                    for &start in candidates.iter().rev().take(64) {
+                        if ip - start > LZSS_WINDOW { break; }
+                        let max = LZSS_MAX_MATCH.min(input.len() - ip);
+                        let length = (0..max).take_while(|&i| input[start + i] == input[ip + i]).count();
+                        if length > best.1 {
+                            best = (ip - start, length);
+                            if length == max { break; }
+                        }
+                    }
+                }
+            }
+
+            let step = if best.1 >= LZSS_MIN_MATCH {
+                output.push((best.0 & 0xff) as u8);
+                output.push((((best.0 >> 4) & 0xf0) | (best.1 - LZSS_MIN_MATCH)) as u8);
+                best.1
+            } else {
+                output[flags_index] |= 1 << bit;
+                output.push(input[ip]);
+                1
+            };
+
+            for position in ip..ip + step {
+                if position + LZSS_MIN_MATCH <= input.len() {
+                    positions.entry([input[position], input[position + 1], input[position + 2]]).or_default().push(position);
+                }
+            }
+            ip += step;
+        }
+    }
+
+    output.extend_from_slice(&lzss_checksum(input).to_le_bytes());
+    output
+}
+
+fn lzss_checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |sum, b| sum.wrapping_add(u32::from(*b)))
+}
+
+/// Number of rows of 16 bytes shown by `hex_diff`
+const HEX_DIFF_ROWS: usize = 4;
+
+fn hex_diff_row(data: &[u8], other: &[u8], start: usize, sign: &str, highlight: fn(String) -> String) -> String {
+    let mut hex = String::new();
+    let mut ascii = String::new();
+    for i in start..start + 16 {
+        match data.get(i) {
+            Some(b) => {
+                let byte = format!("{:02x}", b);
+                hex += &if other.get(i) == Some(b) { byte } else { highlight(byte) };
+                ascii.push(if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' });
+            },
+            None => { hex += "  "; }
+        }
+        hex.push(if i % 16 == 7 { '-' } else { ' ' });
+    }
+
+    format!("{} {:08x}  {} |{}|", sign, start, hex, ascii)
+}
+
+/// Returns the offset of the first byte in which `changed` differs from `original` and a hex dump
+/// of both around it, `None` if they are identical. Differing bytes are highlighted.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::io::hex_diff;
+/// let (offset, dump) = hex_diff(b"PBO header with data", b"PBO header with date").unwrap();
+///
+/// assert_eq!(19, offset);
+/// assert!(dump.starts_with("- 00000000"));
+/// assert_eq!(4, dump.lines().count());
+/// ```
+pub fn hex_diff(original: &[u8], changed: &[u8]) -> Option<(usize, String)> {
+    let offset = match original.iter().zip(changed.iter()).position(|(a, b)| a != b) {
+        Some(offset) => offset,
+        None if original.len() == changed.len() => { return None; },
+        None => original.len().min(changed.len()),
+    };
+
+    let first_row = (offset / 16).saturating_sub(1) * 16;
+    let end = original.len().max(changed.len());
+    let rows: Vec<String> = (first_row..end).step_by(16).take(HEX_DIFF_ROWS)
+        .flat_map(|start| vec![
+            hex_diff_row(original, changed, start, "-", |s| s.red().bold().to_string()),
+            hex_diff_row(changed, original, start, "+", |s| s.green().bold().to_string()),
+        ])
+        .collect();
+
+    Some((offset, rows.join("\n")))
+}
+
+
+#[cfg(unix)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    // the field types differ between platforms
+    #[allow(clippy::useless_conversion)]
+    Some(u64::from(stat.f_bavail) * u64::from(stat.f_frsize))
+}
+
+#[cfg(windows)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+
+    extern "system" {
+        fn GetDiskFreeSpaceExW(directory: *const u16, available: *mut u64, total: *mut u64, free: *mut u64) -> i32;
+    }
+
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut available = 0u64;
+    if unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) } == 0 {
+        return None;
+    }
+
+    Some(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Returns the space available to the user in the file system `path` is on, `None` if it can't
+/// be determined. `path` doesn't have to exist yet, the space of its closest existing ancestor is
+/// returned.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let absolute = if path.is_absolute() { path.to_path_buf() } else { std::env::current_dir().ok()?.join(path) };
+    absolute.ancestors().find(|p| p.exists()).and_then(free_space)
+}
+
+/// Formats a number of bytes for messages, like `1.5 GiB`.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::io::format_size;
+/// assert_eq!("512 B", format_size(512));
+/// assert_eq!("1.5 MiB", format_size(1536 * 1024));
+/// ```
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Checks that the file system `path` is on has at least `required` bytes available, so commands
+/// fail before writing anything instead of with a generic error when the disk is full. Passes if
+/// the available space can't be determined.
+pub fn check_space(path: &Path, required: u64) -> io::Result<()> {
+    match available_space(path) {
+        Some(available) if available < required => Err(error!("Not enough disk space for {}: {} needed, but only {} available.",
+            path.display(), format_size(required), format_size(available))),
+        _ => Ok(()),
+    }
+}
@@ -1,11 +1,35 @@
 //#![deny(missing_docs)]
 
+pub mod archive;
+pub mod audit;
 pub mod binarize;
+pub mod cache;
 pub mod config;
+pub mod deps;
 pub mod error;
+pub mod eval;
+pub mod git;
+pub mod hooks;
+pub mod install;
+pub mod interrupt;
 pub mod io;
+pub mod lint;
+pub mod lock;
+pub mod mission;
 pub mod p3d;
+pub mod paa;
+pub mod paths;
 pub mod pbo;
 pub mod preprocess;
+pub mod project;
 pub mod run;
+pub mod selftest;
+pub mod serve;
 pub mod sign;
+pub mod stringtable;
+pub mod target;
+pub mod temp;
+#[cfg(feature = "test-support")]
+pub mod testing;
+pub mod ui;
+pub mod watch;
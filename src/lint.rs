@@ -0,0 +1,1637 @@
+//! Checks for config values the engine accepts silently but handles wrong, automatic fixes and
+//! checks for secrets in addons
+
+use std::cmp::{min};
+use std::fs::{File};
+use std::io::{Read, Write, Error};
+use std::ops::{Range};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use colored::Colorize;
+use once_cell::sync::Lazy;
+use regex::bytes::{Regex};
+
+use crate::archive::{Archive, Directory, open_archive};
+use crate::config::{Config, ConfigArray, ConfigArrayElement, ConfigClass, ConfigEntry, ConfigFunction, Span};
+use crate::error::*;
+use crate::io::AtomicFile;
+use crate::paths::{normalize, path_key, strip_prefix};
+use crate::preprocess::{preprocess_with, PreprocessInfo, PreprocessOptions};
+use crate::stringtable::{Stringtable, StringtableKey};
+
+/// Extensions of files that configs refer to by path
+const PATH_EXTENSIONS: &[&str] = &["paa", "pac", "p3d", "rtm", "rvmat", "bisurf", "sqf", "sqs", "fsm", "hpp", "wss", "ogg", "wav", "lip", "bikb", "jpg", "png"];
+
+/// Automatic fix for a problem in a config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    /// byte range in the preprocessed config that is replaced, empty for insertions
+    pub range: Range<usize>,
+    pub replacement: String,
+    /// description of the problem
+    pub message: String,
+    /// name of the warning for the problem
+    pub name: &'static str,
+}
+
+/// Parses an argument of a procedural texture as a number.
+fn number(arg: &str) -> Option<f32> {
+    arg.trim().parse::<f32>().ok()
+}
+
+/// Validates a procedural texture string like `#(argb,8,8,3)color(1,0,0,1)`.
+///
+/// Returns `Ok(())` for strings that are not procedural textures. Errors in these strings
+/// don't prevent the config from loading, the engine just shows a white texture instead.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::lint::check_procedural_texture;
+/// assert!(check_procedural_texture("\\a3\\data_f\\white.paa").is_ok());
+/// assert!(check_procedural_texture("#(argb,8,8,3)color(1,0,0,1)").is_ok());
+/// assert!(check_procedural_texture("#(argb,8,8,3)color(1,0,0)").is_err());
+/// assert!(check_procedural_texture("#(argb,8,8,3)colour(1,0,0,1)").is_err());
+/// ```
+pub fn check_procedural_texture(value: &str) -> Result<(), String> {
+    if !value.starts_with("#(") {
+        return Ok(());
+    }
+
+    let header_end = value.find(')').ok_or("missing \")\" after the texture format")?;
+    let header: Vec<&str> = value[2..header_end].split(',').map(|s| s.trim()).collect();
+    if header.len() != 4 {
+        return Err(format!("expected format, width, height and mipmap count, found {} values", header.len()));
+    }
+
+    if !["argb", "rgb", "ai", "a"].contains(&header[0].to_lowercase().as_str()) {
+        return Err(format!("unknown format \"{}\"", header[0]));
+    }
+    for dimension in &header[1..3] {
+        match dimension.parse::<u32>() {
+            Ok(d) if d.is_power_of_two() && d <= 4096 => {},
+            _ => { return Err(format!("size \"{}\" is not a power of two up to 4096", dimension)); }
+        }
+    }
+    match header[3].parse::<u32>() {
+        Ok(m) if m >= 1 => {},
+        _ => { return Err(format!("mipmap count \"{}\" is not a positive integer", header[3])); }
+    }
+
+    let body = &value[header_end + 1..];
+    let args_start = body.find('(').ok_or("missing arguments of the texture type")?;
+    if !body.ends_with(')') {
+        return Err("arguments are not closed with \")\"".to_string());
+    }
+    let kind = &body[..args_start];
+    let args_str = &body[args_start + 1..body.len() - 1];
+    let args: Vec<&str> = if args_str.trim().is_empty() { Vec::new() } else { args_str.split(',').map(|s| s.trim()).collect() };
+
+    // minimum and maximum argument count, and how many leading arguments are numbers in 0..1
+    let (min_args, max_args, unit_args) = match kind {
+        "color" => (4, 5, 4),
+        "fresnel" => (2, 2, 0),
+        "fresnelGlass" => (0, 1, 0),
+        "irradiance" => (1, 1, 0),
+        "perlinNoise" => (4, 4, 0),
+        "waterIrradiance" => (1, 1, 0),
+        "treeCrown" => (1, 1, 0),
+        "treeCrownAmb" => (4, 4, 0),
+        "text" => (1, usize::MAX, 0),
+        _ => { return Err(format!("unknown texture type \"{}\"", kind)); }
+    };
+
+    if args.len() < min_args || args.len() > max_args {
+        let expected = if min_args == max_args { format!("{}", min_args) } else { format!("{} to {}", min_args, max_args) };
+        return Err(format!("{} expects {} arguments, found {}", kind, expected, args.len()));
+    }
+
+    if kind == "text" {
+        return Ok(());
+    }
+
+    for (i, arg) in args.iter().enumerate() {
+        if kind == "color" && i == 4 {
+            break;
+        }
+        let n = number(arg).ok_or_else(|| format!("argument \"{}\" of {} is not a number", arg, kind))?;
+        if i < unit_args && !(0.0..=1.0).contains(&n) {
+            return Err(format!("argument {} of {} is out of range 0..1", arg, kind));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns whether the value is a version like `1.2.3`, optionally with a leading `v` and a
+/// suffix after `-` or `+`.
+fn is_version(value: &str) -> bool {
+    let value = value.strip_prefix('v').unwrap_or(value);
+    let numbers = value.split(['-', '+']).next().unwrap();
+    let parts: Vec<&str> = numbers.split('.').collect();
+
+    parts.len() <= 4 && parts.iter().all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Validates a PBO header extension against the format other tools expect. Unknown keys are
+/// only checked for characters that break the header.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::lint::check_header_extension;
+/// assert!(check_header_extension("prefix", "x\\foo\\addons\\main").is_ok());
+/// assert!(check_header_extension("prefix", "x/foo/addons/main").is_err());
+/// assert!(check_header_extension("version", "1.2.3").is_ok());
+/// assert!(check_header_extension("version", "1.2 beta").is_err());
+/// assert!(check_header_extension("Mikero", "DePbo.dll.9.10").is_ok());
+/// assert!(check_header_extension("hemtt", "latest").is_err());
+/// assert!(check_header_extension("author", "anyone").is_ok());
+/// ```
+pub fn check_header_extension(key: &str, value: &str) -> Result<(), String> {
+    if key.is_empty() {
+        return Err("the key is empty".to_string());
+    }
+    if key.contains('=') {
+        return Err("the key contains \"=\"".to_string());
+    }
+    if let Some(c) = key.chars().chain(value.chars()).find(|c| c.is_control()) {
+        return Err(format!("it contains the control character {:?}", c));
+    }
+
+    match key.to_lowercase().as_str() {
+        "prefix" => {
+            if value.is_empty() {
+                return Err("the prefix is empty".to_string());
+            }
+            if value.contains('/') {
+                return Err("the prefix contains forward slashes".to_string());
+            }
+            if value.starts_with('\\') || value.ends_with('\\') || value.contains("\\\\") {
+                return Err("the prefix starts or ends with a backslash or contains an empty folder name".to_string());
+            }
+            if value.contains(char::is_whitespace) {
+                return Err("the prefix contains whitespace".to_string());
+            }
+        },
+        "version" | "hemtt" if !is_version(value) => {
+            return Err("expected a version like 1.2.3".to_string());
+        },
+        "mikero" if !value.strip_prefix("DePbo.dll.").map(is_version).unwrap_or(false) => {
+            return Err("expected DePbo.dll.<version>".to_string());
+        },
+        "git_hash" if value.len() < 7 || value.len() > 40 || !value.chars().all(|c| c.is_ascii_hexdigit()) => {
+            return Err("expected a git commit hash".to_string());
+        },
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Longest path of a file including the prefix that the engine and tools handle
+const MAX_PATH_LENGTH: usize = 255;
+
+/// Checks the name of a PBO entry for characters known to break the engine or other tools, and
+/// its length combined with the prefix. Returns the problems with the names of their warnings.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::lint::check_entry_path;
+/// assert!(check_entry_path("x\\foo\\addons\\main", "data\\icon_ca.paa").is_empty());
+/// assert_eq!("path-space", check_entry_path("", "my addon").first().unwrap().1);
+/// assert_eq!("non-ascii-path", check_entry_path("x\\foo", "data\\flagge_grün.paa").first().unwrap().1);
+/// assert_eq!("path-length", check_entry_path("x\\foo", &"a".repeat(250)).first().unwrap().1);
+/// ```
+pub fn check_entry_path(prefix: &str, name: &str) -> Vec<(String, &'static str)> {
+    let mut problems: Vec<(String, &'static str)> = Vec::new();
+
+    if name.contains(char::is_whitespace) {
+        problems.push(("contains whitespace".to_string(), "path-space"));
+    }
+    if let Some(c) = name.chars().find(|c| !c.is_ascii()) {
+        problems.push((format!("contains the non-ASCII character {:?}", c), "non-ascii-path"));
+    }
+
+    let prefix = normalize(prefix);
+    let length = if prefix.is_empty() { name.len() } else { prefix.len() + 1 + name.len() };
+    if length > MAX_PATH_LENGTH {
+        problems.push((format!("is {} characters long including the prefix, more than {}", length, MAX_PATH_LENGTH), "path-length"));
+    }
+
+    problems
+}
+
+/// Returns the path with backslashes if it is a path to a game file with forward slashes, which
+/// the engine doesn't find in every place.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::lint::normalize_path_slashes;
+/// assert_eq!(Some("\\x\\foo\\data\\bar.paa".to_string()), normalize_path_slashes("/x/foo/data/bar.paa"));
+/// assert_eq!(None, normalize_path_slashes("\\x\\foo\\data\\bar.paa"));
+/// assert_eq!(None, normalize_path_slashes("https://example.com/logo.paa"));
+/// assert_eq!(None, normalize_path_slashes("1/2"));
+/// ```
+pub fn normalize_path_slashes(value: &str) -> Option<String> {
+    if !value.contains('/') || value.contains("://") || value.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let (_, extension) = value.rsplit_once('.')?;
+    if !PATH_EXTENSIONS.contains(&extension.to_lowercase().as_str()) {
+        return None;
+    }
+
+    Some(value.replace('/', "\\"))
+}
+
+/// Adds the fixes for a string value whose source text is `text`, starting at `start` in the
+/// preprocessed config.
+pub(crate) fn string_fixes(text: &str, value: &str, start: usize, fixes: &mut Vec<Fix>) {
+    let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+
+    if !text.starts_with('"') && !text.starts_with('\'') {
+        fixes.push(Fix {
+            range: start..start + text.len(),
+            replacement: quote(value),
+            message: format!("String \"{}\" is not quoted.", value),
+            name: "unquoted-string",
+        });
+    } else if let Some(normalized) = normalize_path_slashes(value) {
+        fixes.push(Fix {
+            range: start..start + text.len(),
+            replacement: quote(&normalized),
+            message: format!("Path \"{}\" uses forward slashes.", value),
+            name: "path-slashes",
+        });
+    }
+}
+
+/// Returns the byte offsets at which the lines of the text start.
+pub(crate) fn line_starts(text: &str) -> Vec<usize> {
+    std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect()
+}
+
+/// Returns the offset and text without line break of the line with the index.
+fn line_at<'a>(text: &'a str, starts: &[usize], index: usize) -> (usize, &'a str) {
+    let end = starts.get(index + 1).map(|s| s - 1).unwrap_or(text.len());
+    (starts[index], text[starts[index]..end].trim_end_matches('\r'))
+}
+
+/// Returns the line number and file the position in the preprocessed config comes from.
+fn origin<'a>(starts: &[usize], info: &'a PreprocessInfo, pos: usize) -> &'a (u32, Option<PathBuf>) {
+    let line = starts.partition_point(|start| *start <= pos);
+    &info.line_origins[min(line, info.line_origins.len()) - 1]
+}
+
+/// Returns whether the warning is muted with `#pragma armake2 mute` where the node of the span
+/// starts.
+fn muted_at(starts: &[usize], info: &PreprocessInfo, span: Option<&Span>, name: &str) -> bool {
+    span.map(|s| info.is_muted(name, starts.partition_point(|start| *start <= s.range.start))).unwrap_or(false)
+}
+
+/// Applies fixes to the source file at `path` that `preprocessed` was created from. Returns the
+/// fixed source and the fixes that were applied.
+///
+/// Fixes are only applied where the preprocessed line matches the line of the source up to the
+/// end of the fix, apart from indentation, so text coming from macros or included files is never
+/// changed.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::Config;
+/// # use armake2::lint::apply_fixes;
+/// # use armake2::preprocess::preprocess;
+/// let source = "#define VALUE foo\nclass A {\n    x = bar;\n    y = VALUE;\n    z = 1\n};\n";
+/// let (preprocessed, info) = preprocess(source.to_string(), None, &Vec::new()).unwrap();
+/// let fixes = Config::from_preprocessed(&preprocessed, &info).unwrap().fixes(&preprocessed);
+///
+/// let (fixed, applied) = apply_fixes(source, None, &preprocessed, &info, &fixes);
+/// assert_eq!("#define VALUE foo\nclass A {\n    x = \"bar\";\n    y = VALUE;\n    z = 1;\n};\n", fixed);
+/// assert_eq!(2, applied.len());
+/// ```
+pub fn apply_fixes<'a>(source: &str, path: Option<&Path>, preprocessed: &str, info: &PreprocessInfo, fixes: &'a [Fix]) -> (String, Vec<&'a Fix>) {
+    let preprocessed_starts = line_starts(preprocessed);
+    let source_starts = line_starts(source);
+
+    let mut edits: Vec<(Range<usize>, &Fix)> = Vec::new();
+    for fix in fixes {
+        let (line, file) = origin(&preprocessed_starts, info, fix.range.start);
+        if file.as_deref() != path || *line == 0 || *line as usize > source_starts.len() {
+            continue;
+        }
+
+        let line_index = preprocessed_starts.partition_point(|start| *start <= fix.range.start) - 1;
+        let (preprocessed_start, preprocessed_line) = line_at(preprocessed, &preprocessed_starts, line_index);
+        let (source_start, source_line) = line_at(source, &source_starts, *line as usize - 1);
+
+        // the preprocessor removes indentation
+        let preprocessed_start = preprocessed_start + preprocessed_line.len() - preprocessed_line.trim_start().len();
+        let source_start = source_start + source_line.len() - source_line.trim_start().len();
+        let common = preprocessed_line.trim_start().bytes().zip(source_line.trim_start().bytes()).take_while(|(a, b)| a == b).count();
+        if fix.range.start < preprocessed_start || fix.range.end - preprocessed_start > common {
+            continue;
+        }
+
+        let start = source_start + fix.range.start - preprocessed_start;
+        edits.push((start..start + fix.range.len(), fix));
+    }
+
+    edits.sort_by_key(|(range, _)| (range.start, range.end));
+    let mut applied: Vec<(Range<usize>, &Fix)> = Vec::new();
+    for (range, fix) in edits {
+        if applied.last().map(|(last, _)| range.start < last.end || range == *last).unwrap_or(false) {
+            continue;
+        }
+        applied.push((range, fix));
+    }
+
+    let mut fixed = source.to_string();
+    for (range, fix) in applied.iter().rev() {
+        fixed.replace_range(range.clone(), &fix.replacement);
+    }
+
+    (fixed, applied.into_iter().map(|(_, fix)| fix).collect())
+}
+
+/// Checks the functions declared in `CfgFunctions` of an addon for files that don't exist,
+/// duplicate names and tags that aren't valid or spelled differently. Only files inside the
+/// addon, whose paths start with its prefix, can be checked, `files` are the entries of the
+/// addon. With an empty prefix, as for missions, all paths are relative to the folder. Returns
+/// the problems with the names of their warnings.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::ConfigFunction;
+/// # use armake2::lint::check_functions;
+/// let function = |tag: &str, name: &str, path: &str| ConfigFunction { tag: tag.to_string(), category: "misc".to_string(), name: name.to_string(), path: path.to_string() };
+/// let functions = vec![
+///     function("abc", "init", "\\x\\abc\\addons\\main\\functions\\fn_init.sqf"),
+///     function("abc", "INIT", "\\x\\abc\\addons\\main\\functions\\fn_init2.sqf"),
+///     function("abc", "other", "\\x\\other\\fn_other.sqf"),
+/// ];
+///
+/// let problems = check_functions(&functions, "x\\abc\\addons\\main", &["functions\\fn_init.sqf".to_string()]);
+/// let names: Vec<&str> = problems.iter().map(|(_, name)| *name).collect();
+/// assert_eq!(vec!["function-duplicate", "function-missing-file"], names);
+/// ```
+pub fn check_functions(functions: &[ConfigFunction], prefix: &str, files: &[String]) -> Vec<(String, &'static str)> {
+    let mut problems: Vec<(String, &'static str)> = Vec::new();
+    let files: Vec<String> = files.iter().map(|f| path_key(f)).collect();
+    let mut tags: Vec<&str> = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+
+    for function in functions {
+        let tag = function.tag.as_str();
+        if !tags.contains(&tag) {
+            if tag.is_empty() || tag.starts_with(|c: char| c.is_ascii_digit()) || !tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                problems.push((format!("Tag \"{}\" of {} is not a valid identifier.", tag, function.full_name()), "function-tag"));
+            }
+            if let Some(other) = tags.iter().find(|t| t.eq_ignore_ascii_case(tag)) {
+                problems.push((format!("Tag {} is also written as {}.", tag, other), "function-tag"));
+            }
+            tags.push(tag);
+        }
+
+        let name = function.full_name().to_lowercase();
+        if names.contains(&name) {
+            problems.push((format!("Function {} is declared more than once.", function.full_name()), "function-duplicate"));
+        } else {
+            names.push(name);
+        }
+
+        // function paths of missions are relative to the mission folder, which has no prefix
+        if let Some(relative) = strip_prefix(&function.path, prefix) {
+            if !files.contains(&path_key(&relative)) {
+                problems.push((format!("File {} of function {} doesn't exist.", function.path, function.full_name()), "function-missing-file"));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Returns the strings of the array property of the class, and whether it had elements that
+/// aren't strings. `None` if the property is missing or not an array.
+fn string_array<'a>(class: &'a ConfigClass, name: &str) -> Option<(Vec<&'a str>, bool)> {
+    match class.get(name) {
+        Some(ConfigEntry::ArrayEntry(array)) => {
+            let strings: Vec<&str> = array.elements().filter_map(|e| match e {
+                ConfigArrayElement::StringElement(s) => Some(&**s),
+                _ => None,
+            }).collect();
+            let other = strings.len() != array.elements().count();
+            Some((strings, other))
+        },
+        _ => None,
+    }
+}
+
+/// Checks the `CfgPatches` of the root config of an addon with the prefix: that it exists, that
+/// one of its classes is named like the addon (the last part of the prefix, alone or after a tag
+/// like `abc_main`), that the classes in `units` and `weapons` are declared in `CfgVehicles` and
+/// `CfgWeapons` of the config, and that `requiredAddons` only contains addon names. Returns the
+/// problems with the names of their warnings.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::Config;
+/// # use armake2::lint::check_patches;
+/// let input = String::from("
+/// class CfgPatches { class abc_main { units[] = {\"abc_car\", \"abc_truck\"}; weapons[] = {}; requiredAddons[] = {\"cba_main\", \"\"}; }; };
+/// class CfgVehicles { class abc_car; };");
+/// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+///
+/// let names: Vec<&str> = check_patches(&config, "x\\abc\\addons\\main").iter().map(|(_, name)| *name).collect();
+/// assert_eq!(vec!["patches-undeclared-class", "patches-required-addons"], names);
+/// ```
+pub fn check_patches(config: &Config, prefix: &str) -> Vec<(String, &'static str)> {
+    let mut problems: Vec<(String, &'static str)> = Vec::new();
+    let patches = match config.root().classes().find(|(name, _)| name.eq_ignore_ascii_case("CfgPatches")) {
+        Some((_, patches)) => patches,
+        None => {
+            problems.push(("Config has no CfgPatches, so the game doesn't load the addon.".to_string(), "patches-missing"));
+            return problems;
+        }
+    };
+
+    let addon = prefix.trim_matches('\\').rsplit('\\').next().unwrap_or("").to_lowercase();
+    let names: Vec<&str> = patches.classes().map(|(name, _)| name).collect();
+    if names.is_empty() {
+        problems.push(("CfgPatches has no classes.".to_string(), "patches-missing"));
+    } else if !addon.is_empty() && !names.iter().any(|n| n.to_lowercase() == addon || n.to_lowercase().ends_with(&format!("_{}", addon))) {
+        problems.push((format!("None of the CfgPatches classes {} is named like the addon {}.", names.join(", "), addon), "patches-name"));
+    }
+
+    let declared = |kind: &str| -> Vec<String> {
+        config.root().classes()
+            .find(|(name, _)| name.eq_ignore_ascii_case(kind))
+            .map(|(_, class)| class.entries().filter(|(_, e)| matches!(e, ConfigEntry::ClassEntry(_))).map(|(name, _)| name.to_lowercase()).collect())
+            .unwrap_or_default()
+    };
+    let vehicles = declared("CfgVehicles");
+    let weapons = declared("CfgWeapons");
+
+    for (name, patch) in patches.classes() {
+        for (property, kind, classes) in [("units", "CfgVehicles", &vehicles), ("weapons", "CfgWeapons", &weapons)] {
+            let (strings, other) = match string_array(patch, property) {
+                Some(array) => array,
+                None => {
+                    if patch.get(property).is_some() {
+                        problems.push((format!("CfgPatches.{}.{} has to be an array.", name, property), "patches-undeclared-class"));
+                    }
+                    continue;
+                }
+            };
+            if other {
+                problems.push((format!("CfgPatches.{}.{}[] has to contain class names as strings.", name, property), "patches-undeclared-class"));
+            }
+            for class in strings.iter().filter(|c| !classes.contains(&c.to_lowercase())) {
+                problems.push((format!("CfgPatches.{}.{}[] lists {}, which is not declared in {}.", name, property, class, kind), "patches-undeclared-class"));
+            }
+        }
+
+        match string_array(patch, "requiredAddons") {
+            Some((strings, other)) => {
+                if other {
+                    problems.push((format!("CfgPatches.{}.requiredAddons[] has to contain addon names as strings.", name), "patches-required-addons"));
+                }
+                let mut seen: Vec<String> = Vec::new();
+                for required in strings {
+                    if required.is_empty() || !required.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                        problems.push((format!("CfgPatches.{}.requiredAddons[] contains \"{}\", which is not a CfgPatches class name.", name, required), "patches-required-addons"));
+                    } else if required.eq_ignore_ascii_case(name) {
+                        problems.push((format!("CfgPatches.{} requires itself.", name), "patches-required-addons"));
+                    } else if seen.contains(&required.to_lowercase()) {
+                        problems.push((format!("CfgPatches.{}.requiredAddons[] contains {} more than once.", name, required), "patches-required-addons"));
+                    } else {
+                        seen.push(required.to_lowercase());
+                    }
+                }
+            },
+            None if patch.get("requiredAddons").is_some() => {
+                problems.push((format!("CfgPatches.{}.requiredAddons has to be an array.", name), "patches-required-addons"));
+            },
+            None => {}
+        }
+    }
+
+    problems
+}
+
+/// Attribute controls defined by the vanilla Eden editor in `Cfg3DEN >> Attributes`
+const EDEN_CONTROLS: &[&str] = &[
+    "Default", "Title", "Checkbox", "CheckboxNumber", "CheckboxReversed", "CheckboxState", "Combo",
+    "Date", "Edit", "EditArray", "EditCode", "EditCodeMulti3", "EditCodeMulti5", "EditCodeShort",
+    "EditMulti3", "EditMulti5", "EditShort", "EditXY", "EditXYZ", "EditZ", "Skill", "Slider",
+    "SliderMultiplier", "SliderTime", "SliderTimeDay", "Timeout", "Toolbox", "ToolboxLock",
+];
+
+/// Controls whose options are given by a `Values` class in the attribute
+const EDEN_VALUE_CONTROLS: &[&str] = &["Combo", "Toolbox"];
+
+/// Entries of Eden attributes that have to be strings, and whether they are required
+const EDEN_ATTRIBUTE_STRINGS: &[(&str, bool)] = &[
+    ("property", true), ("control", true), ("displayName", false), ("tooltip", false),
+    ("expression", false), ("defaultValue", false), ("typeName", false), ("condition", false),
+    ("validate", false),
+];
+
+/// Checks an Eden attribute against the schema the editor expects. `properties` collects the
+/// properties of the entity type to find duplicates.
+fn check_eden_attribute(path: &str, attribute: &ConfigClass, controls: &[String], properties: &mut Vec<String>, problems: &mut Vec<(String, String, &'static str)>) {
+    for (name, required) in EDEN_ATTRIBUTE_STRINGS {
+        match attribute.get(name) {
+            Some(ConfigEntry::StringEntry(_)) => {},
+            Some(_) => problems.push((path.to_string(), format!("{} has to be a string.", name), "3den-attribute-type")),
+            None if *required => problems.push((path.to_string(), format!("Attribute is missing {}.", name), "3den-attribute-missing")),
+            None => {},
+        }
+    }
+
+    if let Some(property) = attribute.string("property") {
+        if property.is_empty() || property.contains(char::is_whitespace) {
+            problems.push((path.to_string(), format!("Property \"{}\" is empty or contains whitespace.", property), "3den-property"));
+        } else if properties.iter().any(|p| p.eq_ignore_ascii_case(property)) {
+            problems.push((path.to_string(), format!("Property {} is used by more than one attribute.", property), "3den-property"));
+        } else {
+            properties.push(property.to_string());
+        }
+    }
+
+    if let Some(control) = attribute.string("control") {
+        if !controls.iter().any(|c| c.eq_ignore_ascii_case(control)) {
+            problems.push((path.to_string(), format!("Control {} is neither a vanilla control nor defined in Cfg3DEN >> Attributes.", control), "3den-unknown-control"));
+        }
+        let has_values = matches!(attribute.get("Values"), Some(ConfigEntry::ClassEntry(_)));
+        if EDEN_VALUE_CONTROLS.iter().any(|c| c.eq_ignore_ascii_case(control)) && !has_values {
+            problems.push((path.to_string(), format!("Control {} needs a Values class with the options.", control), "3den-values"));
+        }
+    }
+
+    if let Some(type_name) = attribute.string("typeName") {
+        if !["STRING", "NUMBER", "BOOL"].iter().any(|t| t.eq_ignore_ascii_case(type_name)) {
+            problems.push((path.to_string(), format!("typeName \"{}\" is not STRING, NUMBER or BOOL.", type_name), "3den-type-name"));
+        }
+    }
+    if let Some(validate) = attribute.string("validate") {
+        if !["none", "expression", "condition", "number", "variable"].iter().any(|v| v.eq_ignore_ascii_case(validate)) {
+            problems.push((path.to_string(), format!("validate \"{}\" is not none, expression, condition, number or variable.", validate), "3den-validate"));
+        }
+    }
+}
+
+/// Finds the attribute categories in the class and checks their attributes.
+fn check_eden_class(path: &str, class: &ConfigClass, controls: &[String], properties: &mut Vec<String>, problems: &mut Vec<(String, String, &'static str)>) {
+    for (name, child) in class.classes() {
+        let child_path = format!("{}.{}", path, name);
+        if !name.eq_ignore_ascii_case("AttributeCategories") {
+            check_eden_class(&child_path, child, controls, properties, problems);
+            continue;
+        }
+
+        for (category_name, category) in child.classes() {
+            let attributes = match category.get("Attributes") {
+                Some(ConfigEntry::ClassEntry(attributes)) => attributes,
+                _ => { continue; }
+            };
+            let attributes_path = format!("{}.{}.Attributes", child_path, category_name);
+            for (attribute_name, attribute) in attributes.classes() {
+                check_eden_attribute(&format!("{}.{}", attributes_path, attribute_name), attribute, controls, properties, problems);
+            }
+        }
+    }
+}
+
+/// Checks the attributes Eden editor entities get from `Cfg3DEN` for missing and mistyped
+/// entries, unknown controls, duplicate properties and invalid `typeName` and `validate`
+/// values, which crash the editor instead of giving errors. Returns the paths of the attributes
+/// (see `Config::spans`) with the problems and the names of their warnings.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::Config;
+/// # use armake2::lint::check_3den;
+/// let input = String::from("class Cfg3DEN { class Object { class AttributeCategories { class abc { class Attributes {
+///     class abc_hidden { property = \"abc_hidden\"; control = \"Checkbox\"; typeName = \"BOOLEAN\"; };
+/// }; }; }; }; };");
+/// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+///
+/// let problems = check_3den(&config);
+/// assert_eq!("Cfg3DEN.Object.AttributeCategories.abc.Attributes.abc_hidden", problems[0].0);
+/// assert_eq!("3den-type-name", problems[0].2);
+/// ```
+pub fn check_3den(config: &Config) -> Vec<(String, String, &'static str)> {
+    let mut problems: Vec<(String, String, &'static str)> = Vec::new();
+    let (name, eden) = match config.root().classes().find(|(name, _)| name.eq_ignore_ascii_case("Cfg3DEN")) {
+        Some(eden) => eden,
+        None => { return problems; }
+    };
+
+    let mut controls: Vec<String> = EDEN_CONTROLS.iter().map(|c| c.to_string()).collect();
+    if let Some(ConfigEntry::ClassEntry(attributes)) = eden.get("Attributes") {
+        controls.extend(attributes.classes().map(|(name, _)| name.to_string()));
+    }
+
+    // properties only have to be unique per type of entity
+    for (entity_name, entity) in eden.classes().filter(|(n, _)| !n.eq_ignore_ascii_case("Attributes")) {
+        let mut properties: Vec<String> = Vec::new();
+        check_eden_class(&format!("{}.{}", name, entity_name), entity, &controls, &mut properties, &mut problems);
+    }
+
+    problems
+}
+
+/// Commands that run arbitrary code when whitelisted for remote execution
+const REMOTE_EXEC_CODE_COMMANDS: &[&str] = &["call", "spawn", "compile", "compileFinal", "execVM", "exec", "execFSM", "callExtension"];
+
+/// Functions that run arbitrary code when whitelisted for remote execution
+const REMOTE_EXEC_CODE_FUNCTIONS: &[&str] = &["BIS_fnc_call", "BIS_fnc_spawn", "BIS_fnc_execVM", "BIS_fnc_execFSM", "BIS_fnc_codePerformance"];
+
+/// Checks `CfgRemoteExec` for settings that let clients execute any function or command on
+/// other machines: mode 2, which is also the default without a mode, and whitelisted functions
+/// and commands that run arbitrary code. Returns the paths of the classes (see `Config::spans`)
+/// with the problems and the names of their warnings.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::Config;
+/// # use armake2::lint::check_remote_exec;
+/// let input = String::from("class CfgRemoteExec { class Functions { mode = 1; class BIS_fnc_spawn {}; }; class Commands { mode = 2; }; };");
+/// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+///
+/// let names: Vec<&str> = check_remote_exec(&config).iter().map(|(_, _, name)| *name).collect();
+/// assert_eq!(vec!["remote-exec-code", "remote-exec-mode"], names);
+/// ```
+pub fn check_remote_exec(config: &Config) -> Vec<(String, String, &'static str)> {
+    let mut problems: Vec<(String, String, &'static str)> = Vec::new();
+    let (name, remote_exec) = match config.root().classes().find(|(name, _)| name.eq_ignore_ascii_case("CfgRemoteExec")) {
+        Some(remote_exec) => remote_exec,
+        None => { return problems; }
+    };
+
+    for (kind, dangerous) in [("Functions", REMOTE_EXEC_CODE_FUNCTIONS), ("Commands", REMOTE_EXEC_CODE_COMMANDS)] {
+        let class = match remote_exec.get(kind) {
+            Some(ConfigEntry::ClassEntry(class)) => class,
+            _ => {
+                problems.push((name.to_string(), format!("{} is missing, so all {} can be executed remotely.", kind, kind.to_lowercase()), "remote-exec-mode"));
+                continue;
+            }
+        };
+        let path = format!("{}.{}", name, kind);
+
+        let mode = match class.get("mode") {
+            Some(ConfigEntry::IntEntry(i)) => *i,
+            Some(ConfigEntry::FloatEntry(f)) => *f as i32,
+            Some(_) => -1,
+            None => 2,
+        };
+        match mode {
+            0 => {},
+            1 => {
+                for (entry, _) in class.classes().filter(|(entry, _)| dangerous.iter().any(|d| d.eq_ignore_ascii_case(entry))) {
+                    problems.push((format!("{}.{}", path, entry), format!("{} runs arbitrary code and shouldn't be whitelisted.", entry), "remote-exec-code"));
+                }
+            },
+            2 => problems.push((path, format!("Mode 2 allows all {} to be executed remotely, use a whitelist with mode 1.", kind.to_lowercase()), "remote-exec-mode")),
+            _ => problems.push((path, "Mode has to be 0, 1 or 2.".to_string(), "remote-exec-mode")),
+        }
+    }
+
+    problems
+}
+
+/// Respawn types of `respawn` in description.ext, in the order of their numbers
+const RESPAWN_TYPES: &[&str] = &["NONE", "BIRD", "INSTANT", "BASE", "GROUP", "SIDE"];
+
+/// Respawn templates defined by the vanilla `CfgRespawnTemplates`
+const RESPAWN_TEMPLATES: &[&str] = &["None", "Base", "Counter", "EndMission", "Group", "Instant", "MenuInventory", "MenuPosition", "Seagull", "Spectator", "Tickets", "Wave"];
+
+/// Returns the lowercase keys defined in a stringtable.xml.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::lint::stringtable_keys;
+/// let xml = "<Project name=\"abc\"><Package name=\"abc\"><Key ID=\"STR_abc_title\"><English>Title</English></Key></Package></Project>";
+///
+/// assert_eq!(vec!["str_abc_title"], stringtable_keys(xml));
+/// ```
+pub fn stringtable_keys(xml: &str) -> Vec<String> {
+    let regex = regex::Regex::new(r#"(?i)<key\s+id\s*=\s*"([^"]+)""#).unwrap();
+    regex.captures_iter(xml).map(|c| c[1].to_lowercase()).collect()
+}
+
+/// Collects the string values of the class and its subclasses with their paths, skipping the
+/// class names in `skip`.
+fn collect_strings<'a>(path: &str, class: &'a ConfigClass, skip: &[&str], strings: &mut Vec<(String, &'a str)>) {
+    fn array_strings<'a>(path: &str, array: &'a ConfigArray, strings: &mut Vec<(String, &'a str)>) {
+        for (i, element) in array.elements().enumerate() {
+            match element {
+                ConfigArrayElement::StringElement(s) => strings.push((format!("{}[{}]", path, i), s)),
+                ConfigArrayElement::ArrayElement(a) => array_strings(&format!("{}[{}]", path, i), a, strings),
+                _ => {}
+            }
+        }
+    }
+
+    for (name, entry) in class.entries() {
+        let entry_path = if path.is_empty() { name.to_string() } else { format!("{}.{}", path, name) };
+        match entry {
+            ConfigEntry::StringEntry(s) => strings.push((entry_path, s)),
+            ConfigEntry::ArrayEntry(a) => array_strings(&entry_path, a, strings),
+            ConfigEntry::ClassEntry(c) if !skip.iter().any(|s| s.eq_ignore_ascii_case(name)) => collect_strings(&entry_path, c, skip, strings),
+            _ => {}
+        }
+    }
+}
+
+/// Properties shown to players, which teams localizing their addons use stringtable keys for
+const LOCALIZED_PROPERTIES: &[&str] = &["displayName", "displayNameShort", "description", "descriptionShort", "tooltip", "briefingName", "overviewText", "onLoadMission", "onLoadName", "text"];
+
+fn check_localized_class(path: &str, class: &ConfigClass, stringtable: Option<&[String]>, problems: &mut Vec<(String, String, &'static str)>) {
+    for (name, entry) in class.entries() {
+        let entry_path = if path.is_empty() { name.to_string() } else { format!("{}.{}", path, name) };
+        let value = match entry {
+            ConfigEntry::ClassEntry(c) => { check_localized_class(&entry_path, c, stringtable, problems); continue; },
+            ConfigEntry::StringEntry(s) if LOCALIZED_PROPERTIES.iter().any(|p| p.eq_ignore_ascii_case(name)) => s,
+            _ => { continue; }
+        };
+
+        match value.get(..5) {
+            Some(start) if start.eq_ignore_ascii_case("$STR_") => {
+                let key = value[1..].to_lowercase();
+                match stringtable {
+                    Some(keys) if keys.contains(&key) => {},
+                    Some(_) => problems.push((entry_path, format!("Key {} doesn't exist in stringtable.xml.", &value[1..]), "localization-missing-key")),
+                    None => problems.push((entry_path, format!("Key {} is used, but there is no stringtable.xml.", &value[1..]), "localization-missing-key")),
+                }
+            },
+            _ => {
+                // texts without letters and pictures, e.g. of controls, need no translation
+                let extension = value.rsplit_once('.').map(|(_, e)| e.to_lowercase()).unwrap_or_default();
+                if !value.contains(char::is_alphabetic) || (value.contains(['\\', '/']) && PATH_EXTENSIONS.contains(&extension.as_str())) {
+                    continue;
+                }
+                problems.push((entry_path, format!("{} is hardcoded as \"{}\" instead of a stringtable key.", name, value), "localization-hardcoded"));
+            }
+        }
+    }
+}
+
+/// Checks that user-facing properties like `displayName`, `description` and `tooltip` are
+/// localized: their values have to be `$STR_` keys, which have to exist in the stringtable if
+/// it is given. Returns the paths of the properties (see `Config::spans`) with the problems and
+/// the names of their warnings, `localization-hardcoded` for strings that aren't keys and
+/// `localization-missing-key` for unknown keys.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::Config;
+/// # use armake2::lint::check_localization;
+/// let input = String::from("class CfgVehicles { class abc_car {
+///     displayName = \"Car\"; descriptionShort = \"$STR_abc_car\"; tooltip = \"$STR_abc_missing\"; text = \"-\";
+/// }; };");
+/// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+///
+/// let problems = check_localization(&config, Some(&["str_abc_car".to_string()]));
+/// assert_eq!(("CfgVehicles.abc_car.displayName", "localization-hardcoded"), (problems[0].0.as_str(), problems[0].2));
+/// assert_eq!(("CfgVehicles.abc_car.tooltip", "localization-missing-key"), (problems[1].0.as_str(), problems[1].2));
+/// assert_eq!(2, problems.len());
+/// ```
+pub fn check_localization(config: &Config, stringtable: Option<&[String]>) -> Vec<(String, String, &'static str)> {
+    let mut problems: Vec<(String, String, &'static str)> = Vec::new();
+    check_localized_class("", config.root(), stringtable, &mut problems);
+    problems
+}
+
+/// Returns the keys of the stringtable.xml of the addon the config is in: the first one in the
+/// folder of the config or its parents, up to the folder with the `$PBOPREFIX$`.
+fn find_stringtable(path: &Path) -> Result<Option<Vec<String>>, Error> {
+    let folder = match path.parent() {
+        Some(folder) if folder != Path::new("") => folder.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    for folder in folder.ancestors() {
+        let entries = match folder.read_dir() {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.file_name().to_string_lossy().to_string()).collect::<Vec<String>>(),
+            Err(_) => { break; }
+        };
+        if let Some(name) = entries.iter().find(|name| name.eq_ignore_ascii_case("stringtable.xml")) {
+            let mut xml = String::new();
+            File::open(folder.join(name)).prepend_error("Failed to open stringtable.xml:")?.read_to_string(&mut xml)?;
+            return Ok(Some(stringtable_keys(&xml)));
+        }
+        if entries.iter().any(|name| name == "$PBOPREFIX$") {
+            break;
+        }
+    }
+
+    Ok(None)
+}
+
+fn number_entry(entry: Option<&ConfigEntry>) -> Option<f32> {
+    match entry {
+        Some(ConfigEntry::IntEntry(i)) => Some(*i as f32),
+        Some(ConfigEntry::FloatEntry(f)) => Some(*f),
+        _ => None,
+    }
+}
+
+fn number_elements(entry: Option<&ConfigEntry>) -> Option<Vec<Option<f32>>> {
+    match entry {
+        Some(ConfigEntry::ArrayEntry(a)) => Some(a.elements().map(|e| match e {
+            ConfigArrayElement::IntElement(i) => Some(*i as f32),
+            ConfigArrayElement::FloatElement(f) => Some(*f),
+            _ => None,
+        }).collect()),
+        _ => None,
+    }
+}
+
+fn check_respawn(root: &ConfigClass, problems: &mut Vec<(String, String, &'static str)>) {
+    match root.get("respawn") {
+        Some(ConfigEntry::IntEntry(i)) if *i < 0 || *i as usize >= RESPAWN_TYPES.len() => {
+            problems.push(("respawn".to_string(), format!("Respawn type {} doesn't exist, expected 0 to {}.", i, RESPAWN_TYPES.len() - 1), "mission-respawn"));
+        },
+        Some(ConfigEntry::StringEntry(s)) if !RESPAWN_TYPES.iter().any(|t| t.eq_ignore_ascii_case(s)) => {
+            problems.push(("respawn".to_string(), format!("Respawn type \"{}\" doesn't exist, expected one of {}.", s, RESPAWN_TYPES.join(", ")), "mission-respawn"));
+        },
+        Some(ConfigEntry::IntEntry(_)) | Some(ConfigEntry::StringEntry(_)) | None => {},
+        Some(_) => problems.push(("respawn".to_string(), "Respawn type has to be a number or a string.".to_string(), "mission-respawn")),
+    }
+
+    if let Some(entry) = root.get("respawnDelay") {
+        if number_entry(Some(entry)).map(|d| d < 0.0).unwrap_or(true) {
+            problems.push(("respawnDelay".to_string(), "Respawn delay has to be a number of seconds.".to_string(), "mission-respawn"));
+        }
+    }
+
+    let mut templates: Vec<String> = RESPAWN_TEMPLATES.iter().map(|t| t.to_string()).collect();
+    if let Some(ConfigEntry::ClassEntry(defined)) = root.get("CfgRespawnTemplates") {
+        templates.extend(defined.classes().map(|(name, _)| name.to_string()));
+    }
+    for (name, entry) in root.entries().filter(|(name, _)| name.to_lowercase().starts_with("respawntemplates")) {
+        let array = match entry {
+            ConfigEntry::ArrayEntry(a) => a,
+            _ => {
+                problems.push((name.to_string(), "Respawn templates have to be an array.".to_string(), "mission-respawn"));
+                continue;
+            }
+        };
+        for (i, element) in array.elements().enumerate() {
+            match element {
+                ConfigArrayElement::StringElement(s) if templates.iter().any(|t| t.eq_ignore_ascii_case(s)) => {},
+                ConfigArrayElement::StringElement(s) => problems.push((format!("{}[{}]", name, i), format!("Respawn template \"{}\" isn't defined in CfgRespawnTemplates.", s), "mission-respawn")),
+                _ => problems.push((format!("{}[{}]", name, i), "Respawn templates have to be strings.".to_string(), "mission-respawn")),
+            }
+        }
+    }
+}
+
+fn check_loadouts(root: &ConfigClass, problems: &mut Vec<(String, String, &'static str)>) {
+    let (name, loadouts) = match root.classes().find(|(name, _)| name.eq_ignore_ascii_case("CfgRespawnInventory")) {
+        Some(loadouts) => loadouts,
+        None => { return; }
+    };
+    let roles: Option<Vec<&str>> = match root.get("CfgRoles") {
+        Some(ConfigEntry::ClassEntry(roles)) => Some(roles.classes().map(|(name, _)| name).collect()),
+        _ => None,
+    };
+
+    for (loadout_name, loadout) in loadouts.classes() {
+        let path = format!("{}.{}", name, loadout_name);
+        if loadout.string("displayName").is_none() {
+            problems.push((path.clone(), format!("Loadout {} has no displayName.", loadout_name), "mission-loadout"));
+        }
+        for key in ["weapons", "magazines", "items", "linkedItems", "backpack"] {
+            match (key, loadout.get(key)) {
+                ("backpack", Some(ConfigEntry::StringEntry(_))) | (_, Some(ConfigEntry::ArrayEntry(_))) | (_, None) => {},
+                ("backpack", Some(_)) => problems.push((format!("{}.{}", path, key), "Backpack has to be a class name.".to_string(), "mission-loadout")),
+                (_, Some(_)) => problems.push((format!("{}.{}", path, key), format!("{} has to be an array.", key), "mission-loadout")),
+            }
+        }
+        if let (Some(role), Some(roles)) = (loadout.string("role"), &roles) {
+            if !roles.iter().any(|r| r.eq_ignore_ascii_case(role)) {
+                problems.push((format!("{}.role", path), format!("Role {} isn't defined in CfgRoles.", role), "mission-loadout"));
+            }
+        }
+    }
+}
+
+fn check_params(root: &ConfigClass, problems: &mut Vec<(String, String, &'static str)>) {
+    let (name, params) = match root.classes().find(|(name, _)| name.eq_ignore_ascii_case("Params")) {
+        Some(params) => params,
+        None => { return; }
+    };
+
+    for (param_name, param) in params.classes() {
+        let path = format!("{}.{}", name, param_name);
+        if param.string("title").is_none() {
+            problems.push((path.clone(), format!("Parameter {} has no title.", param_name), "mission-param"));
+        }
+
+        let values = match number_elements(param.get("values")) {
+            Some(values) => values,
+            None => {
+                problems.push((path, format!("Parameter {} has no values[] array.", param_name), "mission-param"));
+                continue;
+            }
+        };
+        if values.iter().any(|v| v.is_none()) {
+            problems.push((format!("{}.values", path), "Parameter values have to be numbers.".to_string(), "mission-param"));
+        }
+
+        match param.get("texts") {
+            Some(ConfigEntry::ArrayEntry(texts)) if texts.elements().count() != values.len() => {
+                problems.push((format!("{}.texts", path), format!("Parameter has {} texts for {} values.", texts.elements().count(), values.len()), "mission-param"));
+            },
+            Some(ConfigEntry::ArrayEntry(_)) | None => {},
+            Some(_) => problems.push((format!("{}.texts", path), "Parameter texts have to be an array.".to_string(), "mission-param")),
+        }
+
+        match number_entry(param.get("default")) {
+            Some(default) if !values.contains(&Some(default)) => {
+                problems.push((format!("{}.default", path), format!("Default {} is not one of the values.", default), "mission-param"));
+            },
+            Some(_) => {},
+            None => problems.push((path, format!("Parameter {} has no numeric default.", param_name), "mission-param")),
+        }
+    }
+}
+
+/// Checks the description.ext of a mission: respawn settings and templates, loadouts in
+/// `CfgRespawnInventory`, parameters in `Params`, files of functions, sounds and images, and
+/// stringtable keys. `files` are the entries of the mission folder (see `Archive`), `stringtable`
+/// the keys of its stringtable.xml if it has one. Returns the paths of the entries (see
+/// `Config::spans`) with the problems and the names of their warnings.
+///
+/// Paths starting with a backslash or `@` are in addons and aren't checked.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::Config;
+/// # use armake2::lint::check_description;
+/// let input = String::from("respawn = \"BASE\";\nloadScreen = \"images\\loading.paa\";\n
+///     class Params { class Daytime { title = \"$STR_daytime\"; values[] = {6, 12}; texts[] = {\"Morning\", \"Noon\"}; default = 18; }; };");
+/// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+///
+/// let problems = check_description(&config, &[], Some(&[]));
+/// let names: Vec<&str> = problems.iter().map(|(_, _, name)| *name).collect();
+/// assert_eq!(vec!["mission-param", "mission-file-missing", "mission-stringtable"], names);
+/// assert_eq!("Params.Daytime.default", problems[0].0);
+/// ```
+pub fn check_description(config: &Config, files: &[String], stringtable: Option<&[String]>) -> Vec<(String, String, &'static str)> {
+    let mut problems: Vec<(String, String, &'static str)> = Vec::new();
+    let root = config.root();
+
+    check_respawn(root, &mut problems);
+    check_loadouts(root, &mut problems);
+    check_params(root, &mut problems);
+
+    for (message, name) in check_functions(&config.functions(), "", files) {
+        problems.push(("CfgFunctions".to_string(), message, name));
+    }
+
+    // function paths are checked above and mostly have no extension anyway
+    let mut strings: Vec<(String, &str)> = Vec::new();
+    collect_strings("", root, &["CfgFunctions"], &mut strings);
+    let files: Vec<String> = files.iter().map(|f| path_key(f)).collect();
+
+    for (path, value) in &strings {
+        let file = value.trim();
+        let extension = file.rsplit_once('.').map(|(_, e)| e.to_lowercase()).unwrap_or_default();
+        if PATH_EXTENSIONS.contains(&extension.as_str()) && !file.starts_with(['\\', '/', '@']) && !files.contains(&path_key(file)) {
+            problems.push((path.clone(), format!("File {} doesn't exist in the mission.", value), "mission-file-missing"));
+        }
+    }
+
+    for (path, value) in &strings {
+        let key = match value.get(..5) {
+            Some(start) if start.eq_ignore_ascii_case("$STR_") => value[1..].to_lowercase(),
+            _ => { continue; }
+        };
+        match stringtable {
+            Some(keys) if keys.contains(&key) => {},
+            Some(_) => problems.push((path.clone(), format!("Key {} doesn't exist in stringtable.xml.", &value[1..]), "mission-stringtable")),
+            None => problems.push((path.clone(), format!("Key {} is used, but there is no stringtable.xml.", &value[1..]), "mission-stringtable")),
+        }
+    }
+
+    problems
+}
+
+/// Lints the description.ext of the mission folder and raises the problems as warnings (see
+/// `check_description`). Also lists the automatic fixes of `cmd_lint` for it.
+pub fn cmd_mission_lint(folder: PathBuf, includefolders: &[PathBuf]) -> Result<(), Error> {
+    let path = folder.join("description.ext");
+    let mut source = String::new();
+    File::open(&path).prepend_error(format!("Failed to open {}:", path.display()))?.read_to_string(&mut source)?;
+
+    let (preprocessed, info) = preprocess_with(source, Some(path.clone()), includefolders, &PreprocessOptions::new(&[])).prepend_error("Failed to preprocess description.ext:")?;
+    let config = Config::from_preprocessed(&preprocessed, &info)?;
+
+    let files = Directory::new(folder.clone()).entries()?;
+    let stringtable = match files.iter().find(|f| f.eq_ignore_ascii_case("stringtable.xml")) {
+        Some(name) => {
+            let mut xml = String::new();
+            File::open(folder.join(name)).prepend_error("Failed to open stringtable.xml:")?.read_to_string(&mut xml)?;
+            Some(stringtable_keys(&xml))
+        },
+        None => None,
+    };
+
+    let starts = line_starts(&preprocessed);
+    let problems = check_description(&config, &files, stringtable.as_deref());
+    for (entry, msg, name) in &problems {
+        let span = config.span(entry);
+        if muted_at(&starts, &info, span, name) {
+            continue;
+        }
+        let file = span.and_then(|s| s.file.as_deref()).unwrap_or(&path).display().to_string();
+        warning(format!("{}: {}", entry, msg), Some(name), (Some(file), span.map(|s| s.line)));
+    }
+
+    let fixes = config.fixes(&preprocessed);
+    for fix in &fixes {
+        let (line, file) = origin(&starts, &info, fix.range.start);
+        println!("{}:{}: {} [{}]", file.as_ref().unwrap_or(&path).display(), line, fix.message, fix.name);
+    }
+
+    if problems.is_empty() && fixes.is_empty() {
+        eprintln!("No problems found in {}.", path.display());
+    }
+
+    Ok(())
+}
+
+/// Patterns of secrets that must not be released, with their descriptions
+const SECRET_PATTERNS: &[(&str, &str)] = &[
+    (r"-----BEGIN [A-Z ]*PRIVATE KEY-----", "PEM private key"),
+    (r"gh[pousr]_[A-Za-z0-9]{36}", "GitHub token"),
+    (r"AKIA[0-9A-Z]{16}", "AWS access key"),
+    (r"xox[abprs]-[0-9A-Za-z-]{10,}", "Slack token"),
+    (r"discord(?:app)?\.com/api/webhooks/[0-9]+/[A-Za-z0-9_-]+", "Discord webhook URL"),
+    (r#"(?i)(?:api_?key|apikey|secret|token|password)["']?\s*[=:,]\s*["'][A-Za-z0-9_+/=-]{16,}["']"#, "hardcoded credential"),
+    (r#"(?i)\b[a-z]:[\\/]+(?:users|documents and settings)[\\/]+([^\\/"'\s]+)"#, "absolute path exposing the user name"),
+    (r#"/(?:home|Users)/([^/"'\s]+)"#, "absolute path exposing the user name"),
+];
+
+/// Content of BI private keys after the name and length
+const PRIVATE_KEY_MAGIC: &[u8] = b"\x07\x02\x00\x00\x00\x24\x00\x00RSA2";
+
+/// Secret found in an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Secret {
+    pub entry: String,
+    /// line of the secret, `None` for binary files
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Scans all entries of the archive for BI private keys, API tokens and credentials, and absolute
+/// paths containing user names.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::archive::Archive;
+/// # use armake2::lint::scan_secrets;
+/// # use armake2::pbo::PBO;
+/// let mut pbo = PBO::from_directory(std::path::PathBuf::from("src"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+/// pbo.files.clear();
+/// pbo.write_entry("init.sqf", b"\n#include \"C:\\Users\\jdoe\\mod\\macros.hpp\"\n").unwrap();
+///
+/// let secrets = scan_secrets(&pbo).unwrap();
+/// assert_eq!(Some(2), secrets[0].line);
+/// assert_eq!("absolute path exposing the user name jdoe", secrets[0].message);
+/// ```
+pub fn scan_secrets(archive: &dyn Archive) -> Result<Vec<Secret>, Error> {
+    let patterns: Vec<(Regex, &str)> = SECRET_PATTERNS.iter().map(|(pattern, message)| (Regex::new(pattern).unwrap(), *message)).collect();
+    let mut secrets: Vec<Secret> = Vec::new();
+
+    for entry in archive.entries()? {
+        let data = match archive.read_entry(&entry)? {
+            Some(data) => data,
+            None => { continue; }
+        };
+        let binary = data.contains(&0);
+
+        if entry.to_lowercase().ends_with(".biprivatekey") || data.windows(PRIVATE_KEY_MAGIC.len()).any(|w| w == PRIVATE_KEY_MAGIC) {
+            secrets.push(Secret { entry: entry.clone(), line: None, message: "BI private key".to_string() });
+            continue;
+        }
+
+        for (regex, message) in &patterns {
+            for captures in regex.captures_iter(&data) {
+                let found = captures.get(0).unwrap();
+                let message = match captures.get(1) {
+                    Some(user) => format!("{} {}", message, String::from_utf8_lossy(user.as_bytes())),
+                    None => message.to_string(),
+                };
+                let line = if binary { None } else { Some(data[..found.start()].iter().filter(|b| **b == b'\n').count() + 1) };
+                secrets.push(Secret { entry: entry.clone(), line, message });
+            }
+        }
+    }
+
+    Ok(secrets)
+}
+
+/// Scans the addon folder or PBO at `path` for secrets and prints them. Fails if any are found.
+pub fn cmd_scan_secrets(path: PathBuf) -> Result<(), Error> {
+    let archive = open_archive(&path)?;
+    let secrets = scan_secrets(archive.as_ref())?;
+
+    for secret in &secrets {
+        match secret.line {
+            Some(line) => println!("{}:{}: {}", secret.entry, line, secret.message),
+            None => println!("{}: {}", secret.entry, secret.message),
+        }
+    }
+
+    if !secrets.is_empty() {
+        return Err(error!("Found {} possible secrets in {}.", secrets.len(), path.display()));
+    }
+
+    Ok(())
+}
+
+/// Extensions of the files searched for usages of stringtable keys
+const KEY_USAGE_EXTENSIONS: &[&str] = &["cpp", "hpp", "h", "inc", "ext", "sqm", "sqf", "sqs", "fsm"];
+
+/// Usage of a stringtable key in a config or script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyUsage {
+    /// Key named in full, like `$STR_abc_title` in configs or `localize "STR_abc_title"`
+    Key(String),
+    /// Name given to a CBA macro like `LSTRING(title)`, which expands to a key ending in `_title`
+    Suffix(String),
+}
+
+/// Finds the usages of stringtable keys in a config or script with their lines.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::lint::{key_usages, KeyUsage};
+/// let usages = key_usages("displayName = \"$STR_abc_car\";\nhint localize 'str_abc_hint';\ntooltip = CSTRING(tip);");
+/// assert_eq!(vec![
+///     (KeyUsage::Key("STR_abc_car".to_string()), 1),
+///     (KeyUsage::Key("str_abc_hint".to_string()), 2),
+///     (KeyUsage::Suffix("tip".to_string()), 3),
+/// ], usages);
+/// ```
+pub fn key_usages(text: &str) -> Vec<(KeyUsage, u32)> {
+    let regex = regex::Regex::new(r"(?i:\b(STR_[a-z0-9_]+))|\b[A-Z]*STRING\(\s*(?:[A-Za-z0-9_]+\s*,\s*)?([A-Za-z0-9_]+)\s*\)").unwrap();
+    let starts = line_starts(text);
+
+    regex.captures_iter(text).map(|captures| {
+        let usage = match captures.get(1) {
+            Some(key) => KeyUsage::Key(key.as_str().to_string()),
+            None => KeyUsage::Suffix(captures[2].to_string()),
+        };
+        let line = starts.partition_point(|start| *start <= captures.get(0).unwrap().start());
+        (usage, line as u32)
+    }).collect()
+}
+
+/// Returns the tag of the key, the part after `STR_` up to the next underscore, lowercase.
+fn key_tag(key: &str) -> String {
+    key.get(4..).unwrap_or("").split('_').next().unwrap_or("").to_lowercase()
+}
+
+/// Cross-references the keys of the stringtables in the addon or project folder or PBO with their
+/// usages in its configs and scripts. Returns the problems with the entries and lines they are
+/// in and the names of their warnings: `stringtable-unused-key` for keys used nowhere and
+/// `stringtable-undefined-key` for keys used but defined in no stringtable. Undefined keys whose
+/// tag (like `abc` of `STR_abc_title`) none of the defined keys have belong to the game or other
+/// mods and are ignored.
+pub fn check_key_usages(archive: &dyn Archive) -> Result<Vec<(String, u32, String, &'static str)>, Error> {
+    let mut keys: Vec<(String, u32, StringtableKey)> = Vec::new();
+    let mut usages: Vec<(String, u32, KeyUsage)> = Vec::new();
+
+    for entry in archive.entries()? {
+        let name = entry.rsplit('\\').next().unwrap_or(&entry).to_lowercase();
+        let extension = name.rsplit_once('.').map(|(_, e)| e).unwrap_or("");
+        if name != "stringtable.xml" && !KEY_USAGE_EXTENSIONS.contains(&extension) {
+            continue;
+        }
+
+        let data = match archive.read_entry(&entry)? {
+            Some(data) => data,
+            None => { continue; }
+        };
+        // rapified and binarized files can't be searched
+        if data.contains(&0) {
+            continue;
+        }
+        let text = String::from_utf8_lossy(&data);
+
+        if name == "stringtable.xml" {
+            let stringtable = Stringtable::parse(&text).prepend_error(format!("Failed to parse {}:", entry))?;
+            keys.extend(stringtable.keys.into_iter().map(|key| (entry.clone(), key.line, key)));
+        } else {
+            usages.extend(key_usages(&text).into_iter().map(|(usage, line)| (entry.clone(), line, usage)));
+        }
+    }
+
+    let ids: Vec<String> = keys.iter().map(|(_, _, key)| key.id.to_lowercase()).collect();
+    let tags: Vec<String> = ids.iter().map(|id| key_tag(id)).collect();
+    let mut problems: Vec<(String, u32, String, &'static str)> = Vec::new();
+
+    for ((entry, line, key), id) in keys.iter().zip(&ids) {
+        let used = usages.iter().any(|(_, _, usage)| match usage {
+            KeyUsage::Key(k) => k.eq_ignore_ascii_case(id),
+            KeyUsage::Suffix(suffix) => id.ends_with(&format!("_{}", suffix.to_lowercase())),
+        });
+        if !used {
+            problems.push((entry.clone(), *line, format!("Key {} is used nowhere.", key.id), "stringtable-unused-key"));
+        }
+    }
+
+    for (entry, line, usage) in &usages {
+        if let KeyUsage::Key(key) = usage {
+            if !ids.contains(&key.to_lowercase()) && tags.contains(&key_tag(key)) {
+                problems.push((entry.clone(), *line, format!("Key {} is defined in no stringtable.", key), "stringtable-undefined-key"));
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Checks the usages of stringtable keys in the addon or project folder or PBO at `path` and
+/// raises unused and undefined keys as warnings (see `check_key_usages`).
+pub fn cmd_unused_keys(path: PathBuf) -> Result<(), Error> {
+    let archive = open_archive(&path)?;
+    let problems = check_key_usages(archive.as_ref())?;
+
+    for (entry, line, msg, name) in &problems {
+        warning(msg.clone(), Some(name), (Some(entry.clone()), Some(*line)));
+    }
+
+    if problems.is_empty() {
+        eprintln!("No unused or undefined stringtable keys found in {}.", path.display());
+    }
+
+    Ok(())
+}
+
+/// Information about the addon a config is linted in, for rules that need more than the config
+#[derive(Debug, Clone, Default)]
+pub struct LintContext {
+    /// Lowercase keys of the stringtable of the addon, `None` if it has none
+    pub stringtable: Option<Vec<String>>,
+}
+
+/// Check of configs, run by `lint` and, unless it opts out, by `build`. Problems are raised as
+/// warnings with the names the rule returns them with, so they can be muted one by one with `-w`
+/// and `#pragma armake2 mute`, and muting the name of the rule skips it entirely.
+pub trait LintRule: Send + Sync {
+    /// Returns the name of the rule, also the name of its warning if it only has one.
+    fn name(&self) -> &'static str;
+
+    /// Returns a description of what the rule checks, for `lint --list-rules`.
+    fn description(&self) -> &'static str;
+
+    /// Returns whether `build` runs the rule as well, false for rules most addons don't follow.
+    fn in_build(&self) -> bool {
+        true
+    }
+
+    /// Returns the paths of the entries with problems (see `Config::spans`), the problems and
+    /// the names of their warnings.
+    fn check(&self, config: &Config, context: &LintContext) -> Vec<(String, String, &'static str)>;
+}
+
+/// Config classes of items placed in the editor or the arsenal, whose visibility depends on scope
+const SCOPED_CLASSES: &[&str] = &["CfgVehicles", "CfgWeapons", "CfgMagazines", "CfgGlasses"];
+
+/// Properties the engine reads as numbers
+const NUMERIC_PROPERTIES: &[&str] = &[
+    "scope", "scopeCurator", "scopeArsenal", "side", "requiredVersion", "armor", "mass", "cost",
+    "maxSpeed", "fuelCapacity", "transportSoldier", "hit", "indirectHit", "indirectHitRange",
+    "caliber", "initSpeed", "count", "reloadTime", "magazineReloadTime",
+];
+
+/// Classes in `CfgVehicles` and similar that are named in the editor but don't set `scope`
+struct MissingScope;
+
+impl LintRule for MissingScope {
+    fn name(&self) -> &'static str { "missing-scope" }
+
+    fn description(&self) -> &'static str {
+        "Classes of vehicles, weapons, magazines and glasses with a displayName but no scope, which inherit the visibility of their parent."
+    }
+
+    fn check(&self, config: &Config, _context: &LintContext) -> Vec<(String, String, &'static str)> {
+        let mut problems: Vec<(String, String, &'static str)> = Vec::new();
+        for (root_name, root) in config.root().classes().filter(|(name, _)| SCOPED_CLASSES.iter().any(|c| c.eq_ignore_ascii_case(name))) {
+            for (name, class) in root.classes().filter(|(_, c)| !c.is_external()) {
+                if class.get("displayName").is_some() && class.get("scope").is_none() {
+                    problems.push((format!("{}.{}", root_name, name), format!("{} has a displayName but no scope.", name), self.name()));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// Addons in `CfgPatches` without `author`
+struct MissingAuthor;
+
+impl LintRule for MissingAuthor {
+    fn name(&self) -> &'static str { "missing-author" }
+
+    fn description(&self) -> &'static str {
+        "Addons in CfgPatches without an author, which the launcher and the editor show."
+    }
+
+    fn check(&self, config: &Config, _context: &LintContext) -> Vec<(String, String, &'static str)> {
+        let mut problems: Vec<(String, String, &'static str)> = Vec::new();
+        for (root_name, patches) in config.root().classes().filter(|(name, _)| name.eq_ignore_ascii_case("CfgPatches")) {
+            for (name, addon) in patches.classes().filter(|(_, c)| !c.is_external()) {
+                match addon.get("author") {
+                    Some(ConfigEntry::StringEntry(author)) if !author.trim().is_empty() => {},
+                    _ => problems.push((format!("{}.{}", root_name, name), format!("Addon {} has no author.", name), self.name())),
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// Classes inheriting from themselves or from classes that aren't declared before them
+struct SuspiciousInheritance;
+
+impl SuspiciousInheritance {
+    /// Checks the subclasses of the class, `scopes` holds the lowercase names of the classes
+    /// declared before in the enclosing classes.
+    fn check_class(&self, path: &str, class: &ConfigClass, scopes: &mut Vec<Vec<String>>, problems: &mut Vec<(String, String, &'static str)>) {
+        scopes.push(Vec::new());
+        for (name, child) in class.classes() {
+            let child_path = if path.is_empty() { name.to_string() } else { format!("{}.{}", path, name) };
+            if let Some(parent) = child.parent() {
+                if parent.eq_ignore_ascii_case(name) {
+                    problems.push((child_path.clone(), format!("Class {} inherits from itself.", name), self.name()));
+                } else if !scopes.iter().flatten().any(|c| c.eq_ignore_ascii_case(parent)) {
+                    problems.push((child_path.clone(), format!("Class {} inherits from {}, which isn't declared before it.", name, parent), self.name()));
+                }
+            }
+
+            if !child.is_external() {
+                self.check_class(&child_path, child, scopes, problems);
+            }
+            scopes.last_mut().unwrap().push(name.to_lowercase());
+        }
+        scopes.pop();
+    }
+}
+
+impl LintRule for SuspiciousInheritance {
+    fn name(&self) -> &'static str { "suspicious-inheritance" }
+
+    fn description(&self) -> &'static str {
+        "Classes inheriting from themselves or from classes that aren't declared before them in the same or an enclosing class."
+    }
+
+    fn check(&self, config: &Config, _context: &LintContext) -> Vec<(String, String, &'static str)> {
+        let mut problems: Vec<(String, String, &'static str)> = Vec::new();
+        self.check_class("", config.root(), &mut Vec::new(), &mut problems);
+        problems
+    }
+}
+
+/// Entries defined more than once in the same class, of which the engine uses the last one
+struct DuplicateProperty;
+
+impl LintRule for DuplicateProperty {
+    fn name(&self) -> &'static str { "duplicate-property" }
+
+    fn description(&self) -> &'static str {
+        "Properties and classes defined more than once in the same class, only the last definition is used."
+    }
+
+    fn check(&self, config: &Config, _context: &LintContext) -> Vec<(String, String, &'static str)> {
+        config.duplicates().into_iter()
+            .map(|path| (path.clone(), format!("{} is defined multiple times.", path), self.name()))
+            .collect()
+    }
+}
+
+/// Properties the engine reads as numbers that are strings or arrays
+struct NonNumeric;
+
+impl NonNumeric {
+    fn check_class(&self, path: &str, class: &ConfigClass, problems: &mut Vec<(String, String, &'static str)>) {
+        for (name, entry) in class.entries() {
+            let entry_path = if path.is_empty() { name.to_string() } else { format!("{}.{}", path, name) };
+            let kind = match entry {
+                ConfigEntry::ClassEntry(c) => { self.check_class(&entry_path, c, problems); continue; },
+                ConfigEntry::StringEntry(_) => "a string",
+                ConfigEntry::ArrayEntry(_) => "an array",
+                _ => { continue; }
+            };
+            if NUMERIC_PROPERTIES.iter().any(|p| p.eq_ignore_ascii_case(name)) {
+                problems.push((entry_path, format!("{} is {}, but is read as a number.", name, kind), self.name()));
+            }
+        }
+    }
+}
+
+impl LintRule for NonNumeric {
+    fn name(&self) -> &'static str { "non-numeric" }
+
+    fn description(&self) -> &'static str {
+        "Properties like scope, side, mass and requiredVersion that the engine reads as numbers but are strings or arrays."
+    }
+
+    fn check(&self, config: &Config, _context: &LintContext) -> Vec<(String, String, &'static str)> {
+        let mut problems: Vec<(String, String, &'static str)> = Vec::new();
+        self.check_class("", config.root(), &mut problems);
+        problems
+    }
+}
+
+/// Eden editor attributes, see `check_3den`
+struct EdenAttributes;
+
+impl LintRule for EdenAttributes {
+    fn name(&self) -> &'static str { "3den" }
+
+    fn description(&self) -> &'static str {
+        "Attributes of Cfg3DEN the Eden editor crashes on instead of giving errors."
+    }
+
+    fn check(&self, config: &Config, _context: &LintContext) -> Vec<(String, String, &'static str)> {
+        check_3den(config)
+    }
+}
+
+/// Remote execution settings, see `check_remote_exec`
+struct RemoteExec;
+
+impl LintRule for RemoteExec {
+    fn name(&self) -> &'static str { "remote-exec" }
+
+    fn description(&self) -> &'static str {
+        "Settings of CfgRemoteExec that allow clients to execute arbitrary code."
+    }
+
+    fn check(&self, config: &Config, _context: &LintContext) -> Vec<(String, String, &'static str)> {
+        check_remote_exec(config)
+    }
+}
+
+/// Localization of user-facing strings, see `check_localization`
+struct Localization;
+
+impl LintRule for Localization {
+    fn name(&self) -> &'static str { "localization" }
+
+    fn description(&self) -> &'static str {
+        "User-facing strings like displayName that aren't stringtable keys, and keys missing in the stringtable."
+    }
+
+    fn in_build(&self) -> bool {
+        false
+    }
+
+    fn check(&self, config: &Config, context: &LintContext) -> Vec<(String, String, &'static str)> {
+        check_localization(config, context.stringtable.as_deref())
+    }
+}
+
+/// Rules run by `lint` and `build`, the built-in ones followed by those added with `register_rule`
+static RULES: Lazy<Mutex<Vec<Arc<dyn LintRule>>>> = Lazy::new(|| Mutex::new(vec![
+    Arc::new(MissingScope),
+    Arc::new(MissingAuthor),
+    Arc::new(SuspiciousInheritance),
+    Arc::new(DuplicateProperty),
+    Arc::new(NonNumeric),
+    Arc::new(EdenAttributes),
+    Arc::new(RemoteExec),
+    Arc::new(Localization),
+]));
+
+/// Adds a rule to those run by `lint` and `build`.
+pub fn register_rule(rule: Arc<dyn LintRule>) {
+    RULES.lock().unwrap().push(rule);
+}
+
+/// Returns the rules run by `lint` and `build`.
+pub fn rules() -> Vec<Arc<dyn LintRule>> {
+    RULES.lock().unwrap().clone()
+}
+
+/// Runs the rules on the config, only those also run by `build` if `build` is set, and returns
+/// their problems. Rules whose names are muted are skipped.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::Config;
+/// # use armake2::lint::{run_rules, LintContext};
+/// let input = String::from("class CfgPatches { class abc_main { units[] = {}; requiredVersion = \"2.0\"; }; };
+/// class CfgVehicles { class Car; class abc_car: Car { displayName = \"Car\"; }; class abc_truck: Truck { scope = 2; }; };");
+/// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+///
+/// let problems = run_rules(&config, &LintContext::default(), true);
+/// let names: Vec<&str> = problems.iter().map(|(_, _, name)| *name).collect();
+/// assert_eq!(vec!["missing-scope", "missing-author", "suspicious-inheritance", "non-numeric"], names);
+/// ```
+pub fn run_rules(config: &Config, context: &LintContext, build: bool) -> Vec<(String, String, &'static str)> {
+    rules().iter()
+        .filter(|rule| (rule.in_build() || !build) && !warning_suppressed(Some(rule.name())))
+        .flat_map(|rule| rule.check(config, context))
+        .collect()
+}
+
+/// Prints the names and descriptions of the rules.
+pub fn cmd_list_rules() -> Result<(), Error> {
+    for rule in rules() {
+        let scope = if rule.in_build() { "" } else { " (lint only)" };
+        println!("{}{}\n    {}", rule.name(), scope, rule.description());
+    }
+    Ok(())
+}
+
+/// Lints the config at `path` and prints the problems that can be fixed automatically. With
+/// `fix`, the fixes are applied to the file and the changed lines are printed. The problems the
+/// rules (see `rules`) find are raised as warnings, checking localization against the
+/// stringtable of the addon.
+pub fn cmd_lint(path: PathBuf, includefolders: &[PathBuf], fix: bool) -> Result<(), Error> {
+    let mut source = String::new();
+    File::open(&path).prepend_error(format!("Failed to open {}:", path.display()))?.read_to_string(&mut source)?;
+
+    let (preprocessed, info) = preprocess_with(source.clone(), Some(path.clone()), includefolders, &PreprocessOptions::new(&[])).prepend_error("Failed to preprocess config:")?;
+    let config = Config::from_preprocessed(&preprocessed, &info)?;
+    let fixes = config.fixes(&preprocessed);
+    let starts = line_starts(&preprocessed);
+
+    let context = LintContext { stringtable: find_stringtable(&path)? };
+    for (entry, msg, name) in run_rules(&config, &context, false) {
+        let span = config.span(&entry);
+        if muted_at(&starts, &info, span, name) {
+            continue;
+        }
+        let file = span.and_then(|s| s.file.as_deref()).unwrap_or(&path).display().to_string();
+        warning(format!("{}: {}", entry, msg), Some(name), (Some(file), span.map(|s| s.line)));
+    }
+
+    if !fix {
+        for fix in &fixes {
+            let (line, file) = origin(&starts, &info, fix.range.start);
+            println!("{}:{}: {} [{}]", file.as_ref().unwrap_or(&path).display(), line, fix.message, fix.name);
+        }
+        if !fixes.is_empty() {
+            eprintln!("{} problems can be fixed automatically with --fix.", fixes.len());
+        }
+        return Ok(());
+    }
+
+    let (fixed, applied) = apply_fixes(&source, Some(&path), &preprocessed, &info, &fixes);
+    if applied.is_empty() {
+        eprintln!("Nothing to fix in {}.", path.display());
+        return Ok(());
+    }
+
+    // fixes never add or remove lines
+    for (i, (before, after)) in source.lines().zip(fixed.lines()).enumerate() {
+        if before != after {
+            println!("{}:{}", path.display(), i + 1);
+            println!("{}", format!("-{}", before).red());
+            println!("{}", format!("+{}", after).green());
+        }
+    }
+
+    // the source is only replaced once the fixed file is completely written
+    let permissions = path.metadata().map(|m| m.permissions()).ok();
+    let mut file = AtomicFile::create(&path).prepend_error(format!("Failed to write {}:", path.display()))?;
+    file.write_all(fixed.as_bytes()).prepend_error(format!("Failed to write {}:", path.display()))?;
+    file.commit().prepend_error(format!("Failed to write {}:", path.display()))?;
+    if let Some(permissions) = permissions {
+        std::fs::set_permissions(&path, permissions).prepend_error(format!("Failed to write {}:", path.display()))?;
+    }
+
+    if applied.len() < fixes.len() {
+        eprintln!("Fixed {} of {} problems in {}, the others are in macros or included files.", applied.len(), fixes.len(), path.display());
+    } else {
+        eprintln!("Fixed {} problems in {}.", applied.len(), path.display());
+    }
+
+    Ok(())
+}
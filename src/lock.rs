@@ -0,0 +1,145 @@
+//! Lock file recording the content of the dependency PBOs a build includes headers from
+
+use std::collections::{BTreeMap};
+use std::fs::{File};
+use std::io::{Read, Write, BufRead, BufReader, Error, copy};
+use std::path::{Path, PathBuf};
+
+use openssl::hash::{Hasher, MessageDigest};
+
+use crate::error::*;
+use crate::io::AtomicFile;
+use crate::preprocess::dependency_pbos;
+
+/// Name of the lock file, read from and written to the current folder
+pub const LOCK_FILE: &str = "armake.lock";
+
+/// SHA-1 hashes of dependency PBOs by lowercase file name
+///
+/// PBOs are identified by their file name only, so the lock is the same on every machine no
+/// matter where the dependency mods are installed.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::{Cursor};
+/// # use armake2::lock::DependencyLock;
+/// let locked = DependencyLock::read(&mut Cursor::new("# comment\nda39a3ee5e6b4b0d3255bfef95601890afd80709  cba_main.pbo\n")).unwrap();
+/// let current = DependencyLock::default();
+///
+/// assert_eq!(vec!["cba_main.pbo is missing".to_string()], current.differences(&locked));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependencyLock {
+    pub hashes: BTreeMap<String, String>,
+}
+
+fn hash_file(path: &Path) -> Result<String, Error> {
+    let mut hasher = Hasher::new(MessageDigest::sha1())?;
+    copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(hasher.finish()?.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+impl DependencyLock {
+    /// Hashes the dependency PBOs among the include folders.
+    pub fn from_includefolders(includefolders: &[PathBuf]) -> Result<DependencyLock, Error> {
+        let mut hashes: BTreeMap<String, String> = BTreeMap::new();
+
+        for pbo in dependency_pbos(includefolders)? {
+            let name = pbo.file_name().unwrap().to_string_lossy().to_lowercase();
+            let hash = hash_file(&pbo).prepend_error(format!("Failed to hash {}:", pbo.display()))?;
+            if hashes.insert(name.clone(), hash).is_some() {
+                warning(format!("Dependency {} exists in multiple include folders, locking the one from {}.", name, pbo.display()), Some("duplicate-dependency"), (None, None));
+            }
+        }
+
+        Ok(DependencyLock { hashes })
+    }
+
+    /// Reads a lock file with a hash and a file name per line, like the output of `sha1sum`.
+    pub fn read<I: Read>(input: &mut I) -> Result<DependencyLock, Error> {
+        let mut hashes: BTreeMap<String, String> = BTreeMap::new();
+
+        for line in BufReader::new(input).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            match line.split_once(char::is_whitespace) {
+                Some((hash, name)) => { hashes.insert(name.trim().to_lowercase(), hash.to_lowercase()); },
+                None => { return Err(error!("Invalid line in lock file: {}", line)); }
+            }
+        }
+
+        Ok(DependencyLock { hashes })
+    }
+
+    /// Writes the lock file.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        output.write_all(b"# Hashes of the dependency PBOs used for includes, generated by armake2.\n")?;
+        output.write_all(b"# Delete this file to lock the current versions.\n")?;
+        for (name, hash) in &self.hashes {
+            output.write_all(format!("{}  {}\n", hash, name).as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns a description of every dependency that was added, changed or removed compared to
+    /// the locked dependencies.
+    pub fn differences(&self, locked: &DependencyLock) -> Vec<String> {
+        let mut differences: Vec<String> = Vec::new();
+
+        for (name, hash) in &self.hashes {
+            match locked.hashes.get(name) {
+                Some(locked_hash) if locked_hash != hash => differences.push(format!("{} changed", name)),
+                None => differences.push(format!("{} is not locked", name)),
+                _ => {}
+            }
+        }
+        for name in locked.hashes.keys() {
+            if !self.hashes.contains_key(name) {
+                differences.push(format!("{} is missing", name));
+            }
+        }
+
+        differences
+    }
+}
+
+/// Checks the dependency PBOs among the include folders against the lock file at `path`.
+///
+/// If there is no lock file yet, it is written with the current dependencies. Changed
+/// dependencies raise warnings, or fail the build if `locked` is true.
+pub fn check_dependency_lock(path: &Path, includefolders: &[PathBuf], locked: bool) -> Result<(), Error> {
+    let current = DependencyLock::from_includefolders(includefolders)?;
+
+    if !path.exists() {
+        if locked {
+            return Err(error!("{} doesn't exist.", path.display()));
+        }
+        if !current.hashes.is_empty() {
+            let mut file = AtomicFile::create(path).prepend_error("Failed to write lock file:")?;
+            current.write(&mut file)?;
+            file.commit().prepend_error("Failed to write lock file:")?;
+            eprintln!("Locked {} dependencies in {}.", current.hashes.len(), path.display());
+        }
+        return Ok(());
+    }
+
+    let previous = DependencyLock::read(&mut File::open(path)?).prepend_error("Failed to read lock file:")?;
+    let differences = current.differences(&previous);
+    if differences.is_empty() {
+        return Ok(());
+    }
+
+    if locked {
+        return Err(error!("Dependencies don't match {}:\n    {}", path.display(), differences.join("\n    ")));
+    }
+    for difference in differences {
+        warning(format!("Dependencies don't match {}: {}.", path.display(), difference), Some("dependency-changed"), (None, None));
+    }
+
+    Ok(())
+}
@@ -4,8 +4,26 @@ use docopt::Docopt;
 use crate::run::{USAGE, Args};
 
 fn main() {
-    let mut args: Args = Docopt::new(USAGE)
-                            .and_then(|d| d.deserialize())
-                            .unwrap_or_else(|e| e.exit());
+    let argv: Vec<String> = std::env::args().collect();
+    if argv.len() == 2 && argv[1].starts_with('@') {
+        armake2::run::batch(&argv[1][1..]);
+        return;
+    }
+
+    let mut args: Args = if argv.get(1).map(|a| a == "--compat-v1").unwrap_or(false) {
+        let mut v1_argv = argv.clone();
+        v1_argv.remove(1);
+        let translated = armake2::run::translate_v1_arguments(&v1_argv).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        Docopt::new(USAGE)
+            .and_then(|d| d.argv(translated).deserialize())
+            .unwrap_or_else(|e| e.exit())
+    } else {
+        Docopt::new(USAGE)
+            .and_then(|d| d.deserialize())
+            .unwrap_or_else(|e| e.exit())
+    };
     armake2::run::args(&mut args);
 }
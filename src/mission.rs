@@ -0,0 +1,447 @@
+//! Addons and classes missions and compositions depend on, and checks of campaigns
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File};
+use std::io::{Read, Cursor, Error};
+use std::path::{Component, Path, PathBuf};
+
+use regex::{Regex};
+
+use crate::archive::{Archive, Directory};
+use crate::config::{Config, ConfigArrayElement, ConfigClass, ConfigEntry};
+use crate::error::*;
+use crate::pbo::{FileType, PBOReader, list_files};
+use crate::paths::{to_fs_path};
+use crate::preprocess::{dependency_pbos, preprocess_with, PreprocessOptions};
+
+/// Addons and object classes used by a mission.sqm or a composition.sqe
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::Config;
+/// # use armake2::mission::MissionDependencies;
+/// let input = String::from("version = 53; addons[] = {\"A3_Characters_F\"};
+///     class Mission { class Entities { items = 1; class Item0 { dataType = \"Group\"; class Entities { items = 2;
+///         class Item0 { dataType = \"Object\"; type = \"B_Soldier_F\"; };
+///         class Item1 { dataType = \"Object\"; type = \"B_Soldier_F\"; };
+///     }; }; }; };");
+/// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+///
+/// let dependencies = MissionDependencies::from_config(&config);
+/// assert_eq!(vec!["A3_Characters_F"], dependencies.addons);
+/// assert_eq!(Some(&2), dependencies.classes.get("B_Soldier_F"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MissionDependencies {
+    /// addons listed as required, which the game checks when loading the mission
+    pub addons: Vec<String>,
+    /// object classes with the number of times they are placed
+    pub classes: BTreeMap<String, usize>,
+}
+
+/// Addons available to a mission with the classes they add
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AvailableAddons {
+    /// `CfgPatches` classes by lowercase name
+    pub patches: HashMap<String, String>,
+    /// addon declaring each lowercase class in its `units[]`
+    pub units: HashMap<String, String>,
+}
+
+fn strings(entry: Option<&ConfigEntry>) -> Vec<String> {
+    match entry {
+        Some(ConfigEntry::ArrayEntry(a)) => a.elements().filter_map(|e| match e {
+            ConfigArrayElement::StringElement(s) => Some(s.to_string()),
+            _ => None,
+        }).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn collect_classes(class: &ConfigClass, classes: &mut BTreeMap<String, usize>) {
+    // objects and logics of the Eden editor, and vehicles and units of the 2D editor
+    let object = match class.string("dataType") {
+        Some(data_type) if data_type.eq_ignore_ascii_case("Object") || data_type.eq_ignore_ascii_case("Logic") => class.string("type"),
+        Some(_) => None,
+        None => class.string("vehicle"),
+    };
+    // class names are case-insensitive, the first spelling is kept
+    if let Some(object) = object {
+        let name = classes.keys().find(|c| c.eq_ignore_ascii_case(object)).cloned().unwrap_or_else(|| object.to_string());
+        *classes.entry(name).or_insert(0) += 1;
+    }
+
+    for (_, subclass) in class.classes() {
+        collect_classes(subclass, classes);
+    }
+}
+
+impl MissionDependencies {
+    /// Collects the required addons and placed objects of a mission.sqm or composition.sqe.
+    pub fn from_config(config: &Config) -> MissionDependencies {
+        let root = config.root();
+        let mut addons: Vec<String> = Vec::new();
+
+        // Eden lists the addons at the root, the 2D editor in the Mission class
+        let mut lists: Vec<&ConfigClass> = vec![root];
+        if let Some(ConfigEntry::ClassEntry(mission)) = root.get("Mission") {
+            lists.push(mission);
+        }
+        for class in lists {
+            for addon in strings(class.get("addons")).into_iter().chain(strings(class.get("addOnsAuto"))) {
+                if !addons.iter().any(|a| a.eq_ignore_ascii_case(&addon)) {
+                    addons.push(addon);
+                }
+            }
+        }
+
+        let mut classes: BTreeMap<String, usize> = BTreeMap::new();
+        collect_classes(root, &mut classes);
+
+        MissionDependencies {
+            addons,
+            classes,
+        }
+    }
+
+    /// Reads a mission.sqm or composition.sqe, binarized or as text.
+    pub fn read(path: &Path) -> Result<MissionDependencies, Error> {
+        let mut data: Vec<u8> = Vec::new();
+        File::open(path).prepend_error(format!("Failed to open {}:", path.display()))?.read_to_end(&mut data)?;
+
+        let config = if FileType::detect(&data) == FileType::Config {
+            Config::read_rapified(&mut Cursor::new(data))
+        } else {
+            Config::read(&mut Cursor::new(data), Some(path.to_path_buf()), &[])
+        }.prepend_error(format!("Failed to read {}:", path.display()))?;
+
+        Ok(MissionDependencies::from_config(&config))
+    }
+}
+
+impl AvailableAddons {
+    /// Adds the `CfgPatches` classes of the config.
+    pub fn add_config(&mut self, config: &Config) {
+        let patches = match config.root().get("CfgPatches") {
+            Some(ConfigEntry::ClassEntry(patches)) => patches,
+            _ => { return; }
+        };
+
+        for (name, patch) in patches.classes() {
+            self.patches.insert(name.to_lowercase(), name.to_string());
+            for unit in strings(patch.get("units")) {
+                self.units.entry(unit.to_lowercase()).or_insert_with(|| name.to_string());
+            }
+        }
+    }
+
+    /// Reads the configs of the PBOs.
+    pub fn from_pbos(pbos: &[PathBuf]) -> Result<AvailableAddons, Error> {
+        let mut available = AvailableAddons::default();
+
+        for path in pbos {
+            let mut reader = PBOReader::new(File::open(path)?).prepend_error(format!("Failed to read {}:", path.display()))?;
+            for index in 0..reader.entries.len() {
+                if !reader.entries[index].name.to_lowercase().ends_with("config.bin") { continue; }
+
+                let data = reader.read_entry_at(index)?;
+                match Config::read_rapified(&mut Cursor::new(data)) {
+                    Ok(config) => available.add_config(&config),
+                    Err(e) => warning(format!("Failed to read {} of {}: {}", reader.entries[index].name, path.display(), e), Some("addon-config"), (None, None)),
+                }
+            }
+        }
+
+        Ok(available)
+    }
+}
+
+/// Lists the addons and classes the mission (a mission.sqm, composition.sqe or mission folder)
+/// depends on and the dependency PBOs among the include folders providing them. Fails if
+/// required addons are missing, which players would get as an error when loading the mission.
+pub fn cmd_mission_classes(path: PathBuf, includefolders: &[PathBuf]) -> Result<(), Error> {
+    let path = if path.is_dir() { path.join("mission.sqm") } else { path };
+    let dependencies = MissionDependencies::read(&path)?;
+
+    let pbos = dependency_pbos(includefolders)?;
+    if pbos.is_empty() {
+        warning("No dependency PBOs given with -i, classes and addons can't be checked.", Some("mission-no-addons"), (None, None));
+    }
+    let available = AvailableAddons::from_pbos(&pbos)?;
+    let checked = !pbos.is_empty();
+
+    let mut missing: Vec<&String> = Vec::new();
+    println!("Required addons:");
+    for addon in &dependencies.addons {
+        let found = available.patches.contains_key(&addon.to_lowercase());
+        if checked && !found {
+            missing.push(addon);
+        }
+        println!("- {}{}", addon, if checked && !found { "  missing" } else { "" });
+    }
+
+    println!("\nClass                                              Count  Addon");
+    println!("================================================================================");
+    for (class, count) in &dependencies.classes {
+        let addon = match available.units.get(&class.to_lowercase()) {
+            Some(addon) => addon.as_str(),
+            None if checked => {
+                warning(format!("Class {} isn't in the units of any addon.", class), Some("mission-unknown-class"), (Some(path.display().to_string()), None));
+                "unknown"
+            },
+            None => "",
+        };
+        println!("{:50} {:5}  {}", class, count, addon);
+    }
+
+    if !missing.is_empty() {
+        let missing: Vec<String> = missing.iter().map(|a| a.to_string()).collect();
+        return Err(error!("{} requires {} missing addons: {}", path.display(), missing.len(), missing.join(", ")));
+    }
+
+    Ok(())
+}
+
+/// Entries of missions and chapters naming the mission or chapter that follows
+const CAMPAIGN_ENDS: &[&str] = &["end1", "end2", "end3", "end4", "end5", "end6", "lost"];
+
+/// Returns the entry of the class or the classes it inherits from. Parents are looked up in
+/// `scopes`, the innermost first.
+fn inherited<'a>(class: &'a ConfigClass, name: &str, scopes: &[&'a ConfigClass]) -> Option<&'a ConfigEntry> {
+    let mut class = class;
+    // limits the depth of cyclic inheritance
+    for _ in 0..32 {
+        if let Some(entry) = class.get(name) {
+            return Some(entry);
+        }
+        let parent = class.parent()?;
+        class = scopes.iter().find_map(|scope| match scope.get(parent) {
+            Some(ConfigEntry::ClassEntry(c)) => Some(c),
+            _ => None,
+        })?;
+    }
+
+    None
+}
+
+fn inherited_string<'a>(class: &'a ConfigClass, name: &str, scopes: &[&'a ConfigClass]) -> Option<&'a str> {
+    match inherited(class, name, scopes) {
+        Some(ConfigEntry::StringEntry(s)) => Some(&**s),
+        _ => None,
+    }
+}
+
+/// Checks the mission chain of a campaign description.ext: the first chapter, the first mission
+/// of every chapter and the chapters and missions the ends of chapters and missions lead to
+/// have to exist, and every mission needs a folder with a mission.sqm in `missions\`. `files`
+/// are the entries of the campaign folder (see `Archive`). Returns the paths of the entries (see
+/// `Config::spans`) with the problems and the names of their warnings, nothing if the config
+/// doesn't describe a campaign.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::Config;
+/// # use armake2::mission::check_campaign;
+/// let input = String::from("class Campaign { firstBattle = \"Chapter1\";
+///     class MissionDefault { end1 = \"\"; lost = \"\"; };
+///     class Chapter1 { firstMission = \"M01\"; end1 = \"\";
+///         class M01: MissionDefault { end1 = \"M02\"; template = \"M01.Stratis\"; };
+///         class M02: MissionDefault { template = \"M02.Stratis\"; };
+///     };
+/// };");
+/// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+/// let files = vec!["missions\\M01.Stratis\\mission.sqm".to_string()];
+///
+/// let problems = check_campaign(&config, &files);
+/// assert_eq!(1, problems.len());
+/// assert_eq!(("Campaign.Chapter1.M02", "campaign-mission-missing"), (problems[0].0.as_str(), problems[0].2));
+/// ```
+pub fn check_campaign(config: &Config, files: &[String]) -> Vec<(String, String, &'static str)> {
+    let mut problems: Vec<(String, String, &'static str)> = Vec::new();
+    let (campaign_name, campaign) = match config.root().classes().find(|(name, _)| name.eq_ignore_ascii_case("Campaign")) {
+        Some(campaign) => campaign,
+        None => { return problems; }
+    };
+    let files: Vec<String> = files.iter().map(|f| f.to_lowercase()).collect();
+
+    // classes without a first mission are defaults other classes inherit from
+    let chapters: Vec<(&str, &ConfigClass)> = campaign.classes()
+        .filter(|(_, chapter)| inherited_string(chapter, "firstMission", &[campaign]).is_some())
+        .collect();
+    let is_chapter = |name: &str| chapters.iter().any(|(n, _)| n.eq_ignore_ascii_case(name));
+
+    match campaign.string("firstBattle") {
+        Some(first) if is_chapter(first) => {},
+        Some(first) => problems.push((format!("{}.firstBattle", campaign_name), format!("First chapter {} doesn't exist.", first), "campaign-chain")),
+        None => problems.push((campaign_name.to_string(), "Campaign has no firstBattle.".to_string(), "campaign-chain")),
+    }
+
+    for (chapter_name, chapter) in &chapters {
+        let chapter_path = format!("{}.{}", campaign_name, chapter_name);
+        let scopes = [*chapter, campaign];
+
+        for end in CAMPAIGN_ENDS {
+            match inherited_string(chapter, end, &[campaign]) {
+                Some(next) if !next.is_empty() && !is_chapter(next) => {
+                    problems.push((format!("{}.{}", chapter_path, end), format!("Chapter {} doesn't exist.", next), "campaign-chain"));
+                },
+                _ => {}
+            }
+        }
+
+        let missions: Vec<(&str, &ConfigClass)> = chapter.classes()
+            .filter(|(_, mission)| inherited_string(mission, "template", &scopes).is_some())
+            .collect();
+        let mission_index = |name: &str| missions.iter().position(|(n, _)| n.eq_ignore_ascii_case(name));
+
+        let first = inherited_string(chapter, "firstMission", &[campaign]).unwrap();
+        let mut reachable: Vec<usize> = Vec::new();
+        match mission_index(first) {
+            Some(index) => reachable.push(index),
+            None => problems.push((chapter_path.clone(), format!("First mission {} doesn't exist.", first), "campaign-chain")),
+        }
+
+        for (mission_name, mission) in &missions {
+            let mission_path = format!("{}.{}", chapter_path, mission_name);
+
+            let template = inherited_string(mission, "template", &scopes).unwrap();
+            let sqm = format!("missions\\{}\\mission.sqm", template).to_lowercase();
+            if template.is_empty() || !files.contains(&sqm) {
+                problems.push((mission_path.clone(), format!("Mission folder missions\\{} doesn't contain a mission.sqm.", template), "campaign-mission-missing"));
+            }
+
+            for end in CAMPAIGN_ENDS {
+                match inherited_string(mission, end, &scopes) {
+                    Some(next) if !next.is_empty() && mission_index(next).is_none() => {
+                        problems.push((format!("{}.{}", mission_path, end), format!("Mission {} doesn't exist in chapter {}.", next, chapter_name), "campaign-chain"));
+                    },
+                    _ => {}
+                }
+            }
+        }
+
+        // missions are only played if an end of a played mission leads to them
+        let mut i = 0;
+        while i < reachable.len() {
+            let (_, mission) = missions[reachable[i]];
+            for end in CAMPAIGN_ENDS {
+                if let Some(next) = inherited_string(mission, end, &scopes).and_then(&mission_index) {
+                    if !reachable.contains(&next) {
+                        reachable.push(next);
+                    }
+                }
+            }
+            i += 1;
+        }
+        if !reachable.is_empty() {
+            for (_, (mission_name, _)) in missions.iter().enumerate().filter(|(index, _)| !reachable.contains(index)) {
+                problems.push((format!("{}.{}", chapter_path, mission_name), format!("Mission {} can't be reached from {}.", mission_name, first), "campaign-unreachable"));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Resolves `.` and `..` in the path without accessing the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => { normalized.pop(); },
+            c => normalized.push(c),
+        }
+    }
+
+    normalized
+}
+
+/// Checks the relative includes of the text files in the campaign folder. Includes shared by
+/// several missions have to be inside the campaign folder too, otherwise they aren't packed.
+/// Returns the files with the problems.
+pub fn check_campaign_includes(folder: &Path) -> Result<Vec<(String, String)>, Error> {
+    let include = Regex::new(r#"(?m)^\s*#\s*include\s*["<]([^">]+)[">]"#).unwrap();
+    let root = normalize_path(folder);
+    let mut problems: Vec<(String, String)> = Vec::new();
+
+    let mut files = list_files(&folder.to_path_buf())?;
+    files.sort();
+    for path in files {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if !["ext", "hpp", "h", "inc", "sqf", "sqm", "cpp"].contains(&extension.as_str()) { continue; }
+
+        let mut content: Vec<u8> = Vec::new();
+        File::open(&path)?.read_to_end(&mut content)?;
+        let content = String::from_utf8_lossy(&content);
+        let relative = path.strip_prefix(folder).unwrap().display().to_string();
+
+        for captures in include.captures_iter(&content) {
+            let included = &captures[1];
+            // absolute includes are resolved in the loaded addons
+            if included.starts_with('\\') { continue; }
+
+            let resolved = normalize_path(&path.parent().unwrap().join(to_fs_path(included)));
+            if !resolved.starts_with(&root) {
+                problems.push((relative.clone(), format!("Included file {} is outside the campaign folder and isn't packed.", included)));
+            } else if !resolved.is_file() {
+                problems.push((relative.clone(), format!("Included file {} doesn't exist.", included)));
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Checks the campaigns in the addon folder: every folder with a description.ext describing a
+/// campaign (see `check_campaign` and `check_campaign_includes`). Unreachable missions raise
+/// warnings, all other problems fail with an error listing them.
+pub fn check_campaigns(source: &Path, includefolders: &[PathBuf], defines: &[(String, String)]) -> Result<(), Error> {
+    let mut errors: Vec<String> = Vec::new();
+    let campaign = Regex::new(r"(?i)\bclass\s+Campaign\b").unwrap();
+    let empty_value = Regex::new(r"(\w\s*)=(\s*);").unwrap();
+
+    let mut descriptions: Vec<PathBuf> = list_files(&source.to_path_buf())?.into_iter()
+        .filter(|p| p.file_name().map(|n| n.eq_ignore_ascii_case("description.ext")).unwrap_or(false))
+        .collect();
+    descriptions.sort();
+
+    for path in descriptions {
+        let mut content = String::new();
+        File::open(&path)?.read_to_string(&mut content)?;
+        if !campaign.is_match(&content) { continue; }
+
+        let folder = path.parent().unwrap();
+        let (preprocessed, info) = preprocess_with(content, Some(path.clone()), includefolders, &PreprocessOptions::new(defines))
+            .prepend_error(format!("Failed to preprocess {}:", path.display()))?;
+        // campaigns end chains with empty values like "end1 = ;", the game reads them as empty strings
+        let preprocessed = empty_value.replace_all(&preprocessed, "${1}=${2}\"\";").to_string();
+        let config = Config::from_preprocessed(&preprocessed, &info).prepend_error(format!("Failed to read {}:", path.display()))?;
+        let files = Directory::new(folder.to_path_buf()).entries()?;
+
+        for (entry, msg, name) in check_campaign(&config, &files) {
+            let span = config.span(&entry);
+            let file = span.and_then(|s| s.file.as_deref()).unwrap_or(&path).display().to_string();
+            if name == "campaign-unreachable" {
+                warning(format!("{}: {}", entry, msg), Some(name), (Some(file), span.map(|s| s.line)));
+            } else {
+                match span {
+                    Some(span) => errors.push(format!("{}:{}: {}: {}", file, span.line, entry, msg)),
+                    None => errors.push(format!("{}: {}: {}", file, entry, msg)),
+                }
+            }
+        }
+
+        for (file, msg) in check_campaign_includes(folder)? {
+            errors.push(format!("{}: {}", folder.join(file).display(), msg));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(error!("Campaign is invalid:\n    {}", errors.join("\n    ")));
+    }
+
+    Ok(())
+}
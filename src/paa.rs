@@ -0,0 +1,1393 @@
+//! Functions for reading and writing PAA textures and converting them to and from DDS and TGA
+
+use std::cmp::{max};
+use std::io::{Read, Write, Error, Cursor};
+use std::path::{PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use linked_hash_map::{LinkedHashMap};
+
+use crate::error::*;
+use crate::io::{WriteExt, lzss_decompress};
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+const DDSD_LINEARSIZE: u32 = 0x8_0000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x2_0000;
+
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x40_0000;
+
+/// PAA texture format
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PAAType {
+    /// DXT1 compressed
+    DXT1,
+    /// DXT2 compressed
+    DXT2,
+    /// DXT3 compressed
+    DXT3,
+    /// DXT4 compressed
+    DXT4,
+    /// DXT5 compressed
+    DXT5,
+    /// 16 bit ARGB with 4 bits per channel
+    ARGB4444,
+    /// 16 bit ARGB with 1 bit alpha and 5 bits per color channel
+    ARGB1555,
+    /// 32 bit ARGB
+    ARGB8888,
+    /// 8 bit grayscale with 8 bit alpha
+    AI88,
+}
+
+/// PAA mipmap
+pub struct MipMap {
+    /// Width in pixels
+    pub width: u16,
+    /// Height in pixels
+    pub height: u16,
+    /// Uncompressed pixel data in the format of the texture
+    pub data: Vec<u8>,
+}
+
+/// Uncompressed RGBA image
+#[derive(Clone)]
+pub struct Image {
+    /// Width in pixels
+    pub width: u16,
+    /// Height in pixels
+    pub height: u16,
+    /// RGBA pixels, row by row
+    pub data: Vec<[u8; 4]>,
+}
+
+/// Source of a channel in a swizzled texture, as stored in the `SWIZ` tagg
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelSwizzle {
+    /// Alpha channel
+    Alpha,
+    /// Red channel
+    Red,
+    /// Green channel
+    Green,
+    /// Blue channel
+    Blue,
+    /// Inverted alpha channel
+    InvAlpha,
+    /// Inverted red channel
+    InvRed,
+    /// Inverted green channel
+    InvGreen,
+    /// Inverted blue channel
+    InvBlue,
+    /// Constant 1
+    One,
+}
+
+/// Swizzle of the alpha, red, green and blue channels the engine expects for `_nohq` normal maps.
+/// X is moved to the alpha channel to get a separately compressed DXT5 alpha block.
+pub const NORMALMAP_SWIZZLE: [ChannelSwizzle; 4] = [ChannelSwizzle::Red, ChannelSwizzle::One, ChannelSwizzle::Green, ChannelSwizzle::One];
+
+/// Filter used when generating mipmaps
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MipFilter {
+    /// 2x2 box filter
+    Default,
+    /// 2x2 box filter, renormalizing the normals encoded in RGB afterwards
+    NormalizeNormalMap,
+    /// 2x2 box filter, scaling alpha so the share of pixels passing the alpha test stays the
+    /// same as in the largest mipmap
+    AlphaCoverage,
+}
+
+/// Alpha test reference value used by the engine for alpha coverage
+pub const ALPHA_REFERENCE: u8 = 128;
+
+/// PAA texture
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Cursor;
+/// # use armake2::paa::{PAA, PAAType, MipMap};
+/// let mut paa = PAA::new(PAAType::DXT1);
+/// paa.mipmaps.push(MipMap { width: 4, height: 4, data: vec![0xff, 0xff, 0, 0, 0, 0, 0, 0] });
+///
+/// let mut dds: Vec<u8> = Vec::new();
+/// paa.write_dds(&mut dds).unwrap();
+/// assert_eq!(b"DDS ", &dds[..4]);
+///
+/// let reread = PAA::read_dds(&mut Cursor::new(dds)).unwrap();
+/// assert_eq!(paa.mipmaps[0].data, reread.mipmaps[0].data);
+/// ```
+pub struct PAA {
+    /// Pixel format of all mipmaps
+    pub format: PAAType,
+    /// Taggs (e.g. `AVGC`, `FLAG`), by their unreversed name
+    pub taggs: LinkedHashMap<String, Box<[u8]>>,
+    /// Raw palette data
+    pub palette: Vec<u8>,
+    /// Mipmaps, starting with the largest
+    pub mipmaps: Vec<MipMap>,
+}
+
+impl PAAType {
+    pub(crate) fn from_u16(value: u16) -> Option<PAAType> {
+        match value {
+            0xff01 => Some(PAAType::DXT1),
+            0xff02 => Some(PAAType::DXT2),
+            0xff03 => Some(PAAType::DXT3),
+            0xff04 => Some(PAAType::DXT4),
+            0xff05 => Some(PAAType::DXT5),
+            0x4444 => Some(PAAType::ARGB4444),
+            0x1555 => Some(PAAType::ARGB1555),
+            0x8888 => Some(PAAType::ARGB8888),
+            0x8080 => Some(PAAType::AI88),
+            _ => None,
+        }
+    }
+
+    fn to_u16(self) -> u16 {
+        match self {
+            PAAType::DXT1 => 0xff01,
+            PAAType::DXT2 => 0xff02,
+            PAAType::DXT3 => 0xff03,
+            PAAType::DXT4 => 0xff04,
+            PAAType::DXT5 => 0xff05,
+            PAAType::ARGB4444 => 0x4444,
+            PAAType::ARGB1555 => 0x1555,
+            PAAType::ARGB8888 => 0x8888,
+            PAAType::AI88 => 0x8080,
+        }
+    }
+
+    /// Parses a format name like `DXT5` or `ARGB8888`, ignoring case.
+    pub fn from_name(name: &str) -> Result<PAAType, Error> {
+        match name.to_uppercase().as_str() {
+            "DXT1" => Ok(PAAType::DXT1),
+            "DXT2" => Ok(PAAType::DXT2),
+            "DXT3" => Ok(PAAType::DXT3),
+            "DXT4" => Ok(PAAType::DXT4),
+            "DXT5" => Ok(PAAType::DXT5),
+            "ARGB4444" => Ok(PAAType::ARGB4444),
+            "ARGB1555" => Ok(PAAType::ARGB1555),
+            "ARGB8888" => Ok(PAAType::ARGB8888),
+            "AI88" => Ok(PAAType::AI88),
+            _ => Err(error!("Unknown PAA type \"{}\".", name)),
+        }
+    }
+
+    /// Returns true if the format stores 4x4 blocks of DXT compressed data.
+    pub fn is_dxt(self) -> bool {
+        matches!(self, PAAType::DXT1 | PAAType::DXT2 | PAAType::DXT3 | PAAType::DXT4 | PAAType::DXT5)
+    }
+
+    /// Returns the size of the uncompressed data of a mipmap with the given dimensions.
+    pub fn data_size(self, width: u16, height: u16) -> usize {
+        let (w, h) = (width as usize, height as usize);
+
+        match self {
+            PAAType::DXT1 => max(1, w.div_ceil(4)) * max(1, h.div_ceil(4)) * 8,
+            PAAType::DXT2 | PAAType::DXT3 | PAAType::DXT4 | PAAType::DXT5 => max(1, w.div_ceil(4)) * max(1, h.div_ceil(4)) * 16,
+            PAAType::ARGB8888 => w * h * 4,
+            _ => w * h * 2,
+        }
+    }
+
+    fn fourcc(self) -> Option<&'static [u8; 4]> {
+        match self {
+            PAAType::DXT1 => Some(b"DXT1"),
+            PAAType::DXT2 => Some(b"DXT2"),
+            PAAType::DXT3 => Some(b"DXT3"),
+            PAAType::DXT4 => Some(b"DXT4"),
+            PAAType::DXT5 => Some(b"DXT5"),
+            _ => None,
+        }
+    }
+
+    /// Pixel format as numbered by the engine in `texHeaders.bin`
+    fn engine_format(self) -> u32 {
+        match self {
+            PAAType::AI88 => 1,
+            PAAType::ARGB1555 => 3,
+            PAAType::ARGB4444 => 4,
+            PAAType::ARGB8888 => 5,
+            PAAType::DXT1 => 6,
+            PAAType::DXT2 => 7,
+            PAAType::DXT3 => 8,
+            PAAType::DXT4 => 9,
+            PAAType::DXT5 => 10,
+        }
+    }
+
+    /// Bit count and A, R, G, B masks for uncompressed formats
+    fn masks(self) -> (u32, u32, u32, u32, u32) {
+        match self {
+            PAAType::ARGB4444 => (16, 0xf000, 0x0f00, 0x00f0, 0x000f),
+            PAAType::ARGB1555 => (16, 0x8000, 0x7c00, 0x03e0, 0x001f),
+            PAAType::ARGB8888 => (32, 0xff00_0000, 0x00ff_0000, 0x0000_ff00, 0x0000_00ff),
+            PAAType::AI88 => (16, 0xff00, 0x00ff, 0, 0),
+            _ => (0, 0, 0, 0, 0),
+        }
+    }
+}
+
+fn expand_565(c: u16) -> [u8; 4] {
+    let r = ((c >> 11) & 0x1f) as u32;
+    let g = ((c >> 5) & 0x3f) as u32;
+    let b = (c & 0x1f) as u32;
+    [((r << 3) | (r >> 2)) as u8, ((g << 2) | (g >> 4)) as u8, ((b << 3) | (b >> 2)) as u8, 255]
+}
+
+fn decode_color_block(block: &[u8], pixels: &mut [[u8; 4]; 16], dxt1: bool) {
+    let c0 = u16::from(block[0]) | (u16::from(block[1]) << 8);
+    let c1 = u16::from(block[2]) | (u16::from(block[3]) << 8);
+    let (a, b) = (expand_565(c0), expand_565(c1));
+
+    let mut colors = [a, b, [0, 0, 0, 255], [0, 0, 0, 255]];
+    for i in 0..3 {
+        if c0 > c1 || !dxt1 {
+            colors[2][i] = ((2 * a[i] as u32 + b[i] as u32) / 3) as u8;
+            colors[3][i] = ((a[i] as u32 + 2 * b[i] as u32) / 3) as u8;
+        } else {
+            colors[2][i] = ((a[i] as u32 + b[i] as u32) / 2) as u8;
+        }
+    }
+    if dxt1 && c0 <= c1 {
+        colors[3] = [0, 0, 0, 0];
+    }
+
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let index = (block[4 + i / 4] >> ((i % 4) * 2)) & 3;
+        let alpha = pixel[3];
+        *pixel = colors[index as usize];
+        if !dxt1 {
+            pixel[3] = alpha;
+        }
+    }
+}
+
+fn alpha_palette(a0: u8, a1: u8) -> [u8; 8] {
+    let (a0, a1) = (u32::from(a0), u32::from(a1));
+    let mut alphas = [a0, a1, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for i in 1..7 {
+            alphas[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            alphas[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+        }
+        alphas[6] = 0;
+    }
+
+    let mut result = [0u8; 8];
+    for (r, a) in result.iter_mut().zip(alphas.iter()) {
+        *r = *a as u8;
+    }
+    result
+}
+
+fn decode_alpha_block(block: &[u8], pixels: &mut [[u8; 4]; 16]) {
+    let alphas = alpha_palette(block[0], block[1]);
+
+    let mut bits: u64 = 0;
+    for (i, byte) in block[2..8].iter().enumerate() {
+        bits |= u64::from(*byte) << (8 * i);
+    }
+
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        pixel[3] = alphas[((bits >> (3 * i)) & 7) as usize];
+    }
+}
+
+/// Decodes a single block of a DXT compressed mipmap into RGBA pixels (row-major).
+fn decode_dxt_block(format: PAAType, block: &[u8]) -> [[u8; 4]; 16] {
+    let mut pixels = [[0, 0, 0, 255]; 16];
+
+    match format {
+        PAAType::DXT1 => decode_color_block(block, &mut pixels, true),
+        PAAType::DXT2 | PAAType::DXT3 => {
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                let nibble = (block[i / 2] >> ((i % 2) * 4)) & 0xf;
+                pixel[3] = nibble * 17;
+            }
+            decode_color_block(&block[8..], &mut pixels, false);
+        },
+        _ => {
+            decode_alpha_block(block, &mut pixels);
+            decode_color_block(&block[8..], &mut pixels, false);
+        }
+    }
+
+    pixels
+}
+
+fn decode_pixel(format: PAAType, data: &[u8]) -> [u8; 4] {
+    match format {
+        PAAType::ARGB8888 => [data[2], data[1], data[0], data[3]],
+        PAAType::ARGB4444 => {
+            let v = u16::from(data[0]) | (u16::from(data[1]) << 8);
+            [((v >> 8) & 0xf) as u8 * 17, ((v >> 4) & 0xf) as u8 * 17, (v & 0xf) as u8 * 17, ((v >> 12) & 0xf) as u8 * 17]
+        },
+        PAAType::ARGB1555 => {
+            let v = u16::from(data[0]) | (u16::from(data[1]) << 8);
+            let expand = |c: u16| ((c << 3) | (c >> 2)) as u8;
+            [expand((v >> 10) & 0x1f), expand((v >> 5) & 0x1f), expand(v & 0x1f), if v & 0x8000 != 0 { 255 } else { 0 }]
+        },
+        _ => [data[0], data[0], data[0], data[1]],
+    }
+}
+
+fn pack_565(color: [u8; 4]) -> u16 {
+    (u16::from(color[0]) >> 3) << 11 | (u16::from(color[1]) >> 2) << 5 | u16::from(color[2]) >> 3
+}
+
+fn color_distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    (0..3).map(|i| (i32::from(a[i]) - i32::from(b[i])).pow(2) as u32).sum()
+}
+
+fn encode_color_block(pixels: &[[u8; 4]; 16], dxt1: bool, output: &mut Vec<u8>) {
+    let transparent = dxt1 && pixels.iter().any(|p| p[3] < 128);
+
+    // use the two most distant colors as endpoints
+    let opaque: Vec<[u8; 4]> = pixels.iter().filter(|p| !transparent || p[3] >= 128).cloned().collect();
+    let (mut low, mut high) = (opaque.first().cloned().unwrap_or([0; 4]), opaque.first().cloned().unwrap_or([0; 4]));
+    let mut distance = 0;
+    for (i, a) in opaque.iter().enumerate() {
+        for b in opaque.iter().skip(i + 1) {
+            if color_distance(*a, *b) > distance {
+                distance = color_distance(*a, *b);
+                low = *a;
+                high = *b;
+            }
+        }
+    }
+
+    let (mut c0, mut c1) = (pack_565(high), pack_565(low));
+    if transparent == (c0 > c1) {
+        std::mem::swap(&mut c0, &mut c1);
+    }
+
+    let mut block = [0u8; 8];
+    block[..2].copy_from_slice(&c0.to_le_bytes());
+    block[2..4].copy_from_slice(&c1.to_le_bytes());
+
+    if c0 != c1 || transparent {
+        // decoding indices 0, 1, 2, 3 yields the palette in the first row
+        let mut colors = [[0u8; 4]; 16];
+        decode_color_block(&[block[0], block[1], block[2], block[3], 0xe4, 0, 0, 0], &mut colors, dxt1);
+
+        for (i, pixel) in pixels.iter().enumerate() {
+            let index = if transparent && pixel[3] < 128 {
+                3
+            } else {
+                let candidates = if transparent { 3 } else { 4 };
+                (0..candidates).min_by_key(|c| color_distance(*pixel, colors[*c])).unwrap()
+            };
+            block[4 + i / 4] |= (index as u8) << ((i % 4) * 2);
+        }
+    }
+
+    output.extend_from_slice(&block);
+}
+
+fn encode_alpha_block(pixels: &[[u8; 4]; 16], output: &mut Vec<u8>) {
+    let a0 = pixels.iter().map(|p| p[3]).max().unwrap();
+    let a1 = pixels.iter().map(|p| p[3]).min().unwrap();
+
+    let mut block = [a0, a1, 0, 0, 0, 0, 0, 0];
+    if a0 != a1 {
+        let alphas = alpha_palette(a0, a1);
+
+        let mut bits: u64 = 0;
+        for (i, pixel) in pixels.iter().enumerate() {
+            let index = (0..8).min_by_key(|a| (i32::from(alphas[*a]) - i32::from(pixel[3])).abs()).unwrap();
+            bits |= (index as u64) << (3 * i);
+        }
+        for (i, byte) in block[2..].iter_mut().enumerate() {
+            *byte = (bits >> (8 * i)) as u8;
+        }
+    }
+
+    output.extend_from_slice(&block);
+}
+
+/// Encodes 4x4 RGBA pixels (row-major) into a block of a DXT compressed mipmap.
+fn encode_dxt_block(format: PAAType, pixels: &[[u8; 4]; 16], output: &mut Vec<u8>) {
+    match format {
+        PAAType::DXT1 => encode_color_block(pixels, true, output),
+        PAAType::DXT2 | PAAType::DXT3 => {
+            for pair in pixels.chunks(2) {
+                output.push((pair[0][3] >> 4) | (pair[1][3] >> 4) << 4);
+            }
+            encode_color_block(pixels, false, output);
+        },
+        _ => {
+            encode_alpha_block(pixels, output);
+            encode_color_block(pixels, false, output);
+        }
+    }
+}
+
+fn encode_pixel(format: PAAType, pixel: [u8; 4], output: &mut Vec<u8>) {
+    let [r, g, b, a] = pixel;
+    match format {
+        PAAType::ARGB8888 => output.extend_from_slice(&[b, g, r, a]),
+        PAAType::ARGB4444 => {
+            let v = u16::from(a >> 4) << 12 | u16::from(r >> 4) << 8 | u16::from(g >> 4) << 4 | u16::from(b >> 4);
+            output.extend_from_slice(&v.to_le_bytes());
+        },
+        PAAType::ARGB1555 => {
+            let v = if a >= 128 { 0x8000 } else { 0 } | u16::from(r >> 3) << 10 | u16::from(g >> 3) << 5 | u16::from(b >> 3);
+            output.extend_from_slice(&v.to_le_bytes());
+        },
+        _ => output.extend_from_slice(&[((u16::from(r) + u16::from(g) + u16::from(b)) / 3) as u8, a]),
+    }
+}
+
+impl ChannelSwizzle {
+    fn apply(self, pixel: [u8; 4]) -> u8 {
+        match self {
+            ChannelSwizzle::Alpha => pixel[3],
+            ChannelSwizzle::Red => pixel[0],
+            ChannelSwizzle::Green => pixel[1],
+            ChannelSwizzle::Blue => pixel[2],
+            ChannelSwizzle::InvAlpha => 255 - pixel[3],
+            ChannelSwizzle::InvRed => 255 - pixel[0],
+            ChannelSwizzle::InvGreen => 255 - pixel[1],
+            ChannelSwizzle::InvBlue => 255 - pixel[2],
+            ChannelSwizzle::One => 255,
+        }
+    }
+}
+
+impl Image {
+    /// Creates a black, opaque image.
+    pub fn new(width: u16, height: u16) -> Image {
+        Image {
+            width,
+            height,
+            data: vec![[0, 0, 0, 255]; width as usize * height as usize],
+        }
+    }
+
+    /// Returns the image scaled to half its size (at least 1x1) using a 2x2 box filter.
+    pub fn downsample(&self) -> Image {
+        let mut result = Image::new(max(1, self.width / 2), max(1, self.height / 2));
+        let (w, h) = (self.width as usize, self.height as usize);
+
+        for y in 0..result.height as usize {
+            for x in 0..result.width as usize {
+                let mut sum = [0u32; 4];
+                let mut count = 0;
+                for sy in (y * 2)..(y * 2 + 2).min(h) {
+                    for sx in (x * 2)..(x * 2 + 2).min(w) {
+                        for (i, s) in sum.iter_mut().enumerate() {
+                            *s += u32::from(self.data[sy * w + sx][i]);
+                        }
+                        count += 1;
+                    }
+                }
+                result.data[y * result.width as usize + x] = [
+                    ((sum[0] + count / 2) / count) as u8, ((sum[1] + count / 2) / count) as u8,
+                    ((sum[2] + count / 2) / count) as u8, ((sum[3] + count / 2) / count) as u8];
+            }
+        }
+
+        result
+    }
+
+    /// Scales the normal vectors encoded in the RGB channels back to unit length. Averaging
+    /// normals when downsampling shortens them, which shows up as dark seams in game.
+    pub fn renormalize(&mut self) {
+        for pixel in self.data.iter_mut() {
+            let v: Vec<f32> = pixel[..3].iter().map(|c| f32::from(*c) / 127.5 - 1.0).collect();
+            let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+            if length < 1e-4 { continue; }
+
+            for i in 0..3 {
+                pixel[i] = ((v[i] / length + 1.0) * 127.5).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    /// Returns the share of pixels with an alpha value of at least `reference`.
+    pub fn alpha_coverage(&self, reference: u8) -> f32 {
+        let passing = self.data.iter().filter(|p| p[3] >= reference).count();
+        passing as f32 / max(1, self.data.len()) as f32
+    }
+
+    /// Scales the alpha channel so that `coverage` is the share of pixels passing the alpha test
+    /// with the given reference value. Without this, foliage and decals thin out with distance
+    /// because averaging lowers alpha below the reference.
+    pub fn scale_alpha_to_coverage(&mut self, coverage: f32, reference: u8) {
+        let scaled_coverage = |scale: f32| {
+            self.data.iter().filter(|p| (f32::from(p[3]) * scale).min(255.0) >= f32::from(reference)).count() as f32
+                / max(1, self.data.len()) as f32
+        };
+
+        let (mut low, mut high) = (0.0f32, 8.0f32);
+        for _i in 0..16 {
+            let mid = (low + high) / 2.0;
+            if scaled_coverage(mid) < coverage {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        for pixel in self.data.iter_mut() {
+            pixel[3] = (f32::from(pixel[3]) * high).round().min(255.0) as u8;
+        }
+    }
+
+    /// Applies the swizzle given for the alpha, red, green and blue channels.
+    pub fn swizzle(&mut self, swizzle: &[ChannelSwizzle; 4]) {
+        for pixel in self.data.iter_mut() {
+            let source = *pixel;
+            *pixel = [swizzle[1].apply(source), swizzle[2].apply(source), swizzle[3].apply(source), swizzle[0].apply(source)];
+        }
+    }
+
+    /// Reads an uncompressed or RLE compressed true color or grayscale TGA image.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::Cursor;
+    /// # use armake2::paa::Image;
+    /// let mut image = Image::new(2, 1);
+    /// image.data[1] = [255, 128, 0, 64];
+    ///
+    /// let mut tga: Vec<u8> = Vec::new();
+    /// image.write_tga(&mut tga).unwrap();
+    ///
+    /// assert_eq!(image.data, Image::read_tga(&mut Cursor::new(tga)).unwrap().data);
+    /// ```
+    pub fn read_tga<I: Read>(input: &mut I) -> Result<Image, Error> {
+        let mut header = [0u8; 18];
+        input.read_exact(&mut header)?;
+        let (id_length, colormap_type, image_type) = (header[0], header[1], header[2]);
+        let width = u16::from_le_bytes([header[12], header[13]]);
+        let height = u16::from_le_bytes([header[14], header[15]]);
+        let (bpp, descriptor) = (header[16], header[17]);
+
+        if colormap_type != 0 {
+            return Err(error!("Color mapped TGA images are not supported."));
+        }
+        let (rle, gray) = match image_type {
+            2 => (false, false),
+            3 => (false, true),
+            10 => (true, false),
+            11 => (true, true),
+            _ => { return Err(error!("Unsupported TGA image type {}.", image_type)); }
+        };
+        let bytes = match (gray, bpp) {
+            (false, 24) => 3,
+            (false, 32) => 4,
+            (true, 8) => 1,
+            _ => { return Err(error!("Unsupported TGA pixel depth of {} bits.", bpp)); }
+        };
+        input.read_exact(&mut vec![0; id_length as usize])?;
+
+        let to_rgba = |p: &[u8]| match bytes {
+            1 => [p[0], p[0], p[0], 255],
+            3 => [p[2], p[1], p[0], 255],
+            _ => [p[2], p[1], p[0], p[3]],
+        };
+
+        let count = width as usize * height as usize;
+        let mut pixels: Vec<[u8; 4]> = Vec::with_capacity(count);
+        let mut pixel = vec![0; bytes];
+        while pixels.len() < count {
+            let (repeat, raw) = if rle {
+                let packet = input.read_u8()?;
+                let length = (packet & 0x7f) as usize + 1;
+                if packet & 0x80 != 0 { (length, 1) } else { (1, length) }
+            } else {
+                (1, count)
+            };
+
+            for _ in 0..raw {
+                input.read_exact(&mut pixel).prepend_error("TGA image is truncated:")?;
+                for _ in 0..repeat {
+                    pixels.push(to_rgba(&pixel));
+                }
+            }
+        }
+        pixels.truncate(count);
+
+        // rows are stored bottom to top unless the origin is in the top left
+        if descriptor & 0x20 == 0 {
+            pixels = pixels.chunks(width.max(1) as usize).rev().flatten().copied().collect();
+        }
+
+        Ok(Image {
+            width,
+            height,
+            data: pixels,
+        })
+    }
+
+    /// Writes the image as an uncompressed 32 bit TGA.
+    pub fn write_tga<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        let mut header = [0u8; 18];
+        header[2] = 2;
+        header[12..14].copy_from_slice(&self.width.to_le_bytes());
+        header[14..16].copy_from_slice(&self.height.to_le_bytes());
+        header[16] = 32;
+        // 8 alpha bits, origin in the top left
+        header[17] = 0x28;
+        output.write_all(&header)?;
+
+        for pixel in &self.data {
+            output.write_all(&[pixel[2], pixel[1], pixel[0], pixel[3]])?;
+        }
+
+        Ok(())
+    }
+
+    /// Reverts `NORMALMAP_SWIZZLE`, reconstructing the Z component of the normals.
+    fn unswizzle_normalmap(&mut self) {
+        for pixel in self.data.iter_mut() {
+            let x = f32::from(pixel[3]) / 127.5 - 1.0;
+            let y = f32::from(pixel[1]) / 127.5 - 1.0;
+            let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+            *pixel = [pixel[3], pixel[1], ((z + 1.0) * 127.5).round() as u8, 255];
+        }
+    }
+}
+
+impl MipMap {
+    /// Decodes the mipmap into an RGBA image.
+    pub fn decode(&self, format: PAAType) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        let (w, h) = (self.width as usize, self.height as usize);
+
+        if format.is_dxt() {
+            let block_size = if format == PAAType::DXT1 { 8 } else { 16 };
+            let blocks_x = max(1, w.div_ceil(4));
+
+            for (b, block) in self.data.chunks(block_size).enumerate() {
+                let (bx, by) = (b % blocks_x, b / blocks_x);
+                for (i, pixel) in decode_dxt_block(format, block).iter().enumerate() {
+                    let (x, y) = (bx * 4 + i % 4, by * 4 + i / 4);
+                    if x < w && y < h {
+                        image.data[y * w + x] = *pixel;
+                    }
+                }
+            }
+        } else {
+            let bpp = if format == PAAType::ARGB8888 { 4 } else { 2 };
+            for (pixel, chunk) in image.data.iter_mut().zip(self.data.chunks(bpp)) {
+                *pixel = decode_pixel(format, chunk);
+            }
+        }
+
+        image
+    }
+
+    /// Encodes an RGBA image into a mipmap of the given format.
+    pub fn encode(image: &Image, format: PAAType) -> MipMap {
+        let mut data: Vec<u8> = Vec::with_capacity(format.data_size(image.width, image.height));
+        let (w, h) = (image.width as usize, image.height as usize);
+
+        if format.is_dxt() {
+            for by in 0..max(1, h.div_ceil(4)) {
+                for bx in 0..max(1, w.div_ceil(4)) {
+                    let mut pixels = [[0u8; 4]; 16];
+                    for (i, pixel) in pixels.iter_mut().enumerate() {
+                        let x = (bx * 4 + i % 4).min(w - 1);
+                        let y = (by * 4 + i / 4).min(h - 1);
+                        *pixel = image.data[y * w + x];
+                    }
+                    encode_dxt_block(format, &pixels, &mut data);
+                }
+            }
+        } else {
+            for pixel in &image.data {
+                encode_pixel(format, *pixel, &mut data);
+            }
+        }
+
+        MipMap {
+            width: image.width,
+            height: image.height,
+            data,
+        }
+    }
+
+    /// Calls `f` for every decoded RGBA pixel of the mipmap. The order of the pixels is
+    /// unspecified.
+    pub fn for_each_pixel<F: FnMut([u8; 4])>(&self, format: PAAType, mut f: F) {
+        if format.is_dxt() {
+            let block_size = if format == PAAType::DXT1 { 8 } else { 16 };
+            let blocks_x = max(1, (self.width as usize).div_ceil(4));
+            let blocks_y = max(1, (self.height as usize).div_ceil(4));
+
+            for by in 0..blocks_y {
+                for bx in 0..blocks_x {
+                    let offset = (by * blocks_x + bx) * block_size;
+                    let pixels = decode_dxt_block(format, &self.data[offset..offset + block_size]);
+
+                    for (i, pixel) in pixels.iter().enumerate() {
+                        if bx * 4 + i % 4 < self.width as usize && by * 4 + i / 4 < self.height as usize {
+                            f(*pixel);
+                        }
+                    }
+                }
+            }
+        } else {
+            let bpp = if format == PAAType::ARGB8888 { 4 } else { 2 };
+            for chunk in self.data.chunks(bpp) {
+                f(decode_pixel(format, chunk));
+            }
+        }
+    }
+}
+
+/// Decompresses LZO1X compressed data with a known output size.
+fn lzo_decompress(input: &[u8], expected: usize) -> Result<Vec<u8>, Error> {
+    let mut output: Vec<u8> = Vec::with_capacity(expected);
+    let mut ip = 0;
+
+    macro_rules! next {
+        () => {{
+            let b = *input.get(ip).ok_or_else(|| error!("LZO input overrun"))?;
+            ip += 1;
+            b as usize
+        }};
+    }
+
+    fn copy_match(output: &mut Vec<u8>, distance: usize, length: usize) -> Result<(), Error> {
+        if distance == 0 || distance > output.len() {
+            return Err(error!("LZO lookbehind overrun"));
+        }
+        let start = output.len() - distance;
+        for i in 0..length {
+            let b = output[start + i];
+            output.push(b);
+        }
+        Ok(())
+    }
+
+    fn copy_literals(output: &mut Vec<u8>, input: &[u8], ip: &mut usize, length: usize) -> Result<(), Error> {
+        if *ip + length > input.len() {
+            return Err(error!("LZO input overrun"));
+        }
+        output.extend_from_slice(&input[*ip..*ip + length]);
+        *ip += length;
+        Ok(())
+    }
+
+    enum State { Loop, FirstLiteralRun, Match(usize), MatchNext(usize) }
+
+    let mut state = State::Loop;
+    if input.first().map(|b| *b > 17).unwrap_or(false) {
+        let t = next!() - 17;
+        state = if t < 4 {
+            State::MatchNext(t)
+        } else {
+            copy_literals(&mut output, input, &mut ip, t)?;
+            State::FirstLiteralRun
+        };
+    }
+
+    loop {
+        state = match state {
+            State::Loop => {
+                let mut t = next!();
+                if t >= 16 {
+                    State::Match(t)
+                } else {
+                    if t == 0 {
+                        while *input.get(ip).ok_or_else(|| error!("LZO input overrun"))? == 0 {
+                            t += 255;
+                            ip += 1;
+                        }
+                        t += 15 + next!();
+                    }
+                    copy_literals(&mut output, input, &mut ip, t + 3)?;
+                    State::FirstLiteralRun
+                }
+            },
+            State::FirstLiteralRun => {
+                let t = next!();
+                if t >= 16 {
+                    State::Match(t)
+                } else {
+                    let distance = 1 + 0x0800 + (t >> 2) + (next!() << 2);
+                    copy_match(&mut output, distance, 3)?;
+                    State::MatchNext(input[ip - 2] as usize & 3)
+                }
+            },
+            State::Match(mut t) => {
+                if t >= 64 {
+                    let distance = 1 + ((t >> 2) & 7) + (next!() << 3);
+                    copy_match(&mut output, distance, (t >> 5) + 1)?;
+                } else if t >= 32 {
+                    t &= 31;
+                    if t == 0 {
+                        while *input.get(ip).ok_or_else(|| error!("LZO input overrun"))? == 0 {
+                            t += 255;
+                            ip += 1;
+                        }
+                        t += 31 + next!();
+                    }
+                    let distance = 1 + ((next!() | (next!() << 8)) >> 2);
+                    copy_match(&mut output, distance, t + 2)?;
+                } else if t >= 16 {
+                    let mut distance = (t & 8) << 11;
+                    t &= 7;
+                    if t == 0 {
+                        while *input.get(ip).ok_or_else(|| error!("LZO input overrun"))? == 0 {
+                            t += 255;
+                            ip += 1;
+                        }
+                        t += 7 + next!();
+                    }
+                    distance += (next!() | (next!() << 8)) >> 2;
+                    if distance == 0 {
+                        break;
+                    }
+                    copy_match(&mut output, distance + 0x4000, t + 2)?;
+                } else {
+                    let distance = 1 + (t >> 2) + (next!() << 2);
+                    copy_match(&mut output, distance, 2)?;
+                }
+                State::MatchNext(input[ip - 2] as usize & 3)
+            },
+            State::MatchNext(0) => State::Loop,
+            State::MatchNext(t) => {
+                copy_literals(&mut output, input, &mut ip, t)?;
+                State::Match(next!())
+            },
+        };
+
+        if output.len() > expected {
+            return Err(error!("LZO output overrun"));
+        }
+    }
+
+    if output.len() != expected {
+        return Err(error!("LZO output has unexpected size {} (expected {})", output.len(), expected));
+    }
+
+    Ok(output)
+}
+
+impl PAA {
+    /// Creates an empty PAA of the given format.
+    pub fn new(format: PAAType) -> PAA {
+        PAA {
+            format,
+            taggs: LinkedHashMap::new(),
+            palette: Vec::new(),
+            mipmaps: Vec::new(),
+        }
+    }
+
+    /// Creates a PAA with the image as its only mipmap. Without a format, DXT1 is used for opaque
+    /// images and DXT5 for images with transparency.
+    ///
+    /// Fails for sizes that are not powers of two, which the engine can't load.
+    pub fn from_image(image: &Image, format: Option<PAAType>) -> Result<PAA, Error> {
+        if !image.width.is_power_of_two() || !image.height.is_power_of_two() {
+            return Err(error!("Image size {}x{} is not a power of two.", image.width, image.height));
+        }
+
+        let format = format.unwrap_or_else(|| if image.data.iter().all(|p| p[3] == 255) { PAAType::DXT1 } else { PAAType::DXT5 });
+        let mut paa = PAA::new(format);
+        paa.mipmaps.push(MipMap::encode(image, format));
+
+        Ok(paa)
+    }
+
+    /// Decodes the largest mipmap into an RGBA image. Swizzled normal maps are unswizzled.
+    pub fn to_image(&self) -> Result<Image, Error> {
+        let first = self.mipmaps.first().ok_or_else(|| error!("PAA contains no mipmaps."))?;
+        let mut image = first.decode(self.format);
+        if self.taggs.get("SWIZ") == Some(&PAA::swizzle_bytes(&NORMALMAP_SWIZZLE)) {
+            image.unswizzle_normalmap();
+        }
+
+        Ok(image)
+    }
+
+    /// Reads a PAA from input. LZO compressed DXT mipmaps and LZSS compressed mipmaps of the
+    /// other formats are decompressed. RLE compression is only used by indexed palette textures,
+    /// which are not supported.
+    pub fn read<I: Read>(input: &mut I) -> Result<PAA, Error> {
+        let mut buffer: Vec<u8> = Vec::new();
+        input.read_to_end(&mut buffer)?;
+        let mut cursor = Cursor::new(&buffer[..]);
+
+        let type_id = cursor.read_u16::<LittleEndian>()?;
+        let format = PAAType::from_u16(type_id).ok_or_else(|| error!("Unsupported PAA type: 0x{:04x}", type_id))?;
+
+        let mut taggs: LinkedHashMap<String, Box<[u8]>> = LinkedHashMap::new();
+        while buffer.len() >= cursor.position() as usize + 4 && &buffer[cursor.position() as usize..cursor.position() as usize + 4] == b"GGAT" {
+            let mut name = [0; 8];
+            cursor.read_exact(&mut name)?;
+            let name: String = name[4..].iter().rev().map(|b| *b as char).collect();
+
+            let size = cursor.read_u32::<LittleEndian>()?;
+            let mut data = vec![0; size as usize].into_boxed_slice();
+            cursor.read_exact(&mut data)?;
+
+            taggs.insert(name, data);
+        }
+
+        let palette_len = cursor.read_u16::<LittleEndian>()?;
+        let mut palette = vec![0; palette_len as usize * 3];
+        cursor.read_exact(&mut palette)?;
+
+        let mut mipmaps: Vec<MipMap> = Vec::new();
+        loop {
+            let mut width = cursor.read_u16::<LittleEndian>()?;
+            let height = cursor.read_u16::<LittleEndian>()?;
+            if width == 0 && height == 0 { break; }
+
+            let size = cursor.read_u16::<LittleEndian>()? as usize | (cursor.read_u8()? as usize) << 16;
+            let mut data = vec![0; size];
+            cursor.read_exact(&mut data).prepend_error("Failed to read mipmap data:")?;
+
+            if format.is_dxt() && width & 0x8000 != 0 {
+                width &= 0x7fff;
+                data = lzo_decompress(&data, format.data_size(width, height))
+                    .prepend_error(format!("Failed to decompress {}x{} mipmap:", width, height))?;
+            } else if !format.is_dxt() && data.len() != format.data_size(width, height) {
+                data = lzss_decompress(&data).ok()
+                    .filter(|decompressed| decompressed.len() == format.data_size(width, height))
+                    .ok_or_else(|| error!("Unsupported compression for {}x{} mipmap.", width, height))?;
+            } else if data.len() != format.data_size(width, height) {
+                return Err(error!("Unsupported compression for {}x{} mipmap.", width, height));
+            }
+
+            mipmaps.push(MipMap { width, height, data });
+        }
+
+        Ok(PAA {
+            format,
+            taggs,
+            palette,
+            mipmaps,
+        })
+    }
+
+    /// Writes the PAA to output. Mipmaps are stored uncompressed and the `OFFS` tagg is
+    /// regenerated.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        let mut taggs: Vec<(&str, Vec<u8>)> = self.taggs.iter()
+            .filter(|(name, _)| name.as_str() != "OFFS")
+            .map(|(name, data)| (name.as_str(), data.to_vec()))
+            .collect();
+
+        let header_len = 2 + 2 + self.palette.len() + 12 + 16 * 4 +
+            taggs.iter().map(|(_, data)| 12 + data.len()).sum::<usize>();
+
+        let mut offsets = Vec::with_capacity(16 * 4);
+        let mut offset = header_len;
+        for mipmap in self.mipmaps.iter().take(16) {
+            offsets.write_u32::<LittleEndian>(offset as u32)?;
+            offset += 7 + mipmap.data.len();
+        }
+        offsets.resize(16 * 4, 0);
+        taggs.push(("OFFS", offsets));
+
+        output.write_u16::<LittleEndian>(self.format.to_u16())?;
+
+        for (name, data) in taggs {
+            output.write_all(b"GGAT")?;
+            output.write_all(&name.bytes().rev().collect::<Vec<u8>>())?;
+            output.write_u32::<LittleEndian>(data.len() as u32)?;
+            output.write_all(&data)?;
+        }
+
+        output.write_u16::<LittleEndian>((self.palette.len() / 3) as u16)?;
+        output.write_all(&self.palette)?;
+
+        for mipmap in &self.mipmaps {
+            // the data length is stored in 3 bytes
+            if mipmap.data.len() > 0xff_ffff {
+                return Err(error!("{}x{} mipmap is too large for a PAA ({} bytes).", mipmap.width, mipmap.height, mipmap.data.len()));
+            }
+
+            output.write_u16::<LittleEndian>(mipmap.width)?;
+            output.write_u16::<LittleEndian>(mipmap.height)?;
+            output.write_u16::<LittleEndian>((mipmap.data.len() & 0xffff) as u16)?;
+            output.write_u8((mipmap.data.len() >> 16) as u8)?;
+            output.write_all(&mipmap.data)?;
+        }
+
+        output.write_all(&[0; 6])?;
+
+        Ok(())
+    }
+
+    /// Reads a DDS texture from input. Pixel data is copied without recompression, so only
+    /// formats that have a PAA equivalent are supported.
+    pub fn read_dds<I: Read>(input: &mut I) -> Result<PAA, Error> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != b"DDS " {
+            return Err(error!("File doesn't seem to be a DDS texture."));
+        }
+
+        let mut header = [0u32; 31];
+        input.read_u32_into::<LittleEndian>(&mut header)?;
+
+        let (flags, height, width) = (header[1], header[2], header[3]);
+        let mipmap_count = if flags & DDSD_MIPMAPCOUNT != 0 { max(1, header[6]) } else { 1 };
+        let (pf_flags, fourcc) = (header[19], header[20]);
+        let (bitcount, masks) = (header[21], (header[25], header[22], header[23], header[24]));
+
+        if width > 0x7fff || height > 0x7fff {
+            return Err(error!("DDS dimensions {}x{} are too large for PAA.", width, height));
+        }
+
+        let format = if pf_flags & DDPF_FOURCC != 0 {
+            match &fourcc.to_le_bytes() {
+                b"DXT1" => PAAType::DXT1,
+                b"DXT2" => PAAType::DXT2,
+                b"DXT3" => PAAType::DXT3,
+                b"DXT4" => PAAType::DXT4,
+                b"DXT5" => PAAType::DXT5,
+                b"DX10" => {
+                    let mut dx10 = [0u32; 5];
+                    input.read_u32_into::<LittleEndian>(&mut dx10)?;
+                    match dx10[0] {
+                        71 | 72 => PAAType::DXT1,
+                        74 | 75 => PAAType::DXT3,
+                        77 | 78 => PAAType::DXT5,
+                        87 => PAAType::ARGB8888,
+                        86 => PAAType::ARGB1555,
+                        115 => PAAType::ARGB4444,
+                        f => { return Err(error!("Unsupported DXGI format {} in DDS.", f)); }
+                    }
+                },
+                cc => { return Err(error!("Unsupported DDS compression \"{}\".", String::from_utf8_lossy(cc))); }
+            }
+        } else {
+            [PAAType::ARGB8888, PAAType::ARGB4444, PAAType::ARGB1555, PAAType::AI88].iter().cloned()
+                .find(|f| {
+                    let (b, a, r, g, bl) = f.masks();
+                    b == bitcount && (r, g, bl) == (masks.1, masks.2, masks.3) &&
+                        (a == masks.0 || (pf_flags & DDPF_ALPHAPIXELS == 0 && *f != PAAType::AI88))
+                })
+                .ok_or_else(|| error!("Unsupported uncompressed DDS pixel format ({} bit).", bitcount))?
+        };
+
+        let mut mipmaps: Vec<MipMap> = Vec::new();
+        let (mut w, mut h) = (width as u16, height as u16);
+        for _i in 0..mipmap_count {
+            let mut data = vec![0; format.data_size(w, h)];
+            input.read_exact(&mut data).prepend_error("Failed to read DDS surface:")?;
+
+            if !format.is_dxt() || (w >= 4 && h >= 4) {
+                mipmaps.push(MipMap { width: w, height: h, data });
+            }
+
+            w = max(1, w / 2);
+            h = max(1, h / 2);
+        }
+        mipmaps.truncate(16);
+
+        let mut paa = PAA::new(format);
+        paa.mipmaps = mipmaps;
+        paa.update_taggs();
+
+        Ok(paa)
+    }
+
+    /// Writes the texture to output as a DDS, copying the pixel data as-is.
+    pub fn write_dds<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        let first = self.mipmaps.first().ok_or_else(|| error!("PAA contains no mipmaps."))?;
+
+        let mut header = [0u32; 31];
+        header[0] = 124;
+        header[1] = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_MIPMAPCOUNT | DDSD_LINEARSIZE;
+        header[2] = u32::from(first.height);
+        header[3] = u32::from(first.width);
+        header[4] = first.data.len() as u32;
+        header[6] = self.mipmaps.len() as u32;
+        header[18] = 32;
+
+        if let Some(fourcc) = self.format.fourcc() {
+            header[19] = DDPF_FOURCC;
+            header[20] = u32::from_le_bytes(*fourcc);
+        } else {
+            let (bitcount, a, r, g, b) = self.format.masks();
+            header[19] = if self.format == PAAType::AI88 { DDPF_LUMINANCE } else { DDPF_RGB } | DDPF_ALPHAPIXELS;
+            header[21] = bitcount;
+            header[22] = r;
+            header[23] = g;
+            header[24] = b;
+            header[25] = a;
+        }
+
+        header[26] = DDSCAPS_TEXTURE | if self.mipmaps.len() > 1 { DDSCAPS_COMPLEX | DDSCAPS_MIPMAP } else { 0 };
+
+        output.write_all(b"DDS ")?;
+        for value in header.iter() {
+            output.write_u32::<LittleEndian>(*value)?;
+        }
+
+        for mipmap in &self.mipmaps {
+            output.write_all(&mipmap.data)?;
+        }
+
+        Ok(())
+    }
+
+    fn swizzle_bytes(swizzle: &[ChannelSwizzle; 4]) -> Box<[u8]> {
+        swizzle.iter().map(|s| *s as u8).collect::<Vec<u8>>().into_boxed_slice()
+    }
+
+    /// Regenerates all mipmaps from the largest one, which is kept as-is.
+    ///
+    /// Normal maps stored with `NORMALMAP_SWIZZLE` are unswizzled before filtering.
+    pub fn generate_mipmaps(&mut self, filter: MipFilter) -> Result<(), Error> {
+        let first = self.mipmaps.first().ok_or_else(|| error!("PAA contains no mipmaps."))?;
+        let swizzled = self.taggs.get("SWIZ") == Some(&PAA::swizzle_bytes(&NORMALMAP_SWIZZLE));
+        let min_size = if self.format.is_dxt() { 4 } else { 1 };
+
+        let mut image = first.decode(self.format);
+        if swizzled {
+            image.unswizzle_normalmap();
+        }
+        let coverage = image.alpha_coverage(ALPHA_REFERENCE);
+
+        self.mipmaps.truncate(1);
+        while image.width / 2 >= min_size && image.height / 2 >= min_size && self.mipmaps.len() < 16 {
+            image = image.downsample();
+            if filter == MipFilter::NormalizeNormalMap {
+                image.renormalize();
+            }
+
+            let mut stored = image.clone();
+            if filter == MipFilter::AlphaCoverage {
+                stored.scale_alpha_to_coverage(coverage, ALPHA_REFERENCE);
+            }
+            if swizzled {
+                stored.swizzle(&NORMALMAP_SWIZZLE);
+            }
+            self.mipmaps.push(MipMap::encode(&stored, self.format));
+        }
+
+        Ok(())
+    }
+
+    /// Converts a normal map to the layout the engine expects for `_nohq` textures: DXT5 with
+    /// `NORMALMAP_SWIZZLE` applied and recorded in the `SWIZ` tagg. Textures that are already
+    /// swizzled (e.g. exported with `paa2dds`) are detected and not swizzled again.
+    ///
+    /// Mipmaps are regenerated with the given filter.
+    pub fn convert_normalmap(&mut self, filter: MipFilter) -> Result<(), Error> {
+        let first = self.mipmaps.first().ok_or_else(|| error!("PAA contains no mipmaps."))?;
+        let mut image = first.decode(self.format);
+
+        let swizzled = self.taggs.contains_key("SWIZ") || image.data.iter().all(|p| p[0] >= 250 && p[2] >= 250);
+        if !swizzled {
+            image.swizzle(&NORMALMAP_SWIZZLE);
+        }
+        if !swizzled || self.format != PAAType::DXT5 {
+            self.mipmaps[0] = MipMap::encode(&image, PAAType::DXT5);
+            self.format = PAAType::DXT5;
+        }
+
+        self.taggs.insert("SWIZ".to_string(), PAA::swizzle_bytes(&NORMALMAP_SWIZZLE));
+        self.generate_mipmaps(filter)?;
+        self.update_taggs();
+
+        Ok(())
+    }
+
+    /// Recalculates the `AVGC`, `MAXC` and `FLAG` taggs from the largest mipmap.
+    pub fn update_taggs(&mut self) {
+        let first = match self.mipmaps.first() {
+            Some(mipmap) => mipmap,
+            None => { return; }
+        };
+
+        let mut sum = [0u64; 4];
+        let mut count = 0u64;
+        let mut transparent = false;
+        first.for_each_pixel(self.format, |pixel| {
+            for i in 0..4 {
+                sum[i] += u64::from(pixel[i]);
+            }
+            count += 1;
+            transparent |= pixel[3] < 255;
+        });
+
+        let avg: Vec<u8> = sum.iter().map(|s| (s / max(1, count)) as u8).collect();
+        let avgc = u32::from(avg[3]) << 24 | u32::from(avg[0]) << 16 | u32::from(avg[1]) << 8 | u32::from(avg[2]);
+
+        self.taggs.insert("AVGC".to_string(), avgc.to_le_bytes().to_vec().into_boxed_slice());
+        self.taggs.insert("MAXC".to_string(), vec![0xff; 4].into_boxed_slice());
+        if transparent {
+            self.taggs.insert("FLAG".to_string(), 1u32.to_le_bytes().to_vec().into_boxed_slice());
+        } else {
+            self.taggs.remove("FLAG");
+        }
+    }
+}
+
+/// Texture type the engine derives from the suffix of a texture name: 0 for colors, 1 for other
+/// linear data, 2 for detail maps and 3 for normal maps
+fn texture_type(name: &str) -> u32 {
+    let stem = name.rsplit('\\').next().unwrap_or(name).rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name).to_lowercase();
+    let suffix = stem.rsplit('_').next().unwrap_or("");
+
+    match suffix {
+        "no" | "nohq" | "nopx" | "ns" | "nshq" | "nof" => 3,
+        "dt" | "cdt" | "mco" => 2,
+        "smdi" | "sm" | "as" | "ads" | "mc" | "dtsmdi" | "mask" => 1,
+        _ => 0,
+    }
+}
+
+/// Creates the `texHeaders.bin` of an addon from its PAAs, given as their path in the PBO and
+/// their content. The engine reads the headers of all textures from it instead of opening every
+/// PAA when the addon is loaded.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::paa::{PAA, PAAType, Image, texheaders};
+/// let mut paa = PAA::from_image(&Image::new(8, 8), Some(PAAType::DXT5)).unwrap();
+/// paa.update_taggs();
+/// let mut data: Vec<u8> = Vec::new();
+/// paa.write(&mut data).unwrap();
+///
+/// let headers = texheaders(&[("data\\black_ca.paa".to_string(), data)]).unwrap();
+/// assert_eq!(b"0DHT", &headers[..4]);
+/// assert_eq!(1, u32::from_le_bytes([headers[8], headers[9], headers[10], headers[11]]));
+/// ```
+pub fn texheaders(textures: &[(String, Vec<u8>)]) -> Result<Vec<u8>, Error> {
+    let mut output: Vec<u8> = Vec::new();
+    output.write_all(b"0DHT")?;
+    output.write_u32::<LittleEndian>(1)?;
+    output.write_u32::<LittleEndian>(textures.len() as u32)?;
+
+    for (name, data) in textures {
+        let mut paa = PAA::read(&mut Cursor::new(data)).prepend_error(format!("Failed to read {}:", name))?;
+        if !paa.taggs.contains_key("AVGC") {
+            paa.update_taggs();
+        }
+
+        let tagg_u32 = |name: &str| paa.taggs.get(name).filter(|d| d.len() >= 4).map(|d| u32::from_le_bytes([d[0], d[1], d[2], d[3]]));
+        // colors are stored as ARGB, so their bytes are BGRA
+        let average = tagg_u32("AVGC").unwrap_or(0xff80_8080).to_le_bytes();
+        let max_color = tagg_u32("MAXC");
+        let flag = tagg_u32("FLAG").unwrap_or(0);
+        let offsets: Vec<u32> = paa.taggs.get("OFFS").map(|d| d.chunks_exact(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect()).unwrap_or_default();
+
+        // color palettes, palette pointer
+        output.write_u32::<LittleEndian>(1)?;
+        output.write_u32::<LittleEndian>(0)?;
+        for i in &[2, 1, 0, 3] {
+            output.write_f32::<LittleEndian>(f32::from(average[*i]) / 255.0)?;
+        }
+        output.write_all(&average)?;
+        output.write_all(&max_color.unwrap_or(0xffff_ffff).to_le_bytes())?;
+        // clamp flags, transparent color
+        output.write_u32::<LittleEndian>(0)?;
+        output.write_u32::<LittleEndian>(0xffff_ffff)?;
+        output.write_u8(max_color.is_some() as u8)?;
+        output.write_u8((flag & 1 != 0) as u8)?;
+        output.write_u8((flag & 2 != 0) as u8)?;
+        output.write_u8((flag & 1 != 0 && average[3] < 0x80) as u8)?;
+
+        output.write_u32::<LittleEndian>(paa.mipmaps.len() as u32)?;
+        output.write_u32::<LittleEndian>(paa.format.engine_format())?;
+        // little endian, is PAA
+        output.write_u8(1)?;
+        output.write_u8(1)?;
+        output.write_cstring(name)?;
+        output.write_u32::<LittleEndian>(texture_type(name))?;
+
+        output.write_u32::<LittleEndian>(paa.mipmaps.len() as u32)?;
+        for (i, mipmap) in paa.mipmaps.iter().enumerate() {
+            output.write_u16::<LittleEndian>(mipmap.width)?;
+            output.write_u16::<LittleEndian>(mipmap.height)?;
+            output.write_u16::<LittleEndian>(0)?;
+            output.write_u8(paa.format.engine_format() as u8)?;
+            output.write_u8(3)?;
+            output.write_u32::<LittleEndian>(offsets.get(i).cloned().unwrap_or(0))?;
+        }
+        output.write_u32::<LittleEndian>(data.len() as u32)?;
+    }
+
+    Ok(output)
+}
+
+/// Reads a PAA from input and writes it to output as DDS.
+pub fn cmd_paa2dds<I: Read, O: Write>(input: &mut I, output: &mut O) -> Result<(), Error> {
+    let paa = PAA::read(input).prepend_error("Failed to read PAA:")?;
+
+    paa.write_dds(output).prepend_error("Failed to write DDS:")?;
+
+    Ok(())
+}
+
+/// Reads a DDS from input and writes it to output as PAA.
+///
+/// Missing mipmaps are generated. `path` is the path of the texture if it is known, textures
+/// named like normal maps (`_nohq`) are converted to the swizzled layout the engine expects and
+/// their generated mipmaps are renormalized unless `normalize` is false. If `alpha_coverage` is
+/// true, the mipmaps of alpha tested textures (`_ca`) are regenerated preserving alpha coverage.
+pub fn cmd_dds2paa<I: Read, O: Write>(input: &mut I, output: &mut O, path: Option<PathBuf>, normalize: bool, alpha_coverage: bool) -> Result<(), Error> {
+    let paa = PAA::read_dds(input).prepend_error("Failed to read DDS:")?;
+
+    write_converted(paa, output, path, normalize, alpha_coverage)
+}
+
+/// Reads a TGA image from input and writes it to output as PAA of the given format, or DXT1/DXT5
+/// depending on transparency. Mipmaps, normal maps and alpha coverage are handled like in
+/// `cmd_dds2paa`.
+pub fn cmd_img2paa<I: Read, O: Write>(input: &mut I, output: &mut O, path: Option<PathBuf>, format: Option<PAAType>, normalize: bool, alpha_coverage: bool) -> Result<(), Error> {
+    let image = Image::read_tga(input).prepend_error("Failed to read TGA:")?;
+    let paa = PAA::from_image(&image, format)?;
+
+    write_converted(paa, output, path, normalize, alpha_coverage)
+}
+
+/// Reads a PAA from input and writes its largest mipmap to output as TGA.
+pub fn cmd_paa2img<I: Read, O: Write>(input: &mut I, output: &mut O) -> Result<(), Error> {
+    let paa = PAA::read(input).prepend_error("Failed to read PAA:")?;
+
+    paa.to_image()?.write_tga(output).prepend_error("Failed to write TGA:")?;
+
+    Ok(())
+}
+
+/// Finishes a PAA converted from another format and writes it to output.
+fn write_converted<O: Write>(mut paa: PAA, output: &mut O, path: Option<PathBuf>, normalize: bool, alpha_coverage: bool) -> Result<(), Error> {
+    let name = path.as_ref().and_then(|p| p.file_stem()).map(|s| s.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if name.ends_with("_nohq") {
+        let filter = if normalize { MipFilter::NormalizeNormalMap } else { MipFilter::Default };
+        paa.convert_normalmap(filter).prepend_error("Failed to convert normal map:")?;
+    } else if alpha_coverage && name.ends_with("_ca") {
+        paa.generate_mipmaps(MipFilter::AlphaCoverage).prepend_error("Failed to generate mipmaps:")?;
+        paa.update_taggs();
+    } else if paa.mipmaps.len() == 1 {
+        paa.generate_mipmaps(MipFilter::Default).prepend_error("Failed to generate mipmaps:")?;
+        paa.update_taggs();
+    }
+
+    paa.write(output).prepend_error("Failed to write PAA:")?;
+
+    Ok(())
+}
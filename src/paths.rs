@@ -0,0 +1,329 @@
+//! Converting between the paths in PBOs and configs and the paths of the file system
+
+use std::fs::{read_dir};
+use std::io::{Error};
+use std::path::{Path, PathBuf};
+use std::sync::{RwLock};
+
+use once_cell::sync::Lazy;
+
+use crate::error::*;
+
+/// How paths of PBO entries and game files are normalized and compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathPolicy {
+    /// Treat paths differing only in case as the same file, like the engine does. True by default.
+    pub ignore_case: bool,
+    /// Treat forward slashes as separators and replace them with backslashes. True by default.
+    pub convert_slashes: bool,
+}
+
+impl Default for PathPolicy {
+    fn default() -> PathPolicy {
+        PathPolicy {
+            ignore_case: true,
+            convert_slashes: true,
+        }
+    }
+}
+
+static POLICY: Lazy<RwLock<PathPolicy>> = Lazy::new(|| RwLock::new(PathPolicy::default()));
+
+/// Sets the policy used by the functions of this module, e.g. to tell files apart by case for
+/// tools on case-sensitive file systems.
+pub fn init_path_policy(policy: PathPolicy) {
+    *POLICY.write().unwrap() = policy;
+}
+
+/// Returns the policy used by the functions of this module.
+pub fn path_policy() -> PathPolicy {
+    *POLICY.read().unwrap()
+}
+
+impl PathPolicy {
+    /// Returns the path with backslashes as separators and without leading, trailing and repeated
+    /// separators, as the names of PBO entries and prefixes are written.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::paths::PathPolicy;
+    /// let policy = PathPolicy::default();
+    /// assert_eq!("x\\foo\\data\\icon_ca.paa", policy.normalize("/x/foo//data\\icon_ca.paa"));
+    ///
+    /// let policy = PathPolicy { convert_slashes: false, ..PathPolicy::default() };
+    /// assert_eq!("x/foo\\data", policy.normalize("\\x/foo\\data\\"));
+    /// ```
+    pub fn normalize(&self, path: &str) -> String {
+        let is_separator = |c: char| c == '\\' || (self.convert_slashes && c == '/');
+        path.split(is_separator).filter(|part| !part.is_empty()).collect::<Vec<&str>>().join("\\")
+    }
+
+    /// Returns the normalized path folded to lowercase if case is ignored, for comparing paths
+    /// and as key of maps of files.
+    pub fn key(&self, path: &str) -> String {
+        let normalized = self.normalize(path);
+        if self.ignore_case { normalized.to_lowercase() } else { normalized }
+    }
+
+    /// Returns whether both paths name the same file.
+    pub fn eq(&self, a: &str, b: &str) -> bool {
+        self.key(a) == self.key(b)
+    }
+
+    /// Returns the absolute path of a game file, the file named `path` in the PBO with the
+    /// prefix, with a leading backslash as configs refer to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::paths::PathPolicy;
+    /// let policy = PathPolicy::default();
+    /// assert_eq!("\\x\\foo\\addons\\main\\data\\icon_ca.paa", policy.join_prefix("x\\foo\\addons\\main\\", "data/icon_ca.paa"));
+    /// assert_eq!("\\data\\icon_ca.paa", policy.join_prefix("", "data\\icon_ca.paa"));
+    /// ```
+    pub fn join_prefix(&self, prefix: &str, path: &str) -> String {
+        match self.normalize(prefix).as_str() {
+            "" => format!("\\{}", self.normalize(path)),
+            prefix => format!("\\{}\\{}", prefix, self.normalize(path)),
+        }
+    }
+
+    /// Returns the normalized path relative to the prefix if the path is in the PBO with that
+    /// prefix, with or without a leading backslash. Every path is in a PBO without prefix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::paths::PathPolicy;
+    /// let policy = PathPolicy::default();
+    /// assert_eq!(Some("data\\icon_ca.paa".to_string()), policy.strip_prefix("\\X\\Foo\\data\\icon_ca.paa", "x\\foo"));
+    /// assert_eq!(None, policy.strip_prefix("\\x\\foobar\\icon_ca.paa", "x\\foo"));
+    ///
+    /// let policy = PathPolicy { ignore_case: false, ..PathPolicy::default() };
+    /// assert_eq!(None, policy.strip_prefix("\\X\\Foo\\data\\icon_ca.paa", "x\\foo"));
+    /// ```
+    pub fn strip_prefix(&self, path: &str, prefix: &str) -> Option<String> {
+        let path = self.normalize(path);
+        let prefix = self.normalize(prefix);
+        if prefix.is_empty() {
+            return Some(path);
+        }
+
+        let (head, rest) = (path.get(..prefix.len())?, path.get(prefix.len()..)?);
+        match rest.strip_prefix('\\') {
+            Some(relative) if self.key(head) == self.key(&prefix) => Some(relative.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the path with backslashes as PBO entries are named, see `PathPolicy::normalize`.
+pub fn normalize(path: &str) -> String {
+    path_policy().normalize(path)
+}
+
+/// Returns the key to compare the path with, see `PathPolicy::key`.
+pub fn path_key(path: &str) -> String {
+    path_policy().key(path)
+}
+
+/// Returns whether both paths name the same file, see `PathPolicy::eq`.
+pub fn paths_equal(a: &str, b: &str) -> bool {
+    path_policy().eq(a, b)
+}
+
+/// Returns the absolute path of the file in the PBO with the prefix, see `PathPolicy::join_prefix`.
+pub fn join_prefix(prefix: &str, path: &str) -> String {
+    path_policy().join_prefix(prefix, path)
+}
+
+/// Returns the path relative to the prefix, see `PathPolicy::strip_prefix`.
+pub fn strip_prefix(path: &str, prefix: &str) -> Option<String> {
+    path_policy().strip_prefix(path, prefix)
+}
+
+/// Returns the path seperator used on the current operating system
+pub fn pathsep() -> &'static str {
+    if cfg!(windows) { "\\" } else { "/" }
+}
+
+/// Converts a path with backslashes, like a PBO entry, an include or a prefix, to a path of the
+/// file system. A leading backslash is kept, so the result is absolute like the game path.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::{Path, PathBuf};
+/// # use armake2::paths::to_fs_path;
+/// assert_eq!(Path::new("data").join("icon_ca.paa"), to_fs_path("data\\icon_ca.paa"));
+/// ```
+pub fn to_fs_path(path: &str) -> PathBuf {
+    PathBuf::from(path.replace('\\', pathsep()))
+}
+
+/// Converts a path of the file system, usually relative to the folder being packed, to a path
+/// with backslashes like PBO entries are named.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::{Path};
+/// # use armake2::paths::from_fs_path;
+/// assert_eq!("data\\icon_ca.paa", from_fs_path(&Path::new("data").join("icon_ca.paa")));
+/// ```
+pub fn from_fs_path(path: &Path) -> String {
+    path.to_string_lossy().replace('/', "\\")
+}
+
+/// Returns whether the name matches the pattern, in which `*` matches any part of the name.
+fn matches_name(name: &str, pattern: &str) -> bool {
+    match pattern.find('*') {
+        None => name == pattern,
+        Some(index) => name.len() >= index && name[..index] == pattern[..index] &&
+            (index..=name.len()).any(|i| name.is_char_boundary(i) && matches_name(&name[i..], &pattern[index + 1..])),
+    }
+}
+
+fn matches_parts(path: &[&str], glob: &[&str]) -> bool {
+    match glob.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|i| matches_parts(&path[i..], rest)),
+        Some((part, rest)) => !path.is_empty() && matches_name(path[0], part) && matches_parts(&path[1..], rest),
+    }
+}
+
+/// Returns whether the glob matches the whole path, like a PBO entry. `**` matches any number of
+/// folders, `*` any part of a single file or folder name. Case is ignored as the policy says.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::paths::matches_glob;
+/// assert!(matches_glob("addons\\main\\Macros.hpp", "**/*.hpp"));
+/// assert!(matches_glob("config.cpp", "**\\config.cpp"));
+/// assert!(!matches_glob("main\\config.cpp", "config.cpp"));
+/// ```
+pub fn matches_glob(path: &str, glob: &str) -> bool {
+    let (path, glob) = (path_key(path), path_key(glob));
+    let path: Vec<&str> = path.split('\\').filter(|p| !p.is_empty()).collect();
+    let glob: Vec<&str> = glob.split('\\').filter(|p| !p.is_empty()).collect();
+
+    matches_parts(&path, &glob)
+}
+
+/// Returns whether the glob matches the end of the path, see `matches_glob`.
+pub fn matches_glob_suffix(path: &str, glob: &str) -> bool {
+    let path = normalize(&path.replace('/', "\\"));
+    let parts: Vec<&str> = path.split('\\').filter(|p| !p.is_empty()).collect();
+
+    (0..parts.len()).any(|i| matches_glob(&parts[i..].join("\\"), &glob.replace('/', "\\")))
+}
+
+fn collect_glob(folder: &Path, parts: &[String], files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let (part, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => {
+            if folder.is_file() { files.push(folder.to_path_buf()); }
+            return Ok(());
+        }
+    };
+    if !folder.as_os_str().is_empty() && !folder.is_dir() {
+        return Ok(());
+    }
+
+    let read = if folder.as_os_str().is_empty() { Path::new(".") } else { folder };
+    let mut entries: Vec<PathBuf> = Vec::new();
+    for entry in read_dir(read).prepend_error(format!("Failed to read {}:", read.display()))? {
+        entries.push(folder.join(entry?.file_name()));
+    }
+
+    if part == "**" {
+        collect_glob(folder, rest, files)?;
+        for entry in entries.iter().filter(|e| e.is_dir()) {
+            collect_glob(entry, parts, files)?;
+        }
+    } else {
+        for entry in entries {
+            if matches_name(&entry.file_name().unwrap().to_string_lossy(), part) {
+                collect_glob(&entry, rest, files)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the files of the file system matching the glob, sorted. `**` matches any number of
+/// folders, `*` any part of a single file or folder name, and case matters like on the file
+/// system. Without wildcards, the path itself is returned if it is a file.
+pub fn glob_files(glob: &str) -> Result<Vec<PathBuf>, Error> {
+    let mut folder = PathBuf::new();
+    let mut parts: Vec<String> = Vec::new();
+    for component in Path::new(glob).components() {
+        let name = component.as_os_str().to_string_lossy();
+        if parts.is_empty() && !name.contains('*') {
+            folder.push(component);
+        } else {
+            parts.push(name.to_string());
+        }
+    }
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    collect_glob(&folder, &parts, &mut files)?;
+    files.sort();
+    files.dedup();
+
+    Ok(files)
+}
+
+/// Address of files in PBOs as `<pbo>::<entry>`, both given as globs, e.g.
+/// `addons/*.pbo::**/*.hpp`. Without `::<entry>`, the address names all files of the PBOs.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::paths::EntryAddress;
+/// let address = EntryAddress::parse("addons/*.pbo::**/*.hpp");
+/// assert_eq!("addons/*.pbo", address.pbo);
+/// assert!(address.matches("ui\\Dialogs.HPP"));
+/// assert!(!address.matches("config.cpp"));
+///
+/// assert!(EntryAddress::parse("main.pbo").matches("config.cpp"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryAddress {
+    /// glob of the PBOs on the file system
+    pub pbo: String,
+    /// glob of the entries in the PBOs
+    pub entry: String,
+}
+
+impl EntryAddress {
+    /// Parses an address, splitting at the first `::`.
+    pub fn parse(address: &str) -> EntryAddress {
+        match address.find("::") {
+            Some(index) => EntryAddress { pbo: address[..index].to_string(), entry: address[index + 2..].to_string() },
+            None => EntryAddress { pbo: address.to_string(), entry: "**".to_string() },
+        }
+    }
+
+    /// Returns whether the argument is an address of entries rather than a plain path.
+    pub fn is_address(argument: &str) -> bool {
+        argument.contains("::")
+    }
+
+    /// Returns the PBOs the address names, failing if there are none.
+    pub fn pbos(&self) -> Result<Vec<PathBuf>, Error> {
+        let pbos = glob_files(&self.pbo)?;
+        if pbos.is_empty() {
+            return Err(error!("No PBO matches \"{}\".", self.pbo));
+        }
+        Ok(pbos)
+    }
+
+    /// Returns whether the address names the entry of a PBO it names.
+    pub fn matches(&self, entry: &str) -> bool {
+        matches_glob(entry, &self.entry)
+    }
+}
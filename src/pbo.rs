@@ -1,19 +1,63 @@
-use std::collections::{HashMap};
+use std::borrow::{Cow};
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::{Entry};
 use std::ffi::{OsStr};
-use std::fs::{File, create_dir_all, read_dir};
-use std::io::{Read, Write, Seek, SeekFrom, Error, Cursor};
-use std::path::{PathBuf};
+use std::fmt;
+use std::fs::{File, OpenOptions, create_dir_all, read_dir};
+use std::io::{Read, Write, Seek, SeekFrom, Error, ErrorKind, Cursor, Take, copy, sink};
+use std::path::{Path, PathBuf};
+use std::sync::{RwLock};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use linked_hash_map::{LinkedHashMap};
+use once_cell::sync::Lazy;
 use openssl::hash::{Hasher, MessageDigest};
+use rayon::prelude::*;
 use regex::{Regex};
+use serde::{Serialize, Deserialize};
 
 use crate::error::*;
 use crate::io::*;
 use crate::config::*;
 use crate::preprocess::*;
 use crate::binarize;
+use crate::cache::{cached, cached_with_includes, cache_key};
+use crate::hooks::{Hooks, HookStage};
+use crate::lint::{check_entry_path, check_functions, check_header_extension, check_patches, run_rules, LintContext};
+use crate::paa::PAAType;
+use crate::paths::{from_fs_path, join_prefix, normalize, path_key, to_fs_path, EntryAddress};
+use crate::target::target;
+
+/// Packing method of the header extensions entry ("Vers")
+const PACKING_VERSION: u32 = 0x5665_7273;
+/// Packing method of compressed entries ("Cprs")
+const PACKING_COMPRESSED: u32 = 0x4370_7273;
+/// Packing method of encrypted entries in EBOs ("Encr")
+const PACKING_ENCRYPTED: u32 = 0x456e_6372;
+
+/// Type of a container as identified from its headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContainerType {
+    /// regular PBO
+    PBO,
+    /// PBO with encrypted files (EBO), as used for official DLC content
+    EBO,
+    /// PBO with headers manipulated to break unpacking tools, with the manipulation found
+    Obfuscated(String),
+    /// data that isn't a PBO at all
+    Unknown,
+}
+
+impl fmt::Display for ContainerType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContainerType::PBO => write!(f, "PBO"),
+            ContainerType::EBO => write!(f, "encrypted PBO (EBO)"),
+            ContainerType::Obfuscated(reason) => write!(f, "obfuscated PBO ({})", reason),
+            ContainerType::Unknown => write!(f, "unknown (not a PBO)"),
+        }
+    }
+}
 
 struct PBOHeader {
     filename: String,
@@ -31,7 +75,7 @@ struct PBOHeader {
 /// ```
 /// # use std::path::PathBuf;
 /// # use armake2::pbo::PBO;
-/// let pbo = PBO::from_directory(PathBuf::from("src"), false, &Vec::new(), &Vec::new()).expect("Failed to create PBO");
+/// let pbo = PBO::from_directory(PathBuf::from("src"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).expect("Failed to create PBO");
 ///
 /// assert!(pbo.files.iter().any(|(name, _data)| name == "main.rs"));
 ///
@@ -43,16 +87,77 @@ struct PBOHeader {
 pub struct PBO {
     pub files: LinkedHashMap<String, Cursor<Box<[u8]>>>,
     pub header_extensions: HashMap<String, String>,
-    headers: Vec<PBOHeader>,
     /// only defined when reading existing PBOs, for created PBOs this is calculated during writing
     /// and included in the output
     pub checksum: Option<Vec<u8>>,
+    /// LZSS compressed files of a read PBO as they are stored, `files` has their decompressed
+    /// content. Files are written with this data while their content is unchanged.
+    pub compressed: HashMap<String, CompressedFile>,
+}
+
+/// Data of a compressed file as it is stored in a read PBO
+pub struct CompressedFile {
+    pub data: Box<[u8]>,
+    /// SHA-1 hash of the decompressed content, to tell whether it changed without decompressing
+    /// the data again
+    pub hash: Vec<u8>,
+}
+
+impl CompressedFile {
+    fn new(data: Box<[u8]>, content: &[u8]) -> CompressedFile {
+        CompressedFile {
+            data,
+            hash: sha1(content),
+        }
+    }
+}
+
+fn sha1(data: &[u8]) -> Vec<u8> {
+    let mut h = Hasher::new(MessageDigest::sha1()).unwrap();
+    h.update(data).unwrap();
+    h.finish().unwrap().to_vec()
+}
+
+/// Extensions of the files compressed with `--compress`: scripts and other text files
+pub const COMPRESSED_EXTENSIONS: &[&str] = &["sqf", "sqs", "hpp", "h", "inc", "cpp", "ext", "fsm", "bikb", "sqm", "txt",
+    "xml", "csv", "html", "rvmat", "bisurf", "cfg"];
+
+static COMPRESSION: Lazy<RwLock<Option<Vec<String>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Sets the extensions of the files that are compressed with LZSS when writing PBOs, `None`
+/// stores all files uncompressed.
+pub fn init_compression(extensions: Option<Vec<String>>) {
+    *COMPRESSION.write().unwrap() = extensions.map(|e| e.iter().map(|ext| ext.trim_start_matches('.').to_lowercase()).collect());
+}
+
+/// Returns whether the file is compressed when writing PBOs, see `init_compression`.
+pub fn compresses(name: &str) -> bool {
+    let extension = match name.rsplit_once('.') {
+        Some((_, extension)) if !extension.contains('\\') => extension.to_lowercase(),
+        _ => { return false; }
+    };
+    COMPRESSION.read().unwrap().as_ref().map(|extensions| extensions.contains(&extension)).unwrap_or(false)
+}
+
+/// Returns the time in `SOURCE_DATE_EPOCH`, the timestamp of files packed for reproducible
+/// builds. Without it, files are packed with timestamp 0.
+///
+/// See <https://reproducible-builds.org/specs/source-date-epoch/>.
+pub fn source_date_epoch() -> Option<u32> {
+    let value = std::env::var("SOURCE_DATE_EPOCH").ok()?;
+    match value.trim().parse::<u64>() {
+        Ok(seconds) => Some(seconds.min(u64::from(u32::MAX)) as u32),
+        Err(_) => {
+            warning(format!("SOURCE_DATE_EPOCH \"{}\" is not a number of seconds, ignoring it.", value), Some("invalid-source-date-epoch"), (None, None));
+            None
+        }
+    }
 }
 
 impl PBOHeader {
-    fn read<I: Read>(input: &mut I) -> Result<PBOHeader, Error> {
+    fn read<I: Read>(input: &mut I, max_name_length: usize) -> Result<PBOHeader, Error> {
         Ok(PBOHeader {
-            filename: input.read_cstring()?,
+            filename: input.read_cstring_bounded(max_name_length)?,
             packing_method: input.read_u32::<LittleEndian>()?,
             original_size: input.read_u32::<LittleEndian>()?,
             reserved: input.read_u32::<LittleEndian>()?,
@@ -73,20 +178,306 @@ impl PBOHeader {
 }
 
 fn matches_glob(s: &str, pattern: &str) -> bool {
-    if let Some(index) = pattern.find('*') {
-        if s[..index] != pattern[..index] { return false; }
+    match pattern.find('*') {
+        Some(index) => s.get(..index) == Some(&pattern[..index]) &&
+            (index..=s.len()).any(|i| s.is_char_boundary(i) && matches_glob(&s[i..], &pattern[(index + 1)..])),
+        None => s == pattern,
+    }
+}
+
+/// Returns whether the name of a file in a PBO is safe to extract: relative, without parent
+/// folder components and without characters Windows doesn't allow in file names. Forward slashes
+/// are checked like backslashes, as they separate folders on other systems.
+pub(crate) fn valid_filename(name: &str) -> bool {
+    !name.chars().any(|c| c.is_control() || "<>|\"?*:".contains(c)) &&
+        !name.starts_with('\\') && !name.starts_with('/') &&
+        !name.split(['\\', '/']).any(|part| part.is_empty() || part == "..")
+}
+
+/// Header extensions in the order they are stored in
+type OrderedExtensions = Vec<(String, String)>;
+
+/// Bounds on the headers of PBOs and the data read from them, for reading PBOs from untrusted
+/// sources without the headers making armake2 allocate arbitrary amounts of memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadLimits {
+    /// maximum number of files
+    pub max_entries: usize,
+    /// maximum number of header extensions
+    pub max_header_extensions: usize,
+    /// maximum length in bytes of file names and of the keys and values of header extensions
+    pub max_name_length: usize,
+    /// maximum number of bytes read from a file at once, e.g. to detect its type
+    pub max_read: usize,
+}
+
+impl ReadLimits {
+    /// Limits that regular PBOs stay far below: 65536 files, 256 header extensions, names of
+    /// 1024 bytes and 64 KiB read from a file.
+    pub fn untrusted() -> ReadLimits {
+        ReadLimits {
+            max_entries: 65536,
+            max_header_extensions: 256,
+            max_name_length: 1024,
+            max_read: 65536,
+        }
+    }
 
-        for i in (index+1)..(s.len()-1) {
-            if matches_glob(&s[i..].to_string(), &pattern[(index+1)..].to_string()) { return true; }
+    /// No limits, for PBOs from trusted sources.
+    pub fn unlimited() -> ReadLimits {
+        ReadLimits {
+            max_entries: usize::MAX,
+            max_header_extensions: usize::MAX,
+            max_name_length: usize::MAX,
+            max_read: usize::MAX,
         }
+    }
+}
 
-        false
-    } else {
-        s == pattern
+/// Reads the headers and header extensions of a PBO, identifying the type of container if they
+/// don't describe a regular PBO.
+fn read_headers<I: Read>(input: &mut I) -> Result<(Vec<PBOHeader>, OrderedExtensions), ContainerType> {
+    read_headers_limited(input, &ReadLimits::unlimited())
+}
+
+/// Reads the headers like `read_headers`, PBOs with headers exceeding the limits are identified
+/// as obfuscated.
+fn read_headers_limited<I: Read>(input: &mut I, limits: &ReadLimits) -> Result<(Vec<PBOHeader>, OrderedExtensions), ContainerType> {
+    let mut headers: Vec<PBOHeader> = Vec::new();
+    let mut first = true;
+    let mut header_extensions: Vec<(String, String)> = Vec::new();
+    let truncated = |first: bool| if first { ContainerType::Unknown } else { ContainerType::Obfuscated("truncated headers".to_string()) };
+
+    loop {
+        let header = match PBOHeader::read(input, limits.max_name_length) {
+            Ok(header) => header,
+            // not UTF-8 or longer than the limit
+            Err(ref e) if !first && e.kind() == ErrorKind::InvalidData => { return Err(ContainerType::Obfuscated("unreadable file name".to_string())); },
+            Err(_) => { return Err(truncated(first)); }
+        };
+
+        if header.packing_method == PACKING_VERSION {
+            if !first {
+                return Err(ContainerType::Obfuscated("header extensions after the first file".to_string()));
+            }
+
+            loop {
+                let s = input.read_cstring_bounded(limits.max_name_length).map_err(|_| truncated(false))?;
+                if s.is_empty() { break; }
+
+                let value = input.read_cstring_bounded(limits.max_name_length).map_err(|_| truncated(false))?;
+                header_extensions.retain(|(key, _)| *key != s);
+                header_extensions.push((s, value));
+                if header_extensions.len() > limits.max_header_extensions {
+                    return Err(ContainerType::Obfuscated(format!("more than {} header extensions", limits.max_header_extensions)));
+                }
+            }
+        } else if header.filename.is_empty() {
+            break;
+        } else {
+            if first && !valid_filename(&header.filename) {
+                return Err(ContainerType::Unknown);
+            }
+            headers.push(header);
+            if headers.len() > limits.max_entries {
+                return Err(ContainerType::Obfuscated(format!("more than {} files", limits.max_entries)));
+            }
+        }
+
+        first = false;
+    }
+
+    let mut names: HashSet<String> = HashSet::new();
+    for header in &headers {
+        if header.packing_method == PACKING_ENCRYPTED {
+            return Err(ContainerType::EBO);
+        }
+        if header.packing_method != 0 && header.packing_method != PACKING_COMPRESSED {
+            return Err(ContainerType::Obfuscated(format!("unknown packing method 0x{:08x}", header.packing_method)));
+        }
+        if !valid_filename(&header.filename) {
+            return Err(ContainerType::Obfuscated(format!("invalid file name {:?}", header.filename)));
+        }
+        if !names.insert(path_key(&header.filename)) {
+            return Err(ContainerType::Obfuscated(format!("duplicate file {}", header.filename)));
+        }
+    }
+
+    Ok((headers, header_extensions))
+}
+
+/// Decompresses the data of a compressed entry, checking that it has its original size.
+fn decompress_entry(name: &str, data: &[u8], original_size: u32) -> Result<Vec<u8>, Error> {
+    let decompressed = lzss_decompress(data).prepend_error(format!("Failed to decompress {}:", name))?;
+    if decompressed.len() != original_size as usize {
+        return Err(error!("Failed to decompress {}: {} bytes instead of {}.", name, decompressed.len(), original_size));
+    }
+    Ok(decompressed)
+}
+
+/// Returns the error for containers that can't be read as PBOs.
+fn container_error(container: ContainerType) -> Error {
+    match container {
+        ContainerType::Unknown => error!("Input is not a PBO."),
+        container => error!("Input is an {}, which is not supported.", container),
+    }
+}
+
+/// Identifies the type of the container in input from its headers.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::PathBuf;
+/// # use armake2::pbo::{PBO, ContainerType, detect_container};
+/// let pbo = PBO::from_directory(PathBuf::from("src"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+///
+/// assert_eq!(ContainerType::PBO, detect_container(&mut pbo.to_cursor().unwrap()));
+/// assert_eq!(ContainerType::Unknown, detect_container(&mut "class CfgPatches {};".as_bytes()));
+/// ```
+pub fn detect_container<I: Read>(input: &mut I) -> ContainerType {
+    detect_container_limited(input, &ReadLimits::unlimited())
+}
+
+/// Identifies the type of the container like `detect_container`, PBOs with headers exceeding
+/// the limits are identified as obfuscated.
+pub fn detect_container_limited<I: Read>(input: &mut I, limits: &ReadLimits) -> ContainerType {
+    let (headers, _) = match read_headers_limited(input, limits) {
+        Ok(result) => result,
+        Err(container) => { return container; }
+    };
+
+    let size: u64 = headers.iter().map(|h| u64::from(h.data_size)).sum();
+    // file data, the null byte and the checksum
+    if copy(&mut input.take(size + 21), &mut sink()).unwrap_or(0) != size + 21 {
+        return ContainerType::Obfuscated("file sizes exceed the PBO".to_string());
+    }
+
+    ContainerType::PBO
+}
+
+/// Type of a file as identified by its content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    /// rapified config (raP)
+    Config,
+    /// editable P3D model (MLOD)
+    MLOD,
+    /// binarized P3D model (ODOL)
+    ODOL,
+    /// texture (PAA)
+    PAA,
+    /// sound (WSS)
+    WSS,
+    /// sound (OGG Vorbis)
+    OGG,
+    /// terrain (WRP)
+    WRP,
+    /// animation (RTM)
+    RTM,
+    /// PNG image
+    PNG,
+    /// JPEG image
+    JPEG,
+    /// DDS texture
+    DDS,
+    /// UTF-8 text like scripts, unrapified configs and materials
+    Text,
+    /// empty file
+    Empty,
+    /// unrecognized binary data
+    Binary,
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FileType::Config => "raP config",
+            FileType::MLOD => "P3D (MLOD)",
+            FileType::ODOL => "P3D (ODOL)",
+            FileType::PAA => "PAA",
+            FileType::WSS => "WSS",
+            FileType::OGG => "OGG",
+            FileType::WRP => "WRP",
+            FileType::RTM => "RTM",
+            FileType::PNG => "PNG",
+            FileType::JPEG => "JPEG",
+            FileType::DDS => "DDS",
+            FileType::Text => "text",
+            FileType::Empty => "empty",
+            FileType::Binary => "binary",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FileType {
+    /// Identifies the type of a file from its magic bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::pbo::FileType;
+    /// assert_eq!(FileType::Config, FileType::detect(b"\0raP\0\0\0\0\x08\0\0\0"));
+    /// assert_eq!(FileType::PNG, FileType::detect(b"\x89PNG\r\n\x1a\n"));
+    /// assert_eq!(FileType::Text, FileType::detect(b"hint \"foo\";"));
+    /// ```
+    pub fn detect(data: &[u8]) -> FileType {
+        if data.is_empty() {
+            return FileType::Empty;
+        }
+
+        let magics: [(&[u8], FileType); 13] = [
+            (b"\0raP", FileType::Config),
+            (b"MLOD", FileType::MLOD),
+            (b"ODOL", FileType::ODOL),
+            (b"WSS0", FileType::WSS),
+            (b"OggS", FileType::OGG),
+            (b"4WVR", FileType::WRP),
+            (b"8WVR", FileType::WRP),
+            (b"OPRW", FileType::WRP),
+            (b"RTM_", FileType::RTM),
+            (b"BMTR", FileType::RTM),
+            (b"\x89PNG", FileType::PNG),
+            (b"\xff\xd8\xff", FileType::JPEG),
+            (b"DDS ", FileType::DDS),
+        ];
+        if let Some((_, file_type)) = magics.iter().find(|(magic, _)| data.starts_with(magic)) {
+            return *file_type;
+        }
+
+        if data.len() >= 6 && PAAType::from_u16(u16::from_le_bytes([data[0], data[1]])).is_some() && &data[2..6] == b"GGAT" {
+            return FileType::PAA;
+        }
+
+        match std::str::from_utf8(data) {
+            Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => FileType::Text,
+            _ => FileType::Binary,
+        }
+    }
+
+    /// Returns the types expected for files with the given extension, `None` if any type is
+    /// fine.
+    pub fn expected(extension: &str) -> Option<&'static [FileType]> {
+        Some(match extension.to_lowercase().as_str() {
+            "bin" => &[FileType::Config],
+            "cpp" | "hpp" | "h" | "sqf" | "sqs" | "inc" | "ext" | "fsm" | "bikb" | "txt" | "xml" | "csv" | "html" => &[FileType::Text],
+            "rvmat" | "sqm" | "bisurf" | "cfg" => &[FileType::Text, FileType::Config],
+            "p3d" => &[FileType::MLOD, FileType::ODOL],
+            "paa" | "pac" => &[FileType::PAA],
+            "wss" => &[FileType::WSS],
+            "ogg" => &[FileType::OGG],
+            "wrp" => &[FileType::WRP],
+            "rtm" => &[FileType::RTM],
+            "png" => &[FileType::PNG],
+            "jpg" | "jpeg" => &[FileType::JPEG],
+            "dds" => &[FileType::DDS],
+            _ => { return None; }
+        })
     }
 }
 
-fn file_allowed(name: &str, exclude_patterns: &[String]) -> bool {
+pub(crate) fn file_allowed(name: &str, exclude_patterns: &[String]) -> bool {
     for pattern in exclude_patterns {
         if matches_glob(&name, &pattern) { return false; }
     }
@@ -94,40 +485,177 @@ fn file_allowed(name: &str, exclude_patterns: &[String]) -> bool {
     true
 }
 
-impl PBO {
-    /// Reads an existing PBO from input.
-    pub fn read<I: Read>(input: &mut I) -> Result<PBO, Error> {
-        let mut headers: Vec<PBOHeader> = Vec::new();
-        let mut first = true;
-        let mut header_extensions: HashMap<String, String> = HashMap::new();
+/// Estimates the size of a PBO packed from the folder from the sizes of the files that aren't
+/// excluded and their headers. Rapifying configs and binarizing models changes the sizes of these
+/// files slightly.
+pub fn estimate_size(directory: &Path, exclude_patterns: &[String]) -> Result<u64, Error> {
+    // headers without files with room for header extensions, and the footer
+    let mut size: u64 = 1024;
 
-        loop {
-            let header = PBOHeader::read(input)?;
-            // todo: garbage filter
+    for path in list_files(&directory.to_path_buf())? {
+        let name = from_fs_path(path.strip_prefix(directory).unwrap());
+        if !file_allowed(&name, exclude_patterns) { continue; }
 
-            if header.packing_method == 0x5665_7273 {
-                if !first { unreachable!(); }
+        size += name.len() as u64 + 21 + path.metadata()?.len();
+    }
 
-                loop {
-                    let s = input.read_cstring()?;
-                    if s.is_empty() { break; }
+    Ok(size)
+}
 
-                    header_extensions.insert(s, input.read_cstring()?);
-                }
-            } else if header.filename == "" {
-                break;
-            } else {
-                headers.push(header);
+/// Resolves the path to an absolute one, also for files that don't exist yet.
+pub(crate) fn absolute_path(path: &Path) -> Option<PathBuf> {
+    if let Ok(path) = path.canonicalize() {
+        return Some(path);
+    }
+
+    let parent = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    Some(parent.canonicalize().ok()?.join(path.file_name()?))
+}
+
+/// Returns the files of the folder that are build artifacts rather than addon files, with the
+/// reason: PBOs, signatures, private keys, files in the build cache and the output file itself.
+/// Files matching the exclude patterns are ignored.
+///
+/// Packing these creates nested archives or leaks private keys along with the addon.
+pub fn find_artifacts(directory: &Path, exclude_patterns: &[String], output: Option<&Path>, cache: Option<&Path>) -> Result<Vec<(String, &'static str)>, Error> {
+    let output = output.and_then(absolute_path);
+    let cache = cache.and_then(absolute_path);
+    let mut artifacts: Vec<(String, &'static str)> = Vec::new();
+
+    for path in list_files(&directory.to_path_buf())? {
+        let name = from_fs_path(path.strip_prefix(directory).unwrap());
+        if !file_allowed(&name, exclude_patterns) { continue; }
+
+        let absolute = absolute_path(&path);
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        let reason = if absolute.is_some() && absolute == output {
+            "the output file"
+        } else if absolute.is_some() && cache.is_some() && absolute.as_ref().unwrap().starts_with(cache.as_ref().unwrap()) {
+            "in the build cache"
+        } else {
+            match extension.as_str() {
+                "pbo" | "ebo" => "a PBO",
+                "bisign" => "a signature",
+                "biprivatekey" => "a private key",
+                _ => { continue; }
             }
+        };
+        artifacts.push((name, reason));
+    }
+
+    artifacts.sort();
+    Ok(artifacts)
+}
+
+/// File of a folder as it is added to a PBO
+enum BuiltFile {
+    /// header extensions from a `$PBOPREFIX$` file
+    Prefix(HashMap<String, String>),
+    /// entry with its name in the PBO, its data and the config if it is a rapified `config.bin`
+    Entry(String, Vec<u8>, Option<Config>),
+}
+
+/// Reads a file of the folder, rapifying configs and binarizing models if `binarize` is true.
+/// Returns `None` for excluded files.
+fn build_file(directory: &Path, path: &Path, binarize: bool, exclude_patterns: &[String], includefolders: &[PathBuf], defines: &[(String, String)]) -> Result<Option<BuiltFile>, Error> {
+    let mut relative = path.strip_prefix(directory).unwrap().to_path_buf();
+    if binarize && relative.file_name() == Some(OsStr::new("config.cpp")) {
+        relative = relative.with_file_name("config.bin");
+    }
+
+    let mut name: String = from_fs_path(&relative);
+    let is_binarizable = Regex::new(".(rtm|p3d)$").unwrap().is_match(&name);
+
+    if !file_allowed(&name, exclude_patterns) { return Ok(None); }
+
+    let mut file = File::open(path)?;
+
+    if name == "$PBOPREFIX$" {
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        Ok(Some(BuiltFile::Prefix(parse_prefix(&content))))
+    } else if binarize && ["cpp", "rvmat"].contains(&path.extension().unwrap_or_else(|| OsStr::new("")).to_str().unwrap()) {
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+
+        let options = PreprocessOptions::new(defines);
+        let folders: Vec<String> = includefolders.iter().chain(options.includepaths.iter()).map(|f| f.display().to_string()).collect();
+        let defines_joined: Vec<String> = defines.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        let key = cache_key("config", &[path.to_str().unwrap().as_bytes(), content.as_bytes(), folders.join("\n").as_bytes(), defines_joined.join("\n").as_bytes()]);
 
-            first = false;
+        // configs are only preprocessed again if they or their includes changed
+        let rapified = cached_with_includes(&key, || {
+            let (preprocessed, info) = preprocess_with(content, Some(path.to_path_buf()), includefolders, &options).prepend_error("Failed to parse config:")?;
+            let config = Config::from_preprocessed(&preprocessed, &info).prepend_error("Failed to parse config:")?;
+            Ok((config.to_cursor()?.into_inner().into_vec(), info.includes))
+        })?;
+
+        let config = if name.to_lowercase().ends_with("config.bin") {
+            Some(Config::read_rapified(&mut Cursor::new(&rapified))?)
+        } else {
+            None
+        };
+
+        Ok(Some(BuiltFile::Entry(name, rapified, config)))
+    } else if cfg!(windows) && binarize && is_binarizable {
+        let mut content: Vec<u8> = Vec::new();
+        file.read_to_end(&mut content)?;
+
+        // models are binarized again if a model.cfg or one of its includes changed
+        let mut parts: Vec<Vec<u8>> = vec![name.as_bytes().to_vec(), content];
+        for dependency in binarize::dependencies(path, includefolders) {
+            parts.push(dependency.display().to_string().into_bytes());
+            parts.push(std::fs::read(&dependency).unwrap_or_default());
+        }
+        let key = cache_key("binarize", &parts.iter().map(|p| p.as_slice()).collect::<Vec<&[u8]>>());
+        let binarized = cached(&key, || {
+            let cursor = binarize::binarize(&path.to_path_buf()).prepend_error(format!("Failed to binarize {:?}:", relative).to_string())?;
+            Ok(cursor.into_inner().into_vec())
+        })?;
+
+        Ok(Some(BuiltFile::Entry(name, binarized, None)))
+    } else {
+        if is_binarizable && !cfg!(windows) {
+            warning("On non-Windows systems binarize.exe cannot be used; file will be copied as-is.", Some("non-windows-binarization"), (relative.to_str(), None));
         }
 
+        let mut buffer: Vec<u8> = Vec::new();
+        file.read_to_end(&mut buffer)?;
+
+        // broken stringtables fail silently in game
+        if binarize && relative.file_name().map(|n| n.eq_ignore_ascii_case("stringtable.xml")).unwrap_or(false) {
+            crate::stringtable::check_file(&buffer, &name)?;
+        }
+
+        name = Regex::new(".p3do$").unwrap().replace_all(&name, ".p3d").to_string();
+
+        Ok(Some(BuiltFile::Entry(name, buffer, None)))
+    }
+}
+
+impl PBO {
+    /// Reads an existing PBO from input.
+    ///
+    /// Fails with an error naming the type of container for encrypted, obfuscated and other
+    /// inputs that are not regular PBOs.
+    pub fn read<I: Read>(input: &mut I) -> Result<PBO, Error> {
+        let (headers, header_extensions) = read_headers(input).map_err(container_error)?;
+
         let mut files: LinkedHashMap<String, Cursor<Box<[u8]>>> = LinkedHashMap::new();
+        let mut compressed: HashMap<String, CompressedFile> = HashMap::new();
         for header in &headers {
             let mut buffer: Box<[u8]> = vec![0; header.data_size as usize].into_boxed_slice();
             input.read_exact(&mut buffer)?;
-            files.insert(header.filename.clone(), Cursor::new(buffer));
+            if header.packing_method == PACKING_COMPRESSED {
+                let data = decompress_entry(&header.filename, &buffer, header.original_size)?;
+                compressed.insert(header.filename.clone(), CompressedFile::new(buffer, &data));
+                files.insert(header.filename.clone(), Cursor::new(data.into_boxed_slice()));
+            } else {
+                files.insert(header.filename.clone(), Cursor::new(buffer));
+            }
         }
 
         input.bytes().next();
@@ -136,9 +664,9 @@ impl PBO {
 
         Ok(PBO {
             files,
-            header_extensions,
-            headers,
+            header_extensions: header_extensions.into_iter().collect(),
             checksum: Some(checksum),
+            compressed,
         })
     }
 
@@ -146,62 +674,62 @@ impl PBO {
     ///
     /// `exclude_patterns` contains glob patterns to exclude from the PBO, `includefolders` contain
     /// paths to search for absolute includes and should generally include the current working
-    /// directory.
-    pub fn from_directory(directory: PathBuf, mut binarize: bool, exclude_patterns: &[String], includefolders: &[PathBuf]) -> Result<PBO, Error> {
+    /// directory. `defines` are macros defined before every config is preprocessed.
+    ///
+    /// The first file that fails to build fails the PBO, unless `keep_going` is true. Then all
+    /// files are built and the errors of all failed files are returned together.
+    pub fn from_directory(directory: PathBuf, mut binarize: bool, exclude_patterns: &[String], includefolders: &[PathBuf], defines: &[(String, String)], keep_going: bool) -> Result<PBO, Error> {
         let file_list = list_files(&directory)?;
         let mut files: LinkedHashMap<String, Cursor<Box<[u8]>>> = LinkedHashMap::new();
         let mut header_extensions: HashMap<String,String> = HashMap::new();
+        let mut configs: Vec<(String, Config)> = Vec::new();
 
         if directory.join("$NOBIN$").exists() || directory.join("$NOBIN-NOTEST$").exists() {
             binarize = false;
         }
 
-        for path in file_list {
-            let mut relative = path.strip_prefix(&directory).unwrap().to_path_buf();
-            if binarize && relative.file_name() == Some(OsStr::new("config.cpp")) {
-                relative = relative.with_file_name("config.bin");
-            }
-
-            let mut name: String = relative.to_str().unwrap().replace("/", "\\");
-            let is_binarizable = Regex::new(".(rtm|p3d)$").unwrap().is_match(&name);
-
-            if !file_allowed(&name, &exclude_patterns) { continue; }
-
-            let mut file = File::open(&path)?;
+        // files are built in parallel, but added in the order they are listed in
+        let results: Vec<Result<Option<BuiltFile>, Error>> = file_list.par_iter()
+            .map(|path| build_file(&directory, path, binarize, exclude_patterns, includefolders, defines))
+            .collect();
 
-            if name == "$PBOPREFIX$" {
-                let mut content = String::new();
-                file.read_to_string(&mut content)?;
-                for l in content.lines() {
-                    if l.is_empty() { break; }
+        let mut errors: Vec<String> = Vec::new();
+        for (path, result) in file_list.iter().zip(results) {
+            if let Ok(Some(_)) = result {
+                crate::deps::track(Some(path));
+            }
 
-                    let eq: Vec<String> = l.split('=').map(|s| s.to_string()).collect();
-                    if eq.len() == 1 {
-                        header_extensions.insert("prefix".to_string(), l.to_string());
-                    } else {
-                        header_extensions.insert(eq[0].clone(), eq[1].clone());
+            match result {
+                Ok(Some(BuiltFile::Prefix(extensions))) => { header_extensions.extend(extensions); },
+                Ok(Some(BuiltFile::Entry(name, data, config))) => {
+                    if let Some(config) = config {
+                        configs.push((path.strip_prefix(&directory).unwrap().display().to_string(), config));
                     }
-                }
-            } else if binarize && vec!["cpp", "rvmat"].contains(&path.extension().unwrap_or_else(|| OsStr::new("")).to_str().unwrap()) {
-                let config = Config::read(&mut file, Some(path.clone()), includefolders).prepend_error("Failed to parse config:")?;
-                let cursor = config.to_cursor()?;
+                    files.insert(name, Cursor::new(data.into_boxed_slice()));
+                },
+                Ok(None) => {},
+                Err(e) if !keep_going => { return Err(e); },
+                Err(e) => { errors.push(format!("{}:\n{}", path.strip_prefix(&directory).unwrap().display(), e)); }
+            }
+        }
 
-                files.insert(name, cursor);
-            } else if cfg!(windows) && binarize && is_binarizable {
-                let cursor = binarize::binarize(&path).prepend_error(format!("Failed to binarize {:?}:", relative).to_string())?;
+        if !errors.is_empty() {
+            return Err(error!("{} of {} files failed to build:\n\n{}", errors.len(), file_list.len(), errors.join("\n\n")));
+        }
 
-                files.insert(name, cursor);
-            } else {
-                if is_binarizable && !cfg!(windows) {
-                    warning("On non-Windows systems binarize.exe cannot be used; file will be copied as-is.", Some("non-windows-binarization"), (Some(&relative.to_str().unwrap()), None));
+        // binarized addons come with the headers of their textures, like those built by BI's tools
+        if binarize && !files.keys().any(|name| name.eq_ignore_ascii_case("texHeaders.bin")) {
+            let mut textures: Vec<(String, Vec<u8>)> = files.iter()
+                .filter(|(name, _)| name.to_lowercase().ends_with(".paa") || name.to_lowercase().ends_with(".pac"))
+                .map(|(name, cursor)| (name.clone(), cursor.get_ref().to_vec()))
+                .collect();
+            textures.sort_by_key(|(name, _)| name.to_lowercase());
+
+            if !textures.is_empty() {
+                match crate::paa::texheaders(&textures) {
+                    Ok(data) => { files.insert("texHeaders.bin".to_string(), Cursor::new(data.into_boxed_slice())); },
+                    Err(e) => { warning(format!("Failed to generate texHeaders.bin: {}", e), Some("texheaders"), (None, None)); }
                 }
-
-                let mut buffer: Vec<u8> = Vec::new();
-                file.read_to_end(&mut buffer)?;
-
-                name = Regex::new(".p3do$").unwrap().replace_all(&name, ".p3d").to_string();
-
-                files.insert(name, Cursor::new(buffer.into_boxed_slice()));
             }
         }
 
@@ -210,54 +738,212 @@ impl PBO {
             header_extensions.insert("prefix".to_string(), prefix);
         }
 
+        let names: Vec<String> = files.keys().cloned().collect();
+        for (path, config) in &configs {
+            for (msg, name) in check_functions(&config.functions(), &header_extensions["prefix"], &names) {
+                warning(msg, Some(name), (Some(path.clone()), None));
+            }
+            for (entry, msg, name) in run_rules(config, &LintContext::default(), true) {
+                warning(format!("{}: {}", entry, msg), Some(name), (Some(path.clone()), None));
+            }
+        }
+        // the root config is the config.cpp in the root of the folder, packed as config.bin
+        if let Some((path, config)) = configs.iter().find(|(path, _)| path_key(path) == path_key("config.cpp")) {
+            for (msg, name) in check_patches(config, &header_extensions["prefix"]) {
+                warning(msg, Some(name), (Some(path.clone()), None));
+            }
+        }
+
         Ok(PBO {
             files,
             header_extensions,
-            headers: Vec::new(),
             checksum: None,
+            compressed: HashMap::new(),
         })
     }
 
+    /// Rewrites paths in rapified configs that are relative to the config or to the root of the
+    /// PBO, like `data\tex_co.paa`, to the full paths starting with the prefix the engine expects,
+    /// like `\x\addon\data\tex_co.paa`. Only strings naming a file of the PBO are rewritten.
+    /// Returns the number of rewritten paths.
+    pub fn rewrite_paths(&mut self) -> Result<usize, Error> {
+        let prefix = self.header_extensions.get("prefix").map(|p| normalize(p)).unwrap_or_default();
+        let names: HashMap<String, String> = self.files.keys().map(|name| (path_key(name), name.clone())).collect();
+        let configs: Vec<String> = self.files.iter()
+            .filter(|(_, cursor)| cursor.get_ref().starts_with(b"\0raP"))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let mut rewritten = 0;
+        for name in configs {
+            let folder = name.rsplit_once('\\').map(|(folder, _)| folder.to_string()).unwrap_or_default();
+            let mut config = Config::read_rapified(&mut Cursor::new(self.files[&name].get_ref())).prepend_error(format!("Failed to read {}:", name))?;
+
+            let count = config.replace_strings(|s| {
+                if s.starts_with('\\') || s.starts_with('/') || !s.contains('.') { return None; }
+
+                [format!("{}\\{}", folder, s), s.to_string()].iter()
+                    .find_map(|candidate| names.get(&path_key(candidate)))
+                    .map(|file| join_prefix(&prefix, file))
+            });
+
+            if count > 0 {
+                *self.files.get_mut(&name).unwrap() = config.to_cursor()?;
+                rewritten += count;
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Returns the compressed data of the file as it was read, `None` if it wasn't compressed or
+    /// its content changed since.
+    fn read_compressed(&self, name: &str) -> Option<&[u8]> {
+        let stored = self.compressed.get(name)?;
+        let data = self.files.get(name)?.get_ref();
+        if sha1(data) == stored.hash { Some(&stored.data) } else { None }
+    }
+
+    /// Returns the data of the file as it was read: compressed if it was read compressed and is
+    /// unchanged. Unlike `stored_data`, this never compresses files. `None` if there is no such
+    /// file.
+    pub fn read_data(&self, name: &str) -> Option<(u32, &[u8])> {
+        match self.read_compressed(name) {
+            Some(stored) => Some((PACKING_COMPRESSED, stored)),
+            None => self.files.get(name).map(|cursor| (0, &cursor.get_ref()[..])),
+        }
+    }
+
+    /// Returns the data of the file as it is written: compressed if it was read compressed and is
+    /// unchanged, or if files like it are compressed (see `init_compression`) and compression
+    /// makes it smaller. `None` if there is no such file.
+    pub fn stored_data(&self, name: &str) -> Option<(u32, Cow<'_, [u8]>)> {
+        if let Some(stored) = self.read_compressed(name) {
+            return Some((PACKING_COMPRESSED, Cow::Borrowed(stored)));
+        }
+        let data = self.files.get(name)?.get_ref();
+        if !data.is_empty() && compresses(name) {
+            let compressed = lzss_compress(data);
+            if compressed.len() < data.len() {
+                return Some((PACKING_COMPRESSED, Cow::Owned(compressed)));
+            }
+        }
+        Some((0, Cow::Borrowed(&data[..])))
+    }
+
+    /// Returns the data of the file compressed or uncompressed regardless of `init_compression`,
+    /// reusing the compressed data it was read with if it is unchanged. `None` if there is no
+    /// such file.
+    fn packed_data(&self, name: &str, compressed: bool) -> Option<(u32, Cow<'_, [u8]>)> {
+        let data = self.files.get(name)?.get_ref();
+        if !compressed {
+            return Some((0, Cow::Borrowed(&data[..])));
+        }
+
+        match self.read_compressed(name) {
+            Some(stored) => Some((PACKING_COMPRESSED, Cow::Borrowed(stored))),
+            None => Some((PACKING_COMPRESSED, Cow::Owned(lzss_compress(data)))),
+        }
+    }
+
     /// Writes PBO to output.
+    ///
+    /// Header extensions are left out if the target engine doesn't support them.
     pub fn write<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        self.write_entries(output, None)
+    }
+
+    /// Writes PBO to output with the order of header extensions and files and the file headers
+    /// from the manifest.
+    ///
+    /// Files whose size differs from the manifest, as well as files and header extensions that are
+    /// not in it, are written like in `write` after the ones from the manifest.
+    pub fn write_with_manifest<O: Write>(&self, output: &mut O, manifest: &PBOManifest) -> Result<(), Error> {
+        self.write_entries(output, Some(manifest))
+    }
+
+    fn write_entries<O: Write>(&self, output: &mut O, manifest: Option<&PBOManifest>) -> Result<(), Error> {
         let mut headers: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let header_extensions = target().supports_header_extensions();
+        if !header_extensions && !self.header_extensions.is_empty() {
+            warning(format!("Header extensions are not supported by {}, leaving them out.", target().name()), Some("target-feature"), (None, None));
+        }
 
         let ext_header = PBOHeader {
             filename: "".to_string(),
-            packing_method: 0x5665_7273,
+            packing_method: PACKING_VERSION,
             original_size: 0,
             reserved: 0,
             timestamp: 0,
             data_size: 0,
         };
-        ext_header.write(&mut headers)?;
+        if header_extensions {
+            ext_header.write(&mut headers)?;
 
-        if let Some(prefix) = self.header_extensions.get("prefix") {
-            headers.write_all(b"prefix\0")?;
-            headers.write_cstring(prefix)?;
-        }
-
-        for (key, value) in self.header_extensions.iter() {
-            if key == "prefix" { continue; }
+            let mut keys: Vec<&String> = Vec::new();
+            if let Some(manifest) = manifest {
+                keys.extend(manifest.header_extensions.iter().map(|(key, _)| key).filter(|key| self.header_extensions.contains_key(*key)));
+            }
+            if let Some((key, _)) = self.header_extensions.get_key_value("prefix") {
+                if !keys.contains(&key) { keys.push(key); }
+            }
+            let mut rest: Vec<&String> = self.header_extensions.keys().filter(|key| *key != "prefix" && !manifest.map(|m| m.header_extensions.iter().any(|(k, _)| k == *key)).unwrap_or(false)).collect();
+            rest.sort();
+            keys.extend(rest);
 
-            headers.write_cstring(key)?;
-            headers.write_cstring(value)?;
+            for key in keys {
+                headers.write_cstring(key)?;
+                headers.write_cstring(&self.header_extensions[key])?;
+            }
+            headers.write_cstring("")?;
         }
-        headers.write_cstring("".to_string())?;
 
-        let mut files_sorted: Vec<(String,&Cursor<Box<[u8]>>)> = self.files.iter().map(|(a,b)| (a.clone(),b)).collect();
-        files_sorted.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
+        // files in the manifest are stored compressed exactly if they were in the original
+        let manifest_compressed: HashMap<&str, bool> = manifest.map(|m| m.entries.iter().map(|e| (e.name.as_str(), e.is_compressed())).collect()).unwrap_or_default();
+        let mut files_sorted: Vec<(String, u32, Cow<[u8]>)> = self.files.keys().collect::<Vec<_>>().par_iter()
+            .map(|name| {
+                let (packing_method, data) = match manifest_compressed.get(name.as_str()) {
+                    Some(compressed) => self.packed_data(name, *compressed).unwrap(),
+                    None => self.stored_data(name).unwrap(),
+                };
+                ((*name).clone(), packing_method, data)
+            }).collect();
+        files_sorted.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()).then_with(|| a.0.cmp(&b.0)));
+
+        let mut file_headers: Vec<PBOHeader> = Vec::new();
+        if let Some(manifest) = manifest {
+            for entry in &manifest.entries {
+                let data_size = match files_sorted.iter().find(|(name, _, _)| *name == entry.name) {
+                    Some((_, packing_method, data)) if *packing_method == entry.packing_method => data.len() as u32,
+                    _ => { continue; }
+                };
+                if data_size != entry.data_size { continue; }
+
+                file_headers.push(PBOHeader {
+                    filename: entry.name.clone(),
+                    packing_method: entry.packing_method,
+                    original_size: entry.original_size,
+                    reserved: entry.reserved,
+                    timestamp: entry.timestamp,
+                    data_size,
+                });
+            }
+            files_sorted.sort_by_key(|(name, _, _)| file_headers.iter().position(|h| h.filename == *name).unwrap_or(file_headers.len()));
+        }
 
-        for (name, cursor) in &files_sorted {
-            let header = PBOHeader {
+        let timestamp = source_date_epoch().unwrap_or(0);
+        for (name, packing_method, data) in &files_sorted[file_headers.len()..] {
+            file_headers.push(PBOHeader {
                 filename: name.clone(),
-                packing_method: 0,
-                original_size: cursor.get_ref().len() as u32,
+                packing_method: *packing_method,
+                original_size: self.files[name].get_ref().len() as u32,
                 reserved: 0,
-                timestamp: 0,
-                data_size: cursor.get_ref().len() as u32,
-            };
+                timestamp,
+                data_size: data.len() as u32,
+            });
+        }
 
+        for header in &file_headers {
             header.write(&mut headers)?;
         }
 
@@ -272,9 +958,9 @@ impl PBO {
         output.write_all(headers.get_ref())?;
         h.update(headers.get_ref()).unwrap();
 
-        for (_, cursor) in &files_sorted {
-            output.write_all(cursor.get_ref())?;
-            h.update(cursor.get_ref()).unwrap();
+        for (_, _, data) in &files_sorted {
+            output.write_all(data)?;
+            h.update(data).unwrap();
         }
 
         output.write_all(&[0])?;
@@ -294,7 +980,264 @@ impl PBO {
     }
 }
 
-fn list_files(directory: &PathBuf) -> Result<Vec<PathBuf>, Error> {
+/// Header of a file in a PBO manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub packing_method: u32,
+    pub original_size: u32,
+    pub reserved: u32,
+    pub timestamp: u32,
+    pub data_size: u32,
+}
+
+impl ManifestEntry {
+    /// Returns whether the data of the entry is compressed with LZSS.
+    pub fn is_compressed(&self) -> bool {
+        self.packing_method == PACKING_COMPRESSED
+    }
+
+    /// Returns the size of the content of the entry, the original size of compressed entries.
+    pub fn size(&self) -> u32 {
+        if self.is_compressed() { self.original_size } else { self.data_size }
+    }
+}
+
+impl From<PBOHeader> for ManifestEntry {
+    fn from(header: PBOHeader) -> ManifestEntry {
+        ManifestEntry {
+            name: header.filename,
+            packing_method: header.packing_method,
+            original_size: header.original_size,
+            reserved: header.reserved,
+            timestamp: header.timestamp,
+            data_size: header.data_size,
+        }
+    }
+}
+
+/// Metadata of a PBO that is lost when unpacking it: the order of header extensions and files
+/// and the file headers. Together with the unpacked files it allows writing the exact same PBO.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::PathBuf;
+/// # use armake2::pbo::{PBO, PBOManifest};
+/// let pbo = PBO::from_directory(PathBuf::from("src"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+/// let written = pbo.to_cursor().unwrap().into_inner();
+///
+/// let manifest = PBOManifest::from_pbo(&mut &written[..]).unwrap();
+/// assert_eq!(("prefix".to_string(), "src".to_string()), manifest.header_extensions[0]);
+/// assert!(manifest.entries.iter().any(|e| e.name == "main.rs"));
+///
+/// let mut rewritten: Vec<u8> = Vec::new();
+/// pbo.write_with_manifest(&mut rewritten, &manifest).unwrap();
+/// assert_eq!(written, rewritten);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PBOManifest {
+    pub header_extensions: Vec<(String, String)>,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl PBOManifest {
+    /// Reads the manifest from the headers of the PBO in input.
+    pub fn from_pbo<I: Read>(input: &mut I) -> Result<PBOManifest, Error> {
+        let (headers, header_extensions) = read_headers(input).map_err(container_error)?;
+
+        Ok(PBOManifest {
+            header_extensions,
+            entries: headers.into_iter().map(ManifestEntry::from).collect(),
+        })
+    }
+
+    /// Reads a manifest written as JSON.
+    pub fn read<I: Read>(input: &mut I) -> Result<PBOManifest, Error> {
+        serde_json::from_reader(input).map_err(|e| error!("Failed to parse manifest: {}", e))
+    }
+
+    /// Writes the manifest as JSON.
+    pub fn write<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        serde_json::to_writer_pretty(&mut *output, self).map_err(|e| error!("{}", e))?;
+        output.write_all(b"\n")
+    }
+}
+
+/// PBO read lazily from a seekable input
+///
+/// Only the headers are read when it is opened, the data of an entry is read when it is
+/// requested, so PBOs don't have to fit into memory.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::{Cursor, Read};
+/// # use std::path::PathBuf;
+/// # use armake2::pbo::{PBO, PBOReader};
+/// let pbo = PBO::from_directory(PathBuf::from("src"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+///
+/// let mut reader = PBOReader::new(pbo.to_cursor().unwrap()).unwrap();
+/// assert_eq!(pbo.files.len(), reader.entries.len());
+///
+/// let mut main = String::new();
+/// reader.entry("main.rs").unwrap().unwrap().read_to_string(&mut main).unwrap();
+/// assert!(main.contains("fn main()"));
+/// ```
+pub struct PBOReader<I: Read + Seek> {
+    input: I,
+    /// header extensions in the order they are stored in
+    pub header_extensions: Vec<(String, String)>,
+    /// headers of the entries in the order they are stored in
+    pub entries: Vec<ManifestEntry>,
+    /// offsets of the data of the entries in input
+    offsets: Vec<u64>,
+    /// offset of the footer after the data
+    end: u64,
+}
+
+impl<I: Read + Seek> PBOReader<I> {
+    /// Reads the headers of the PBO in input.
+    pub fn new(input: I) -> Result<PBOReader<I>, Error> {
+        PBOReader::read(input, &ReadLimits::unlimited())
+    }
+
+    /// Reads the headers of the PBO in input if they are within the limits and the data of all
+    /// entries is in the input, for PBOs from untrusted sources.
+    pub fn with_limits(input: I, limits: &ReadLimits) -> Result<PBOReader<I>, Error> {
+        let mut reader = PBOReader::read(input, limits)?;
+        let size = reader.input.seek(SeekFrom::End(0))?;
+        if reader.end > size {
+            return Err(container_error(ContainerType::Obfuscated("file sizes exceed the PBO".to_string())));
+        }
+
+        Ok(reader)
+    }
+
+    fn read(mut input: I, limits: &ReadLimits) -> Result<PBOReader<I>, Error> {
+        let (headers, header_extensions) = read_headers_limited(&mut input, limits).map_err(container_error)?;
+
+        let mut offset = input.stream_position()?;
+        let mut offsets: Vec<u64> = Vec::new();
+        for header in &headers {
+            offsets.push(offset);
+            offset += u64::from(header.data_size);
+        }
+
+        Ok(PBOReader {
+            input,
+            header_extensions,
+            entries: headers.into_iter().map(ManifestEntry::from).collect(),
+            offsets,
+            end: offset,
+        })
+    }
+
+    /// Returns the size of the content of all entries, the space needed to unpack them.
+    pub fn data_size(&self) -> u64 {
+        self.entries.iter().map(|e| u64::from(e.size())).sum()
+    }
+
+    /// Reads the SHA-1 checksum from the footer, `None` if the PBO has no footer.
+    pub fn checksum(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        self.input.seek(SeekFrom::Start(self.end))?;
+        let mut footer: Vec<u8> = Vec::new();
+        (&mut self.input).take(21).read_to_end(&mut footer)?;
+
+        match footer.split_first() {
+            Some((0, checksum)) if checksum.len() == 20 => Ok(Some(checksum.to_vec())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Computes the SHA-1 checksum of the headers and data, as it should be stored in the footer.
+    pub fn compute_checksum(&mut self) -> Result<Vec<u8>, Error> {
+        self.input.seek(SeekFrom::Start(0))?;
+        let mut h = Hasher::new(MessageDigest::sha1()).unwrap();
+        let mut limited = (&mut self.input).take(self.end);
+        let mut buffer = [0u8; 65536];
+        loop {
+            let read = limited.read(&mut buffer)?;
+            if read == 0 { break; }
+            h.update(&buffer[..read]).unwrap();
+        }
+        if limited.limit() > 0 {
+            return Err(error!("PBO is truncated."));
+        }
+
+        Ok(h.finish().unwrap().to_vec())
+    }
+
+    /// Returns the manifest of the PBO (see `PBOManifest`).
+    pub fn manifest(&self) -> PBOManifest {
+        PBOManifest {
+            header_extensions: self.header_extensions.clone(),
+            entries: self.entries.clone(),
+        }
+    }
+
+    /// Returns a reader of the data of the entry at the index in `entries` as it is stored,
+    /// compressed for compressed entries.
+    pub fn stored_entry_at(&mut self, index: usize) -> Result<Take<&mut I>, Error> {
+        self.input.seek(SeekFrom::Start(self.offsets[index]))?;
+        Ok((&mut self.input).take(u64::from(self.entries[index].data_size)))
+    }
+
+    /// Returns a reader of the content of the entry at the index in `entries`. Compressed
+    /// entries are decompressed in memory.
+    pub fn entry_at(&mut self, index: usize) -> Result<Box<dyn Read + '_>, Error> {
+        if self.entries[index].is_compressed() {
+            return Ok(Box::new(Cursor::new(self.read_entry_at(index)?)));
+        }
+        Ok(Box::new(self.stored_entry_at(index)?))
+    }
+
+    /// Returns a reader of the content of the entry with the name, `None` if there is no such
+    /// entry.
+    pub fn entry(&mut self, name: &str) -> Result<Option<Box<dyn Read + '_>>, Error> {
+        match self.entries.iter().position(|e| e.name == name) {
+            Some(index) => Ok(Some(self.entry_at(index)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the content of the entry at the index in `entries`, decompressing compressed
+    /// entries.
+    pub fn read_entry_at(&mut self, index: usize) -> Result<Vec<u8>, Error> {
+        let mut buffer: Vec<u8> = Vec::with_capacity(self.entries[index].data_size as usize);
+        self.stored_entry_at(index)?.read_to_end(&mut buffer)?;
+        let entry = &self.entries[index];
+        if buffer.len() != entry.data_size as usize {
+            return Err(error!("{} is truncated.", entry.name));
+        }
+
+        if entry.is_compressed() {
+            return decompress_entry(&entry.name, &buffer, entry.original_size);
+        }
+        Ok(buffer)
+    }
+}
+
+/// Parses the content of a `$PBOPREFIX$` file into header extensions. The file either contains
+/// just the prefix or lines of `key=value`.
+pub(crate) fn parse_prefix(content: &str) -> HashMap<String, String> {
+    let mut header_extensions: HashMap<String, String> = HashMap::new();
+
+    for l in content.lines() {
+        if l.is_empty() { break; }
+
+        let eq: Vec<String> = l.split('=').map(|s| s.to_string()).collect();
+        if eq.len() == 1 {
+            header_extensions.insert("prefix".to_string(), l.to_string());
+        } else {
+            header_extensions.insert(eq[0].clone(), eq[1].clone());
+        }
+    }
+
+    header_extensions
+}
+
+pub(crate) fn list_files(directory: &PathBuf) -> Result<Vec<PathBuf>, Error> {
     let mut files: Vec<PathBuf> = Vec::new();
 
     for entry in read_dir(directory)? {
@@ -311,24 +1254,119 @@ fn list_files(directory: &PathBuf) -> Result<Vec<PathBuf>, Error> {
     Ok(files)
 }
 
-pub fn cmd_inspect<I: Read>(input: &mut I) -> Result<(), Error> {
-    let pbo = PBO::read(input).prepend_error("Failed to read PBO:")?;
+/// Returns the string with control characters escaped, so header extensions can't mess with the
+/// terminal they are printed to.
+fn printable(s: &str) -> String {
+    s.chars().map(|c| if c.is_control() { c.escape_default().to_string() } else { c.to_string() }).collect()
+}
+
+/// Prints the type of every file of the PBO and flags files whose content doesn't match the
+/// extension. With `max_read`, the type is detected from at most that many bytes of each file.
+fn print_types<I: Read + Seek>(reader: &mut PBOReader<I>, max_read: Option<usize>) -> Result<(), Error> {
+    let mut mismatches = 0;
+
+    println!("Path                                               Type");
+    println!("================================================================================");
+    for index in 0..reader.entries.len() {
+        let data = match max_read {
+            // decompressing the whole file for a sample could take any amount of memory
+            Some(max_read) if reader.entries[index].is_compressed() && reader.entries[index].data_size as usize > max_read => {
+                println!("{:50} {:12}", reader.entries[index].name, "compressed");
+                continue;
+            },
+            Some(max_read) => {
+                let mut data: Vec<u8> = Vec::new();
+                reader.entry_at(index)?.take(max_read as u64).read_to_end(&mut data)?;
+                // the sample may end within a UTF-8 character
+                if let Err(e) = std::str::from_utf8(&data) {
+                    if e.error_len().is_none() && data.len() < reader.entries[index].size() as usize {
+                        data.truncate(e.valid_up_to());
+                    }
+                }
+                data
+            },
+            None => reader.read_entry_at(index)?,
+        };
+        let file_type = FileType::detect(&data);
+        let name = &reader.entries[index].name;
+        let extension = name.rsplit('.').next().unwrap_or("");
+        let mismatch = match FileType::expected(extension) {
+            Some(expected) if file_type != FileType::Empty && !expected.contains(&file_type) => {
+                mismatches += 1;
+                format!("  mismatch: expected {}", expected.iter().map(|t| t.to_string()).collect::<Vec<String>>().join(" or "))
+            },
+            _ => String::new(),
+        };
+        println!("{:50} {:12}{}", name, file_type.to_string(), mismatch);
+    }
+
+    if mismatches > 0 {
+        println!("\n{} files don't match their extension.", mismatches);
+    }
 
-    if !pbo.header_extensions.is_empty() {
+    Ok(())
+}
+
+/// Prints the header extensions, the headers of all files, the total size and the checksum of
+/// the PBO in input. Only the headers and the footer are read, and with `types` one file at a
+/// time.
+///
+/// With `manifest`, the order of header extensions and files and the file headers are also
+/// written to that path, so `cmd_pack` can recreate the PBO from its unpacked files.
+///
+/// With `untrusted`, the PBO is read within `ReadLimits::untrusted`, PBOs whose file data
+/// exceeds the input are rejected and nothing is written, for services inspecting uploaded PBOs.
+pub fn cmd_inspect<I: Read + Seek>(input: &mut I, detect: bool, types: bool, manifest: Option<PathBuf>, untrusted: bool) -> Result<(), Error> {
+    let limits = if untrusted { ReadLimits::untrusted() } else { ReadLimits::unlimited() };
+    if untrusted && manifest.is_some() {
+        return Err(error!("A manifest can't be exported when inspecting untrusted PBOs."));
+    }
+
+    if detect {
+        println!("{}", detect_container_limited(input, &limits));
+        return Ok(());
+    }
+
+    let mut reader = if untrusted {
+        PBOReader::with_limits(input, &limits).prepend_error("Failed to read PBO:")?
+    } else {
+        PBOReader::new(input).prepend_error("Failed to read PBO:")?
+    };
+
+    if let Some(path) = manifest {
+        let mut file = File::create(&path).prepend_error(format!("Failed to create {}:", path.display()))?;
+        reader.manifest().write(&mut file).prepend_error("Failed to write manifest:")?;
+    }
+
+    if !reader.header_extensions.is_empty() {
         println!("Header extensions:");
-        for (key, value) in pbo.header_extensions.iter() {
-            println!("- {}={}", key, value);
+        for (key, value) in reader.header_extensions.iter() {
+            println!("- {}={}", printable(key), printable(value));
         }
         println!();
     }
 
-    println!("# Files: {}\n", pbo.files.len());
+    println!("# Files: {}\n", reader.entries.len());
+
+    if types {
+        return print_types(&mut reader, if untrusted { Some(limits.max_read) } else { None }).prepend_error("Failed to read PBO:");
+    }
 
-    println!("Path                                                  Method  Original    Packed");
+    println!("Path                                                  Method  Original    Packed   Timestamp");
     println!("                                                                  Size      Size");
-    println!("================================================================================");
-    for header in pbo.headers {
-        println!("{:50} {:9} {:9} {:9}", header.filename, header.packing_method, header.original_size, header.data_size);
+    println!("===========================================================================================");
+    for entry in &reader.entries {
+        println!("{:50} {:9} {:9} {:9} {:11}", entry.name, entry.packing_method, entry.original_size, entry.data_size, entry.timestamp);
+    }
+
+    // the original size is 0 for uncompressed files
+    let packed: u64 = reader.entries.iter().map(|e| u64::from(e.data_size)).sum();
+    let original: u64 = reader.entries.iter().map(|e| u64::from(if e.original_size > 0 { e.original_size } else { e.data_size })).sum();
+    println!("\nTotal size: {} bytes packed, {} bytes unpacked", packed, original);
+
+    match reader.checksum().prepend_error("Failed to read PBO:")? {
+        Some(checksum) => println!("Checksum: {}", checksum.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+        None => println!("Checksum: missing"),
     }
 
     Ok(())
@@ -349,59 +1387,602 @@ pub fn cmd_cat<I: Read, O: Write>(input: &mut I, output: &mut O, name: &str) ->
     Ok(())
 }
 
-pub fn cmd_unpack<I: Read>(input: &mut I, output: PathBuf, force: bool) -> Result<(), Error> {
-    let pbo = PBO::read(input).prepend_error("Failed to read PBO:")?;
+/// Writes the files named by the addresses (see `EntryAddress`) to output one after another,
+/// or with `folder`, to their game paths in that folder: the prefix of their PBO and their name.
+/// Fails if an address names no PBO or no file.
+pub fn cmd_cat_entries<O: Write>(addresses: &[String], output: &mut O, folder: Option<PathBuf>, force: bool) -> Result<(), Error> {
+    // PBOs that several addresses point into are only opened once
+    let mut readers: HashMap<PathBuf, PBOReader<File>> = HashMap::new();
+    for address in addresses.iter().map(|a| EntryAddress::parse(a)) {
+        let mut found = false;
+        for pbo_path in address.pbos()? {
+            let reader = match readers.entry(pbo_path.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(open_reader(&pbo_path)?),
+            };
+            let prefix = reader.header_extensions.iter().find(|(key, _)| key == "prefix").map(|(_, value)| value.clone()).unwrap_or_default();
+            let indices: Vec<usize> = (0..reader.entries.len()).filter(|i| address.matches(&reader.entries[*i].name)).collect();
+
+            for index in indices {
+                found = true;
+                let name = reader.entries[index].name.clone();
+                let data = reader.read_entry_at(index).prepend_error(format!("Failed to read {}:", pbo_path.display()))?;
+                let folder = match folder {
+                    Some(ref folder) => folder,
+                    None => {
+                        output.write_all(&data).prepend_error("Failed to write output:")?;
+                        continue;
+                    }
+                };
+
+                let game_path = normalize(&join_prefix(&prefix, &name));
+                if game_path.split('\\').any(|part| part == ".." || part.contains(':')) {
+                    return Err(error!("{} in {} has an invalid prefix \"{}\".", name, pbo_path.display(), prefix));
+                }
+                let path = folder.join(to_fs_path(&game_path));
+                if !force && path.exists() {
+                    return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", path.display()));
+                }
+                create_dir_all(path.parent().unwrap()).prepend_error("Failed to create output folder:")?;
+                create_overwriting(&path)?.write_all(&data).prepend_error(format!("Failed to write {}:", path.display()))?;
+            }
+        }
+
+        if !found {
+            return Err(error!("No file in \"{}\" matches \"{}\".", address.pbo, address.entry));
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the single file named by the address (see `EntryAddress`), so commands taking a file
+/// can read it from a PBO as `main.pbo::config.bin`. Fails if no file or more than one file
+/// matches.
+pub fn read_address(address: &str) -> Result<Box<[u8]>, Error> {
+    let address = EntryAddress::parse(address);
+    let mut found: Vec<(PathBuf, String)> = Vec::new();
+    let mut last: Option<(PBOReader<File>, usize)> = None;
+    for pbo_path in address.pbos()? {
+        let reader = open_reader(&pbo_path)?;
+        let indices: Vec<usize> = (0..reader.entries.len()).filter(|i| address.matches(&reader.entries[*i].name)).collect();
+        found.extend(indices.iter().map(|i| (pbo_path.clone(), reader.entries[*i].name.clone())));
+        if let Some(index) = indices.last() {
+            last = Some((reader, *index));
+        }
+    }
+
+    match found.len() {
+        0 => Err(error!("No file in \"{}\" matches \"{}\".", address.pbo, address.entry)),
+        1 => {
+            let (mut reader, index) = last.unwrap();
+            Ok(reader.read_entry_at(index).prepend_error(format!("Failed to read {}:", found[0].0.display()))?.into_boxed_slice())
+        },
+        n => {
+            let names: Vec<String> = found.iter().take(5).map(|(pbo, name)| format!("{}::{}", pbo.display(), name)).collect();
+            Err(error!("{} files match \"{}::{}\", expected one: {}{}", n, address.pbo, address.entry, names.join(", "), if n > 5 { ", ..." } else { "" }))
+        }
+    }
+}
+
+/// Opens the PBO at the path to read single entries from it.
+fn open_reader(path: &Path) -> Result<PBOReader<File>, Error> {
+    PBOReader::new(File::open(path).prepend_error(format!("Failed to open {}:", path.display()))?)
+        .prepend_error(format!("Failed to read {}:", path.display()))
+}
+
+/// Converts a rapified config, material or mission to text. Returns the new file name and the
+/// text, or `None` if the file is not rapified or can't be derapified.
+fn convert_file(name: &str, data: &[u8]) -> Option<(String, Vec<u8>)> {
+    if FileType::detect(data) != FileType::Config {
+        return None;
+    }
+
+    let config = match Config::read_rapified(&mut Cursor::new(data)) {
+        Ok(config) => config,
+        Err(e) => {
+            warning(format!("Failed to derapify {}, extracting it as is: {}", name, e), Some("convert-failed"), (None, None));
+            return None;
+        }
+    };
+    let text = config.to_string().ok()?;
 
+    let name = if name.to_lowercase().ends_with(".bin") {
+        format!("{}.cpp", &name[..name.len() - 4])
+    } else {
+        name.to_string()
+    };
+
+    Some((name, text.into_bytes()))
+}
+
+/// Options for `cmd_unpack`
+#[derive(Debug, Clone, Default)]
+pub struct UnpackOptions {
+    /// overwrite existing files, also read-only ones
+    pub force: bool,
+    /// write rapified configs, materials and missions as text and rename `.bin` configs to `.cpp`
+    pub convert: bool,
+    /// permissions of the extracted files like `0o644`. On Windows, files are only made
+    /// read-only if the mode has no write permission for the owner.
+    pub mode: Option<u32>,
+    /// extensions of files to make executable for everyone who can read them, like `sh`
+    pub executable_extensions: Vec<String>,
+}
+
+/// Parses a file mode given in octal like `644` or `0644`.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::pbo::parse_mode;
+/// assert_eq!(0o644, parse_mode("644").unwrap());
+/// assert_eq!(0o2750, parse_mode("02750").unwrap());
+/// assert!(parse_mode("rw-r--r--").is_err());
+/// assert!(parse_mode("17777").is_err());
+/// ```
+pub fn parse_mode(mode: &str) -> Result<u32, Error> {
+    match u32::from_str_radix(mode, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(error!("Invalid file mode \"{}\", expected octal permissions like 644.", mode)),
+    }
+}
+
+/// Fails with a clear message if files can't be created in the folder, e.g. on a read-only
+/// network share, before anything is extracted.
+fn check_writable(folder: &Path) -> Result<(), Error> {
+    let probe = folder.join(format!(".armake2-write-test{}", std::process::id()));
+    match File::create(&probe) {
+        Ok(_) => std::fs::remove_file(&probe).or(Ok(())),
+        Err(e) => Err(error!("Target folder \"{}\" is not writable: {}", folder.display(), e)),
+    }
+}
+
+/// Creates the file for writing, first removing the read-only flag of an existing file if it is
+/// overwritten.
+fn create_overwriting(path: &Path) -> Result<File, Error> {
+    if let Ok(metadata) = path.metadata() {
+        let mut permissions = metadata.permissions();
+        if permissions.readonly() {
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            std::fs::set_permissions(path, permissions).prepend_error(format!("Failed to make read-only file \"{}\" writable:", path.display()))?;
+        }
+    }
+
+    File::create(path).prepend_error("Failed to open output file:")
+}
+
+/// Sets the permissions of an extracted file as given in the options. Failures are only warned
+/// about, some network shares don't support permissions.
+fn set_extracted_permissions(path: &Path, options: &UnpackOptions) {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let executable = options.executable_extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&extension));
+    if options.mode.is_none() && !executable {
+        return;
+    }
+
+    let result = path.metadata().and_then(|metadata| {
+        let mut permissions = metadata.permissions();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut mode = options.mode.unwrap_or(permissions.mode() & 0o7777);
+            if executable {
+                mode |= (mode & 0o444) >> 2;
+            }
+            permissions.set_mode(mode);
+        }
+        #[cfg(not(unix))]
+        {
+            if let Some(mode) = options.mode {
+                permissions.set_readonly(mode & 0o200 == 0);
+            }
+        }
+
+        std::fs::set_permissions(path, permissions)
+    });
+
+    if let Err(e) = result {
+        warning(format!("Failed to set permissions of \"{}\": {}", path.display(), e), Some("unpack-permissions"), (None, None));
+    }
+}
+
+/// Unpacks the PBO in input into the output folder (see `UnpackOptions`). Existing files are
+/// only overwritten with `force`, read-only ones included.
+pub fn cmd_unpack<I: Read + Seek>(input: &mut I, output: PathBuf, options: &UnpackOptions) -> Result<(), Error> {
+    let (force, convert) = (options.force, options.convert);
+    let mut reader = PBOReader::new(input).prepend_error("Failed to read PBO:")?;
+
+    check_space(&output, reader.data_size())?;
     create_dir_all(&output).prepend_error("Failed to create output folder:")?;
+    check_writable(&output)?;
 
-    if !pbo.header_extensions.is_empty() {
+    if !reader.header_extensions.is_empty() {
         let prefix_path = output.join(PathBuf::from("$PBOPREFIX$"));
         if !force && prefix_path.exists() {
             return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", prefix_path.display()));
         }
-        let mut prefix_file = File::create(prefix_path).prepend_error("Failed to create prefix file:")?;
+        let mut prefix_file = create_overwriting(&prefix_path).prepend_error("Failed to create prefix file:")?;
 
-        for (key, value) in pbo.header_extensions.iter() {
+        for (key, value) in reader.header_extensions.iter() {
             prefix_file.write_all(format!("{}={}\n", key, value).as_bytes()).prepend_error("Failed to write prefix file:")?;
         }
+        drop(prefix_file);
+        set_extracted_permissions(&prefix_path, options);
     }
 
-    for (file_name, cursor) in pbo.files.iter() {
-        // @todo: windows
-        let path = output.join(PathBuf::from(file_name.replace("\\", pathsep())));
+    for index in 0..reader.entries.len() {
+        // files are only read into memory for converting them, otherwise they are copied
+        let name = reader.entries[index].name.clone();
+        let data = if convert { Some(reader.read_entry_at(index)?) } else { None };
+        let converted = data.as_ref().and_then(|data| convert_file(&name, data));
+        let file_name = match &converted {
+            Some((converted_name, _)) => converted_name,
+            None => &name,
+        };
+
+        let path = output.join(to_fs_path(file_name));
         if !force && path.exists() {
             return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", path.display()));
         }
         create_dir_all(path.parent().unwrap()).prepend_error("Failed to create output folder:")?;
-        let mut file = File::create(path).prepend_error("Failed to open output file:")?;
-        file.write_all(cursor.get_ref()).prepend_error("Failed to write output file:")?;
+        let mut file = create_overwriting(&path)?;
+        match (&converted, &data) {
+            (Some((_, converted)), _) => file.write_all(converted),
+            (None, Some(data)) => file.write_all(data),
+            (None, None) => copy(&mut reader.entry_at(index)?, &mut file).map(|_| ()),
+        }.prepend_error("Failed to write output file:")?;
+        drop(file);
+        set_extracted_permissions(&path, options);
     }
 
     Ok(())
 }
 
-pub fn cmd_pack<O: Write>(input: PathBuf, output: &mut O, headerext: &[String], excludes: &[String]) -> Result<(), Error> {
-    let mut pbo = PBO::from_directory(input, false, excludes, &Vec::new())?;
+/// Writes the PBO in `original` again and returns the differences between the result and the
+/// original, an empty list if both are byte-identical.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::PathBuf;
+/// # use armake2::pbo::{PBO, roundtrip_differences};
+/// let pbo = PBO::from_directory(PathBuf::from("src"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+/// let written = pbo.to_cursor().unwrap().into_inner();
+///
+/// assert!(roundtrip_differences(&written).unwrap().is_empty());
+/// ```
+pub fn roundtrip_differences(original: &[u8]) -> Result<Vec<String>, Error> {
+    let pbo = PBO::read(&mut Cursor::new(original)).prepend_error("Failed to read PBO:")?;
+    let repacked = pbo.to_cursor()?.into_inner();
+    if repacked == original {
+        return Ok(Vec::new());
+    }
+
+    let mut differences: Vec<String> = Vec::new();
+    let (original_headers, original_extensions) = read_headers(&mut Cursor::new(original)).unwrap();
+    let (headers, extensions) = read_headers(&mut Cursor::new(&repacked)).unwrap();
+    let original_extensions: HashMap<String, String> = original_extensions.into_iter().collect();
+    let extensions: HashMap<String, String> = extensions.into_iter().collect();
+
+    for (key, value) in original_extensions.iter() {
+        match extensions.get(key) {
+            None => differences.push(format!("header extension {} is missing", key)),
+            Some(v) if v != value => differences.push(format!("header extension {} changed from \"{}\" to \"{}\"", key, value, v)),
+            _ => {}
+        }
+    }
+    for key in extensions.keys().filter(|k| !original_extensions.contains_key(*k)) {
+        differences.push(format!("header extension {} was added", key));
+    }
+
+    let original_names: Vec<&str> = original_headers.iter().map(|h| h.filename.as_str()).collect();
+    let names: Vec<&str> = headers.iter().map(|h| h.filename.as_str()).collect();
+    if original_names != names {
+        differences.push("files are stored in a different order".to_string());
+    }
+
+    for original_header in &original_headers {
+        let header = match headers.iter().find(|h| h.filename == original_header.filename) {
+            Some(header) => header,
+            None => {
+                differences.push(format!("{}: missing", original_header.filename));
+                continue;
+            }
+        };
+
+        let fields = [
+            ("packing method", original_header.packing_method, header.packing_method),
+            ("original size", original_header.original_size, header.original_size),
+            ("reserved", original_header.reserved, header.reserved),
+            ("timestamp", original_header.timestamp, header.timestamp),
+            ("data size", original_header.data_size, header.data_size),
+        ];
+        for (field, original_value, value) in fields.iter().filter(|(_, a, b)| a != b) {
+            differences.push(format!("{}: {} changed from {} to {}", original_header.filename, field, original_value, value));
+        }
+    }
+
+    if differences.is_empty() {
+        if original.len() != repacked.len() {
+            differences.push(format!("size changed from {} to {} bytes, the original has data outside of files", original.len(), repacked.len()));
+        } else {
+            differences.push("header extensions are stored in a different order".to_string());
+        }
+    }
+
+    Ok(differences)
+}
+
+/// Returns the part of a PBO containing the offset: the headers, an entry with the offset
+/// relative to its data, or the checksum.
+fn pbo_location(headers: &[PBOHeader], data_start: usize, offset: usize) -> String {
+    if offset < data_start {
+        return "the headers".to_string();
+    }
+
+    let mut start = data_start;
+    for header in headers {
+        let end = start + header.data_size as usize;
+        if offset < end {
+            return format!("{} at {:#x}", header.filename, offset - start);
+        }
+        start = end;
+    }
+
+    "the checksum".to_string()
+}
+
+/// Returns where the repacked PBO first differs from the original, with a hex dump of both around
+/// it. `None` if the repacked PBO is identical.
+pub fn roundtrip_hex_diff(original: &[u8]) -> Result<Option<String>, Error> {
+    let pbo = PBO::read(&mut Cursor::new(original)).prepend_error("Failed to read PBO:")?;
+    let repacked = pbo.to_cursor()?.into_inner();
+
+    let (offset, dump) = match hex_diff(original, &repacked) {
+        Some(diff) => diff,
+        None => { return Ok(None); }
+    };
+
+    let mut cursor = Cursor::new(original);
+    let (headers, _) = read_headers(&mut cursor).unwrap();
+    let location = pbo_location(&headers, cursor.position() as usize, offset);
+
+    Ok(Some(format!("First difference at offset {:#x} in {}:\n{}", offset, location, dump)))
+}
+
+/// Repacks the PBO in input and prints whether the result is identical to it. Fails if it is not.
+pub fn cmd_roundtrip<I: Read>(input: &mut I) -> Result<(), Error> {
+    let mut original: Vec<u8> = Vec::new();
+    input.read_to_end(&mut original).prepend_error("Failed to read PBO:")?;
+
+    let differences = roundtrip_differences(&original)?;
+    if differences.is_empty() {
+        println!("Repacked PBO is byte-identical to the original.");
+        return Ok(());
+    }
+
+    println!("Repacked PBO differs from the original:");
+    for difference in &differences {
+        println!("- {}", difference);
+    }
+    if let Some(diff) = roundtrip_hex_diff(&original)? {
+        println!("\n{}", diff);
+    }
+
+    Err(error!("Repacked PBO is not identical, {} differences.", differences.len()))
+}
+
+/// Recomputes the checksum of the PBO at the path and compares it to the one in the footer.
+/// Fails on a missing or wrong checksum, unless `fix` is set, in which case the footer is
+/// rewritten with the correct checksum.
+pub fn cmd_checksum(path: PathBuf, fix: bool) -> Result<(), Error> {
+    let file = OpenOptions::new().read(true).write(fix).open(&path).prepend_error(format!("Failed to open {}:", path.display()))?;
+    let mut reader = PBOReader::new(file).prepend_error("Failed to read PBO:")?;
+
+    let expected = reader.compute_checksum().prepend_error("Failed to read PBO:")?;
+    let stored = reader.checksum().prepend_error("Failed to read PBO:")?;
+    let hex = |checksum: &[u8]| checksum.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+
+    let msg = match &stored {
+        Some(checksum) if *checksum == expected => {
+            println!("Checksum is valid: {}", hex(&expected));
+            return Ok(());
+        },
+        Some(checksum) => format!("Checksum {} doesn't match the content, expected {}.", hex(checksum), hex(&expected)),
+        None => format!("Checksum is missing, expected {}.", hex(&expected)),
+    };
+
+    if !fix {
+        return Err(error!("{}", msg));
+    }
+
+    let end = reader.end;
+    let mut file = reader.input;
+    // anything after the footer is dropped along with a corrupt one
+    file.set_len(end).prepend_error("Failed to write PBO:")?;
+    file.seek(SeekFrom::Start(end)).prepend_error("Failed to write PBO:")?;
+    file.write_all(&[0]).prepend_error("Failed to write PBO:")?;
+    file.write_all(&expected).prepend_error("Failed to write PBO:")?;
+
+    println!("{}\nFixed checksum: {}", msg, hex(&expected));
+    Ok(())
+}
+
+/// Warns about header extensions that other tools can't parse.
+fn check_header_extensions(pbo: &PBO) {
+    let mut keys: Vec<&String> = pbo.header_extensions.keys().collect();
+    keys.sort();
+
+    for key in keys {
+        let value = &pbo.header_extensions[key];
+        if let Err(msg) = check_header_extension(key, value) {
+            warning(format!("Header extension {}={} is malformed: {}.", key, value, msg), Some("header-extension"), (None, None));
+        }
+    }
+}
+
+/// Warns about entry paths and a prefix that break the engine or other tools.
+fn check_entry_paths(pbo: &PBO) {
+    let prefix = pbo.header_extensions.get("prefix").map(|p| p.as_str()).unwrap_or("");
+    for (msg, name) in check_entry_path("", prefix) {
+        warning(format!("Prefix {} {}.", prefix, msg), Some(name), (None, None));
+    }
+
+    let mut names: Vec<&String> = pbo.files.keys().collect();
+    names.sort();
+    for file in names {
+        for (msg, name) in check_entry_path(prefix, file) {
+            warning(format!("Path {} {}.", file, msg), Some(name), (None, None));
+        }
+    }
+}
+
+/// Packs the folder in input into a PBO without binarization.
+///
+/// With a `manifest` written by `cmd_inspect`, header extensions missing from the folder are
+/// added and the order of header extensions and files and the file headers are restored, so
+/// packing an unpacked PBO gives the original file.
+pub fn cmd_pack<O: Write>(input: PathBuf, output: &mut O, headerext: &[String], excludes: &[String], hooks: &Hooks, manifest: Option<PathBuf>) -> Result<(), Error> {
+    hooks.run(HookStage::PrePreprocess)?;
+
+    let manifest = match manifest {
+        Some(path) => Some(PBOManifest::read(&mut File::open(&path).prepend_error(format!("Failed to open {}:", path.display()))?)?),
+        None => None,
+    };
+
+    let mut pbo = PBO::from_directory(input.clone(), false, excludes, &Vec::new(), &Vec::new(), false)?;
+
+    if let Some(manifest) = &manifest {
+        let mut prefix = String::new();
+        if let Ok(mut file) = File::open(input.join("$PBOPREFIX$")) {
+            file.read_to_string(&mut prefix)?;
+        }
+        // the default prefix of from_directory wasn't in the original PBO
+        if !parse_prefix(&prefix).contains_key("prefix") && !manifest.header_extensions.iter().any(|(key, _)| key == "prefix") {
+            pbo.header_extensions.remove("prefix");
+        }
+
+        for (key, value) in &manifest.header_extensions {
+            pbo.header_extensions.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    }
 
     for h in headerext {
         let (key, value) = (h.split('=').nth(0).unwrap(), h.split('=').nth(1).unwrap());
         pbo.header_extensions.insert(key.to_string(), value.to_string());
     }
+    check_header_extensions(&pbo);
+    check_entry_paths(&pbo);
 
-    pbo.write(output).prepend_error("Failed to write PBO:")?;
+    hooks.run_with_files(HookStage::PrePack, &mut pbo)?;
+
+    match &manifest {
+        Some(manifest) => pbo.write_with_manifest(output, manifest),
+        None => pbo.write(output),
+    }.prepend_error("Failed to write PBO:")?;
 
     Ok(())
 }
 
-pub fn cmd_build<O: Write>(input: PathBuf, output: &mut O, headerext: &[String], excludes: &[String], includefolders: &[PathBuf]) -> Result<(), Error> {
-    let mut pbo = PBO::from_directory(input, true, excludes, includefolders)?;
+#[allow(clippy::too_many_arguments)]
+pub fn cmd_build<O: Write>(input: PathBuf, output: &mut O, headerext: &[String], excludes: &[String], includefolders: &[PathBuf], defines: &[(String, String)], hooks: &Hooks, keep_going: bool, rewrite_paths: bool) -> Result<(), Error> {
+    hooks.run(HookStage::PrePreprocess)?;
+
+    let mut pbo = PBO::from_directory(input, true, excludes, includefolders, defines, keep_going)?;
+
+    hooks.run_with_files(HookStage::PostRapify, &mut pbo)?;
 
     for h in headerext {
         let (key, value) = (h.split('=').nth(0).unwrap(), h.split('=').nth(1).unwrap());
         pbo.header_extensions.insert(key.to_string(), value.to_string());
     }
+    if rewrite_paths {
+        pbo.rewrite_paths().prepend_error("Failed to rewrite paths:")?;
+    }
+    check_header_extensions(&pbo);
+    check_entry_paths(&pbo);
+
+    hooks.run_with_files(HookStage::PrePack, &mut pbo)?;
 
     pbo.write(output).prepend_error("Failed to write PBO:")?;
 
     Ok(())
 }
+
+/// Names of files the editors leave in mission folders that are never packed, in any subfolder
+const MISSION_EXCLUDES: &[&str] = &["*.bak", "*.biedi", "*~", "Thumbs.db", "desktop.ini"];
+
+/// Splits the name of a mission folder into the mission name and the world, e.g.
+/// `co10_escape.Altis` into `co10_escape` and `Altis`.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use armake2::pbo::mission_name;
+/// assert_eq!(("co10_escape".to_string(), "Altis".to_string()), mission_name(Path::new("missions/co10_escape.Altis")).unwrap());
+/// assert!(mission_name(Path::new("missions/co10_escape")).is_err());
+/// ```
+pub fn mission_name(folder: &Path) -> Result<(String, String), Error> {
+    let name = folder.canonicalize().unwrap_or_else(|_| folder.to_path_buf()).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+
+    match name.rsplit_once('.') {
+        Some((mission, world)) if !mission.is_empty() && !world.is_empty() => Ok((mission.to_string(), world.to_string())),
+        _ => Err(error!("Mission folder \"{}\" has to be named <mission>.<world>, e.g. co10_escape.Altis.", name)),
+    }
+}
+
+/// Packs the mission folder into `<mission>.<world>.pbo`. `target` is the output file or the
+/// folder to write it to, the current folder by default. Editor backups are excluded, and with
+/// `binarize` the mission.sqm is rapified. Returns the path of the written PBO.
+pub fn cmd_mission_pack(input: PathBuf, target: Option<PathBuf>, excludes: &[String], binarize: bool, force: bool) -> Result<PathBuf, Error> {
+    let (mission, world) = mission_name(&input)?;
+    if !input.join("mission.sqm").is_file() {
+        return Err(error!("Mission folder \"{}\" has no mission.sqm.", input.display()));
+    }
+
+    let file_name = format!("{}.{}.pbo", mission, world);
+    let output = match target {
+        Some(target) if target.is_dir() => target.join(&file_name),
+        Some(target) => {
+            let name = target.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+            if !name.ends_with(&format!(".{}.pbo", world.to_lowercase())) {
+                warning(format!("Mission PBOs have to be named <mission>.<world>.pbo for the game to find the world, like {}.", file_name), Some("mission-name"), (Some(target.display().to_string()), None));
+            }
+            target
+        },
+        None => PathBuf::from(&file_name),
+    };
+    if !force && output.exists() {
+        return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", output.display()));
+    }
+
+    let excludes: Vec<String> = MISSION_EXCLUDES.iter()
+        .flat_map(|name| [name.to_string(), format!("*\\{}", name)])
+        .chain(excludes.iter().cloned())
+        .collect();
+    check_space(&output, estimate_size(&input, &excludes)?)?;
+    let mut pbo = PBO::from_directory(input.clone(), false, &excludes, &Vec::new(), &Vec::new(), false)?;
+
+    // missions saved as binarized in the editor are rapified already
+    match pbo.files.get_mut("mission.sqm") {
+        Some(sqm) if binarize && FileType::detect(sqm.get_ref()) != FileType::Config => {
+            let config = Config::read(&mut Cursor::new(sqm.get_ref()), Some(input.join("mission.sqm")), &Vec::new())
+                .prepend_error("Failed to binarize mission.sqm:")?;
+            *sqm = config.to_cursor()?;
+        },
+        _ => {}
+    }
+
+    check_header_extensions(&pbo);
+    check_entry_paths(&pbo);
+
+    let mut file = AtomicFile::create(&output).prepend_error("Failed to open output file:")?;
+    pbo.write(&mut file).prepend_error("Failed to write PBO:")?;
+    file.commit().prepend_error("Failed to write PBO:")?;
+
+    Ok(output)
+}
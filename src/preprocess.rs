@@ -1,14 +1,27 @@
 //! Functions for preprocessing Arma configs and scripts
 
+use std::cell::{RefCell};
 use std::clone::Clone;
 use std::collections::HashMap;
-use std::env::current_dir;
-use std::fs::{File, read_dir};
-use std::io::{Read, Write, Error};
+use std::env::{current_dir};
+use std::fs::{File, create_dir_all, read_dir};
+use std::io::{Read, Write, Error, Cursor};
 use std::iter::{Sum};
+use std::ops::{Range};
 use std::path::{Path, PathBuf, Component};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use once_cell::sync::Lazy;
+
+use crate::cache::cache_key;
+use crate::config::Config;
 use crate::error::*;
+use crate::eval;
+use crate::paths::{from_fs_path, join_prefix, path_key, paths_equal, to_fs_path};
+use crate::pbo::{PBO, valid_filename};
+use crate::temp::TempDir;
 
 peg::parser!{
     pub grammar preprocess_grammar() for str {
@@ -19,8 +32,8 @@ peg::parser!{
         }
 
         rule include_path() -> String =
-            "\"" path:$(!['"'] [_]*) "\"" { path.to_string() } /
-            "<" path:$(!['>'] [_]*) ">"   { path.to_string() }
+            "\"" path:$((!['"'] [_])*) "\"" { path.to_string() } /
+            "<" path:$((!['>'] [_])*) ">"   { path.to_string() }
 
         rule parameters() -> Vec<String> = "(" [' ' | '\t']* p:(name() ** ([' ' | '\t']* "," [' ' | '\t']*)) [' ' | '\t']* ")" {
             p
@@ -46,9 +59,10 @@ peg::parser!{
             "#" [' ' | '\t']* "ifdef" [' ' | '\t']+ n:name() { Directive::IfDefDirective(n) } /
             "#" [' ' | '\t']* "ifndef" [' ' | '\t']+ n:name() { Directive::IfNDefDirective(n) } /
             "#" [' ' | '\t']* "else" { Directive::ElseDirective } /
-            "#" [' ' | '\t']* "endif" { Directive::EndIfDirective }
+            "#" [' ' | '\t']* "endif" { Directive::EndIfDirective } /
+            "#" [' ' | '\t']* "pragma" [' ' | '\t']+ p:$((!newline() !comment_token() [_])*) { Directive::PragmaDirective(p.trim_end().to_string()) }
 
-        rule arg_rec() = "(" (arg_rec() / "\\\\" / ("\\" newline()) / !['\r' | '\n'] [_])* ")"
+        rule arg_rec() = "(" (arg_rec() / "\\\\" / ("\\" newline()) / !['\r' | '\n' | ')'] [_])* ")"
 
         rule argument() -> String = a:$((arg_rec() / "\\\\" / ("\\" newline()) / !['\r' | '\n' | ',' | ')'] [_])*) {
             a.to_string()
@@ -147,6 +161,8 @@ pub enum Directive {
     ElseDirective,
     /// `#endif` directive
     EndIfDirective,
+    /// `#pragma` directive containing everything after `#pragma`
+    PragmaDirective(String),
 }
 
 /// Potential macro invocation
@@ -190,9 +206,23 @@ pub struct PreprocessInfo {
     /// `PathBuf` to the file where the line was found. The path may be `None` if the line was in the
     /// original input to `preprocess` and `origin` was not given.
     pub line_origins: Vec<(u32, Option<PathBuf>)>,
+    /// Files included while preprocessing, in the order they were first included. Unlike
+    /// `line_origins`, this also lists includes that only define macros.
+    pub includes: Vec<PathBuf>,
+    /// Warnings muted with `#pragma armake2 mute` and the ranges of output lines (starting at 0)
+    /// they are muted in.
+    pub muted: Vec<(String, Range<usize>)>,
     import_stack: Vec<PathBuf>
 }
 
+impl PreprocessInfo {
+    /// Returns whether the warning is muted with `#pragma armake2 mute` in the line of the output
+    /// (starting at 1).
+    pub fn is_muted(&self, name: &str, line: usize) -> bool {
+        self.muted.iter().any(|(muted, lines)| muted == name && lines.contains(&(line.max(1) - 1)))
+    }
+}
+
 fn parse_macro(input: &str) -> Macro {
     let without_original: Macro = preprocess_grammar::macro_proper(input).unwrap();
 
@@ -303,22 +333,40 @@ impl Macro {
                 }
 
                 if let Some(tokens) = value {
-                    if self.quoted {
+                    let tokens = if self.quoted {
                         let (concatted, newlines) = Token::concat(&tokens);
                         let mut tokens: Vec<Token> = Vec::new();
                         tokens.push(Token::NewlineToken(format!("\"{}\"", concatted.trim()), newlines));
-                        Ok(tokens)
+                        tokens
                     } else {
-                        Ok(tokens)
+                        tokens
+                    };
+
+                    if !def.local {
+                        trace_expansion(self, stack.len(), &tokens);
                     }
+                    Ok(tokens)
                 } else {
                     self.resolve_pseudoargs(def_map, stack)
                 }
             },
+            None if self.name == "__EVAL" && self.arguments.is_some() && !self.quoted => self.resolve_eval(def_map, stack),
             None => self.resolve_pseudoargs(def_map, stack)
         }
     }
 
+    /// Evaluates `__EVAL(expression)` with the macros in the expression resolved first.
+    fn resolve_eval(&self, def_map: &HashMap<String, Definition>, stack: &[Definition]) -> Result<Vec<Token>, Error> {
+        let (_, without_name) = self.original.split_at(self.name.len());
+        let tokens = preprocess_grammar::tokens(without_name).map_err(|e| error!("Failed to parse __EVAL expression: {}", e))?;
+        let (expression, newlines) = Token::concat(&Macro::resolve_all(&tokens, def_map, stack)?);
+
+        let value = eval::evaluate(&expression).prepend_error(format!("Failed to evaluate __EVAL{}:", without_name.trim()))?;
+        let tokens = vec![Token::NewlineToken(value.to_config(), newlines)];
+        trace_expansion(self, stack.len(), &tokens);
+        Ok(tokens)
+    }
+
     fn resolve_all(tokens: &[Token], def_map: &HashMap<String, Definition>, stack: &[Definition]) -> Result<Vec<Token>, Error> {
         let mut result: Vec<Token> = Vec::new();
 
@@ -340,6 +388,37 @@ impl Macro {
     }
 }
 
+/// Prints all macro expansions to stderr if set, see `init_trace_macros`
+static TRACE_MACROS: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// File and line whose macros are being expanded on this thread, for tracing
+    static TRACE_LOCATION: RefCell<(Option<PathBuf>, u32)> = const { RefCell::new((None, 0)) };
+}
+
+/// Prints every macro expansion of all following commands to stderr: the file and line, the macro
+/// with its arguments and the result. Expansions inside other macros are indented below them and
+/// printed before them, as they are expanded first.
+pub fn init_trace_macros(trace: bool) {
+    TRACE_MACROS.store(trace, Ordering::Relaxed);
+}
+
+fn trace_expansion(m: &Macro, depth: usize, result: &[Token]) {
+    if !TRACE_MACROS.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let location = TRACE_LOCATION.with(|l| {
+        let l = l.borrow();
+        format!("{}:{}", l.0.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "<input>".to_string()), l.1)
+    });
+    let arguments = m.arguments.as_ref().map(|a| format!("({})", a.join(", "))).unwrap_or_default();
+    let (result, _) = Token::concat(result);
+
+    eprintln!("{}: {}{}{}{} -> {}", location, "  ".repeat(depth), if m.quoted { "#" } else { "" }, m.name, arguments,
+        result.trim().replace("\\\n", " ").replace('\n', " "));
+}
+
 impl Token {
     fn concat(tokens: &[Token]) -> (String, u32) {
         let mut output = String::new();
@@ -375,15 +454,9 @@ fn read_prefix(prefix_path: &Path) -> String {
     content.lines().nth(0).unwrap().to_string()
 }
 
-/// Returns the path seperator used on the current operating system
-pub fn pathsep() -> &'static str {
-    if cfg!(windows) { "\\" } else { "/" }
-}
-
-fn matches_include_path(path: &PathBuf, include_path: &str) -> bool {
-    let include_pathbuf = PathBuf::from(&include_path.replace("\\", pathsep()));
-
-    if path.file_name() != include_pathbuf.file_name() { return false; }
+fn matches_include_path(path: &Path, include_path: &str) -> bool {
+    let file_name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    if path_key(include_path).rsplit('\\').next() != Some(path_key(&file_name).as_str()) { return false; }
 
     for parent in path.ancestors() {
         if parent.is_file() { continue; }
@@ -391,20 +464,10 @@ fn matches_include_path(path: &PathBuf, include_path: &str) -> bool {
         let prefixpath = parent.join("$PBOPREFIX$");
         if !prefixpath.is_file() { continue; }
 
-        let mut prefix = read_prefix(&prefixpath);
-
-        prefix = if !prefix.is_empty() && prefix.chars().nth(0).unwrap() != '\\' {
-            format!("\\{}", prefix)
-        } else {
-            prefix
-        };
-
-        let prefix_pathbuf = PathBuf::from(prefix.replace("\\", pathsep()));
+        let prefix = read_prefix(&prefixpath);
+        let relative = from_fs_path(path.strip_prefix(parent).unwrap());
 
-        let relative = path.strip_prefix(parent).unwrap();
-        let test_path = prefix_pathbuf.join(relative);
-
-        if test_path == include_pathbuf {
+        if paths_equal(&join_prefix(&prefix, &relative), include_path) {
             return true;
         }
     }
@@ -428,8 +491,7 @@ fn search_directory(include_path: &str, directory: PathBuf) -> Option<PathBuf> {
         }
     }
 
-    let direct_path = (&directory).to_str().unwrap().to_string() + &include_path.replace("\\", pathsep());
-    let direct_pathbuf = PathBuf::from(direct_path);
+    let direct_pathbuf = directory.join(to_fs_path(include_path.trim_start_matches('\\')));
 
     if direct_pathbuf.is_file() {
         return Some(direct_pathbuf);
@@ -453,29 +515,49 @@ fn canonicalize(path: PathBuf) -> PathBuf {
     result
 }
 
-fn find_include_file(include_path: &str, origin: Option<&PathBuf>, search_paths: &[PathBuf]) -> Result<PathBuf, Error> {
+/// Returns the file `path` refers to in one of the include paths given with `-I`, if any.
+fn find_in_include_paths(path: &Path, includepaths: &[PathBuf]) -> Result<Option<PathBuf>, Error> {
+    for includepath in includepaths {
+        let candidate = canonicalize(current_dir()?.join(includepath).join(path));
+        if candidate.is_file() {
+            return Ok(Some(candidate));
+        }
+    }
+
+    Ok(None)
+}
+
+fn find_include_file(include_path: &str, origin: Option<&PathBuf>, includepaths: &[PathBuf], search_paths: &[PathBuf]) -> Result<PathBuf, Error> {
     if include_path.chars().nth(0).unwrap() != '\\' {
-        let mut path = PathBuf::from(include_path.replace("\\", pathsep()));
+        let relative = to_fs_path(include_path);
 
-        if let Some(origin_path) = origin {
+        let path = if let Some(origin_path) = origin {
             let absolute = PathBuf::from(&origin_path).canonicalize()?;
             let origin_dir = absolute.parent().unwrap();
-            path = origin_dir.join(path);
+            origin_dir.join(&relative)
         } else {
-            path = current_dir()?.join(path);
-        }
+            current_dir()?.join(&relative)
+        };
 
         let absolute = canonicalize(path);
+        if absolute.is_file() {
+            return Ok(absolute);
+        }
 
-        if !absolute.is_file() {
-            match origin {
-                Some(origin_path) => Err(error!("File \"{}\" included from \"{}\" not found.", include_path, origin_path.to_str().unwrap().to_string())),
-                None => Err(error!("Included file \"{}\" not found.", include_path))
-            }
-        } else {
-            Ok(absolute)
+        if let Some(file_path) = find_in_include_paths(&relative, includepaths)? {
+            return Ok(file_path);
+        }
+
+        match origin {
+            Some(origin_path) => Err(error!("File \"{}\" included from \"{}\" not found.", include_path, origin_path.to_str().unwrap().to_string())),
+            None => Err(error!("Included file \"{}\" not found.", include_path))
         }
     } else {
+        let relative = to_fs_path(include_path.trim_start_matches('\\'));
+        if let Some(file_path) = find_in_include_paths(&relative, includepaths)? {
+            return Ok(file_path);
+        }
+
         for search_path in search_paths {
             if let Some(file_path) = search_directory(include_path, search_path.canonicalize()?) {
                 return Ok(file_path);
@@ -489,12 +571,168 @@ fn find_include_file(include_path: &str, origin: Option<&PathBuf>, search_paths:
     }
 }
 
-fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut HashMap<String, Definition>, info: &mut PreprocessInfo, includefolders: &[PathBuf]) -> Result<String, Error> {
-    let lines = PreprocessParseErrorExt::format_error(preprocess_grammar::file(&input), &origin, &input)?;
+/// Extensions of files in PBOs that are never included and not extracted for includes
+const NON_INCLUDE_EXTENSIONS: &[&str] = &["paa", "pac", "p3d", "wss", "ogg", "wav", "wrp", "rtm", "jpg", "png", "lip", "bisurf", "bikb", "fxy"];
+
+/// Dependency PBOs extracted for includes by cache key, see `extract_include_pbo`
+static EXTRACTED_INCLUDES: Lazy<Mutex<HashMap<String, TempDir>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Removes the folders of the dependency PBOs extracted for includes when it is dropped, at the
+/// end of a command. They are kept with `--keep-temp`, like other temporary folders.
+pub struct ExtractedIncludes;
+
+impl Drop for ExtractedIncludes {
+    fn drop(&mut self) {
+        EXTRACTED_INCLUDES.lock().unwrap().clear();
+    }
+}
+
+/// Extracts the files of a PBO that can be included into a temporary folder with a
+/// `$PBOPREFIX$`, so it can be searched like a source folder. Extracted PBOs are reused until
+/// the PBO changes or the command is done.
+fn extract_include_pbo(path: &Path) -> Result<PathBuf, Error> {
+    let metadata = path.metadata()?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let key = cache_key("includes", &[path.canonicalize()?.to_string_lossy().as_bytes(), &metadata.len().to_le_bytes(), &modified.to_le_bytes()]);
+
+    // the lock is held while extracting so parallel builds never see partial folders
+    let mut extracted = EXTRACTED_INCLUDES.lock().unwrap();
+    if let Some(folder) = extracted.get(&key) {
+        return Ok(folder.path().to_path_buf());
+    }
+
+    let pbo = PBO::read(&mut File::open(path)?).prepend_error(format!("Failed to read include PBO \"{}\":", path.display()))?;
+
+    let folder = TempDir::new("includes")?;
+    for (name, cursor) in pbo.files.iter() {
+        let extension = name.rsplit('.').next().unwrap_or("").to_lowercase();
+        if NON_INCLUDE_EXTENSIONS.contains(&extension.as_str()) { continue; }
+
+        if !valid_filename(name) {
+            return Err(error!("Include PBO \"{}\" has an invalid file name {:?}.", path.display(), name));
+        }
+        let file_path = folder.path().join(to_fs_path(name));
+        create_dir_all(file_path.parent().unwrap())?;
+        File::create(file_path)?.write_all(cursor.get_ref())?;
+    }
+    if let Some(prefix) = pbo.header_extensions.get("prefix") {
+        File::create(folder.path().join("$PBOPREFIX$"))?.write_all(prefix.as_bytes())?;
+    }
+
+    let target = folder.path().to_path_buf();
+    extracted.insert(key, folder);
+    Ok(target)
+}
+
+/// Returns the dependency PBOs among the include folders: PBOs given directly and the PBOs in
+/// the `addons` folder of given mod folders. The current folder isn't treated as a mod folder,
+/// its `addons` folder usually contains the PBOs being built.
+pub fn dependency_pbos(includefolders: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut pbos: Vec<PathBuf> = Vec::new();
+
+    for folder in includefolders {
+        if folder.is_file() {
+            pbos.push(folder.clone());
+            continue;
+        }
+        if folder == Path::new(".") {
+            continue;
+        }
+
+        let addons = folder.join("addons");
+        if addons.is_dir() {
+            let mut found: Vec<PathBuf> = Vec::new();
+            for entry in read_dir(addons)? {
+                let path = entry?.path();
+                if path.is_file() && path.extension().map(|e| e.eq_ignore_ascii_case("pbo")).unwrap_or(false) {
+                    found.push(path);
+                }
+            }
+            found.sort();
+            pbos.append(&mut found);
+        }
+    }
+
+    Ok(pbos)
+}
+
+/// Returns the folders searched for includes. Dependency PBOs are searched through their
+/// extracted files.
+fn include_search_paths(includefolders: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut search_paths: Vec<PathBuf> = includefolders.iter().filter(|f| !f.is_file()).cloned().collect();
+
+    for pbo in dependency_pbos(includefolders)? {
+        search_paths.push(extract_include_pbo(&pbo)?);
+    }
+
+    Ok(search_paths)
+}
+
+/// Include paths of all following commands, see `init_include_paths`
+static INCLUDE_PATHS: Lazy<Mutex<Vec<PathBuf>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Sets the include paths given with `-I` used by all following commands that read configs, like
+/// rapifying and building. `preprocess_with` itself only uses the include paths in its options.
+pub fn init_include_paths(includepaths: Vec<PathBuf>) {
+    *INCLUDE_PATHS.lock().unwrap() = includepaths;
+}
+
+/// Returns the include paths set with `init_include_paths`.
+pub fn include_paths() -> Vec<PathBuf> {
+    INCLUDE_PATHS.lock().unwrap().clone()
+}
+
+/// Parsed lines of an included file with the modification time and size it was read with
+struct ParsedInclude {
+    modified: Option<SystemTime>,
+    len: u64,
+    lines: Arc<Vec<Line>>,
+}
+
+/// Parsed includes by path, so headers included by many files of a build are only read and
+/// parsed once
+static PARSED_INCLUDES: Lazy<Mutex<HashMap<PathBuf, ParsedInclude>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Returns the parsed lines of an included file, reading and parsing it only if it wasn't parsed
+/// before or changed since.
+fn include_lines(path: &Path) -> Result<Arc<Vec<Line>>, Error> {
+    let metadata = path.metadata()?;
+    let (modified, len) = (metadata.modified().ok(), metadata.len());
+
+    if let Some(parsed) = PARSED_INCLUDES.lock().unwrap().get(path) {
+        if parsed.modified == modified && parsed.len == len && modified.is_some() {
+            return Ok(parsed.lines.clone());
+        }
+    }
+
+    let content = read_include(path)?;
+    let lines = Arc::new(PreprocessParseErrorExt::format_error(preprocess_grammar::file(&content), &Some(path.to_path_buf()), &content)?);
+    PARSED_INCLUDES.lock().unwrap().insert(path.to_path_buf(), ParsedInclude { modified, len, lines: lines.clone() });
+
+    Ok(lines)
+}
+
+/// Reads an included file. Rapified configs are derapified so their classes can be used like
+/// the ones of text configs.
+fn read_include(path: &Path) -> Result<String, Error> {
+    let mut content: Vec<u8> = Vec::new();
+    File::open(path)?.read_to_end(&mut content)?;
+
+    if content.starts_with(b"\0raP") {
+        let config = Config::read_rapified(&mut Cursor::new(content)).prepend_error(format!("Failed to derapify include \"{}\":", path.display()))?;
+        return config.to_string();
+    }
+
+    String::from_utf8(content).map_err(|_| error!("Include \"{}\" is not valid UTF-8.", path.display()))
+}
+
+fn preprocess_rec(lines: &[Line], origin: Option<PathBuf>, definition_map: &mut HashMap<String, Definition>, info: &mut PreprocessInfo, includepaths: &[PathBuf], includefolders: &[PathBuf]) -> Result<String, Error> {
     let mut output = String::from("");
     let mut original_lineno = 1;
     let mut level = 0;
     let mut level_true = 0;
+    // warnings muted in this file with the output line they are muted from
+    let mut muted: Vec<(String, usize)> = Vec::new();
 
     for line in lines {
         match line {
@@ -511,13 +749,15 @@ fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut H
                         //    // @todo: complain
                         //}
 
-                        let file_path = find_include_file(&path, origin.as_ref(), includefolders)?;
+                        let file_path = find_include_file(path, origin.as_ref(), includepaths, includefolders)?;
 
+                        if !info.includes.contains(&file_path) {
+                            info.includes.push(file_path.clone());
+                        }
                         info.import_stack.push(file_path.clone());
 
-                        let mut content = String::new();
-                        File::open(&file_path)?.read_to_string(&mut content)?;
-                        let result = preprocess_rec(content, Some(file_path), definition_map, info, includefolders).prepend_error(format!("Failed to preprocess include \"{}\":", path))?;
+                        let lines = include_lines(&file_path)?;
+                        let result = preprocess_rec(&lines, Some(file_path), definition_map, info, includepaths, includefolders).prepend_error(format!("Failed to preprocess include \"{}\":", path))?;
 
                         info.import_stack.pop();
 
@@ -536,19 +776,19 @@ fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut H
                             // @todo: warn about redefine
                         }
 
-                        definition_map.insert(def.name.clone(), def);
+                        definition_map.insert(def.name.clone(), def.clone());
                     }
                     Directive::UndefDirective(name) => {
                         if level > level_true { continue; }
 
-                        definition_map.remove(&name);
+                        definition_map.remove(name);
                     }
                     Directive::IfDefDirective(name) => {
-                        level_true += if level_true == level && definition_map.contains_key(&name) { 1 } else { 0 };
+                        level_true += if level_true == level && definition_map.contains_key(name) { 1 } else { 0 };
                         level += 1;
                     }
                     Directive::IfNDefDirective(name) => {
-                        level_true += if level_true == level && !definition_map.contains_key(&name) { 1 } else { 0 };
+                        level_true += if level_true == level && !definition_map.contains_key(name) { 1 } else { 0 };
                         level += 1;
                     }
                     Directive::ElseDirective => {
@@ -565,11 +805,49 @@ fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut H
                             level_true -= 1;
                         }
                     }
+                    Directive::PragmaDirective(pragma) => {
+                        if level > level_true { continue; }
+
+                        let mut words = pragma.split_whitespace();
+                        if words.next() != Some("armake2") {
+                            // unknown pragmas are left for the tools reading the output
+                            output += &format!("#pragma {}\n", pragma);
+                            info.line_origins.push((original_lineno, origin.clone()));
+                            original_lineno += 1;
+                            continue;
+                        }
+
+                        match words.next() {
+                            Some("mute") => {
+                                for name in words {
+                                    if !muted.iter().any(|(n, _)| n == name) {
+                                        muted.push((name.to_string(), info.line_origins.len()));
+                                    }
+                                }
+                            },
+                            Some("unmute") => {
+                                for name in words {
+                                    if let Some(index) = muted.iter().position(|(n, _)| n == name) {
+                                        let (name, start) = muted.remove(index);
+                                        info.muted.push((name, start..info.line_origins.len()));
+                                    }
+                                }
+                            },
+                            _ => {
+                                let location = origin.as_ref().map(|p| format!("{}:", p.display())).unwrap_or_default();
+                                return Err(error!("In line {}{}: Unknown pragma \"#pragma {}\", expected \"#pragma armake2 mute\" or \"#pragma armake2 unmute\".", location, original_lineno, pragma));
+                            }
+                        }
+                    }
                 }
             },
             Line::TokenLine(tokens) => {
+                if TRACE_MACROS.load(Ordering::Relaxed) {
+                    TRACE_LOCATION.with(|l| *l.borrow_mut() = (origin.clone(), original_lineno));
+                }
+
                 let stack: Vec<Definition> = Vec::new();
-                let resolved = Macro::resolve_all(&tokens, &definition_map, &stack).prepend_error("Failed to resolve macros:")?;
+                let resolved = Macro::resolve_all(tokens, definition_map, &stack).prepend_error("Failed to resolve macros:")?;
 
                 let (mut result, newlines) = Token::concat(&resolved);
                 result = result.replace("\r\n", "\n");
@@ -594,15 +872,63 @@ fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut H
         }
     }
 
+    // mutes end with the file they are in
+    for (name, start) in muted {
+        info.muted.push((name, start..info.line_origins.len()));
+    }
+
     Ok(output)
 }
 
+/// Parses a macro definition given on the command line as `NAME=value`, or `NAME` to define it
+/// as 1 like C compilers do.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::preprocess::parse_define;
+/// assert_eq!(("DEBUG".to_string(), "1".to_string()), parse_define("DEBUG").unwrap());
+/// assert_eq!(("VERSION".to_string(), "1.2=3".to_string()), parse_define("VERSION=1.2=3").unwrap());
+/// assert!(parse_define("1ST=x").is_err());
+/// ```
+pub fn parse_define(define: &str) -> Result<(String, String), Error> {
+    let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+    let valid = name.chars().next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false) &&
+        name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(error!("Invalid macro name \"{}\" in definition \"{}\".", name, define));
+    }
+
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Options for `preprocess_with` beyond the include folders
+#[derive(Clone, Debug, Default)]
+pub struct PreprocessOptions {
+    /// Folders with shared headers, like the `-I` folders of C compilers
+    pub includepaths: Vec<PathBuf>,
+    /// Macros defined before the input is read, given as name and value
+    pub defines: Vec<(String, String)>,
+}
+
+impl PreprocessOptions {
+    /// Returns the options used by commands: the include paths set with `init_include_paths` and
+    /// the given defines.
+    pub fn new(defines: &[(String, String)]) -> PreprocessOptions {
+        PreprocessOptions {
+            includepaths: include_paths(),
+            defines: defines.to_vec(),
+        }
+    }
+}
+
 /// Reads input string and returns preprocessed string with an info struct containing the origins
 /// of the lines in the output.
 ///
 /// `path` is the path to the input if it is known and is used for relative includes and error
 /// messages. `includefolders` are the folders searched for absolute includes and should usually at
-/// least include the current working directory.
+/// least include the current working directory. Use `preprocess_with` for include paths and
+/// predefined macros.
 ///
 /// # Examples
 ///
@@ -619,13 +945,57 @@ fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut H
 ///
 /// assert_eq!("foo = \"abc_xyz\";", output.trim());
 /// ```
-pub fn preprocess(mut input: String, origin: Option<PathBuf>, includefolders: &[PathBuf]) -> Result<(String, PreprocessInfo), Error> {
-    if input[..3].as_bytes() == [0xef,0xbb,0xbf] {
+pub fn preprocess(input: String, origin: Option<PathBuf>, includefolders: &[PathBuf]) -> Result<(String, PreprocessInfo), Error> {
+    preprocess_with(input, origin, includefolders, &PreprocessOptions::default())
+}
+
+/// Reads input string and returns preprocessed string with an info struct containing the origins
+/// of the lines in the output.
+///
+/// `path` is the path to the input if it is known and is used for relative includes and error
+/// messages. `includefolders` are the folders searched for absolute includes and should usually at
+/// least include the current working directory. `options` holds the include paths with shared
+/// headers and the macros defined before the input is read.
+///
+/// Includes are searched in this order:
+///
+/// - relative includes like `#include "script_component.hpp"`: relative to the including file
+///   (or the current folder for input without a path), then in the include paths in order
+/// - absolute includes like `#include "\x\cba\addons\main\script_macros.hpp"`: in the include
+///   paths in order, with the path taken as relative to them like on a P drive, then in the
+///   include folders and dependency PBOs by their `$PBOPREFIX$`
+///
+/// `#pragma armake2 mute <warning>...` mutes the warnings for the following lines until
+/// `#pragma armake2 unmute <warning>...` or the end of the file, see `PreprocessInfo::is_muted`.
+/// Other pragmas are kept in the output.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::preprocess::{preprocess_with, PreprocessOptions};
+/// let input = String::from("
+/// #define QUOTE(x) #x
+///
+/// foo = QUOTE(NAME);
+/// ");
+/// let options = PreprocessOptions {
+///     defines: vec![(String::from("NAME"), String::from("abc"))],
+///     ..Default::default()
+/// };
+///
+/// let (output, _) = preprocess_with(input, None, &Vec::new(), &options).expect("Failed to preprocess");
+///
+/// assert_eq!("foo = \"abc\";", output.trim());
+/// ```
+pub fn preprocess_with(mut input: String, origin: Option<PathBuf>, includefolders: &[PathBuf], options: &PreprocessOptions) -> Result<(String, PreprocessInfo), Error> {
+    if input.as_bytes().starts_with(&[0xef,0xbb,0xbf]) {
         input = input[3..].to_string();
     }
 
     let mut info = PreprocessInfo {
         line_origins: Vec::new(),
+        includes: Vec::new(),
+        muted: Vec::new(),
         import_stack: Vec::new()
     };
 
@@ -634,23 +1004,37 @@ pub fn preprocess(mut input: String, origin: Option<PathBuf>, includefolders: &[
     }
 
     let mut def_map: HashMap<String, Definition> = HashMap::new();
-
-    match preprocess_rec(input, origin, &mut def_map, &mut info, includefolders) {
-        Ok(result) => Ok((result, info)),
-        Err(e) => Err(e)
+    for (name, value) in &options.defines {
+        let tokens = preprocess_grammar::tokens(value).map_err(|e| error!("Invalid value for macro {}: {}", name, e))?;
+        def_map.insert(name.clone(), Definition {
+            name: name.clone(),
+            parameters: None,
+            value: tokens,
+            local: false
+        });
     }
+
+    let search_paths = include_search_paths(includefolders).prepend_error("Failed to read include folders:")?;
+
+    let lines = PreprocessParseErrorExt::format_error(preprocess_grammar::file(&input), &origin, &input)?;
+    let result = preprocess_rec(&lines, origin.clone(), &mut def_map, &mut info, &options.includepaths, &search_paths)?;
+
+    crate::deps::track(origin.iter().chain(info.includes.iter()));
+
+    Ok((result, info))
 }
 
 /// Reads input, preprocesses it and writes to output.
 ///
 /// `path` is the `path` to the input if it is known and is used for relative includes and error
 /// messages. `includefolders` are the folders searched for absolute includes and should usually at
-/// least include the current working directory.
-pub fn cmd_preprocess<I: Read, O: Write>(input: &mut I, output: &mut O, path: Option<PathBuf>, includefolders: &[PathBuf]) -> Result<(), Error> {
+/// least include the current working directory. `defines` are macros defined before the input is
+/// read. Include paths are the ones set with `init_include_paths`.
+pub fn cmd_preprocess<I: Read, O: Write>(input: &mut I, output: &mut O, path: Option<PathBuf>, includefolders: &[PathBuf], defines: &[(String, String)]) -> Result<(), Error> {
     let mut buffer = String::new();
     input.read_to_string(&mut buffer).prepend_error("Failed to read input file")?;
 
-    let (result, _) = preprocess(buffer, path, includefolders)?;
+    let (result, _) = preprocess_with(buffer, path, includefolders, &PreprocessOptions::new(defines))?;
 
     output.write_all(result.as_bytes()).prepend_error("Failed to write output")?;
 
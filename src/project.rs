@@ -0,0 +1,483 @@
+//! Functions for building all addons of a project
+
+use std::collections::{HashMap};
+use std::ffi::{OsStr};
+use std::fmt;
+use std::fs::{File, create_dir_all, read_dir};
+use std::io::{Read, Write, Error};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use openssl::hash::{hash, MessageDigest};
+use serde::{Deserialize, Serialize};
+
+use crate::error::*;
+use crate::hooks::Hooks;
+use crate::io::{AtomicFile, check_space};
+use crate::mission;
+use crate::paths::{from_fs_path};
+use crate::pbo;
+use crate::preprocess::{preprocess_with, PreprocessOptions};
+use crate::sign;
+use crate::target::target;
+
+/// Addon of a project, built from a folder in `addons/` into a PBO of the same name
+#[derive(Debug, Clone)]
+pub struct Addon {
+    pub name: String,
+    pub source: PathBuf,
+    pub target: PathBuf,
+}
+
+/// Project containing one or more addons in its `addons/` folder
+///
+/// # Examples
+///
+/// ```
+/// # use std::fs::create_dir_all;
+/// # use armake2::project::Project;
+/// let dir = tempfile::tempdir().unwrap();
+/// create_dir_all(dir.path().join("addons").join("main")).unwrap();
+/// create_dir_all(dir.path().join("addons").join("common")).unwrap();
+///
+/// let project = Project::read(dir.path().to_path_buf(), None).unwrap();
+///
+/// assert_eq!(vec!["common", "main"], project.addons.iter().map(|a| a.name.as_str()).collect::<Vec<&str>>());
+/// assert_eq!(dir.path().join("addons").join("main.pbo"), project.addons[1].target);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub root: PathBuf,
+    pub addons: Vec<Addon>,
+}
+
+/// Options used for building every addon of a project
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// maximum number of addons built at the same time, 0 for one per CPU
+    pub jobs: usize,
+    pub force: bool,
+    pub headerext: Vec<String>,
+    pub excludes: Vec<String>,
+    pub includefolders: Vec<PathBuf>,
+    /// macros defined before every config is preprocessed
+    pub defines: Vec<(String, String)>,
+    /// private key to sign every PBO with
+    pub privatekey: Option<PathBuf>,
+    /// only rebuild addons whose files changed since the last build
+    pub incremental: bool,
+    /// build all files of an addon and report all failed files instead of stopping at the first
+    pub keep_going: bool,
+    /// rewrite paths in configs relative to the addon to full paths with the prefix
+    pub rewrite_paths: bool,
+}
+
+/// Kind of a node in the build graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    Addon,
+    /// file in the addon folder
+    Source,
+    /// file included by a source file during preprocessing
+    Include,
+    /// built PBO
+    Output,
+    /// signature of a built PBO
+    Signature,
+}
+
+/// Node in the build graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub id: usize,
+    pub kind: NodeKind,
+    /// addon name for addons, path for everything else
+    pub name: String,
+    /// SHA-1 of the file contents for sources and includes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// Graph of everything a project build reads and writes. Edges point from an addon to its
+/// sources, from sources and includes to what they are used for and from PBOs to their
+/// signatures.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuildGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<(usize, usize)>,
+    #[serde(skip)]
+    ids: HashMap<(NodeKind, String), usize>,
+}
+
+/// Reason for rebuilding an output of the build graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebuildReason {
+    /// output is not part of the previous build
+    NotBuilt,
+    /// output file doesn't exist anymore
+    Missing,
+    /// source file was added to the addon
+    SourceAdded(String),
+    /// content of the source file changed
+    SourceChanged(String),
+    /// source file was removed from the addon
+    SourceRemoved(String),
+    /// content of an include changed, given together with the including source file
+    IncludeChanged(String, String),
+}
+
+impl fmt::Display for RebuildReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RebuildReason::NotBuilt => write!(f, "not built before"),
+            RebuildReason::Missing => write!(f, "output is missing"),
+            RebuildReason::SourceAdded(path) => write!(f, "{} was added", path),
+            RebuildReason::SourceChanged(path) => write!(f, "{} changed", path),
+            RebuildReason::SourceRemoved(path) => write!(f, "{} was removed", path),
+            RebuildReason::IncludeChanged(include, source) => write!(f, "{} (included by {}) changed", include, source),
+        }
+    }
+}
+
+impl BuildGraph {
+    /// Reads a graph written with `write_json`.
+    pub fn read<I: Read>(input: &mut I) -> Result<BuildGraph, Error> {
+        let mut graph: BuildGraph = serde_json::from_reader(input).map_err(|e| error!("{}", e))?;
+        graph.ids = graph.nodes.iter().map(|n| ((n.kind, n.name.clone()), n.id)).collect();
+        Ok(graph)
+    }
+
+    fn sources(&self, output: usize) -> Vec<usize> {
+        self.edges.iter()
+            .filter(|(from, to)| *to == output && self.nodes[*from].kind == NodeKind::Source)
+            .map(|(from, _)| *from).collect()
+    }
+
+    fn includes(&self, source: usize) -> Vec<usize> {
+        self.edges.iter()
+            .filter(|(from, to)| *to == source && self.nodes[*from].kind == NodeKind::Include)
+            .map(|(from, _)| *from).collect()
+    }
+
+    /// Returns why the output with the given name has to be rebuilt, compared to the graph of the
+    /// previous build. An empty list means the output is up to date.
+    pub fn rebuild_reasons(&self, previous: &BuildGraph, output: &str) -> Vec<RebuildReason> {
+        let id = match self.find(NodeKind::Output, output) {
+            Some(id) => id,
+            None => { return Vec::new(); }
+        };
+        let previous_id = match previous.find(NodeKind::Output, output) {
+            Some(id) => id,
+            None => { return vec![RebuildReason::NotBuilt]; }
+        };
+
+        let mut reasons: Vec<RebuildReason> = Vec::new();
+        if !Path::new(output).exists() {
+            reasons.push(RebuildReason::Missing);
+        }
+
+        let previous_sources = previous.sources(previous_id);
+        for source in self.sources(id) {
+            let node = &self.nodes[source];
+            let previous_source = previous_sources.iter().find(|s| previous.nodes[**s].name == node.name);
+            match previous_source {
+                None => { reasons.push(RebuildReason::SourceAdded(node.name.clone())); },
+                Some(s) if previous.nodes[*s].hash != node.hash => { reasons.push(RebuildReason::SourceChanged(node.name.clone())); },
+                Some(s) => {
+                    let previous_includes = previous.includes(*s);
+                    for include in self.includes(source) {
+                        let include_node = &self.nodes[include];
+                        let unchanged = previous_includes.iter().any(|i| previous.nodes[*i].name == include_node.name && previous.nodes[*i].hash == include_node.hash);
+                        if !unchanged {
+                            reasons.push(RebuildReason::IncludeChanged(include_node.name.clone(), node.name.clone()));
+                        }
+                    }
+                }
+            }
+        }
+
+        for source in previous_sources {
+            let name = &previous.nodes[source].name;
+            if self.find(NodeKind::Source, name).map(|s| !self.edges.contains(&(s, id))).unwrap_or(true) {
+                reasons.push(RebuildReason::SourceRemoved(name.clone()));
+            }
+        }
+
+        reasons
+    }
+
+    fn node(&mut self, kind: NodeKind, name: String, hash: Option<String>) -> usize {
+        if let Some(id) = self.ids.get(&(kind, name.clone())) {
+            return *id;
+        }
+
+        let id = self.nodes.len();
+        self.ids.insert((kind, name.clone()), id);
+        self.nodes.push(GraphNode { id, kind, name, hash });
+        id
+    }
+
+    fn edge(&mut self, from: usize, to: usize) {
+        if !self.edges.contains(&(from, to)) {
+            self.edges.push((from, to));
+        }
+    }
+
+    /// Returns the ID of the node with the given kind and name.
+    pub fn find(&self, kind: NodeKind, name: &str) -> Option<usize> {
+        self.ids.get(&(kind, name.to_string())).cloned()
+    }
+
+    /// Writes the graph as JSON with a list of nodes and a list of `[from, to]` edges.
+    pub fn write_json<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        serde_json::to_writer_pretty(&mut *output, self).map_err(|e| error!("{}", e))?;
+        writeln!(output)?;
+        Ok(())
+    }
+
+    /// Writes the graph in the DOT format of Graphviz.
+    pub fn write_dot<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        writeln!(output, "digraph build {{")?;
+        writeln!(output, "    rankdir=LR;")?;
+        for node in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::Addon => "box3d",
+                NodeKind::Source => "note",
+                NodeKind::Include => "note, style=dashed",
+                NodeKind::Output => "box",
+                NodeKind::Signature => "octagon",
+            };
+            writeln!(output, "    n{} [label=\"{}\", shape={}];", node.id, node.name.replace('\\', "\\\\").replace('"', "\\\""), shape)?;
+        }
+        for (from, to) in &self.edges {
+            writeln!(output, "    n{} -> n{};", from, to)?;
+        }
+        writeln!(output, "}}")?;
+        Ok(())
+    }
+}
+
+fn file_hash(path: &Path) -> Result<String, Error> {
+    let mut buffer: Vec<u8> = Vec::new();
+    File::open(path)?.read_to_end(&mut buffer)?;
+    let digest = hash(MessageDigest::sha1(), &buffer).map_err(|e| error!("{}", e))?;
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+impl Project {
+    /// Reads the addons of the project at `root`. PBOs are placed in `target` or next to the
+    /// addon folders if it is not given.
+    pub fn read(root: PathBuf, target: Option<PathBuf>) -> Result<Project, Error> {
+        let addons_dir = root.join("addons");
+        if !addons_dir.is_dir() {
+            return Err(error!("Project folder \"{}\" does not contain an addons folder.", root.display()));
+        }
+        let target = target.unwrap_or_else(|| addons_dir.clone());
+
+        let mut addons: Vec<Addon> = Vec::new();
+        for entry in read_dir(&addons_dir).prepend_error("Failed to read addons folder:")? {
+            let path = entry?.path();
+            if !path.is_dir() { continue; }
+
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            addons.push(Addon {
+                target: target.join(format!("{}.pbo", name)),
+                name,
+                source: path,
+            });
+        }
+        addons.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Project {
+            root,
+            addons,
+        })
+    }
+
+    /// Returns the graph of all files read and written when building the project with the given
+    /// options. Configs are preprocessed to find their includes.
+    pub fn graph(&self, options: &BuildOptions) -> Result<BuildGraph, Error> {
+        let mut graph = BuildGraph::default();
+
+        for addon in &self.addons {
+            let addon_id = graph.node(NodeKind::Addon, addon.name.clone(), None);
+            let output_id = graph.node(NodeKind::Output, addon.target.display().to_string(), None);
+
+            let mut files = pbo::list_files(&addon.source)?;
+            files.sort();
+            for path in files {
+                let name = from_fs_path(path.strip_prefix(&addon.source).unwrap());
+                if !pbo::file_allowed(&name, &options.excludes) { continue; }
+
+                let source_id = graph.node(NodeKind::Source, path.display().to_string(), Some(file_hash(&path)?));
+                graph.edge(addon_id, source_id);
+                graph.edge(source_id, output_id);
+
+                let extension = path.extension().unwrap_or_else(|| OsStr::new("")).to_str().unwrap();
+                if !["cpp", "rvmat"].contains(&extension) { continue; }
+
+                let mut content = String::new();
+                File::open(&path)?.read_to_string(&mut content)?;
+                let (_, info) = preprocess_with(content, Some(path.clone()), &options.includefolders, &PreprocessOptions::new(&options.defines))
+                    .prepend_error(format!("Failed to preprocess {}:", path.display()))?;
+
+                let mut includes: Vec<PathBuf> = info.line_origins.into_iter().filter_map(|(_, origin)| origin).collect();
+                includes.sort();
+                includes.dedup();
+                for include in includes.into_iter().filter(|i| i != &path) {
+                    let include_id = graph.node(NodeKind::Include, include.display().to_string(), Some(file_hash(&include)?));
+                    graph.edge(include_id, source_id);
+                }
+            }
+
+            if let Some(ref privatekey) = options.privatekey {
+                let keyname = privatekey.file_stem().unwrap().to_str().unwrap();
+                let mut signature = addon.target.clone();
+                signature.set_extension(format!("pbo.{}.bisign", keyname));
+                let signature_id = graph.node(NodeKind::Signature, signature.display().to_string(), None);
+                graph.edge(output_id, signature_id);
+            }
+        }
+
+        Ok(graph)
+    }
+}
+
+impl Addon {
+    /// Builds the addon into its target PBO and signs it if a private key is given. Campaigns in
+    /// the addon are checked first (see `mission::check_campaigns`).
+    pub fn build(&self, options: &BuildOptions) -> Result<(), Error> {
+        if !options.force && self.target.exists() {
+            return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", self.target.display()));
+        }
+
+        mission::check_campaigns(&self.source, &options.includefolders, &options.defines)?;
+        check_space(&self.target, pbo::estimate_size(&self.source, &options.excludes)?)?;
+
+        let mut file = AtomicFile::create(&self.target).prepend_error("Failed to open output file:")?;
+        pbo::cmd_build(self.source.clone(), &mut file, &options.headerext, &options.excludes, &options.includefolders, &options.defines, &Hooks::default(), options.keep_going, options.rewrite_paths)?;
+        file.commit().prepend_error("Failed to write PBO:")?;
+
+        if let Some(ref privatekey) = options.privatekey {
+            let version = target().signature_version().ok_or_else(|| error!("{} doesn't support signatures.", target().name()))?;
+            sign::cmd_sign(std::slice::from_ref(privatekey), self.target.clone(), None, version, options.force)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds all addons of the project, up to `options.jobs` at the same time.
+///
+/// Failing addons don't stop the others from being built. Returns the names of the addons that
+/// failed together with their errors.
+pub fn build_project(project: &Project, options: &BuildOptions) -> Vec<(String, Error)> {
+    let jobs = if options.jobs == 0 {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    } else {
+        options.jobs
+    };
+
+    let next = AtomicUsize::new(0);
+    let failed: Mutex<Vec<(usize, Error)>> = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _i in 0..jobs.min(project.addons.len()) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let addon = match project.addons.get(index) {
+                        Some(addon) => addon,
+                        None => break
+                    };
+
+                    if let Err(e) = addon.build(options) {
+                        failed.lock().unwrap().push((index, e));
+                    }
+                }
+            });
+        }
+    });
+
+    let mut failed = failed.into_inner().unwrap();
+    failed.sort_by_key(|(index, _)| *index);
+    failed.into_iter().map(|(index, e)| (project.addons[index].name.clone(), e)).collect()
+}
+
+/// File the build graph of the last successful incremental build is stored in, in the folder
+/// containing the PBOs
+pub const GRAPH_FILE: &str = ".armake2-graph.json";
+
+/// Builds all addons of the project at `root` and prints a summary of the failed addons.
+///
+/// For incremental builds, only addons whose files changed according to the build graph of the
+/// previous build are built, and the changes causing them to be rebuilt are printed.
+pub fn cmd_project_build(root: PathBuf, target: Option<PathBuf>, options: &BuildOptions) -> Result<(), Error> {
+    let mut project = Project::read(root, target).prepend_error("Failed to read project:")?;
+    let total = project.addons.len();
+
+    let target_dir = match project.addons.first().and_then(|a| a.target.parent()) {
+        Some(parent) => parent.to_path_buf(),
+        None => { return Err(error!("Project doesn't contain any addons.")); }
+    };
+    create_dir_all(&target_dir).prepend_error("Failed to create target folder:")?;
+
+    let mut options = options.clone();
+    let graph_path = target_dir.join(GRAPH_FILE);
+    let mut graph: Option<BuildGraph> = None;
+    if options.incremental {
+        let current = project.graph(&options)?;
+        let previous = if graph_path.exists() {
+            BuildGraph::read(&mut File::open(&graph_path)?).prepend_error("Failed to read previous build graph:")?
+        } else {
+            BuildGraph::default()
+        };
+
+        project.addons.retain(|addon| {
+            let reasons = current.rebuild_reasons(&previous, &addon.target.display().to_string());
+            for reason in &reasons {
+                eprintln!("Rebuilding {}: {}", addon.name, reason);
+            }
+            !reasons.is_empty()
+        });
+
+        // outputs of changed addons are outdated and always replaced
+        options.force = true;
+        graph = Some(current);
+    }
+
+    let failed = build_project(&project, &options);
+    for (name, error) in &failed {
+        eprintln!("Failed to build {}:\n{}", name, error);
+    }
+
+    if !failed.is_empty() {
+        return Err(error!("{} of {} addons failed to build.", failed.len(), project.addons.len()));
+    }
+
+    if let Some(graph) = graph {
+        let mut file = AtomicFile::create(&graph_path).prepend_error("Failed to write build graph:")?;
+        graph.write_json(&mut file)?;
+        file.commit().prepend_error("Failed to write build graph:")?;
+        eprintln!("Built {} of {} addons, {} up to date.", project.addons.len(), total, total - project.addons.len());
+    } else {
+        eprintln!("Built {} addons.", project.addons.len());
+    }
+
+    Ok(())
+}
+
+/// Writes the build graph of the project at `root` to output as JSON or DOT.
+pub fn cmd_project_graph<O: Write>(output: &mut O, root: PathBuf, target: Option<PathBuf>, options: &BuildOptions, format: &str) -> Result<(), Error> {
+    let project = Project::read(root, target).prepend_error("Failed to read project:")?;
+    let graph = project.graph(options)?;
+
+    match format {
+        "json" => graph.write_json(output),
+        "dot" => graph.write_dot(output),
+        _ => Err(error!("Unknown graph format \"{}\", expected json or dot.", format))
+    }
+}
@@ -1,65 +1,268 @@
 use std::collections::{HashSet};
 use std::fs::{File};
-use std::io::{Error, Read, Cursor, stdin, stdout};
+use std::io::{Error, Read, Write, Cursor, stdin, stdout};
 use std::iter::{FromIterator};
-use std::path::{PathBuf};
+use std::path::{Path, PathBuf};
+use std::time::{Duration};
 
 use crate::*;
+use crate::audit;
 use crate::binarize;
+use crate::cache;
 use crate::config;
+use crate::deps;
 use crate::error::*;
-use crate::io::{Input, Output};
+use crate::git;
+use crate::hooks::{Hooks, HookStage};
+use crate::install;
+use crate::interrupt;
+use crate::io::{AtomicFile, Input, Output, check_space};
+use crate::lint;
+use crate::lock;
+use crate::mission;
+use crate::paa;
+use crate::paths;
 use crate::pbo;
 use crate::preprocess;
+use crate::project;
+use crate::selftest;
+use crate::serve;
 use crate::sign;
+use crate::stringtable;
+use crate::target;
+use crate::temp;
+use crate::ui;
+use crate::watch;
 
+use docopt::Docopt;
 use serde::Deserialize;
 
 pub const USAGE: &str = "
 armake2
 
 Usage:
-    armake2 rapify [-v] [-f] [-w <wname>]... [-i <includefolder>]... [<source> [<target>]]
-    armake2 preprocess [-v] [-f] [-w <wname>]... [-i <includefolder>]... [<source> [<target>]]
-    armake2 derapify [-v] [-f] [-d <indentation>] [<source> [<target>]]
-    armake2 binarize [-v] [-f] [-w <wname>]... <source> <target>
-    armake2 build [-v] [-f] [-w <wname>]... [-i <includefolder>]... [-x <excludepattern>]... [-e <headerext>]... [-k <privatekey>] [-s <signature>] <sourcefolder> [<target>]
-    armake2 pack [-v] [-f] [-x <excludepattern>]... [-e <headerext>]... [-k <privatekey>] [-s <signature>] <sourcefolder> [<target>]
-    armake2 inspect [-v] [<source>]
-    armake2 unpack [-v] [-f] <source> <targetfolder>
+    armake2 rapify [-v] [-f] [--optimize] [--compress] [--strict] [--recover] [--json] [--target <target>] [--trace-macros] [--summary] [--summary-json <file>] [-M <depfile>] [-w <wname>]... [-i <includefolder>]... [-I <includepath>]... [-D <define>]... [<source> [<target>]]
+    armake2 lint [-v] [--fix] [--case-sensitive-paths] [-w <wname>]... [-i <includefolder>]... [-I <includepath>]... <source>
+    armake2 lint [-v] --scan-secrets <source>
+    armake2 lint [-v] [-w <wname>]... --unused-keys <source>
+    armake2 lint [-v] --list-rules
+    armake2 mission lint [-v] [-w <wname>]... [-i <includefolder>]... [-I <includepath>]... [<missionfolder>]
+    armake2 mission pack [-v] [-f] [--binarize] [-x <excludepattern>]... <missionfolder> [<target>]
+    armake2 mission classes [-v] [-w <wname>]... [-i <includefolder>]... [-I <includepath>]... [<missionfolder>]
+    armake2 preprocess [-v] [-f] [--trace-macros] [-w <wname>]... [-i <includefolder>]... [-I <includepath>]... [-D <define>]... [<source> [<target>]]
+    armake2 derapify [-v] [-f] [-d <indentation>] [--format <format>] [<source> [<target>]]
+    armake2 config get [-v] [--full] [--format <format>] [-i <includefolder>]... [-I <includepath>]... <source> <path>
+    armake2 binarize [-v] [-f] [--keep-temp] [-w <wname>]... <source> <target>
+    armake2 build [-v] [-f] [--target <target>] [-w <wname>]... [-i <includefolder>]... [-I <includepath>]... [-D <define>]... [-x <excludepattern>]... [-e <headerext>]... [--compress] [--compress-ext <extension>]... [-k <privatekey>]... [--signer <command>] [-s <signature>] [--hook <hook>]... [--cache <cache>] [--git] [--require-clean] [--locked] [--keep-going] [--rewrite-paths] [--allow-artifacts] [--case-sensitive-paths] [--keep-temp] [--trace-macros] [-M <depfile>] [--summary] [--summary-json <file>] <sourcefolder> [<target>]
+    armake2 pack [-v] [-f] [--target <target>] [-x <excludepattern>]... [-e <headerext>]... [--compress] [--compress-ext <extension>]... [-k <privatekey>]... [--signer <command>] [-s <signature>] [--hook <hook>]... [--manifest <manifest>] [--allow-artifacts] [--case-sensitive-paths] [--keep-temp] <sourcefolder> [<target>]
+    armake2 inspect [-v] [--detect] [--types] [--export-manifest <manifest>] [--untrusted] [<source>]
+    armake2 audit [-v] <pbo>
+    armake2 unpack [-v] [-f] [--convert-configs] [--chmod <mode>] [--exec-ext <extension>]... <source> <targetfolder>
     armake2 cat [-v] <source> <filename> [<target>]
-    armake2 keygen [-v] [-f] <keyname>
-    armake2 sign [-v] [-f] [--v2] <privatekey> <pbo> [<signature>]
-    armake2 verify [-v] <publickey> <pbo> [<signature>]
+    armake2 cat [-v] [-f] [--into <folder>] <address>...
+    armake2 roundtrip [-v] <pbo>
+    armake2 checksum [-v] [--fix] <pbo>
+    armake2 install [-v] <pbo> <targetfolder>
+    armake2 keygen [-v] [-f] [--owner <owner>] [--comment <comment>] <keyname>
+    armake2 key info [-v] <key>
+    armake2 sign [-v] [-f] [--target <target>] [--v2] [-k <privatekey>]... [--signer <command>] <privatekey> <pbo> [<signature>]
+    armake2 sign-mod [-v] [-f] [--target <target>] [--v2] [--copy-key] [--signer <command>] <privatekey> <mod>
+    armake2 verify [-v] [--warn-old-keys <days>] <publickey> <pbo> [<signature>]
+    armake2 paa2dds [-v] [-f] [<source> [<target>]]
+    armake2 dds2paa [-v] [-f] [--no-normalize] [--alpha-coverage] [<source> [<target>]]
+    armake2 img2paa [-v] [-f] [-t <paatype>] [--no-normalize] [--alpha-coverage] [<source> [<target>]]
+    armake2 paa2img [-v] [-f] [<source> [<target>]]
+    armake2 stringtable check [-v] [<source>]
+    armake2 stringtable export [-v] [-f] [<source> [<target>]]
+    armake2 stringtable import [-v] [-f] [--name <name>] [<source> [<target>]]
+    armake2 ui atlas [-v] [-f] [--name <name>] [--size <size>] [--prefix <prefix>] <targetfolder> <images>...
+    armake2 project build [-v] [-f] [--incremental] [--target <target>] [-j <jobs>] [-w <wname>]... [-i <includefolder>]... [-I <includepath>]... [-D <define>]... [-x <excludepattern>]... [-e <headerext>]... [--compress] [--compress-ext <extension>]... [-k <privatekey>] [--signer <command>] [--cache <cache>] [--git] [--require-clean] [--locked] [--keep-going] [--rewrite-paths] [--case-sensitive-paths] [--keep-temp] [--trace-macros] [--summary] [--summary-json <file>] [<projectfolder> [<targetfolder>]]
+    armake2 project graph [-v] [--format <format>] [-i <includefolder>]... [-I <includepath>]... [-x <excludepattern>]... [-k <privatekey>] [<projectfolder> [<targetfolder>]]
+    armake2 watch [-v] [--pack] [--interval <ms>] [-w <wname>]... [-i <includefolder>]... [-I <includepath>]... [-D <define>]... [-x <excludepattern>]... [-e <headerext>]... [-k <privatekey>]... [--install <modfolder>] <sourcefolder> <target>
+    armake2 serve [-v] [--address <address>] <sources>...
+    armake2 selftest [-v]
     armake2 (-h | --help)
     armake2 --version
 
 Commands:
     rapify      Preprocess and rapify a config file.
+    lint        List problems of a config that can be fixed automatically, or fix them, and
+                check its Eden editor attributes, remote execution settings and the
+                localization of user-facing strings like displayName.
+                With --scan-secrets, check an addon folder or PBO for secrets instead.
+                With --unused-keys, list the stringtable keys of an addon or project
+                folder or PBO that its configs and scripts don't use, and the keys
+                they use that no stringtable defines.
+                With --list-rules, list the rules configs are checked with. Builds run
+                them as well, except localization.
+    mission lint    Check the description.ext of a mission folder: respawn, loadouts,
+                    parameters, referenced files and stringtable keys.
+    mission pack    Pack a mission folder named <mission>.<world> into <mission>.<world>.pbo,
+                    in the current folder or the target folder.
+    mission classes List the addons and object classes a mission.sqm or composition.sqe
+                    depends on and check them against the dependency PBOs given with -i.
     preprocess  Preprocess a file.
     derapify    Derapify a config.
+    config get  Print the value at a path like \"CfgPatches/my_addon/requiredVersion\" in a
+                config.cpp or config.bin, or the entries of the class at the path.
     binarize    Binarize a file using BI's binarize.exe (Windows only).
     build       Build a PBO from a folder.
     pack        Pack a folder into a PBO without any binarization or rapification.
-    inspect     Inspect a PBO and list contained files.
+    inspect     Inspect a PBO: header extensions, file headers, total size and checksum.
+    audit       Report which tool likely produced a PBO, whether it is binarized, obfuscated
+                or signed and by which key, checking signatures with the keys of the mod.
     unpack      Unpack a PBO into a folder.
-    cat         Read the named file from the target PBO to stdout.
+    cat         Read the named file from the target PBO to stdout. Files can also be given
+                as <pbo>::<entry> with globs on both sides, e.g. \"addons/*.pbo::**/*.hpp\".
+    roundtrip   Repack a PBO and check that the result is identical to it.
+    checksum    Recompute the SHA-1 checksum of a PBO and compare it to the one in its footer.
+                With --fix, rewrite the footer with the correct checksum.
+    install     Copy a PBO and its signatures into a folder and remove stale signatures.
     keygen      Generate a keypair with the specified path (extensions are added).
+    key info    Print the name, length and metadata (creation date, owner, comment) of a key.
     sign        Sign a PBO with the given private key.
+    sign-mod    Sign every PBO in the addons folder of a mod with the given private key.
     verify      Verify a PBO's signature with the given public key.
+    paa2dds     Convert a PAA texture to DDS without recompression.
+    dds2paa     Convert a DDS texture to PAA without recompression.
+    img2paa     Convert a TGA image to a PAA texture.
+    paa2img     Convert a PAA texture to a TGA image.
+    stringtable check   Check the keys and languages of a stringtable.xml. Stringtables are
+                        also checked when building.
+    stringtable export  Convert a stringtable.xml to CSV for translators.
+    stringtable import  Convert CSV from stringtable export back to a stringtable.xml.
+    ui atlas    Pack PAA/DDS images into texture atlases with a header of UV macros.
+    project build   Build all addons in the addons folder of a project in parallel. The mission
+                    chains and includes of campaigns in the addons are checked first.
+    project graph   Print the build graph of a project (addons, files, outputs, signatures).
+    watch       Build a PBO from a folder and build it again whenever files in the folder change,
+                optionally signing it and installing it into a mod folder after every build.
+    serve       Serve the content of PBOs and folders read-only over HTTP/WebDAV.
+    selftest    Check this build against embedded known-good files and list its capabilities.
+
+    Instead of a command, \"@<file>\" runs all commands listed in a response file, one per line
+    and without the leading \"armake2\", in a single process. Empty lines and lines starting
+    with \"#\" are ignored.
+
+    With \"--compat-v1\" as the first argument, the command line of armake 1.x is accepted, e.g.
+    \"armake2 --compat-v1 build -p addons/main main.pbo\".
+
+    Files read from <source> can also be given as <pbo>::<entry> to read them from a PBO
+    without unpacking it, e.g. \"armake2 derapify addons/main.pbo::config.bin\". Globs
+    are allowed if they match a single file.
 
 Options:
     -v --verbose                Enable verbose output.
     -f --force                  Overwrite the target file/folder if it already exists.
     -w --warning <wname>        Warning to disable
-    -i --include <includefolder>    Folder to search for includes, defaults to CWD.
+    -i --include <includefolder>    Folder, mod folder or PBO to search for includes, defaults to CWD.
+    -I --include-path <includepath>     Folder with shared headers. Relative includes not found next
+                                          to the including file and absolute includes are looked up
+                                          in these folders first, in the given order.
+    -D --define <define>        Macro to define before preprocessing as \"NAME=value\", or \"NAME\"
+                                  to define it as 1, e.g. for debug builds.
     -x --exclude <excludepattern>   Glob pattern to exclude from PBO.
                                       For unpack: pattern to exclude from output folder.
     -d --indent <indentation>   String to use for indentation. 4 spaces by default.
     -e --headerext <headerext>  Extension to add to PBO header as \"key=value\".
-    -k --key <privatekey>       Sign the PBO with the given private key.
+    --compress-ext <extension>  Compress files with the extension instead of scripts and text files.
+                                  Can be given multiple times, implies --compress.
+    -k --key <privatekey>       Sign the PBO with the given private key. Can be given multiple times
+                                  to create a signature for every key.
     -s --signature <signature>  Signature path to use when signing the PBO.
+    --signer <command>          Command creating the signatures instead of the private keys, e.g. a
+                                  PKCS#11 tool using a key in an HSM. The keys are given as the
+                                  public keys. The command reads the padded hash from stdin and
+                                  writes the raw RSA signature to stdout.
     --v2                     Generate an older v2 signature.
+    --copy-key                  Write the public key to the keys folder of the mod.
+    --owner <owner>             Owner recorded in the metadata of a generated key.
+    --comment <comment>         Comment recorded in the metadata of a generated key.
+    --warn-old-keys <days>      Warn if the public key was created more than that many days ago.
+    --hook <hook>               Command to run at a build stage as \"stage=command\". Stages are
+                                  pre-preprocess, post-rapify, pre-pack and post-sign.
+    -t --type <paatype>         PAA type to encode images as, e.g. DXT1, DXT5 or ARGB8888. DXT1 for
+                                  opaque and DXT5 for transparent images by default.
+    --no-normalize              Don't renormalize generated mipmaps of normal maps (_nohq).
+    --alpha-coverage            Preserve alpha test coverage in mipmaps of _ca textures.
+    --name <name>               Name of the atlas files and macro prefix. \"atlas\" by default.
+                                  For stringtable import: name of the project, the first
+                                  package by default.
+    --size <size>               Width and height of atlas pages. 1024 by default.
+    --prefix <prefix>           Path prepended to atlas textures in the header.
+    --format <format>           Output format, json (default) or dot for project graph.
+                                  For derapify and config get: cpp (default), json or yaml.
+    --cache <cache>             Folder or http(s):// URL to cache rapified and binarized files in.
+                                  Configs are only preprocessed again if they or their includes
+                                  changed, e.g. with \"--cache .armake2-cache\".
+    --git                       Define __GIT_HASH__ and add a git_hash header extension with the
+                                  current commit hash.
+    --require-clean             Like --git, but fail if the git tree has uncommitted changes.
+    -j --jobs <jobs>            Number of addons to build at the same time. One per CPU by default.
+    --incremental               Only rebuild addons whose files changed since the last build and
+                                  print the changes causing each rebuild.
+    --target <target>           Engine to build for: ofp, a2, a3 (default) or dayz. Selects the
+                                  signature version and warns about unsupported features.
+    --optimize                  Remove redundant properties from the rapified config and print the
+                                  size savings.
+    --compress                  Compress the rapified config with LZSS. For build and pack, compress
+                                  scripts and other text files in the PBO with LZSS.
+    --strict                    Fail for unquoted strings, missing semicolons and duplicate
+                                properties instead of tolerating them.
+    --recover                   Skip invalid entries and report all syntax errors of the config
+                                instead of only the first.
+    --json                      Read the config to rapify as JSON, like derapify --format json
+                                  writes it.
+    --fix                       Quote unquoted strings, add missing semicolons and replace forward
+                                  slashes in paths in the config file and print the changes.
+                                  For checksum: rewrite a missing or wrong checksum.
+    --scan-secrets              Check for private keys, API tokens and absolute paths exposing
+                                  user names and fail if any are found.
+    --unused-keys               Check the usages of stringtable keys instead of a config.
+    --list-rules                List the rules of lint with their names, which mute them with -w.
+    --detect                    Only print the type of container (PBO, EBO, obfuscated PBO).
+    --types                     List the type of every file by content and flag mismatching
+                                  extensions.
+    --into <folder>             Write the files read by cat to their game paths in the folder.
+    --untrusted                 Inspect a PBO from an untrusted source with bounded header counts,
+                                  name lengths and memory, without writing anything.
+    --convert-configs           Derapify configs, materials and missions while unpacking.
+    --chmod <mode>              Permissions of unpacked files in octal, e.g. 644. On Windows, files
+                                  without write permission for the owner are made read-only.
+    --exec-ext <extension>      Make unpacked files with the extension executable, e.g. sh.
+    --export-manifest <manifest>    Write the order and headers of files and header extensions as
+                                  JSON, to recreate the PBO with pack --manifest after unpacking.
+    --manifest <manifest>       Manifest from inspect --export-manifest to restore when packing.
+    --address <address>         Address to serve on, 127.0.0.1:8080 by default.
+    --locked                    Fail instead of warning if dependency PBOs don't match armake.lock.
+    --keep-going                Build all files of an addon and report every failed file instead of
+                                stopping at the first.
+    --binarize                  Rapify the mission.sqm when packing a mission.
+    -M --deps <depfile>         Write the config and included files read by rapify or build to a
+                                  dependency file for make and ninja, or as JSON if the file
+                                  name ends in .json.
+    --keep-temp                 Keep the temporary folders of binarize.exe and hooks and print
+                                  their paths instead of removing them, for debugging.
+    --trace-macros              Print every macro expansion with its arguments, result, file and
+                                  line while preprocessing. Configs restored from the cache
+                                  are not preprocessed again.
+    --rewrite-paths             Rewrite paths in configs that are relative to the config or the
+                                  addon folder, like \"data\\tex_co.paa\", to full paths with
+                                  the prefix, like \"\\x\\addon\\data\\tex_co.paa\".
+    --allow-artifacts           Pack PBOs, signatures, private keys, cache files and the output file
+                                  found in the source folder instead of failing.
+    --case-sensitive-paths      Tell files apart whose paths only differ in case, e.g. when checking
+                                  function files or rewriting paths. The engine ignores case.
+    --full                      For config get: print the whole class instead of its entry names.
+    --pack                      For watch: pack the folder like pack instead of building it.
+    --interval <ms>             Milliseconds the watched folder has to be unchanged before a build,
+                                  and between checks for changes where the system doesn't
+                                  report them [default: 500].
+    --install <modfolder>       Install the PBO and its signatures into the folder after every
+                                  build, e.g. the addons folder of a mod in the game folder.
+    --summary                   Print the number of errors and warnings and the most frequent
+                                warnings and files with the most warnings at the end.
+    --summary-json <file>       Write the summary of errors and warnings as JSON to the file.
     -h --help                   Show usage information and exit.
        --version                Print the version number and exit.
 ";
@@ -68,42 +271,136 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 #[derive(Debug, Deserialize)]
 pub struct Args {
     cmd_rapify: bool,
+    cmd_lint: bool,
+    cmd_mission: bool,
+    cmd_classes: bool,
     cmd_preprocess: bool,
     cmd_derapify: bool,
+    cmd_config: bool,
+    cmd_get: bool,
     cmd_binarize: bool,
     cmd_build: bool,
     cmd_pack: bool,
     cmd_inspect: bool,
     cmd_unpack: bool,
+    cmd_roundtrip: bool,
+    cmd_checksum: bool,
+    cmd_install: bool,
+    cmd_audit: bool,
     cmd_cat: bool,
     cmd_keygen: bool,
+    cmd_key: bool,
+    cmd_info: bool,
     cmd_sign: bool,
+    cmd_sign_mod: bool,
     cmd_verify: bool,
+    cmd_paa2dds: bool,
+    cmd_dds2paa: bool,
+    cmd_img2paa: bool,
+    cmd_paa2img: bool,
+    cmd_stringtable: bool,
+    cmd_check: bool,
+    cmd_export: bool,
+    cmd_import: bool,
+    cmd_ui: bool,
+    cmd_atlas: bool,
+    cmd_project: bool,
+    cmd_serve: bool,
+    cmd_selftest: bool,
+    cmd_graph: bool,
+    cmd_watch: bool,
     flag_verbose: bool,
     flag_force: bool,
     flag_warning: Vec<String>,
     flag_include: Vec<String>,
+    flag_include_path: Vec<String>,
+    flag_define: Vec<String>,
     flag_exclude: Vec<String>,
     flag_headerext: Vec<String>,
-    flag_key: Option<String>,
+    flag_key: Vec<String>,
     flag_signature: Option<String>,
+    flag_hook: Vec<String>,
+    flag_cache: Option<String>,
+    flag_git: bool,
+    flag_require_clean: bool,
+    flag_incremental: bool,
+    flag_locked: bool,
+    flag_keep_going: bool,
+    flag_rewrite_paths: bool,
+    flag_allow_artifacts: bool,
+    flag_case_sensitive_paths: bool,
+    flag_binarize: bool,
+    flag_summary: bool,
+    flag_summary_json: Option<String>,
+    flag_detect: bool,
+    flag_types: bool,
+    flag_convert_configs: bool,
+    flag_chmod: Option<String>,
+    flag_exec_ext: Vec<String>,
+    flag_export_manifest: Option<String>,
+    flag_untrusted: bool,
+    flag_into: Option<String>,
+    flag_compress_ext: Vec<String>,
+    flag_manifest: Option<String>,
+    flag_optimize: bool,
+    flag_compress: bool,
+    flag_strict: bool,
+    flag_recover: bool,
+    flag_json: bool,
+    flag_fix: bool,
+    flag_scan_secrets: bool,
+    flag_list_rules: bool,
+    flag_unused_keys: bool,
+    flag_target: Option<String>,
+    flag_address: Option<String>,
     flag_indent: Option<String>,
     flag_v2: bool,
+    flag_copy_key: bool,
+    flag_signer: Option<String>,
+    flag_owner: Option<String>,
+    flag_comment: Option<String>,
+    flag_warn_old_keys: Option<u64>,
+    flag_no_normalize: bool,
+    flag_alpha_coverage: bool,
+    flag_type: Option<String>,
+    flag_name: Option<String>,
+    flag_size: Option<u16>,
+    flag_prefix: Option<String>,
+    flag_jobs: Option<usize>,
+    flag_keep_temp: bool,
+    flag_trace_macros: bool,
+    flag_deps: Option<String>,
+    flag_format: Option<String>,
+    flag_full: bool,
+    flag_pack: bool,
+    flag_interval: u64,
+    flag_install: Option<String>,
     flag_version: bool,
     arg_source: Option<String>,
     arg_target: Option<String>,
     arg_filename: String,
+    arg_address: Vec<String>,
     arg_sourcefolder: String,
     arg_targetfolder: String,
     arg_keyname: String,
+    arg_key: String,
     arg_privatekey: String,
     arg_publickey: String,
     arg_signature: Option<String>,
     arg_pbo: String,
+    arg_mod: String,
+    arg_path: String,
+    arg_images: Vec<String>,
+    arg_sources: Vec<String>,
+    arg_projectfolder: Option<String>,
+    arg_missionfolder: Option<String>,
 }
 
 fn get_input(args: &Args) -> Result<Input, Error> {
     if let Some(ref source) = args.arg_source {
+        if paths::EntryAddress::is_address(source) {
+            return Ok(Input::Cursor(Cursor::new(pbo::read_address(source)?)));
+        }
         Ok(Input::File(File::open(source).prepend_error("Failed to open input file:")?))
     } else {
         let mut buffer: Vec<u8> = Vec::new();
@@ -118,61 +415,478 @@ fn get_output(args: &Args) -> Result<Output, Error> {
         if !args.flag_force && path.exists() {
             return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", target));
         }
-        Ok(Output::File(File::create(path).prepend_error("Failed to open output file:")?))
+        Ok(Output::File(AtomicFile::create(&path).prepend_error("Failed to open output file:")?))
     } else {
         Ok(Output::Standard(stdout()))
     }
 }
 
+/// Runs the command with the output for the arguments. Output files only replace the target
+/// once the command succeeded.
+fn with_output<F: FnOnce(&mut Output) -> Result<(), Error>>(args: &Args, command: F) -> Result<(), Error> {
+    let mut output = get_output(args)?;
+    command(&mut output)?;
+    output.commit().prepend_error("Failed to write output file:")
+}
+
+/// Runs the command and writes the files it read to the dependency file given with `--deps`, as
+/// dependencies of `target`.
+fn with_deps<F: FnOnce() -> Result<(), Error>>(args: &Args, target: Option<&String>, command: F) -> Result<(), Error> {
+    let depfile = match args.flag_deps {
+        Some(ref depfile) => depfile,
+        None => { return command(); }
+    };
+    let target = target.ok_or_else(|| error!("Dependency files can only be written for a target file, not for output to stdout."))?;
+
+    deps::start_tracking();
+    let result = command();
+    let dependencies = deps::finish_tracking();
+    result?;
+
+    deps::write_file(Path::new(depfile), Path::new(target), &dependencies)
+}
+
+/// Returns the macros defined with `-D`.
+fn get_defines(args: &Args) -> Result<Vec<(String, String)>, Error> {
+    args.flag_define.iter().map(|d| preprocess::parse_define(d)).collect()
+}
+
+/// Adds the macros and header extensions for a build from `directory` given by the arguments,
+/// including git information if requested.
+fn add_build_info(args: &Args, directory: &Path, defines: &mut Vec<(String, String)>, headerext: &mut Vec<String>) -> Result<(), Error> {
+    defines.extend(get_defines(args)?);
+    headerext.extend(args.flag_headerext.iter().cloned());
+
+    if args.flag_git || args.flag_require_clean {
+        git::add_git_build_info(directory, args.flag_require_clean, defines, headerext)?;
+    }
+
+    Ok(())
+}
+
+/// Sets up warnings muted with `-w` and configured in the warning configuration file.
+fn init_warnings(args: &Args) -> Result<(), Error> {
+    let config = error::WarningConfig::read(Path::new(error::WARNING_CONFIG_FILE))?;
+    error::init_warnings(HashSet::from_iter(args.flag_warning.clone()), args.flag_verbose, config);
+    error::init_summary(args.flag_summary, args.flag_summary_json.as_ref().map(PathBuf::from));
+
+    Ok(())
+}
+
+/// Returns the signature version for the arguments, v2 if requested or the one of the target.
+fn signature_version(args: &Args) -> Result<sign::BISignVersion, Error> {
+    if args.flag_v2 {
+        return Ok(sign::BISignVersion::V2);
+    }
+
+    let target = target::target();
+    target.signature_version().ok_or_else(|| error!("{} doesn't support signatures.", target.name()))
+}
+
 fn run_command(args: &Args) -> Result<(), Error> {
     let mut includefolders: Vec<PathBuf> = args.flag_include.iter().map(PathBuf::from).collect();
     includefolders.push(PathBuf::from("."));
 
+    if let Some(ref name) = args.flag_target {
+        target::init_target(target::Target::from_name(name)?);
+    }
+
+    temp::init_keep_temp(args.flag_keep_temp);
+    let _extracted_includes = preprocess::ExtractedIncludes;
+    preprocess::init_trace_macros(args.flag_trace_macros);
+    sign::init_signer_command(args.flag_signer.clone());
+    pbo::init_compression(if !args.flag_compress_ext.is_empty() {
+        Some(args.flag_compress_ext.clone())
+    } else if args.flag_compress {
+        Some(pbo::COMPRESSED_EXTENSIONS.iter().map(|e| e.to_string()).collect())
+    } else {
+        None
+    });
+    paths::init_path_policy(paths::PathPolicy { ignore_case: !args.flag_case_sensitive_paths, ..paths::PathPolicy::default() });
+    preprocess::init_include_paths(args.flag_include_path.iter().map(PathBuf::from).collect());
+
+    if let Some(ref location) = args.flag_cache {
+        cache::init_cache(Some(cache::open_cache(location).prepend_error("Failed to open build cache:")?));
+    }
+
     if args.cmd_binarize {
         binarize::cmd_binarize(PathBuf::from(args.arg_source.as_ref().unwrap()), PathBuf::from(args.arg_target.as_ref().unwrap()), args.flag_force)
     } else if args.cmd_rapify {
-        config::cmd_rapify(&mut get_input(&args)?, &mut get_output(&args)?, args.arg_source.as_ref().map(PathBuf::from), &includefolders)
+        let options = config::RapifyOptions {
+            optimize: args.flag_optimize,
+            compress: args.flag_compress,
+            strict: args.flag_strict,
+            recover: args.flag_recover,
+            json: args.flag_json,
+        };
+        with_deps(args, args.arg_target.as_ref(), || {
+            let source = args.arg_source.as_ref().map(PathBuf::from);
+            deps::track(source.iter());
+            with_output(args, |output| config::cmd_rapify(&mut get_input(args)?, output, source, &includefolders, &get_defines(args)?, &options))
+        })
+    } else if args.cmd_mission && args.cmd_pack {
+        let output = pbo::cmd_mission_pack(PathBuf::from(args.arg_missionfolder.as_ref().unwrap()), args.arg_target.as_ref().map(PathBuf::from), &args.flag_exclude, args.flag_binarize, args.flag_force)?;
+        eprintln!("Packed {}.", output.display());
+        Ok(())
+    } else if args.cmd_mission && args.cmd_classes {
+        mission::cmd_mission_classes(PathBuf::from(args.arg_missionfolder.as_deref().unwrap_or(".")), &includefolders)
+    } else if args.cmd_mission {
+        lint::cmd_mission_lint(PathBuf::from(args.arg_missionfolder.as_deref().unwrap_or(".")), &includefolders)
+    } else if args.cmd_lint && args.flag_list_rules {
+        lint::cmd_list_rules()
+    } else if args.cmd_lint && args.flag_unused_keys {
+        lint::cmd_unused_keys(PathBuf::from(args.arg_source.as_ref().unwrap()))
+    } else if args.cmd_lint && args.flag_scan_secrets {
+        lint::cmd_scan_secrets(PathBuf::from(args.arg_source.as_ref().unwrap()))
+    } else if args.cmd_lint {
+        lint::cmd_lint(PathBuf::from(args.arg_source.as_ref().unwrap()), &includefolders, args.flag_fix)
     } else if args.cmd_derapify {
-        config::cmd_derapify(&mut get_input(&args)?, &mut get_output(&args)?)
+        with_output(args, |output| config::cmd_derapify(&mut get_input(args)?, output, args.flag_format.as_deref().unwrap_or("cpp")))
+    } else if args.cmd_config && args.cmd_get {
+        config::cmd_config_get(&mut stdout(), PathBuf::from(args.arg_source.as_ref().unwrap()), &includefolders, &args.arg_path, args.flag_format.as_deref().unwrap_or("cpp"), args.flag_full)
     } else if args.cmd_preprocess {
-        preprocess::cmd_preprocess(&mut get_input(&args)?, &mut get_output(&args)?, args.arg_source.as_ref().map(PathBuf::from), &includefolders)
+        with_output(args, |output| preprocess::cmd_preprocess(&mut get_input(args)?, output, args.arg_source.as_ref().map(PathBuf::from), &includefolders, &get_defines(args)?))
+    } else if args.cmd_project {
+        let root = PathBuf::from(args.arg_projectfolder.as_deref().unwrap_or("."));
+        let mut defines: Vec<(String, String)> = Vec::new();
+        let mut headerext: Vec<String> = Vec::new();
+        add_build_info(args, &root, &mut defines, &mut headerext)?;
+        let options = project::BuildOptions {
+            jobs: args.flag_jobs.unwrap_or(0),
+            force: args.flag_force,
+            headerext,
+            excludes: args.flag_exclude.clone(),
+            includefolders,
+            defines,
+            privatekey: args.flag_key.first().map(PathBuf::from),
+            incremental: args.flag_incremental,
+            keep_going: args.flag_keep_going,
+            rewrite_paths: args.flag_rewrite_paths,
+        };
+        let target = Some(&args.arg_targetfolder).filter(|t| !t.is_empty()).map(PathBuf::from);
+
+        if args.cmd_graph {
+            project::cmd_project_graph(&mut stdout(), root, target, &options, args.flag_format.as_deref().unwrap_or("json"))
+        } else {
+            lock::check_dependency_lock(Path::new(lock::LOCK_FILE), &options.includefolders, args.flag_locked)?;
+            project::cmd_project_build(root, target, &options)
+        }
+    } else if args.cmd_selftest {
+        selftest::cmd_selftest()
+    } else if args.cmd_watch {
+        let source = PathBuf::from(&args.arg_sourcefolder);
+        let target = PathBuf::from(args.arg_target.as_ref().unwrap());
+        let privatekeys: Vec<PathBuf> = args.flag_key.iter().map(PathBuf::from).collect();
+        let hooks = Hooks::new(&[], source.clone(), Some(target.clone()))?;
+        let defines = get_defines(args)?;
+
+        watch::cmd_watch(&source, vec![target.clone()], Duration::from_millis(args.flag_interval), || {
+            // built in memory so a failed build leaves the previous PBO in place
+            let mut buffer: Vec<u8> = Vec::new();
+            if args.flag_pack {
+                pbo::cmd_pack(source.clone(), &mut buffer, &args.flag_headerext, &args.flag_exclude, &hooks, None)?;
+            } else {
+                pbo::cmd_build(source.clone(), &mut buffer, &args.flag_headerext, &args.flag_exclude, &includefolders, &defines, &hooks, false, false)?;
+            }
+            AtomicFile::create(&target).and_then(|mut f| f.write_all(&buffer).and_then(|_| f.commit())).prepend_error("Failed to write PBO:")?;
+
+            if !privatekeys.is_empty() {
+                sign::cmd_sign(&privatekeys, target.clone(), None, signature_version(args)?, true)?;
+            }
+            if let Some(ref folder) = args.flag_install {
+                install::install(&target, Path::new(folder))?;
+            }
+
+            Ok(())
+        })
+    } else if args.cmd_serve {
+        serve::cmd_serve(&args.arg_sources, args.flag_address.as_deref().unwrap_or("127.0.0.1:8080"))
     } else if args.cmd_build || args.cmd_pack {
-        let flag_privatekey = args.flag_key.as_ref().map(PathBuf::from);
+        let flag_privatekeys: Vec<PathBuf> = args.flag_key.iter().map(PathBuf::from).collect();
         let flag_signature = args.flag_signature.as_ref().map(PathBuf::from);
 
-        if flag_privatekey.is_some() && args.arg_target.is_none() {
+        if !flag_privatekeys.is_empty() && args.arg_target.is_none() {
             return Err(error!("Cannot sign a pbo that is piped to stdout."));
         }
 
+        if !args.flag_allow_artifacts {
+            let cache = args.flag_cache.as_ref().filter(|c| !c.contains("://")).map(Path::new);
+            let artifacts = pbo::find_artifacts(Path::new(&args.arg_sourcefolder), &args.flag_exclude, args.arg_target.as_ref().map(Path::new), cache)?;
+            if !artifacts.is_empty() {
+                let list: Vec<String> = artifacts.iter().map(|(name, reason)| format!("{} ({})", name, reason)).collect();
+                return Err(error!("Source folder contains build artifacts:\n    {}\nExclude them with -x or use --allow-artifacts to pack them anyway.", list.join("\n    ")));
+            }
+        }
+
+        if let Some(target) = &args.arg_target {
+            check_space(Path::new(target), pbo::estimate_size(Path::new(&args.arg_sourcefolder), &args.flag_exclude)?)?;
+        }
+
+        let hooks = Hooks::new(&args.flag_hook, PathBuf::from(&args.arg_sourcefolder), args.arg_target.as_ref().map(PathBuf::from))?;
+
         if args.cmd_build {
-            pbo::cmd_build(PathBuf::from(&args.arg_sourcefolder), &mut get_output(&args)?, &args.flag_headerext, &args.flag_exclude, &includefolders)?;
+            let mut defines: Vec<(String, String)> = Vec::new();
+            let mut headerext: Vec<String> = Vec::new();
+            add_build_info(args, &PathBuf::from(&args.arg_sourcefolder), &mut defines, &mut headerext)?;
+            lock::check_dependency_lock(Path::new(lock::LOCK_FILE), &includefolders, args.flag_locked)?;
+            with_deps(args, args.arg_target.as_ref(), || {
+                with_output(args, |output| pbo::cmd_build(PathBuf::from(&args.arg_sourcefolder), output, &headerext, &args.flag_exclude, &includefolders, &defines, &hooks, args.flag_keep_going, args.flag_rewrite_paths))
+            })?;
         } else {
-            pbo::cmd_pack(PathBuf::from(&args.arg_sourcefolder), &mut get_output(&args)?, &args.flag_headerext, &args.flag_exclude)?;
+            with_output(args, |output| pbo::cmd_pack(PathBuf::from(&args.arg_sourcefolder), output, &args.flag_headerext, &args.flag_exclude, &hooks, args.flag_manifest.as_ref().map(PathBuf::from)))?;
         }
 
-        if let Some(pkey) = flag_privatekey {
-            sign::cmd_sign(pkey, PathBuf::from(args.arg_target.as_ref().unwrap()), flag_signature, sign::BISignVersion::V3, args.flag_force)?;
+        if !flag_privatekeys.is_empty() {
+            sign::cmd_sign(&flag_privatekeys, PathBuf::from(args.arg_target.as_ref().unwrap()), flag_signature, signature_version(args)?, args.flag_force)?;
         }
 
+        hooks.run(HookStage::PostSign)?;
+
         Ok(())
     } else if args.cmd_inspect {
-        pbo::cmd_inspect(&mut get_input(&args)?)
+        if args.flag_untrusted && args.arg_source.is_none() {
+            return Err(error!("Untrusted PBOs can only be inspected from a file, stdin would be read into memory."));
+        }
+        pbo::cmd_inspect(&mut get_input(args)?, args.flag_detect, args.flag_types, args.flag_export_manifest.as_ref().map(PathBuf::from), args.flag_untrusted)
     } else if args.cmd_cat {
-        pbo::cmd_cat(&mut get_input(&args)?, &mut get_output(&args)?, &args.arg_filename)
+        if !args.arg_address.is_empty() || paths::EntryAddress::is_address(args.arg_source.as_deref().unwrap_or("")) {
+            let addresses: Vec<String> = if args.arg_address.is_empty() {
+                args.arg_source.iter().chain(std::iter::once(&args.arg_filename)).chain(args.arg_target.iter()).cloned().collect()
+            } else {
+                args.arg_address.clone()
+            };
+            pbo::cmd_cat_entries(&addresses, &mut stdout(), args.flag_into.as_ref().map(PathBuf::from), args.flag_force)
+        } else {
+            with_output(args, |output| pbo::cmd_cat(&mut get_input(args)?, output, &args.arg_filename))
+        }
     } else if args.cmd_unpack {
-        pbo::cmd_unpack(&mut get_input(&args)?, PathBuf::from(&args.arg_targetfolder), args.flag_force)
+        let options = pbo::UnpackOptions {
+            force: args.flag_force,
+            convert: args.flag_convert_configs,
+            mode: args.flag_chmod.as_deref().map(pbo::parse_mode).transpose()?,
+            executable_extensions: args.flag_exec_ext.clone(),
+        };
+        pbo::cmd_unpack(&mut get_input(args)?, PathBuf::from(&args.arg_targetfolder), &options)
+    } else if args.cmd_audit {
+        audit::cmd_audit(PathBuf::from(&args.arg_pbo))
+    } else if args.cmd_install {
+        install::cmd_install(PathBuf::from(&args.arg_pbo), PathBuf::from(&args.arg_targetfolder))
+    } else if args.cmd_roundtrip {
+        pbo::cmd_roundtrip(&mut File::open(&args.arg_pbo).prepend_error("Failed to open PBO:")?)
+    } else if args.cmd_checksum {
+        pbo::cmd_checksum(PathBuf::from(&args.arg_pbo), args.flag_fix)
     } else if args.cmd_keygen {
-        sign::cmd_keygen(PathBuf::from(&args.arg_keyname), args.flag_force)
+        sign::cmd_keygen(PathBuf::from(&args.arg_keyname), args.flag_force, args.flag_owner.clone(), args.flag_comment.clone())
+    } else if args.cmd_key && args.cmd_info {
+        sign::cmd_key_info(PathBuf::from(&args.arg_key))
     } else if args.cmd_sign {
-        let version = if args.flag_v2 { sign::BISignVersion::V2 } else { sign::BISignVersion::V3 };
-        sign::cmd_sign(PathBuf::from(&args.arg_privatekey), PathBuf::from(&args.arg_pbo), args.arg_signature.as_ref().map(PathBuf::from), version, args.flag_force)
+        let privatekeys: Vec<PathBuf> = std::iter::once(&args.arg_privatekey).chain(args.flag_key.iter()).map(PathBuf::from).collect();
+        sign::cmd_sign(&privatekeys, PathBuf::from(&args.arg_pbo), args.arg_signature.as_ref().map(PathBuf::from), signature_version(args)?, args.flag_force)
+    } else if args.cmd_sign_mod {
+        sign::cmd_sign_mod(PathBuf::from(&args.arg_privatekey), PathBuf::from(&args.arg_mod), signature_version(args)?, args.flag_force, args.flag_copy_key)
     } else if args.cmd_verify {
-        sign::cmd_verify(PathBuf::from(&args.arg_publickey), PathBuf::from(&args.arg_pbo), args.arg_signature.as_ref().map(PathBuf::from))
+        sign::cmd_verify(PathBuf::from(&args.arg_publickey), PathBuf::from(&args.arg_pbo), args.arg_signature.as_ref().map(PathBuf::from), args.flag_warn_old_keys)
+    } else if args.cmd_paa2dds {
+        with_output(args, |output| paa::cmd_paa2dds(&mut get_input(args)?, output))
+    } else if args.cmd_dds2paa {
+        let path = args.arg_target.as_ref().or(args.arg_source.as_ref()).map(PathBuf::from);
+        with_output(args, |output| paa::cmd_dds2paa(&mut get_input(args)?, output, path, !args.flag_no_normalize, args.flag_alpha_coverage))
+    } else if args.cmd_img2paa {
+        let path = args.arg_target.as_ref().or(args.arg_source.as_ref()).map(PathBuf::from);
+        let format = args.flag_type.as_deref().map(paa::PAAType::from_name).transpose()?;
+        with_output(args, |output| paa::cmd_img2paa(&mut get_input(args)?, output, path, format, !args.flag_no_normalize, args.flag_alpha_coverage))
+    } else if args.cmd_paa2img {
+        with_output(args, |output| paa::cmd_paa2img(&mut get_input(args)?, output))
+    } else if args.cmd_stringtable && args.cmd_check {
+        let name = args.arg_source.as_deref().unwrap_or("stringtable.xml");
+        stringtable::cmd_stringtable_check(&mut get_input(args)?, name)
+    } else if args.cmd_stringtable && args.cmd_export {
+        with_output(args, |output| stringtable::cmd_stringtable_export(&mut get_input(args)?, output))
+    } else if args.cmd_stringtable && args.cmd_import {
+        with_output(args, |output| stringtable::cmd_stringtable_import(&mut get_input(args)?, output, args.flag_name.as_deref()))
+    } else if args.cmd_ui && args.cmd_atlas {
+        ui::cmd_atlas(&args.arg_images, PathBuf::from(&args.arg_targetfolder), args.flag_name.as_deref().unwrap_or("atlas"),
+            args.flag_size.unwrap_or(1024), args.flag_prefix.as_deref().unwrap_or(""), args.flag_force)
     } else {
         unreachable!()
     }
 }
 
+/// Splits a line of a response file into arguments. Arguments containing whitespace can be
+/// enclosed in double quotes, backslashes are kept as they are for Windows paths.
+fn split_arguments(line: &str) -> Vec<String> {
+    let mut arguments: Vec<String> = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quoted = false;
+
+    for c in line.chars() {
+        if c == '"' {
+            quoted = !quoted;
+            current.get_or_insert_with(String::new);
+        } else if c.is_whitespace() && !quoted {
+            arguments.extend(current.take());
+        } else {
+            current.get_or_insert_with(String::new).push(c);
+        }
+    }
+    arguments.extend(current.take());
+
+    arguments
+}
+
+/// Reads a response file and returns the arguments of every command in it, together with the
+/// line number the command was found in.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Cursor;
+/// # use armake2::run::read_response_file;
+/// let input = "# build the addon\npack addons/main \"out dir/main.pbo\"\n\nsign key.biprivatekey \"out dir/main.pbo\"\n";
+/// let commands = read_response_file(&mut Cursor::new(input)).unwrap();
+///
+/// assert_eq!(2, commands.len());
+/// assert_eq!((2, vec!["armake2", "pack", "addons/main", "out dir/main.pbo"]), (commands[0].0, commands[0].1.iter().map(|s| s.as_str()).collect()));
+/// assert_eq!(4, commands[1].0);
+/// ```
+pub fn read_response_file<I: Read>(input: &mut I) -> Result<Vec<(usize, Vec<String>)>, Error> {
+    let mut buffer = String::new();
+    input.read_to_string(&mut buffer).prepend_error("Failed to read response file:")?;
+
+    let mut commands: Vec<(usize, Vec<String>)> = Vec::new();
+    for (i, line) in buffer.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('@') {
+            return Err(error!("Nested response files are not supported (line {}).", i + 1));
+        }
+
+        let mut arguments = vec!["armake2".to_string()];
+        arguments.extend(split_arguments(line));
+        commands.push((i + 1, arguments));
+    }
+
+    Ok(commands)
+}
+
+/// Runs all commands from the response file at the given path in order, stopping at the first
+/// failing command. Every command is parsed like a regular command line, so flags like `-f` and
+/// `-w` apply only to the command they are given for.
+pub fn run_response_file(path: &Path) -> Result<(), Error> {
+    let mut file = File::open(path).prepend_error("Failed to open response file:")?;
+    let commands = read_response_file(&mut file)?;
+
+    for (line, arguments) in commands {
+        let mut args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(arguments).deserialize())
+            .map_err(|e| error!("Invalid command in line {}:\n{}", line, e))?;
+        if args.flag_indent.is_none() {
+            args.flag_indent = Some("    ".to_string());
+        }
+
+        init_warnings(&args)?;
+        run_command(&args).prepend_error(format!("Command in line {} failed:", line))?;
+    }
+
+    Ok(())
+}
+
+/// Runs the commands from a response file given as `@<file>` and prints a combined warning
+/// summary at the end.
+pub fn batch(path: &str) {
+    interrupt::install_handler();
+    run_response_file(Path::new(path)).print_error(true);
+
+    print_warning_summary();
+}
+
+/// Options of armake 1.x that take a value
+const V1_VALUE_OPTIONS: &[&str] = &["-w", "--warning", "-i", "--include", "-x", "--exclude", "-k", "--key",
+    "-s", "--signature", "-e", "--headerext", "-d", "--indent", "-t", "--type"];
+
+/// Translates a command line of armake 1.x into the equivalent armake2 command line. Both
+/// include the program name.
+///
+/// `build -p` becomes `pack`, comma-separated exclude lists are split and the signature of `sign`
+/// given with `-s` becomes the last argument.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::run::translate_v1_arguments;
+/// let argv: Vec<String> = "armake build -p -f -x *.psd,*.txt addons/main main.pbo".split(' ').map(String::from).collect();
+///
+/// assert_eq!("armake2 pack -f -x *.psd -x *.txt addons/main main.pbo", translate_v1_arguments(&argv).unwrap().join(" "));
+/// ```
+pub fn translate_v1_arguments(argv: &[String]) -> Result<Vec<String>, Error> {
+    let command = argv.get(1).map(|s| s.as_str()).unwrap_or("");
+    let mut options: Vec<(String, Option<String>)> = Vec::new();
+    let mut positional: Vec<String> = Vec::new();
+
+    let mut iter = argv.iter().skip(2);
+    while let Some(arg) = iter.next() {
+        if V1_VALUE_OPTIONS.contains(&arg.as_str()) {
+            let value = iter.next().ok_or_else(|| error!("Option {} requires a value.", arg))?;
+            options.push((arg.clone(), Some(value.clone())));
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            options.push((arg.clone(), None));
+        } else {
+            positional.push(arg.clone());
+        }
+    }
+
+    let is_option = |options: &[(String, Option<String>)], names: &[&str]| options.iter().any(|(o, _)| names.contains(&o.as_str()));
+    let mut translated_command = command.to_string();
+    match command {
+        "build" if is_option(&options, &["-p", "--packonly"]) => {
+            translated_command = "pack".to_string();
+            options.retain(|(o, _)| !["-p", "--packonly", "-w", "--warning", "-i", "--include"].contains(&o.as_str()));
+        },
+        "sign" => {
+            if let Some(index) = options.iter().position(|(o, _)| o == "-s" || o == "--signature") {
+                positional.push(options.remove(index).1.unwrap());
+            }
+        },
+        "unpack" if is_option(&options, &["-i", "--include", "-x", "--exclude"]) => {
+            return Err(error!("Include and exclude patterns for unpack are not supported by armake2."));
+        },
+        "paa2img" | "img2paa" => {
+            let image = if command == "img2paa" { positional.first() } else { positional.get(1) };
+            if !image.map(|p| p.to_lowercase().ends_with(".tga")).unwrap_or(false) {
+                return Err(error!("{} only supports TGA images in armake2, use paa2dds and dds2paa for other formats.", command));
+            }
+            // mipmaps are always stored uncompressed
+            options.retain(|(o, _)| !["-z", "--compress"].contains(&o.as_str()));
+        },
+        _ => {}
+    }
+
+    let mut translated = vec!["armake2".to_string()];
+    if !translated_command.is_empty() {
+        translated.push(translated_command);
+    }
+    for (option, value) in options {
+        match value {
+            Some(value) if option == "-x" || option == "--exclude" => {
+                for pattern in value.split(',').filter(|p| !p.is_empty()) {
+                    translated.push(option.clone());
+                    translated.push(pattern.to_string());
+                }
+            },
+            Some(value) => {
+                translated.push(option);
+                translated.push(value);
+            },
+            None => { translated.push(option); }
+        }
+    }
+    translated.extend(positional);
+
+    Ok(translated)
+}
+
 pub fn args(args: &mut Args) {
     if args.flag_indent.is_none() {
         args.flag_indent = Some("    ".to_string());
@@ -185,7 +899,8 @@ pub fn args(args: &mut Args) {
         std::process::exit(0);
     }
 
-    error::init_warnings(HashSet::from_iter(args.flag_warning.clone()), args.flag_verbose);
+    init_warnings(args).print_error(true);
+    interrupt::install_handler();
     run_command(&args).print_error(true);
 
     print_warning_summary();
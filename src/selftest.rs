@@ -0,0 +1,137 @@
+//! Checks of the parsers, the packer and the signer against embedded known-good files
+
+use std::io::{Cursor, Error};
+
+use crate::archive::{Archive};
+use crate::binarize::{binarize_available};
+use crate::config::{Config};
+use crate::error::*;
+use crate::io::{lzss_compress, lzss_decompress};
+use crate::pbo::{PBO, ContainerType, detect_container, roundtrip_differences};
+use crate::sign::{BIPrivateKey, BIPublicKey, BISign, BISignVersion};
+
+const CONFIG: &str = include_str!("selftest/config.cpp");
+const CONFIG_BIN: &[u8] = include_bytes!("selftest/config.bin");
+/// packed from config.cpp and a script with the prefix `x\selftest\addons\main`
+const PBO_FILE: &[u8] = include_bytes!("selftest/test.pbo");
+const PRIVATE_KEY: &[u8] = include_bytes!("selftest/test.biprivatekey");
+const PUBLIC_KEY: &[u8] = include_bytes!("selftest/test.bikey");
+/// v3 signature of test.pbo with test.biprivatekey
+const SIGNATURE: &[u8] = include_bytes!("selftest/test.pbo.test.bisign");
+
+type Check = fn() -> Result<(), Error>;
+
+fn expect(condition: bool, message: &str) -> Result<(), Error> {
+    if condition { Ok(()) } else { Err(error!("{}", message)) }
+}
+
+fn rapify(input: &str) -> Result<Vec<u8>, Error> {
+    let config = Config::from_string(input.to_string(), None, &Vec::new())?;
+    Ok(config.to_cursor()?.into_inner().into_vec())
+}
+
+fn check_rapify() -> Result<(), Error> {
+    expect(rapify(CONFIG)? == CONFIG_BIN, "rapified config differs from the known-good file")
+}
+
+fn check_derapify() -> Result<(), Error> {
+    let config = Config::read_rapified(&mut Cursor::new(CONFIG_BIN))?;
+    expect(rapify(&config.to_string()?)? == CONFIG_BIN, "derapified config doesn't rapify to the same file")
+}
+
+fn check_lzss() -> Result<(), Error> {
+    expect(lzss_decompress(&lzss_compress(CONFIG_BIN))? == CONFIG_BIN, "decompressed data differs")?;
+
+    let mut compressed: Vec<u8> = Vec::new();
+    Config::read_rapified(&mut Cursor::new(CONFIG_BIN))?.write_rapified_compressed(&mut compressed)?;
+    let config = Config::read_rapified(&mut Cursor::new(compressed))?;
+    expect(config.to_cursor()?.into_inner().into_vec() == CONFIG_BIN, "compressed config differs after reading")
+}
+
+fn check_pbo_read() -> Result<(), Error> {
+    expect(detect_container(&mut Cursor::new(PBO_FILE)) == ContainerType::PBO, "PBO isn't detected as a PBO")?;
+
+    let pbo = PBO::read(&mut Cursor::new(PBO_FILE))?;
+    expect(pbo.header_extensions.get("prefix").map(|p| p.as_str()) == Some("x\\selftest\\addons\\main"), "prefix differs")?;
+    expect(pbo.read_entry("config.cpp")?.map(|data| data.into_owned()) == Some(CONFIG.as_bytes().to_vec()), "config.cpp differs")?;
+    expect(pbo.entries()? == vec!["config.cpp", "script.sqf"], "entries differ")
+}
+
+fn check_pbo_write() -> Result<(), Error> {
+    let differences = roundtrip_differences(PBO_FILE)?;
+    expect(differences.is_empty(), &format!("repacked PBO differs: {}", differences.join(", ")))
+}
+
+fn check_sign() -> Result<(), Error> {
+    let pbo = PBO::read(&mut Cursor::new(PBO_FILE))?;
+    let key = BIPrivateKey::read(&mut Cursor::new(PRIVATE_KEY))?;
+
+    let mut signature: Vec<u8> = Vec::new();
+    key.sign(&pbo, BISignVersion::V3).write(&mut signature)?;
+    expect(signature == SIGNATURE, "v3 signature differs from the known-good file")?;
+
+    key.to_public_key().verify(&pbo, &key.sign(&pbo, BISignVersion::V2)).prepend_error("v2 signature doesn't verify:")
+}
+
+fn check_verify() -> Result<(), Error> {
+    let mut pbo = PBO::read(&mut Cursor::new(PBO_FILE))?;
+    let key = BIPublicKey::read(&mut Cursor::new(PUBLIC_KEY))?;
+    let signature = BISign::read(&mut Cursor::new(SIGNATURE))?;
+    key.verify(&pbo, &signature)?;
+
+    pbo.write_entry("script.sqf", b"tampered")?;
+    let tampered = PBO::read(&mut pbo.to_cursor()?)?;
+    expect(key.verify(&tampered, &signature).is_err(), "signature of a modified PBO verifies")
+}
+
+/// Runs all checks and returns their names and results.
+pub fn run_checks() -> Vec<(&'static str, Result<(), Error>)> {
+    let checks: [(&'static str, Check); 7] = [
+        ("Preprocess and rapify config", check_rapify),
+        ("Derapify config", check_derapify),
+        ("LZSS compression", check_lzss),
+        ("Read PBO", check_pbo_read),
+        ("Write PBO", check_pbo_write),
+        ("Sign PBO (v2, v3)", check_sign),
+        ("Verify signature", check_verify),
+    ];
+
+    checks.iter().map(|(name, check)| (*name, check())).collect()
+}
+
+/// Returns the features of this build and the formats it supports.
+pub fn capabilities() -> Vec<(&'static str, String)> {
+    vec![
+        ("Version", env!("CARGO_PKG_VERSION").to_string()),
+        ("OpenSSL", openssl::version::version().to_string()),
+        ("Targets", "OFP, Arma 2, Arma 3, DayZ".to_string()),
+        ("Containers", "PBO (read, write), EBO and obfuscated PBOs (detection only)".to_string()),
+        ("Configs", "rapify, derapify, LZSS compressed".to_string()),
+        ("Signatures", "v2, v3".to_string()),
+        ("Binarization", if binarize_available() { "binarize.exe" } else { "not available (Windows with Arma 3 Tools only)" }.to_string()),
+        ("Remote build cache", if cfg!(feature = "remote-cache") { "http(s)" } else { "not available (built without remote-cache)" }.to_string()),
+    ]
+}
+
+/// Prints the capabilities of this build and the results of all checks. Fails if any check fails.
+pub fn cmd_selftest() -> Result<(), Error> {
+    for (name, value) in capabilities() {
+        println!("{:<20}{}", format!("{}:", name), value);
+    }
+    println!();
+
+    let results = run_checks();
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    for (name, result) in &results {
+        match result {
+            Ok(()) => println!("ok      {}", name),
+            Err(e) => println!("FAILED  {}: {}", name, e),
+        }
+    }
+
+    if failed > 0 {
+        return Err(error!("{} of {} checks failed.", failed, results.len()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,22 @@
+#define QUOTE(x) #x
+#define VERSION 1.2
+#define ADDON selftest
+
+class CfgPatches {
+    class ADDON {
+        name = QUOTE(ADDON);
+        requiredVersion = VERSION;
+        units[] = {};
+        weapons[] = {"a", "b"};
+        author = "armake2";
+    };
+};
+
+class CfgVehicles {
+    class Base;
+    class Derived: Base {
+        scope = 2;
+        mass = 150.5;
+        nested[] = {1, {2, 3}, "four"};
+    };
+};
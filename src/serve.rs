@@ -0,0 +1,297 @@
+//! Read-only HTTP/WebDAV server for the content of PBOs and folders
+
+use std::collections::{BTreeMap};
+use std::io::{Read, Write, BufRead, BufReader, Error, copy, sink};
+use std::net::{TcpListener};
+use std::path::{PathBuf};
+use std::thread;
+use std::time::{Duration};
+
+use crate::archive::{Archive, open_archive};
+use crate::error::*;
+
+/// Server exposing the files of several archives in a single tree, laid out by their prefixes
+///
+/// Files are served with `GET` and `HEAD`, folders are listed as HTML. The WebDAV methods
+/// `OPTIONS` and `PROPFIND` are supported so the tree can be mounted as a read-only network
+/// drive, e.g. with "Map network drive" in the Windows explorer or `mount -t davfs`.
+pub struct Server {
+    archives: Vec<Box<dyn Archive>>,
+    /// path of every file in the tree, without leading slash, and the entry it is read from
+    files: BTreeMap<String, (usize, String)>,
+}
+
+/// Largest request body accepted, PROPFIND property lists are much smaller
+const MAX_BODY_SIZE: usize = 64 * 1024;
+/// Largest request line and headers accepted
+const MAX_HEADER_SIZE: u64 = 64 * 1024;
+/// Time a client may take to send more of its request before the connection is closed
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<String>,
+    body: Vec<u8>,
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        207 => "Multi-Status",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+fn url_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded: Vec<u8> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(b) = std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn url_encode(path: &str) -> String {
+    path.bytes().map(|b| match b {
+        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+impl Server {
+    /// Creates a server for the given PBOs and folders. Every archive is placed at the path of
+    /// its prefix, or at its file name if it has no prefix.
+    pub fn new(paths: &[PathBuf]) -> Result<Server, Error> {
+        let mut archives: Vec<Box<dyn Archive>> = Vec::new();
+        let mut files: BTreeMap<String, (usize, String)> = BTreeMap::new();
+
+        for path in paths {
+            let archive = open_archive(path)?;
+            let prefix = match archive.metadata()?.get("prefix") {
+                Some(prefix) => prefix.replace('\\', "/").trim_matches('/').to_string(),
+                None => path.file_stem().unwrap().to_str().unwrap().to_string(),
+            };
+
+            for entry in archive.entries()? {
+                let name = format!("{}/{}", prefix, entry.replace('\\', "/")).trim_start_matches('/').to_string();
+                if files.insert(name.clone(), (archives.len(), entry)).is_some() {
+                    warning(format!("{} exists in multiple archives, serving the one from {}.", name, path.display()), Some("duplicate-file"), (None, None));
+                }
+            }
+            archives.push(archive);
+        }
+
+        Ok(Server {
+            archives,
+            files,
+        })
+    }
+
+    /// Returns the names of the files and folders directly inside the folder, `None` if the
+    /// folder doesn't exist. Folder names end with a slash.
+    fn list(&self, folder: &str) -> Option<Vec<String>> {
+        let start = if folder.is_empty() { String::new() } else { format!("{}/", folder) };
+        let mut children: Vec<String> = Vec::new();
+
+        for name in self.files.range(start.clone()..).map(|(name, _)| name).take_while(|name| name.starts_with(&start)) {
+            let child = match name[start.len()..].find('/') {
+                Some(index) => &name[start.len()..start.len() + index + 1],
+                None => &name[start.len()..],
+            };
+            if children.last().map(|c| c != child).unwrap_or(true) {
+                children.push(child.to_string());
+            }
+        }
+
+        if children.is_empty() && !folder.is_empty() { None } else { Some(children) }
+    }
+
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        match self.files.get(path) {
+            Some((index, entry)) => Ok(self.archives[*index].read_entry(entry)?.map(|data| data.into_owned())),
+            None => Ok(None),
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<Response, Error> {
+        if let Some(data) = self.read(path)? {
+            return Ok(Response { status: 200, content_type: "application/octet-stream", headers: Vec::new(), body: data });
+        }
+
+        match self.list(path) {
+            Some(children) => {
+                let mut html = format!("<html><head><title>/{0}</title></head><body><h1>/{0}</h1><ul>\n", xml_escape(path));
+                for child in children {
+                    let href = if path.is_empty() { format!("/{}", child) } else { format!("/{}/{}", path, child) };
+                    html += &format!("<li><a href=\"{}\">{}</a></li>\n", xml_escape(&url_encode(&href)), xml_escape(&child));
+                }
+                html += "</ul></body></html>\n";
+                Ok(Response { status: 200, content_type: "text/html; charset=utf-8", headers: Vec::new(), body: html.into_bytes() })
+            },
+            None => Ok(Response { status: 404, content_type: "text/plain", headers: Vec::new(), body: b"Not found\n".to_vec() })
+        }
+    }
+
+    fn propfind_entry(&self, path: &str, folder: bool) -> Result<String, Error> {
+        let href = if folder && !path.is_empty() { format!("/{}/", path) } else { format!("/{}", path) };
+        let props = if folder {
+            "<D:resourcetype><D:collection/></D:resourcetype>".to_string()
+        } else {
+            let length = match self.files.get(path) {
+                Some((index, entry)) => self.archives[*index].entry_size(entry)?.unwrap_or(0),
+                None => 0,
+            };
+            format!("<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength><D:getcontenttype>application/octet-stream</D:getcontenttype>", length)
+        };
+
+        Ok(format!("<D:response><D:href>{}</D:href><D:propstat><D:prop>{}</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n",
+            xml_escape(&url_encode(&href)), props))
+    }
+
+    fn propfind(&self, path: &str, depth: &str) -> Result<Response, Error> {
+        let mut xml = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n".to_string();
+
+        if self.files.contains_key(path) {
+            xml += &self.propfind_entry(path, false)?;
+        } else {
+            let children = match self.list(path) {
+                Some(children) => children,
+                None => { return Ok(Response { status: 404, content_type: "text/plain", headers: Vec::new(), body: b"Not found\n".to_vec() }); }
+            };
+
+            xml += &self.propfind_entry(path, true)?;
+            if depth != "0" {
+                for child in children {
+                    let child_path = format!("{}/{}", path, child.trim_end_matches('/')).trim_start_matches('/').to_string();
+                    xml += &self.propfind_entry(&child_path, child.ends_with('/'))?;
+                }
+            }
+        }
+
+        xml += "</D:multistatus>\n";
+        Ok(Response { status: 207, content_type: "application/xml; charset=utf-8", headers: Vec::new(), body: xml.into_bytes() })
+    }
+
+    /// Reads a single request from the stream and writes the response.
+    pub fn handle<S: Read + Write>(&self, stream: &mut S) -> Result<(), Error> {
+        let mut reader = BufReader::new((&mut *stream).take(MAX_HEADER_SIZE + MAX_BODY_SIZE as u64));
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        let mut depth = "1".to_string();
+        let mut content_length: usize = 0;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() { break; }
+            if let Some(index) = line.find(':') {
+                let (key, value) = (line[..index].trim().to_lowercase(), line[index + 1..].trim().to_string());
+                if key == "depth" {
+                    depth = value;
+                } else if key == "content-length" {
+                    content_length = value.parse().unwrap_or(0);
+                }
+            }
+        }
+
+        let parts: Vec<&str> = request_line.split_whitespace().collect();
+        let (method, target) = match parts.as_slice() {
+            [method, target, _] => (*method, *target),
+            _ => { return Err(error!("Invalid HTTP request: {}", request_line.trim())); }
+        };
+        let path = url_decode(target.split('?').next().unwrap()).trim_matches('/').to_string();
+
+        if content_length <= MAX_BODY_SIZE {
+            // request bodies (PROPFIND property lists) are ignored, all properties are returned
+            copy(&mut reader.by_ref().take(content_length as u64), &mut sink())?;
+        }
+        drop(reader);
+
+        let response = if content_length > MAX_BODY_SIZE {
+            Response { status: 413, content_type: "text/plain", headers: Vec::new(), body: b"Request body too large\n".to_vec() }
+        } else {
+            self.respond(method, &path, &depth)?
+        };
+
+        let mut head = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            response.status, status_text(response.status), response.content_type, response.body.len());
+        for header in &response.headers {
+            head += &format!("{}\r\n", header);
+        }
+        head += "\r\n";
+
+        stream.write_all(head.as_bytes())?;
+        if method != "HEAD" {
+            stream.write_all(&response.body)?;
+        }
+        stream.flush()
+    }
+
+    fn respond(&self, method: &str, path: &str, depth: &str) -> Result<Response, Error> {
+        Ok(match method {
+            "GET" | "HEAD" => self.get(path)?,
+            "OPTIONS" => Response {
+                status: 200,
+                content_type: "text/plain",
+                headers: vec!["DAV: 1".to_string(), "Allow: OPTIONS, GET, HEAD, PROPFIND".to_string()],
+                body: Vec::new(),
+            },
+            "PROPFIND" => self.propfind(path, depth)?,
+            _ => Response {
+                status: 405,
+                content_type: "text/plain",
+                headers: vec!["Allow: OPTIONS, GET, HEAD, PROPFIND".to_string()],
+                body: b"Read-only\n".to_vec(),
+            },
+        })
+    }
+
+    /// Serves requests on the address until the process is terminated. Every connection is
+    /// handled on its own thread, so a slow client doesn't block the others.
+    pub fn serve(&self, address: &str) -> Result<(), Error> {
+        let listener = TcpListener::bind(address).prepend_error(format!("Failed to listen on {}:", address))?;
+        eprintln!("Serving {} files on http://{}/", self.files.len(), listener.local_addr()?);
+
+        thread::scope(|scope| {
+            for stream in listener.incoming() {
+                scope.spawn(move || {
+                    let result = stream.and_then(|mut s| {
+                        s.set_read_timeout(Some(READ_TIMEOUT))?;
+                        self.handle(&mut s)
+                    });
+                    if let Err(e) = result {
+                        warning(format!("Failed to handle request: {}", e), Some("serve-request"), (None, None));
+                    }
+                });
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Serves the content of the PBOs and folders over HTTP/WebDAV on the address.
+pub fn cmd_serve(paths: &[String], address: &str) -> Result<(), Error> {
+    let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+    let server = Server::new(&paths)?;
+
+    server.serve(address)
+}
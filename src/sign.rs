@@ -1,16 +1,26 @@
 //! Functions for creating and working with BI keys and signatures
 
+use std::borrow::{Cow};
 use std::fs::{File};
-use std::io::{Read, Write, Error, Cursor};
-use std::path::{PathBuf};
+use std::io::{Read, Write, Error};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use openssl::bn::{BigNum, BigNumContext};
 use openssl::hash::{Hasher, MessageDigest, DigestBytes};
+use once_cell::sync::Lazy;
 use openssl::rsa::{Rsa};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
+use crate::archive::Archive;
+use crate::error::*;
 use crate::io::*;
 use crate::pbo::*;
+use crate::target::target;
 
 /// BI private key (.biprivatekey)
 pub struct BIPrivateKey {
@@ -55,6 +65,91 @@ pub struct BISign {
     sig3: BigNum
 }
 
+/// Metadata of a key pair, stored next to the keys in `<keyname>.keyinfo.json`
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::{Path, PathBuf};
+/// # use armake2::sign::KeyMetadata;
+/// assert_eq!(PathBuf::from("keys/mymod.keyinfo.json"), KeyMetadata::path(Path::new("keys/mymod.bikey")));
+///
+/// let metadata = KeyMetadata { created: 1_600_000_000, owner: None, comment: None };
+/// assert_eq!("2020-09-13", metadata.created_date());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyMetadata {
+    /// creation time in seconds since the Unix epoch
+    pub created: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl KeyMetadata {
+    /// Creates metadata for a key created now.
+    pub fn new(owner: Option<String>, comment: Option<String>) -> KeyMetadata {
+        KeyMetadata {
+            created: now(),
+            owner,
+            comment,
+        }
+    }
+
+    /// Returns the path of the metadata for a key or key name, with or without extension.
+    pub fn path(key: &Path) -> PathBuf {
+        let name = key.file_name().unwrap().to_string_lossy();
+        let stem = name.strip_suffix(".bikey").or_else(|| name.strip_suffix(".biprivatekey")).unwrap_or(&name);
+
+        key.with_file_name(format!("{}.keyinfo.json", stem))
+    }
+
+    /// Reads the metadata of the key, `None` if it has no metadata.
+    pub fn read(key: &Path) -> Result<Option<KeyMetadata>, Error> {
+        let path = KeyMetadata::path(key);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let metadata = serde_json::from_reader(File::open(&path)?).map_err(|e| error!("Failed to read {}: {}", path.display(), e))?;
+        Ok(Some(metadata))
+    }
+
+    /// Writes the metadata of the key.
+    pub fn write(&self, key: &Path) -> Result<(), Error> {
+        let mut file = AtomicFile::create(&KeyMetadata::path(key))?;
+        serde_json::to_writer_pretty(&mut file, self).map_err(|e| error!("{}", e))?;
+        file.write_all(b"\n")?;
+        file.commit()
+    }
+
+    /// Returns the number of full days since the key was created.
+    pub fn age_days(&self) -> u64 {
+        now().saturating_sub(self.created) / 86400
+    }
+
+    /// Returns the creation date as `YYYY-MM-DD` (UTC).
+    pub fn created_date(&self) -> String {
+        // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+        let z = (self.created / 86400) as i64 + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
 fn write_bignum<O: Write>(output: &mut O, bn: &BigNum, size: usize) -> Result<(), Error> {
     let mut vec: Vec<u8> = bn.to_vec();
     vec = vec.iter().rev().cloned().collect();
@@ -63,28 +158,42 @@ fn write_bignum<O: Write>(output: &mut O, bn: &BigNum, size: usize) -> Result<()
     Ok(output.write_all(&vec)?)
 }
 
-fn namehash(pbo: &PBO) -> DigestBytes {
-    let mut files_sorted: Vec<(String,&Cursor<Box<[u8]>>)> = pbo.files.iter().map(|(a,b)| (a.to_lowercase(),b)).collect();
-    files_sorted.sort_by(|a, b| a.0.cmp(&b.0));
+/// Returns the hash of the names of all non-empty entries, as used in signatures.
+pub fn namehash<A: Archive + ?Sized>(archive: &A) -> Result<DigestBytes, Error> {
+    let mut names: Vec<String> = Vec::new();
+    for name in archive.entries()? {
+        if archive.read_entry(&name)?.map(|data| !data.is_empty()).unwrap_or(false) {
+            names.push(name.to_lowercase());
+        }
+    }
+    names.sort();
 
     let mut h = Hasher::new(MessageDigest::sha1()).unwrap();
 
-    for (name, data) in &files_sorted {
-        if data.get_ref().len() == 0 {
-            continue;
-        }
-
+    for name in &names {
         h.update(name.as_bytes()).unwrap();
     }
 
-    h.finish().unwrap()
+    Ok(h.finish().unwrap())
+}
+
+/// Returns the hash of the content of all entries that are signed with the given version, in
+/// the order of the archive. For DayZ, v3 signatures also cover Enforce scripts.
+pub fn filehash<A: Archive + ?Sized>(archive: &A, version: BISignVersion) -> Result<DigestBytes, Error> {
+    hash_entries(archive.entries()?, |name| archive.read_entry(name), version)
 }
 
-fn filehash(pbo: &PBO, version: BISignVersion) -> DigestBytes {
+/// Returns the hash like `filehash` of the data of the entries as it is stored in the read PBO,
+/// which is what the game checks for compressed files.
+fn pbo_filehash(pbo: &PBO, version: BISignVersion) -> Result<DigestBytes, Error> {
+    hash_entries(pbo.files.keys().cloned().collect(), |name| Ok(pbo.read_data(name).map(|(_, data)| Cow::Borrowed(data))), version)
+}
+
+fn hash_entries<'a, F: Fn(&str) -> Result<Option<Cow<'a, [u8]>>, Error>>(entries: Vec<String>, read_entry: F, version: BISignVersion) -> Result<DigestBytes, Error> {
     let mut h = Hasher::new(MessageDigest::sha1()).unwrap();
     let mut nothing = true;
 
-    for (name, cursor) in pbo.files.iter() {
+    for name in entries {
         let ext = name.split('.').last().unwrap();
 
         match version {
@@ -99,12 +208,14 @@ fn filehash(pbo: &PBO, version: BISignVersion) -> DigestBytes {
                 if ext != "sqf" && ext != "inc" && ext != "bikb" &&
                     ext != "ext" && ext != "fsm" && ext != "sqm" &&
                     ext != "hpp" && ext != "cfg" && ext != "sqs" &&
-                    ext != "h" { continue; }
+                    ext != "h" && !(ext == "c" && target().signs_enforce_scripts()) { continue; }
             }
         }
 
-        h.update(cursor.get_ref()).unwrap();
-        nothing = false;
+        if let Some(data) = read_entry(&name)? {
+            h.update(&data).unwrap();
+            nothing = false;
+        }
     }
 
     match version {
@@ -112,38 +223,71 @@ fn filehash(pbo: &PBO, version: BISignVersion) -> DigestBytes {
         BISignVersion::V3 => if nothing { h.update(b"gnihton").unwrap(); }
     }
 
-    h.finish().unwrap()
+    Ok(h.finish().unwrap())
+}
+
+/// Hashes of a PBO that signatures are created from
+///
+/// The hashes don't depend on the key, so a PBO can be signed with several keys while only
+/// hashing its content once.
+pub struct PBOHashes {
+    version: BISignVersion,
+    hash1: Vec<u8>,
+    hash2: Vec<u8>,
+    hash3: Vec<u8>,
+}
+
+impl PBOHashes {
+    /// Hashes the PBO for signatures of the version.
+    pub fn new(pbo: &PBO, version: BISignVersion) -> PBOHashes {
+        let (hash1, hash2, hash3) = hash_pbo(pbo, version);
+
+        PBOHashes {
+            version,
+            hash1,
+            hash2,
+            hash3,
+        }
+    }
+
+    fn padded(&self, length: u32) -> (BigNum, BigNum, BigNum) {
+        (pad_hash(&self.hash1, (length / 8) as usize),
+            pad_hash(&self.hash2, (length / 8) as usize),
+            pad_hash(&self.hash3, (length / 8) as usize))
+    }
 }
 
 fn generate_hashes(pbo: &PBO, version: BISignVersion, length: u32) -> (BigNum, BigNum, BigNum) {
+    PBOHashes::new(pbo, version).padded(length)
+}
+
+fn hash_pbo(pbo: &PBO, version: BISignVersion) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
     let checksum = pbo.checksum.clone().unwrap();
     let hash1 = checksum.as_slice();
 
     let mut h = Hasher::new(MessageDigest::sha1()).unwrap();
     h.update(hash1).unwrap();
-    h.update(&*namehash(pbo)).unwrap();
+    h.update(&namehash(pbo).unwrap()).unwrap();
     if let Some(prefix) = pbo.header_extensions.get("prefix") {
         h.update(prefix.as_bytes()).unwrap();
         if !prefix.ends_with('\\') {
             h.update(b"\\").unwrap();
         }
     }
-    let hash2 = &*h.finish().unwrap();
+    let hash2 = h.finish().unwrap();
 
     h = Hasher::new(MessageDigest::sha1()).unwrap();
-    h.update(&*filehash(pbo, version)).unwrap();
-    h.update(&*namehash(pbo)).unwrap();
+    h.update(&pbo_filehash(pbo, version).unwrap()).unwrap();
+    h.update(&namehash(pbo).unwrap()).unwrap();
     if let Some(prefix) = pbo.header_extensions.get("prefix") {
         h.update(prefix.as_bytes()).unwrap();
         if !prefix.ends_with('\\') {
             h.update(b"\\").unwrap();
         }
     }
-    let hash3 = &*h.finish().unwrap();
+    let hash3 = h.finish().unwrap();
 
-    (pad_hash(hash1, (length / 8) as usize),
-        pad_hash(hash2, (length / 8) as usize),
-        pad_hash(hash3, (length / 8) as usize))
+    (hash1.to_vec(), hash2.to_vec(), hash3.to_vec())
 }
 
 fn pad_hash(hash: &[u8], size: usize) -> BigNum {
@@ -271,27 +415,12 @@ impl BIPrivateKey {
 
     /// Signs the given PBO with this private key.
     pub fn sign(&self, pbo: &PBO, version: BISignVersion) -> BISign {
-        let (hash1, hash2, hash3) = generate_hashes(pbo, version, self.length);
-
-        let mut ctx = BigNumContext::new().unwrap();
-
-        let mut sig1: BigNum = BigNum::new().unwrap();
-        sig1.mod_exp(&hash1, &self.d, &self.n, &mut ctx).unwrap();
-        let mut sig2: BigNum = BigNum::new().unwrap();
-        sig2.mod_exp(&hash2, &self.d, &self.n, &mut ctx).unwrap();
-        let mut sig3: BigNum = BigNum::new().unwrap();
-        sig3.mod_exp(&hash3, &self.d, &self.n, &mut ctx).unwrap();
+        self.sign_hashes(&PBOHashes::new(pbo, version))
+    }
 
-        BISign {
-            version,
-            name: self.name.clone(),
-            length: self.length,
-            exponent: self.exponent,
-            n: BigNum::from_slice(&self.n.to_vec()).unwrap(),
-            sig1,
-            sig2,
-            sig3,
-        }
+    /// Creates a signature from the hashes of a PBO.
+    pub fn sign_hashes(&self, hashes: &PBOHashes) -> BISign {
+        self.create_signature(hashes).unwrap()
     }
 
     /// Write private key to output.
@@ -341,7 +470,17 @@ impl BIPublicKey {
 
     // @todo: example
     /// Verifies a signature against this public key.
+    ///
+    /// Like the game, the signature has to name this key and contain its modulus before any of
+    /// the three hashes are compared.
     pub fn verify(&self, pbo: &PBO, signature: &BISign) -> Result<(), Error> {
+        if signature.name != self.name {
+            return Err(error!("Signature was created with key \"{}\", not \"{}\"", signature.name, self.name));
+        }
+        if signature.length != self.length || signature.n != self.n {
+            return Err(error!("Signature was created with a different key named \"{}\"", signature.name));
+        }
+
         let (real_hash1, real_hash2, real_hash3) = generate_hashes(pbo, signature.version, self.length);
 
         let mut ctx = BigNumContext::new().unwrap();
@@ -386,6 +525,144 @@ impl BIPublicKey {
     }
 }
 
+/// Backend for the RSA operation of signatures, so the private key doesn't have to be on disk
+pub trait Signer: Send + Sync {
+    /// Returns the public key of the key signing.
+    fn public_key(&self) -> BIPublicKey;
+
+    /// Returns the raw RSA signature `hash^d mod n` of a padded hash.
+    fn sign_raw(&self, hash: &BigNum) -> Result<BigNum, Error>;
+
+    /// Creates a signature from the hashes of a PBO.
+    fn create_signature(&self, hashes: &PBOHashes) -> Result<BISign, Error> {
+        let key = self.public_key();
+        let (hash1, hash2, hash3) = hashes.padded(key.length);
+
+        Ok(BISign {
+            version: hashes.version,
+            sig1: self.sign_raw(&hash1)?,
+            sig2: self.sign_raw(&hash2)?,
+            sig3: self.sign_raw(&hash3)?,
+            name: key.name,
+            length: key.length,
+            exponent: key.exponent,
+            n: key.n,
+        })
+    }
+}
+
+impl Signer for BIPrivateKey {
+    fn public_key(&self) -> BIPublicKey {
+        self.to_public_key()
+    }
+
+    fn sign_raw(&self, hash: &BigNum) -> Result<BigNum, Error> {
+        let mut ctx = BigNumContext::new().unwrap();
+        let mut sig: BigNum = BigNum::new().unwrap();
+        sig.mod_exp(hash, &self.d, &self.n, &mut ctx).unwrap();
+        Ok(sig)
+    }
+}
+
+/// Signer running a command for the RSA operation, e.g. a PKCS#11 tool using a key on a token
+/// or in an HSM
+///
+/// The command is run with the shell and gets the name of the key in `ARMAKE2_KEY`. It reads
+/// the padded hash from stdin and writes the raw signature to stdout, both big-endian and as
+/// long as the modulus, like `pkcs11-tool --sign -m RSA-X-509` does. Every signature is checked
+/// with the public key, so a command using the wrong key fails instead of writing invalid
+/// signatures.
+pub struct ExternalSigner {
+    command: String,
+    key: BIPublicKey,
+}
+
+impl ExternalSigner {
+    /// Creates a signer running the command to sign with the private key of the public key.
+    pub fn new(command: String, key: BIPublicKey) -> ExternalSigner {
+        ExternalSigner { command, key }
+    }
+}
+
+impl Signer for ExternalSigner {
+    fn public_key(&self) -> BIPublicKey {
+        BIPublicKey {
+            name: self.key.name.clone(),
+            length: self.key.length,
+            exponent: self.key.exponent,
+            n: BigNum::from_slice(&self.key.n.to_vec()).unwrap(),
+        }
+    }
+
+    fn sign_raw(&self, hash: &BigNum) -> Result<BigNum, Error> {
+        let size = (self.key.length / 8) as usize;
+        let mut input = hash.to_vec();
+        input.splice(0..0, std::iter::repeat_n(0, size.saturating_sub(input.len())));
+
+        let mut command = if cfg!(windows) {
+            let mut c = Command::new("cmd");
+            c.arg("/C").arg(&self.command);
+            c
+        } else {
+            let mut c = Command::new("sh");
+            c.arg("-c").arg(&self.command);
+            c
+        };
+        let mut child = command.env("ARMAKE2_KEY", &self.key.name)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn().prepend_error(format!("Failed to run signer \"{}\":", self.command))?;
+        child.stdin.take().unwrap().write_all(&input).prepend_error(format!("Failed to write to signer \"{}\":", self.command))?;
+
+        let output = child.wait_with_output().prepend_error(format!("Failed to run signer \"{}\":", self.command))?;
+        if !output.status.success() {
+            return Err(match output.status.code() {
+                Some(code) => error!("Signer \"{}\" terminated with exit code: {}", self.command, code),
+                None => error!("Signer \"{}\" terminated by signal.", self.command),
+            });
+        }
+        if output.stdout.len() != size {
+            return Err(error!("Signer \"{}\" returned {} bytes instead of a {} bit signature.", self.command, output.stdout.len(), self.key.length));
+        }
+
+        let sig = BigNum::from_slice(&output.stdout).unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let mut signed_hash: BigNum = BigNum::new().unwrap();
+        signed_hash.mod_exp(&sig, &BigNum::from_u32(self.key.exponent).unwrap(), &self.key.n, &mut ctx).unwrap();
+        if &signed_hash != hash {
+            return Err(error!("Signer \"{}\" didn't sign with key \"{}\".", self.command, self.key.name));
+        }
+
+        Ok(sig)
+    }
+}
+
+static SIGNER_COMMAND: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
+
+/// Sets the command signatures are created with instead of private keys, see `ExternalSigner`.
+pub fn init_signer_command(command: Option<String>) {
+    *SIGNER_COMMAND.write().unwrap() = command;
+}
+
+/// Returns the signer for a key given to a command: the private key, or with a signer command
+/// set, the command with the public key. A `.biprivatekey` path then names the `.bikey` next
+/// to it, so the same arguments work with and without a signer command.
+pub fn read_signer(path: &Path) -> Result<Box<dyn Signer>, Error> {
+    if let Some(command) = SIGNER_COMMAND.read().unwrap().clone() {
+        let path = match path.extension() {
+            Some(extension) if extension == "biprivatekey" => path.with_extension("bikey"),
+            _ => path.to_path_buf(),
+        };
+        let key = BIPublicKey::read(&mut File::open(&path).prepend_error(format!("Failed to open public key {}:", path.display()))?)
+            .prepend_error(format!("Failed to read public key {}:", path.display()))?;
+        return Ok(Box::new(ExternalSigner::new(command, key)));
+    }
+
+    let privatekey = BIPrivateKey::read(&mut File::open(path).prepend_error(format!("Failed to open private key {}:", path.display()))?)
+        .prepend_error(format!("Failed to read private key {}:", path.display()))?;
+    Ok(Box::new(privatekey))
+}
+
 impl Into<u32> for BISignVersion {
     fn into(self) -> u32 {
         match self {
@@ -407,7 +684,9 @@ impl BISign {
         let length = input.read_u32::<LittleEndian>()?;
         let exponent = input.read_u32::<LittleEndian>()?;
 
-        assert_eq!(temp, length / 8 + 20);
+        if temp != length / 8 + 20 {
+            return Err(error!("Invalid BISign header."));
+        }
 
         let mut buffer = vec![0; (length / 8) as usize];
         input.read_exact(&mut buffer)?;
@@ -455,6 +734,16 @@ impl BISign {
         })
     }
 
+    /// Returns the name of the key the signature was created with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the version of the signature.
+    pub fn version(&self) -> BISignVersion {
+        self.version
+    }
+
     /// Writes the signature to the given output.
     pub fn write<O: Write>(&self, output: &mut O) -> Result<(), Error> {
         output.write_cstring(&self.name)?;
@@ -475,62 +764,197 @@ impl BISign {
     }
 }
 
-/// Generates a key pair with the given name.
+/// Generates a key pair with the given name and writes its metadata (see `KeyMetadata`).
 ///
 /// The output paths are created by appending extensions to the keyname.
-pub fn cmd_keygen(keyname: PathBuf, force: bool) -> Result<(), Error> {
-    let private_key = BIPrivateKey::generate(1024, keyname.file_name().unwrap().to_str().unwrap().to_string());
+pub fn cmd_keygen(keyname: PathBuf, force: bool, owner: Option<String>, comment: Option<String>) -> Result<(), Error> {
+    let name = keyname.file_name().and_then(|n| n.to_str())
+        .ok_or_else(|| error!("Invalid key name \"{}\".", keyname.display()))?;
+    let private_key = BIPrivateKey::generate(1024, name.to_string());
     let public_key = private_key.to_public_key();
-    let name = keyname.file_name().unwrap().to_str().unwrap();
 
     let mut private_key_path = keyname.clone();
     private_key_path.set_file_name(format!("{}.biprivatekey", name));
     if !force && private_key_path.exists() {
         return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", private_key_path.display()));
     }
-    private_key.write(&mut File::create(&private_key_path).unwrap()).expect("Failed to write private key");
+    let mut file = AtomicFile::create(&private_key_path).prepend_error(format!("Failed to open {}:", private_key_path.display()))?;
+    private_key.write(&mut file).prepend_error("Failed to write private key:")?;
+    file.commit().prepend_error("Failed to write private key:")?;
 
     let mut public_key_path = keyname.clone();
     public_key_path.set_file_name(format!("{}.bikey", name));
     if !force && public_key_path.exists() {
         return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", public_key_path.display()));
     }
-    public_key.write(&mut File::create(&public_key_path).unwrap()).expect("Failed to write public key");
+    let mut file = AtomicFile::create(&public_key_path).prepend_error(format!("Failed to open {}:", public_key_path.display()))?;
+    public_key.write(&mut file).prepend_error("Failed to write public key:")?;
+    file.commit().prepend_error("Failed to write public key:")?;
+
+    KeyMetadata::new(owner, comment).write(&keyname).prepend_error("Failed to write key metadata:")?;
 
     Ok(())
 }
 
-/// Signs a PBO with the given private key.
+/// Prints the name, length and metadata of a public or private key.
+pub fn cmd_key_info(key_path: PathBuf) -> Result<(), Error> {
+    let mut file = File::open(&key_path).prepend_error(format!("Failed to open {}:", key_path.display()))?;
+    let (kind, name, length) = if key_path.extension().map(|e| e == "biprivatekey").unwrap_or(false) {
+        let key = BIPrivateKey::read(&mut file).prepend_error("Failed to read private key:")?;
+        ("private key", key.name, key.length)
+    } else {
+        let key = BIPublicKey::read(&mut file).prepend_error("Failed to read public key:")?;
+        ("public key", key.name, key.length)
+    };
+
+    println!("Name:    {}", name);
+    println!("Type:    {}", kind);
+    println!("Length:  {} bits", length);
+
+    match KeyMetadata::read(&key_path)? {
+        Some(metadata) => {
+            println!("Created: {} ({} days ago)", metadata.created_date(), metadata.age_days());
+            if let Some(owner) = metadata.owner {
+                println!("Owner:   {}", owner);
+            }
+            if let Some(comment) = metadata.comment {
+                println!("Comment: {}", comment);
+            }
+        },
+        None => println!("No metadata found at {}.", KeyMetadata::path(&key_path).display()),
+    }
+
+    Ok(())
+}
+
+/// Signs a PBO with the given private keys, one signature per key from a single hashing pass.
 ///
-/// If the signature path is not given it is inferred from the PBO path.
-pub fn cmd_sign(privatekey_path: PathBuf, pbo_path: PathBuf, signature_path: Option<PathBuf>, version: BISignVersion, force: bool) -> Result<(), Error> {
-    let privatekey = BIPrivateKey::read(&mut File::open(&privatekey_path).expect("Failed to open private key")).expect("Failed to read private key");
-    let pbo = PBO::read(&mut File::open(&pbo_path).expect("Failed to open PBO")).expect("Failed to read PBO");
+/// If the signature path is not given it is inferred from the PBO path and the key name. A
+/// signature path can only be given when signing with a single key.
+pub fn cmd_sign(privatekey_paths: &[PathBuf], pbo_path: PathBuf, signature_path: Option<PathBuf>, version: BISignVersion, force: bool) -> Result<(), Error> {
+    if signature_path.is_some() && privatekey_paths.len() > 1 {
+        return Err(error!("A signature path can only be given when signing with a single key."));
+    }
 
-    let sig_path = match signature_path {
-        Some(path) => path,
-        None => {
-            let mut path = pbo_path.clone();
-            path.set_extension(format!("pbo.{}.bisign", privatekey.name));
-            path
+    let mut signers: Vec<(Box<dyn Signer>, String, PathBuf)> = Vec::with_capacity(privatekey_paths.len());
+    for privatekey_path in privatekey_paths {
+        let signer = read_signer(privatekey_path)?;
+        let name = signer.public_key().name;
+
+        let sig_path = match signature_path {
+            Some(ref path) => path.clone(),
+            None => {
+                let mut path = pbo_path.clone();
+                path.set_extension(format!("pbo.{}.bisign", name));
+                path
+            }
+        };
+
+        if signers.iter().any(|(_, _, path)| path == &sig_path) {
+            return Err(error!("Multiple keys are named \"{}\".", name));
         }
-    };
+        if !force && sig_path.exists() {
+            return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", sig_path.display()));
+        }
+        signers.push((signer, name, sig_path));
+    }
 
-    if !force && sig_path.exists() {
-        return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", sig_path.display()));
+    let pbo = PBO::read(&mut File::open(&pbo_path).prepend_error("Failed to open PBO:")?).prepend_error("Failed to read PBO:")?;
+    let hashes = PBOHashes::new(&pbo, version);
+
+    for (signer, name, sig_path) in signers {
+        let sig = signer.create_signature(&hashes).prepend_error(format!("Failed to sign with key \"{}\":", name))?;
+        let mut file = AtomicFile::create(&sig_path).prepend_error(format!("Failed to open {}:", sig_path.display()))?;
+        sig.write(&mut file).prepend_error("Failed to write signature:")?;
+        file.commit().prepend_error("Failed to write signature:")?;
+    }
+
+    Ok(())
+}
+
+/// Returns the PBOs in the `addons` folder of a mod, sorted by name.
+pub fn mod_pbos(modfolder: &Path) -> Result<Vec<PathBuf>, Error> {
+    let addons = std::fs::read_dir(modfolder).prepend_error(format!("Failed to read mod folder {}:", modfolder.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| path.is_dir() && path.file_name().map(|n| n.to_string_lossy().eq_ignore_ascii_case("addons")).unwrap_or(false))
+        .ok_or_else(|| error!("Mod folder {} has no addons folder.", modfolder.display()))?;
+
+    let mut pbos: Vec<PathBuf> = std::fs::read_dir(&addons).prepend_error(format!("Failed to read {}:", addons.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().map(|e| e.to_string_lossy().eq_ignore_ascii_case("pbo")).unwrap_or(false))
+        .collect();
+    pbos.sort();
+
+    Ok(pbos)
+}
+
+/// Signs every PBO in the `addons` folder of a mod with the private key, writing the signatures
+/// next to the PBOs. With `copy_key`, the public key is written to the `keys` folder of the mod.
+///
+/// Fails before signing anything if a signature already exists and `force` is not set.
+pub fn cmd_sign_mod(privatekey_path: PathBuf, modfolder: PathBuf, version: BISignVersion, force: bool, copy_key: bool) -> Result<(), Error> {
+    let signer = read_signer(&privatekey_path)?;
+    let publickey = signer.public_key();
+
+    let pbos = mod_pbos(&modfolder)?;
+    if pbos.is_empty() {
+        return Err(error!("Mod folder {} has no PBOs to sign.", modfolder.display()));
+    }
+
+    let targets: Vec<(PathBuf, PathBuf)> = pbos.into_iter().map(|pbo_path| {
+        let mut sig_path = pbo_path.clone();
+        sig_path.set_extension(format!("pbo.{}.bisign", publickey.name));
+        (pbo_path, sig_path)
+    }).collect();
+    if !force {
+        if let Some((_, sig_path)) = targets.iter().find(|(_, sig_path)| sig_path.exists()) {
+            return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", sig_path.display()));
+        }
+    }
+
+    targets.par_iter().map(|(pbo_path, sig_path)| {
+        let pbo = PBO::read(&mut File::open(pbo_path).prepend_error(format!("Failed to open {}:", pbo_path.display()))?)
+            .prepend_error(format!("Failed to read {}:", pbo_path.display()))?;
+        let sig = signer.create_signature(&PBOHashes::new(&pbo, version)).prepend_error(format!("Failed to sign {}:", pbo_path.display()))?;
+
+        let mut file = AtomicFile::create(sig_path).prepend_error(format!("Failed to open {}:", sig_path.display()))?;
+        sig.write(&mut file).prepend_error(format!("Failed to write {}:", sig_path.display()))?;
+        file.commit()
+    }).collect::<Result<Vec<()>, Error>>()?;
+
+    if copy_key {
+        let keys = modfolder.join("keys");
+        std::fs::create_dir_all(&keys).prepend_error(format!("Failed to create {}:", keys.display()))?;
+        let key_path = keys.join(format!("{}.bikey", publickey.name));
+        let mut file = AtomicFile::create(&key_path).prepend_error(format!("Failed to open {}:", key_path.display()))?;
+        publickey.write(&mut file).prepend_error(format!("Failed to write {}:", key_path.display()))?;
+        file.commit()?;
     }
-    let sig = privatekey.sign(&pbo, version);
-    sig.write(&mut File::create(&sig_path).expect("Failed to open signature file")).expect("Failed to write signature");
 
     Ok(())
 }
 
 /// Verifies a signature for a pbo against a given public key.
 ///
-/// If the signature path is not given it is inferred from the PBO path.
-pub fn cmd_verify(publickey_path: PathBuf, pbo_path: PathBuf, signature_path: Option<PathBuf>) -> Result<(), Error> {
-    let publickey = BIPublicKey::read(&mut File::open(&publickey_path).expect("Failed to open public key")).expect("Failed to read public key");
-    let pbo = PBO::read(&mut File::open(&pbo_path).expect("Failed to open PBO")).expect("Failed to read PBO");
+/// If the signature path is not given it is inferred from the PBO path. With `warn_old_keys`, a
+/// warning is raised if the key was created more than that many days ago or its age is unknown.
+pub fn cmd_verify(publickey_path: PathBuf, pbo_path: PathBuf, signature_path: Option<PathBuf>, warn_old_keys: Option<u64>) -> Result<(), Error> {
+    if let Some(days) = warn_old_keys {
+        match KeyMetadata::read(&publickey_path)? {
+            Some(metadata) if metadata.age_days() > days => {
+                warning(format!("Key {} was created {} days ago, on {}.", publickey_path.display(), metadata.age_days(), metadata.created_date()), Some("old-key"), (None, None));
+            },
+            Some(_) => {},
+            None => {
+                warning(format!("Key {} has no metadata, its age is unknown.", publickey_path.display()), Some("old-key"), (None, None));
+            }
+        }
+    }
+
+    let publickey = BIPublicKey::read(&mut File::open(&publickey_path).prepend_error("Failed to open public key:")?).prepend_error("Failed to read public key:")?;
+    let pbo = PBO::read(&mut File::open(&pbo_path).prepend_error("Failed to open PBO:")?).prepend_error("Failed to read PBO:")?;
 
     let sig_path = match signature_path {
         Some(path) => path,
@@ -541,7 +965,7 @@ pub fn cmd_verify(publickey_path: PathBuf, pbo_path: PathBuf, signature_path: Op
         }
     };
 
-    let sig = BISign::read(&mut File::open(&sig_path).expect("Failed to open signature")).expect("Failed to read signature");
+    let sig = BISign::read(&mut File::open(&sig_path).prepend_error("Failed to open signature:")?).prepend_error("Failed to read signature:")?;
 
     publickey.verify(&pbo, &sig)
 }
@@ -0,0 +1,532 @@
+//! Parsing, checking and converting stringtable.xml files
+
+use std::collections::{HashMap};
+use std::io::{Read, Write, Error};
+
+use crate::error::*;
+
+/// Languages the engine reads from stringtables, besides `Original` used when a language is
+/// missing
+pub const LANGUAGES: &[&str] = &["Original", "English", "Czech", "French", "Spanish", "Italian", "Polish", "Portuguese", "Russian", "German", "Korean", "Japanese", "Chinese", "Chinesesimp", "Turkish", "Hungarian"];
+
+/// Key of a stringtable with its translations
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StringtableKey {
+    /// ID of the key, like `STR_abc_title`
+    pub id: String,
+    /// package the key is in
+    pub package: String,
+    /// container in the package the key is in, if any
+    pub container: Option<String>,
+    /// translations by language, in the order of the file
+    pub texts: Vec<(String, String)>,
+    /// line of the key in the XML, 0 if it wasn't read from XML
+    pub line: u32,
+}
+
+/// Stringtable.xml of an addon or mission
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::stringtable::Stringtable;
+/// let xml = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+/// <Project name=\"ABC\">
+///     <Package name=\"Main\">
+///         <Key ID=\"STR_abc_title\">
+///             <English>Title &amp; more</English>
+///             <German>Titel</German>
+///         </Key>
+///     </Package>
+/// </Project>";
+/// let stringtable = Stringtable::parse(xml).unwrap();
+///
+/// assert_eq!("ABC", stringtable.project);
+/// assert_eq!("STR_abc_title", stringtable.keys[0].id);
+/// assert_eq!(Some("Title & more"), stringtable.keys[0].text("english"));
+/// assert!(stringtable.check().is_empty());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stringtable {
+    /// name of the project
+    pub project: String,
+    pub keys: Vec<StringtableKey>,
+}
+
+/// Part of an XML document
+#[derive(Debug)]
+enum XmlEvent {
+    Start(String, Vec<(String, String)>),
+    End(String),
+    Text(String),
+}
+
+fn decode_entities(text: &str, line: u32) -> Result<String, Error> {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let end = rest[start..].find(';').ok_or_else(|| error!("Line {}: Unterminated entity.", line))? + start;
+        let entity = &rest[start + 1..end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    u32::from_str_radix(hex, 16).ok()
+                } else {
+                    entity.strip_prefix('#').and_then(|dec| dec.parse().ok())
+                };
+                code.and_then(char::from_u32).ok_or_else(|| error!("Line {}: Unknown entity &{};.", line, entity))?
+            }
+        };
+        decoded.push(c);
+        rest = &rest[end + 1..];
+    }
+    decoded.push_str(rest);
+
+    Ok(decoded)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Splits the XML into elements and text with the lines they start at. Only the parts of XML
+/// used by stringtables are supported, declarations, comments and doctypes are skipped.
+fn xml_events(xml: &str) -> Result<Vec<(u32, XmlEvent)>, Error> {
+    let mut events: Vec<(u32, XmlEvent)> = Vec::new();
+    let mut pos = 0;
+    let mut line = 1;
+    let mut counted = 0;
+
+    while pos < xml.len() {
+        line += xml[counted..pos].matches('\n').count() as u32;
+        counted = pos;
+
+        let rest = &xml[pos..];
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            events.push((line, XmlEvent::Text(decode_entities(&rest[..end], line)?)));
+            pos += end;
+            continue;
+        }
+
+        let skipped = [("<?", "?>"), ("<!--", "-->"), ("<![CDATA[", "]]>"), ("<!", ">")].iter().find(|(start, _)| rest.starts_with(start));
+        if let Some((start, end)) = skipped {
+            let len = rest.find(end).ok_or_else(|| error!("Line {}: Unterminated {}.", line, start))? + end.len();
+            if *start == "<![CDATA[" {
+                events.push((line, XmlEvent::Text(rest[start.len()..len - end.len()].to_string())));
+            }
+            pos += len;
+            continue;
+        }
+
+        let len = rest.find('>').ok_or_else(|| error!("Line {}: Unterminated tag.", line))? + 1;
+        let tag = &rest[1..len - 1];
+        pos += len;
+
+        if let Some(name) = tag.strip_prefix('/') {
+            events.push((line, XmlEvent::End(name.trim().to_string())));
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name_len = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+        let name = &tag[..name_len];
+        if name.is_empty() {
+            return Err(error!("Line {}: Tag without a name.", line));
+        }
+
+        let mut attributes: Vec<(String, String)> = Vec::new();
+        let mut attrs = tag[name_len..].trim_start();
+        while !attrs.is_empty() {
+            let equals = attrs.find('=').ok_or_else(|| error!("Line {}: Attribute without a value in <{}>.", line, name))?;
+            let key = attrs[..equals].trim();
+            let value = attrs[equals + 1..].trim_start();
+            let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')
+                .ok_or_else(|| error!("Line {}: Unquoted value of attribute {} in <{}>.", line, key, name))?;
+            let end = value[1..].find(quote).ok_or_else(|| error!("Line {}: Unterminated value of attribute {} in <{}>.", line, key, name))? + 1;
+            attributes.push((key.to_string(), decode_entities(&value[1..end], line)?));
+            attrs = value[end + 1..].trim_start();
+        }
+
+        events.push((line, XmlEvent::Start(name.to_string(), attributes)));
+        if self_closing {
+            events.push((line, XmlEvent::End(name.to_string())));
+        }
+    }
+
+    Ok(events)
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+}
+
+/// Parses a CSV document into rows of fields, with fields quoted and quotes doubled like RFC 4180.
+fn csv_rows(csv: &str) -> Result<Vec<Vec<String>>, Error> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut row: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut chars = csv.chars().peekable();
+    let mut quoted = false;
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); field.push('"'); },
+            '"' if quoted => { quoted = false; },
+            '"' if field.is_empty() => { quoted = true; },
+            '"' => { return Err(error!("Line {}: Quote in unquoted field.", line)); },
+            ',' if !quoted => { row.push(std::mem::take(&mut field)); },
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {},
+            '\n' if !quoted => {
+                line += 1;
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            },
+            _ => {
+                if c == '\n' { line += 1; }
+                field.push(c);
+            }
+        }
+    }
+    if quoted {
+        return Err(error!("Line {}: Unterminated quoted field.", line));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn write_csv_row<O: Write>(output: &mut O, fields: &[&str]) -> Result<(), Error> {
+    let fields: Vec<String> = fields.iter().map(|f| {
+        if f.contains(['"', ',', '\n', '\r']) {
+            format!("\"{}\"", f.replace('"', "\"\""))
+        } else {
+            f.to_string()
+        }
+    }).collect();
+    output.write_all(fields.join(",").as_bytes())?;
+    output.write_all(b"\r\n")?;
+
+    Ok(())
+}
+
+impl StringtableKey {
+    /// Returns the text for the language, ignoring its case.
+    pub fn text(&self, language: &str) -> Option<&str> {
+        self.texts.iter().find(|(l, _)| l.eq_ignore_ascii_case(language)).map(|(_, t)| t.as_str())
+    }
+}
+
+impl Stringtable {
+    /// Parses a stringtable.xml. Keys can be directly in a package or in containers in it.
+    pub fn parse(xml: &str) -> Result<Stringtable, Error> {
+        let mut stringtable = Stringtable::default();
+        let mut stack: Vec<String> = Vec::new();
+        let mut package = String::new();
+        let mut container: Option<String> = None;
+        let mut key: Option<StringtableKey> = None;
+        let mut language: Option<(String, String)> = None;
+
+        for (line, event) in xml_events(xml.trim_start_matches('\u{feff}'))? {
+            match event {
+                XmlEvent::Start(name, attributes) => {
+                    let name_attribute = attribute(&attributes, "name").unwrap_or("").to_string();
+                    if language.is_some() {
+                        return Err(error!("Line {}: Unexpected element <{}> in a translation.", line, name));
+                    } else if key.is_some() {
+                        language = Some((name.clone(), String::new()));
+                    } else if name.eq_ignore_ascii_case("Project") {
+                        stringtable.project = name_attribute;
+                    } else if name.eq_ignore_ascii_case("Package") {
+                        package = name_attribute;
+                        container = None;
+                    } else if name.eq_ignore_ascii_case("Container") {
+                        container = Some(name_attribute);
+                    } else if name.eq_ignore_ascii_case("Key") {
+                        key = Some(StringtableKey {
+                            id: attribute(&attributes, "ID").unwrap_or("").to_string(),
+                            package: package.clone(),
+                            container: container.clone(),
+                            texts: Vec::new(),
+                            line,
+                        });
+                    } else {
+                        return Err(error!("Line {}: Unexpected element <{}>.", line, name));
+                    }
+                    stack.push(name);
+                },
+                XmlEvent::End(name) => {
+                    match stack.pop() {
+                        Some(open) if open == name => {},
+                        Some(open) => { return Err(error!("Line {}: Expected </{}>, found </{}>.", line, open, name)); },
+                        None => { return Err(error!("Line {}: Unexpected </{}>.", line, name)); },
+                    }
+                    if let Some(text) = language.take() {
+                        key.as_mut().unwrap().texts.push(text);
+                    } else if let Some(key) = key.take() {
+                        stringtable.keys.push(key);
+                    } else if name.eq_ignore_ascii_case("Container") {
+                        container = None;
+                    }
+                },
+                XmlEvent::Text(text) => {
+                    if let Some((_, t)) = language.as_mut() {
+                        t.push_str(&text);
+                    } else if !text.trim().is_empty() {
+                        return Err(error!("Line {}: Unexpected text \"{}\".", line, text.trim()));
+                    }
+                },
+            }
+        }
+
+        if let Some(open) = stack.pop() {
+            return Err(error!("Unclosed element <{}>.", open));
+        }
+
+        Ok(stringtable)
+    }
+
+    /// Returns the problems of the stringtable with the line they are in and the name of their
+    /// warning: keys defined more than once, translations into unknown languages or defined
+    /// more than once, keys without English or original text and key IDs not starting with
+    /// `STR_`, which can't be used in `$STR_` references.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::stringtable::Stringtable;
+    /// let xml = "<Project name=\"ABC\"><Package name=\"Main\">
+    /// <Key ID=\"STR_a\"><English>A</English><Klingon>A</Klingon></Key>
+    /// <Key ID=\"str_A\"><German>A</German></Key>
+    /// </Package></Project>";
+    /// let problems = Stringtable::parse(xml).unwrap().check();
+    ///
+    /// let names: Vec<&str> = problems.iter().map(|p| p.2).collect();
+    /// assert_eq!(vec!["stringtable-language", "stringtable-duplicate", "stringtable-missing"], names);
+    /// assert_eq!(3, problems[1].0);
+    /// ```
+    pub fn check(&self) -> Vec<(u32, String, &'static str)> {
+        let mut problems: Vec<(u32, String, &'static str)> = Vec::new();
+        let mut seen: HashMap<String, u32> = HashMap::new();
+
+        for key in &self.keys {
+            if key.id.is_empty() {
+                problems.push((key.line, "Key has no ID.".to_string(), "stringtable-key"));
+                continue;
+            } else if !key.id.to_lowercase().starts_with("str_") {
+                problems.push((key.line, format!("Key {} doesn't start with STR_ and can't be referenced.", key.id), "stringtable-key"));
+            }
+
+            if let Some(line) = seen.insert(key.id.to_lowercase(), key.line) {
+                problems.push((key.line, format!("Key {} is already defined in line {}.", key.id, line), "stringtable-duplicate"));
+            }
+
+            for (i, (language, _)) in key.texts.iter().enumerate() {
+                if !LANGUAGES.iter().any(|l| l.eq_ignore_ascii_case(language)) {
+                    problems.push((key.line, format!("Key {} has a translation into unknown language {}.", key.id, language), "stringtable-language"));
+                } else if key.texts[..i].iter().any(|(l, _)| l.eq_ignore_ascii_case(language)) {
+                    problems.push((key.line, format!("Key {} has multiple {} translations.", key.id, language), "stringtable-duplicate"));
+                }
+            }
+
+            if key.text("English").is_none() && key.text("Original").is_none() {
+                problems.push((key.line, format!("Key {} has no English or Original text.", key.id), "stringtable-missing"));
+            }
+        }
+
+        problems
+    }
+
+    /// Returns the languages of all keys in the order they first appear in.
+    pub fn languages(&self) -> Vec<&str> {
+        let mut languages: Vec<&str> = Vec::new();
+        for (language, _) in self.keys.iter().flat_map(|k| k.texts.iter()) {
+            if !languages.iter().any(|l| l.eq_ignore_ascii_case(language)) {
+                languages.push(language);
+            }
+        }
+
+        languages
+    }
+
+    /// Writes the stringtable as XML, with the keys grouped by package and container in the
+    /// order they first appear in.
+    pub fn write_xml<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        writeln!(output, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+        writeln!(output, "<Project name=\"{}\">", escape_xml(&self.project))?;
+
+        let mut packages: Vec<&str> = Vec::new();
+        for key in &self.keys {
+            if !packages.contains(&key.package.as_str()) {
+                packages.push(&key.package);
+            }
+        }
+
+        for package in packages {
+            writeln!(output, "    <Package name=\"{}\">", escape_xml(package))?;
+
+            let mut containers: Vec<Option<&str>> = Vec::new();
+            for key in self.keys.iter().filter(|k| k.package == package) {
+                if !containers.contains(&key.container.as_deref()) {
+                    containers.push(key.container.as_deref());
+                }
+            }
+
+            for container in containers {
+                let indent = if container.is_some() { "            " } else { "        " };
+                if let Some(name) = container {
+                    writeln!(output, "        <Container name=\"{}\">", escape_xml(name))?;
+                }
+                for key in self.keys.iter().filter(|k| k.package == package && k.container.as_deref() == container) {
+                    writeln!(output, "{}<Key ID=\"{}\">", indent, escape_xml(&key.id))?;
+                    for (language, text) in &key.texts {
+                        writeln!(output, "{}    <{}>{}</{}>", indent, language, escape_xml(text), language)?;
+                    }
+                    writeln!(output, "{}</Key>", indent)?;
+                }
+                if container.is_some() {
+                    writeln!(output, "        </Container>")?;
+                }
+            }
+
+            writeln!(output, "    </Package>")?;
+        }
+
+        writeln!(output, "</Project>")?;
+        Ok(())
+    }
+
+    /// Writes the keys as CSV for translators, one key per row with the columns `ID`, `Package`,
+    /// `Container` and one column per language (see `languages`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::stringtable::Stringtable;
+    /// let xml = "<Project name=\"ABC\"><Package name=\"Main\"><Key ID=\"STR_a\"><English>A, \"B\"</English></Key></Package></Project>";
+    /// let stringtable = Stringtable::parse(xml).unwrap();
+    ///
+    /// let mut csv: Vec<u8> = Vec::new();
+    /// stringtable.write_csv(&mut csv).unwrap();
+    /// assert_eq!("ID,Package,Container,English\r\nSTR_a,Main,,\"A, \"\"B\"\"\"\r\n", String::from_utf8(csv.clone()).unwrap());
+    ///
+    /// let reread = Stringtable::read_csv(&String::from_utf8(csv).unwrap(), "ABC").unwrap();
+    /// assert_eq!(stringtable.keys[0].texts, reread.keys[0].texts);
+    /// ```
+    pub fn write_csv<O: Write>(&self, output: &mut O) -> Result<(), Error> {
+        let languages = self.languages();
+        let mut header: Vec<&str> = vec!["ID", "Package", "Container"];
+        header.extend(languages.iter());
+        write_csv_row(output, &header)?;
+
+        for key in &self.keys {
+            let mut row: Vec<&str> = vec![&key.id, &key.package, key.container.as_deref().unwrap_or("")];
+            row.extend(languages.iter().map(|l| key.text(l).unwrap_or("")));
+            write_csv_row(output, &row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads keys from CSV written by `write_csv` into a stringtable for the project. Empty
+    /// cells are left out of the translations of their key.
+    pub fn read_csv(csv: &str, project: &str) -> Result<Stringtable, Error> {
+        let mut rows = csv_rows(csv.trim_start_matches('\u{feff}'))?.into_iter();
+        let header = rows.next().unwrap_or_default();
+        let expected = ["ID", "Package", "Container"];
+        if header.len() < expected.len() || !expected.iter().zip(&header).all(|(e, h)| e.eq_ignore_ascii_case(h.trim())) {
+            return Err(error!("The first columns have to be ID, Package and Container."));
+        }
+
+        let mut keys: Vec<StringtableKey> = Vec::new();
+        for (i, row) in rows.enumerate() {
+            if row.iter().all(|f| f.is_empty()) { continue; }
+            if row.len() > header.len() {
+                return Err(error!("Row {} has more columns than the header.", i + 2));
+            }
+
+            let field = |i: usize| row.get(i).map(|f| f.as_str()).unwrap_or("");
+            keys.push(StringtableKey {
+                id: field(0).to_string(),
+                package: field(1).to_string(),
+                container: Some(field(2).to_string()).filter(|c| !c.is_empty()),
+                texts: header.iter().enumerate().skip(3)
+                    .filter(|(i, _)| !field(*i).is_empty())
+                    .map(|(i, language)| (language.trim().to_string(), field(i).to_string()))
+                    .collect(),
+                line: 0,
+            });
+        }
+
+        Ok(Stringtable {
+            project: project.to_string(),
+            keys,
+        })
+    }
+}
+
+/// Parses and checks a stringtable.xml that is packed at `name`, raising its problems as
+/// warnings. Fails if it is no valid stringtable.
+pub fn check_file(data: &[u8], name: &str) -> Result<(), Error> {
+    let xml = std::str::from_utf8(data).map_err(|e| error!("{} is not UTF-8: {}", name, e))?;
+    let stringtable = Stringtable::parse(xml).prepend_error(format!("Failed to parse {}:", name))?;
+
+    for (line, msg, warning_name) in stringtable.check() {
+        warning(msg, Some(warning_name), (Some(name.to_string()), Some(line)));
+    }
+
+    Ok(())
+}
+
+/// Reads a stringtable.xml from input, raises its problems as warnings (see `Stringtable::check`)
+/// and fails if there are any.
+pub fn cmd_stringtable_check<I: Read>(input: &mut I, name: &str) -> Result<(), Error> {
+    let mut xml = String::new();
+    input.read_to_string(&mut xml).prepend_error("Failed to read stringtable:")?;
+    let stringtable = Stringtable::parse(&xml).prepend_error(format!("Failed to parse {}:", name))?;
+
+    let problems = stringtable.check();
+    for (line, msg, warning_name) in &problems {
+        warning(msg.clone(), Some(warning_name), (Some(name.to_string()), Some(*line)));
+    }
+
+    if !problems.is_empty() {
+        return Err(error!("Found {} problems in {}.", problems.len(), name));
+    }
+    eprintln!("{} keys in {} languages, no problems found.", stringtable.keys.len(), stringtable.languages().len());
+    Ok(())
+}
+
+/// Reads a stringtable.xml from input and writes its keys to output as CSV (see
+/// `Stringtable::write_csv`).
+pub fn cmd_stringtable_export<I: Read, O: Write>(input: &mut I, output: &mut O) -> Result<(), Error> {
+    let mut xml = String::new();
+    input.read_to_string(&mut xml).prepend_error("Failed to read stringtable:")?;
+
+    Stringtable::parse(&xml).prepend_error("Failed to parse stringtable:")?.write_csv(output)
+}
+
+/// Reads CSV written by `cmd_stringtable_export` from input and writes it to output as a
+/// stringtable.xml. The project is named `project`, or like the package of the first key.
+pub fn cmd_stringtable_import<I: Read, O: Write>(input: &mut I, output: &mut O, project: Option<&str>) -> Result<(), Error> {
+    let mut csv = String::new();
+    input.read_to_string(&mut csv).prepend_error("Failed to read CSV:")?;
+
+    let mut stringtable = Stringtable::read_csv(&csv, project.unwrap_or("")).prepend_error("Failed to parse CSV:")?;
+    if project.is_none() {
+        stringtable.project = stringtable.keys.first().map(|k| k.package.clone()).unwrap_or_default();
+    }
+
+    stringtable.write_xml(output)
+}
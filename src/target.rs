@@ -0,0 +1,95 @@
+//! Target engines and the file format features they support
+
+use std::io::{Error};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::sign::BISignVersion;
+
+static TARGET: AtomicU8 = AtomicU8::new(Target::A3 as u8);
+
+/// Engine the built files are meant for
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::target::Target;
+/// let target = Target::from_name("ofp").unwrap();
+///
+/// assert_eq!(Target::OFP, target);
+/// assert!(target.signature_version().is_none());
+/// assert!(!target.supports_header_extensions());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Operation Flashpoint and Arma: Cold War Assault
+    OFP = 0,
+    /// Arma 2 and Operation Arrowhead
+    A2 = 1,
+    /// Arma 3
+    A3 = 2,
+    /// DayZ Standalone
+    DayZ = 3,
+}
+
+impl Target {
+    /// Parses a target given as `ofp`, `a2`, `a3` or `dayz`.
+    pub fn from_name(name: &str) -> Result<Target, Error> {
+        match name.to_lowercase().as_str() {
+            "ofp" => Ok(Target::OFP),
+            "a2" => Ok(Target::A2),
+            "a3" => Ok(Target::A3),
+            "dayz" => Ok(Target::DayZ),
+            _ => Err(error!("Unknown target \"{}\", expected ofp, a2, a3 or dayz.", name)),
+        }
+    }
+
+    /// Returns the name of the engine for messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::OFP => "OFP",
+            Target::A2 => "Arma 2",
+            Target::A3 => "Arma 3",
+            Target::DayZ => "DayZ",
+        }
+    }
+
+    /// Returns whether PBOs can contain header extensions like the prefix.
+    pub fn supports_header_extensions(self) -> bool {
+        self != Target::OFP
+    }
+
+    /// Returns the signature version checked by the engine, `None` if it doesn't check signatures.
+    pub fn signature_version(self) -> Option<BISignVersion> {
+        match self {
+            Target::OFP => None,
+            Target::A2 => Some(BISignVersion::V2),
+            Target::A3 | Target::DayZ => Some(BISignVersion::V3),
+        }
+    }
+
+    /// Returns whether Enforce scripts (`.c` files) are part of the file hash of v3 signatures.
+    pub fn signs_enforce_scripts(self) -> bool {
+        self == Target::DayZ
+    }
+
+    /// Returns whether rapified configs can contain array expansions (`foo[] += {}`) and class
+    /// deletions (`delete foo;`).
+    pub fn supports_config_extensions(self) -> bool {
+        self != Target::OFP
+    }
+}
+
+/// Sets the target engine of all following commands, Arma 3 by default.
+pub fn init_target(target: Target) {
+    TARGET.store(target as u8, Ordering::Relaxed);
+}
+
+/// Returns the current target engine.
+pub fn target() -> Target {
+    match TARGET.load(Ordering::Relaxed) {
+        0 => Target::OFP,
+        1 => Target::A2,
+        3 => Target::DayZ,
+        _ => Target::A3,
+    }
+}
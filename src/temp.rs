@@ -0,0 +1,111 @@
+//! Temporary folders for binarize.exe and hooks that are removed again when they are dropped
+
+use std::env::{temp_dir};
+use std::fs::{create_dir, create_dir_all, read_dir, remove_dir_all};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Once};
+use std::time::{Duration, SystemTime};
+
+use crate::error::*;
+
+static KEEP_TEMP: AtomicBool = AtomicBool::new(false);
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static CLEANUP: Once = Once::new();
+
+/// Age from which temporary folders are assumed to be left over by a crashed process
+const STALE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Keeps temporary folders after use and prints their paths instead of removing them, for
+/// debugging binarize.exe and hooks.
+pub fn init_keep_temp(keep: bool) {
+    KEEP_TEMP.store(keep, Ordering::Relaxed);
+}
+
+/// Returns the folder all temporary folders of armake2 are created in.
+pub fn temp_root() -> PathBuf {
+    temp_dir().join("armake2")
+}
+
+/// Removes temporary folders older than a day, left over by processes that crashed or were
+/// killed before they could remove them. Errors are ignored, the folders may still be in use.
+fn remove_stale() {
+    let entries = match read_dir(temp_root()) {
+        Ok(entries) => entries,
+        Err(_) => { return; }
+    };
+
+    let now = SystemTime::now();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let modified = entry.metadata().and_then(|m| m.modified());
+        if let Ok(age) = modified.map(|m| now.duration_since(m).unwrap_or_default()) {
+            if age > STALE_AGE {
+                let _ = remove_dir_all(entry.path());
+            }
+        }
+    }
+}
+
+/// Temporary folder, removed with its content when it is dropped, so also when a build fails.
+///
+/// Folders are named `<name>_<process id>_<n>` in the `armake2` folder of the system's temporary
+/// folder, so parallel builds and processes never share one and leftovers can be traced back to
+/// the build creating them.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::temp::TempDir;
+/// let dir = TempDir::new("example").unwrap();
+/// let path = dir.path().to_path_buf();
+/// std::fs::write(path.join("file.txt"), "content").unwrap();
+///
+/// drop(dir);
+/// assert!(!path.exists());
+/// ```
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a new temporary folder whose name starts with `name`.
+    pub fn new(name: &str) -> Result<TempDir, Error> {
+        CLEANUP.call_once(remove_stale);
+
+        let root = temp_root();
+        create_dir_all(&root)?;
+
+        let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' }).collect();
+
+        // creating the folder fails if it exists, e.g. left over by a crashed process with the
+        // same id, so this never reuses a folder
+        loop {
+            let path = root.join(format!("{}_{}_{}", name, std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+            match create_dir(&path) {
+                Ok(()) => { return Ok(TempDir { path }); },
+                Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {},
+                Err(e) => { return Err(e); }
+            }
+        }
+    }
+
+    /// Returns the path of the folder.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if KEEP_TEMP.load(Ordering::Relaxed) {
+            eprintln!("Kept temporary folder {}.", self.path.display());
+            return;
+        }
+
+        if let Err(e) = remove_dir_all(&self.path) {
+            warning(format!("Failed to remove temporary folder {}: {}", self.path.display(), e), Some("temp-cleanup"), (None, None));
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Generators and round trip checks for testing code that works with configs
+//!
+//! Only available with the `test-support` feature. Projects transforming configs can run their
+//! transformations on generated configs and check that the results still survive the round trip
+//! from text over the rapified format back to text:
+//!
+//! ```
+//! # use armake2::testing::*;
+//! check_generated(42, 50, |_source, mut config| {
+//!     config.optimize();
+//!     check_roundtrip(&config)
+//! }).unwrap();
+//! ```
+
+use std::io::{Cursor, Error};
+
+use crate::config::{Config};
+use crate::error::*;
+
+/// Names of entries can't be these, they are keywords of the config grammar
+const KEYWORDS: &[&str] = &["class", "delete"];
+
+/// Characters of generated strings, including quotes to test their escaping
+const STRING_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 _-.,;:=+*/()[]{}\"'";
+
+/// Generates random but valid config sources from a seed. The same seed always generates the
+/// same configs.
+///
+/// Generated configs contain every kind of entry: nested classes with and without parents,
+/// external classes, class deletions, strings with quotes, integers, floats and arrays, nested and
+/// expanded with `+=`. They don't use the preprocessor.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::testing::ConfigGenerator;
+/// let source = ConfigGenerator::new(1).source();
+///
+/// assert_eq!(source, ConfigGenerator::new(1).source());
+/// assert!(armake2::config::Config::from_string(source, None, &Vec::new()).is_ok());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigGenerator {
+    state: u64,
+    /// maximum nesting of classes and arrays
+    pub max_depth: u32,
+    /// maximum number of entries in a class and elements in an array
+    pub max_entries: u32,
+}
+
+impl ConfigGenerator {
+    /// Creates a generator for the seed with a maximum depth of 3 and 8 entries per class.
+    pub fn new(seed: u64) -> ConfigGenerator {
+        ConfigGenerator {
+            state: seed,
+            max_depth: 3,
+            max_entries: 8,
+        }
+    }
+
+    /// Returns the next random number, using SplitMix64.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random number below `n`.
+    fn below(&mut self, n: u32) -> u32 {
+        (self.next() % u64::from(n.max(1))) as u32
+    }
+
+    fn chance(&mut self, percent: u32) -> bool {
+        self.below(100) < percent
+    }
+
+    /// Generates a name not in `taken`, which is compared case-insensitively like the engine does.
+    fn name(&mut self, taken: &mut Vec<String>) -> String {
+        loop {
+            let len = 1 + self.below(10) as usize;
+            let mut name = String::with_capacity(len);
+            for i in 0..len {
+                let chars: &[u8] = if i == 0 {
+                    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ"
+                } else {
+                    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_"
+                };
+                name.push(chars[self.below(chars.len() as u32) as usize] as char);
+            }
+
+            let lower = name.to_lowercase();
+            if !KEYWORDS.contains(&lower.as_str()) && !taken.contains(&lower) {
+                taken.push(lower);
+                return name;
+            }
+        }
+    }
+
+    fn string(&mut self, output: &mut String) {
+        let quote = if self.chance(20) { '\'' } else { '"' };
+        output.push(quote);
+        for _ in 0..self.below(24) {
+            let c = STRING_CHARS[self.below(STRING_CHARS.len() as u32) as usize] as char;
+            // the preprocessor only knows double quoted strings, so it would take anything but
+            // plain text in single quoted ones for strings, comments and macro arguments
+            if quote == '\'' && !c.is_ascii_alphanumeric() && c != ' ' {
+                continue;
+            }
+            output.push(c);
+            if c == '"' {
+                output.push(c);
+            }
+        }
+        output.push(quote);
+    }
+
+    fn number(&mut self, output: &mut String) {
+        match self.below(4) {
+            0 => output.push_str(&(self.next() as i32).to_string()),
+            1 => output.push_str(&(self.below(2001) as i32 - 1000).to_string()),
+            // multiples of 1/8 are exact in floats, so they are written back the same way
+            _ => output.push_str(&format!("{:?}", (self.below(64001) as i32 - 32000) as f32 / 8.0)),
+        }
+    }
+
+    fn array(&mut self, output: &mut String, depth: u32) {
+        output.push('{');
+        for i in 0..self.below(self.max_entries + 1) {
+            if i > 0 {
+                output.push_str(", ");
+            }
+            match self.below(if depth < self.max_depth { 3 } else { 2 }) {
+                0 => self.string(output),
+                1 => self.number(output),
+                _ => self.array(output, depth + 1),
+            }
+        }
+        output.push('}');
+    }
+
+    fn class_body(&mut self, output: &mut String, depth: u32) {
+        let mut taken = Vec::new();
+        let mut classes: Vec<String> = Vec::new();
+        for _ in 0..self.below(self.max_entries + 1) {
+            let indent = "    ".repeat(depth as usize);
+            output.push_str(&indent);
+
+            let name = self.name(&mut taken);
+            match self.below(if depth < self.max_depth { 6 } else { 4 }) {
+                0 => {
+                    output.push_str(&name);
+                    output.push_str(" = ");
+                    self.string(output);
+                },
+                1 => {
+                    output.push_str(&name);
+                    output.push_str(" = ");
+                    self.number(output);
+                },
+                2 => {
+                    output.push_str(&name);
+                    output.push_str(if self.chance(20) { "[] += " } else { "[] = " });
+                    self.array(output, 1);
+                },
+                3 => {
+                    output.push_str(if self.chance(50) { "class " } else { "delete " });
+                    output.push_str(&name);
+                },
+                _ => {
+                    output.push_str("class ");
+                    output.push_str(&name);
+                    if !classes.is_empty() && self.chance(30) {
+                        let parent = &classes[self.below(classes.len() as u32) as usize];
+                        output.push_str(": ");
+                        output.push_str(parent);
+                    }
+                    output.push_str(" {\n");
+                    self.class_body(output, depth + 1);
+                    output.push_str(&indent);
+                    output.push('}');
+                    classes.push(name);
+                },
+            }
+            output.push_str(";\n");
+        }
+    }
+
+    /// Generates the source of a config.
+    pub fn source(&mut self) -> String {
+        let mut output = String::new();
+        self.class_body(&mut output, 0);
+        output
+    }
+
+    /// Generates a config, panicking if the generated source doesn't parse.
+    pub fn config(&mut self) -> Config {
+        let source = self.source();
+        Config::from_string(source.clone(), None, &Vec::new())
+            .unwrap_or_else(|e| panic!("Generated config doesn't parse: {}\n{}", e, source))
+    }
+}
+
+/// Describes the first line in which `a` and `b` differ.
+fn first_difference(a: &str, b: &str) -> String {
+    let mut lines_a = a.lines();
+    let mut lines_b = b.lines();
+    let mut line = 1;
+    loop {
+        match (lines_a.next(), lines_b.next()) {
+            (Some(x), Some(y)) if x == y => { line += 1; },
+            (x, y) => {
+                return format!("line {}: {:?} became {:?}", line, x.unwrap_or("<end>"), y.unwrap_or("<end>"));
+            },
+        }
+    }
+}
+
+/// Checks that the config survives the round trip from text over the rapified format back to
+/// text:
+///
+/// - its rapified size is what `Config::rapified_length` predicts,
+/// - derapifying it gives the same text and rapifying that again the same bytes,
+/// - parsing its text gives the same text again.
+pub fn check_roundtrip(config: &Config) -> Result<(), Error> {
+    let text = config.to_string()?;
+
+    let mut rapified = Vec::new();
+    config.write_rapified(&mut rapified).prepend_error("Failed to rapify config:")?;
+    if rapified.len() != config.rapified_length() {
+        return Err(error!("Rapified config has {} bytes instead of the expected {}.", rapified.len(), config.rapified_length()));
+    }
+
+    let derapified = Config::read_rapified(&mut Cursor::new(&rapified)).prepend_error("Failed to derapify config:")?;
+    let derapified_text = derapified.to_string()?;
+    if derapified_text != text {
+        return Err(error!("Config changed when rapifying and derapifying it, {}.", first_difference(&text, &derapified_text)));
+    }
+
+    let mut rerapified = Vec::new();
+    derapified.write_rapified(&mut rerapified)?;
+    if rerapified != rapified {
+        return Err(error!("Rapifying the derapified config gives different bytes."));
+    }
+
+    let reparsed = Config::from_string(text.clone(), None, &Vec::new()).prepend_error("Failed to parse written config:")?;
+    let reparsed_text = reparsed.to_string()?;
+    if reparsed_text != text {
+        return Err(error!("Config changed when writing and parsing it, {}.", first_difference(&text, &reparsed_text)));
+    }
+
+    Ok(())
+}
+
+/// Parses the config source and checks its round trip with `check_roundtrip`.
+pub fn check_source_roundtrip(source: &str) -> Result<(), Error> {
+    let config = Config::from_string(source.to_string(), None, &Vec::new()).prepend_error("Failed to parse config:")?;
+    check_roundtrip(&config)
+}
+
+/// Runs `check` on `cases` configs generated from the seed, passing their source and the parsed
+/// config. Stops at the first failing case and returns its error with the seed, case and source
+/// needed to reproduce it.
+pub fn check_generated<F: FnMut(&str, Config) -> Result<(), Error>>(seed: u64, cases: u32, mut check: F) -> Result<(), Error> {
+    let mut generator = ConfigGenerator::new(seed);
+    for case in 0..cases {
+        let source = generator.source();
+        let config = Config::from_string(source.clone(), None, &Vec::new())
+            .prepend_error(format!("Case {} of seed {} failed to parse:\n{}", case, seed, source))?;
+
+        check(&source, config).prepend_error(format!("Case {} of seed {} failed for config:\n{}", case, seed, source))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! Functions for building UI resources like texture atlases
+
+use std::fs::{File};
+use std::io::{Write, Error};
+use std::path::{PathBuf};
+
+use crate::error::*;
+use crate::io::AtomicFile;
+use crate::paa::{PAA, PAAType, MipMap, MipFilter, Image};
+
+/// Empty pixels left around each image to avoid bleeding when filtering
+const ATLAS_PADDING: u16 = 1;
+
+/// Image placed in an atlas
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasEntry {
+    /// name used for the generated macros
+    pub name: String,
+    /// index of the atlas page containing the image
+    pub page: usize,
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// Set of square atlas pages with the images packed into them
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::paa::Image;
+/// # use armake2::ui::Atlas;
+/// let images = vec![
+///     ("icon_a".to_string(), Image::new(16, 16)),
+///     ("icon_b".to_string(), Image::new(16, 8)),
+/// ];
+/// let atlas = Atlas::pack(images, 64).unwrap();
+///
+/// assert_eq!(1, atlas.pages.len());
+/// assert_eq!((1, 1), (atlas.entries[0].x, atlas.entries[0].y));
+/// assert_eq!((19, 1), (atlas.entries[1].x, atlas.entries[1].y));
+/// ```
+pub struct Atlas {
+    /// width and height of every page
+    pub size: u16,
+    pub pages: Vec<Image>,
+    /// entries in the order the images were given in
+    pub entries: Vec<AtlasEntry>,
+}
+
+struct Shelf {
+    y: u16,
+    height: u16,
+    x: u16,
+}
+
+impl Atlas {
+    /// Packs the images into as many pages of the given size as necessary. Unused space is left
+    /// transparent.
+    ///
+    /// Images are sorted by height and placed on shelves, which wastes little space for the
+    /// similarly sized icons UIs are usually made of.
+    pub fn pack(images: Vec<(String, Image)>, size: u16) -> Result<Atlas, Error> {
+        if !size.is_power_of_two() {
+            return Err(error!("Atlas size {} is not a power of two.", size));
+        }
+
+        let mut order: Vec<usize> = (0..images.len()).collect();
+        order.sort_by(|a, b| images[*b].1.height.cmp(&images[*a].1.height).then(a.cmp(b)));
+
+        let mut pages: Vec<Image> = Vec::new();
+        let mut shelves: Vec<Vec<Shelf>> = Vec::new();
+        let mut entries: Vec<Option<AtlasEntry>> = vec![None; images.len()];
+
+        for i in order {
+            let (name, image) = &images[i];
+            let width = u32::from(image.width) + 2 * u32::from(ATLAS_PADDING);
+            let height = u32::from(image.height) + 2 * u32::from(ATLAS_PADDING);
+            if width > u32::from(size) || height > u32::from(size) {
+                return Err(error!("Image \"{}\" ({}x{}) does not fit into an atlas of size {}.", name, image.width, image.height, size));
+            }
+            let (width, height) = (width as u16, height as u16);
+
+            let mut position = None;
+            'pages: for (page, page_shelves) in shelves.iter_mut().enumerate() {
+                for shelf in page_shelves.iter_mut() {
+                    if height <= shelf.height && u32::from(shelf.x) + u32::from(width) <= u32::from(size) {
+                        position = Some((page, shelf.x, shelf.y));
+                        shelf.x += width;
+                        break 'pages;
+                    }
+                }
+
+                let top = page_shelves.last().map(|s| s.y + s.height).unwrap_or(0);
+                if u32::from(top) + u32::from(height) <= u32::from(size) {
+                    page_shelves.push(Shelf { y: top, height, x: width });
+                    position = Some((page, 0, top));
+                    break;
+                }
+            }
+
+            let (page, x, y) = position.unwrap_or_else(|| {
+                let mut page = Image::new(size, size);
+                page.data.iter_mut().for_each(|p| *p = [0, 0, 0, 0]);
+                pages.push(page);
+                shelves.push(vec![Shelf { y: 0, height, x: width }]);
+                (pages.len() - 1, 0, 0)
+            });
+
+            let (x, y) = (x + ATLAS_PADDING, y + ATLAS_PADDING);
+            let target = &mut pages[page];
+            for row in 0..image.height {
+                let source_start = usize::from(row) * usize::from(image.width);
+                let target_start = usize::from(y + row) * usize::from(size) + usize::from(x);
+                target.data[target_start..target_start + usize::from(image.width)]
+                    .copy_from_slice(&image.data[source_start..source_start + usize::from(image.width)]);
+            }
+
+            entries[i] = Some(AtlasEntry {
+                name: name.clone(),
+                page,
+                x,
+                y,
+                width: image.width,
+                height: image.height,
+            });
+        }
+
+        Ok(Atlas {
+            size,
+            pages,
+            entries: entries.into_iter().map(|e| e.unwrap()).collect(),
+        })
+    }
+
+    /// Writes a header defining a texture and a UV macro for every image. `textures` are the
+    /// paths of the pages as they should appear in the config.
+    ///
+    /// UVs are given as `u, v, width, height` relative to the page size.
+    pub fn write_header<O: Write>(&self, output: &mut O, textures: &[String]) -> Result<(), Error> {
+        writeln!(output, "// Generated by armake2, do not edit.")?;
+
+        for entry in &self.entries {
+            let name = macro_name(&entry.name);
+            let size = f32::from(self.size);
+            writeln!(output)?;
+            writeln!(output, "#define {}_TEXTURE \"{}\"", name, textures[entry.page])?;
+            writeln!(output, "#define {}_UV {:.6}, {:.6}, {:.6}, {:.6}", name,
+                f32::from(entry.x) / size, f32::from(entry.y) / size,
+                f32::from(entry.width) / size, f32::from(entry.height) / size)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn macro_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect()
+}
+
+fn read_image(path: &PathBuf) -> Result<Image, Error> {
+    let mut file = File::open(path).prepend_error("Failed to open image:")?;
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+
+    let paa = match extension.as_deref() {
+        Some("paa") | Some("pac") => PAA::read(&mut file)?,
+        Some("dds") => PAA::read_dds(&mut file)?,
+        _ => { return Err(error!("Unsupported image format, expected PAA or DDS.")); }
+    };
+
+    match paa.mipmaps.first() {
+        Some(mipmap) => Ok(mipmap.decode(paa.format)),
+        None => Err(error!("Image contains no mipmaps."))
+    }
+}
+
+/// Packs the given PAA or DDS images into atlas pages of the given size and writes them to the
+/// target folder as `<name>_<page>_ca.paa` together with a `<name>.hpp` containing the macros.
+///
+/// `prefix` is prepended to the page paths in the header, usually the in-game path of the
+/// target folder.
+pub fn cmd_atlas(images: &[String], target: PathBuf, name: &str, size: u16, prefix: &str, force: bool) -> Result<(), Error> {
+    let mut loaded: Vec<(String, Image)> = Vec::with_capacity(images.len());
+    for path in images {
+        let path = PathBuf::from(path);
+        let image = read_image(&path).prepend_error(format!("Failed to read {}:", path.to_string_lossy()))?;
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        loaded.push((format!("{}_{}", name, stem), image));
+    }
+
+    let atlas = Atlas::pack(loaded, size)?;
+
+    std::fs::create_dir_all(&target).prepend_error("Failed to create target folder:")?;
+
+    let header_path = target.join(format!("{}.hpp", name));
+    if !force && header_path.exists() {
+        return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", header_path.to_string_lossy()));
+    }
+
+    let mut textures: Vec<String> = Vec::with_capacity(atlas.pages.len());
+    for (i, page) in atlas.pages.iter().enumerate() {
+        let filename = format!("{}_{}_ca.paa", name, i);
+        let path = target.join(&filename);
+        if !force && path.exists() {
+            return Err(error!("Target file \"{}\" already exists. Use --force to overwrite.", path.to_string_lossy()));
+        }
+
+        let mut paa = PAA::new(PAAType::DXT5);
+        paa.mipmaps.push(MipMap::encode(page, PAAType::DXT5));
+        paa.generate_mipmaps(MipFilter::Default)?;
+        paa.update_taggs();
+
+        let mut file = AtomicFile::create(&path).prepend_error("Failed to open output file:")?;
+        paa.write(&mut file)?;
+        file.commit().prepend_error("Failed to write output file:")?;
+
+        textures.push(if prefix.is_empty() { filename } else { format!("{}\\{}", prefix.trim_end_matches('\\'), filename) });
+    }
+
+    let mut header = AtomicFile::create(&header_path).prepend_error("Failed to open output file:")?;
+    atlas.write_header(&mut header, &textures)?;
+    header.commit().prepend_error("Failed to write output file:")
+}
@@ -0,0 +1,308 @@
+//! Rebuilding an addon whenever the files in its source folder change
+
+use std::collections::{BTreeMap};
+use std::io::{Error};
+use std::path::{Path, PathBuf};
+use std::thread::{sleep};
+use std::time::{Duration, SystemTime};
+
+use crate::error::*;
+use crate::pbo::{absolute_path, list_files};
+
+/// Returns whether changes of the path are ignored: the ignored files and folders, files named
+/// like them with another extension appended, like the signatures of a PBO, and the temporary
+/// files they are written to first (see `AtomicFile`).
+fn is_ignored(ignored: &[PathBuf], path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = name.strip_prefix('.').unwrap_or(&name);
+
+    ignored.iter().any(|i| {
+        let ignored_name = i.file_name().unwrap_or_default().to_string_lossy();
+        path.starts_with(i) || (path.parent() == i.parent() && (name == ignored_name || name.starts_with(&format!("{}.", ignored_name))))
+    })
+}
+
+/// Events of a folder tree reported by inotify, so changes are noticed without listing the
+/// folder again and again.
+#[cfg(target_os = "linux")]
+mod events {
+    use std::collections::{HashMap};
+    use std::ffi::{CString, OsStr};
+    use std::io::{Error, ErrorKind};
+    use std::os::unix::ffi::{OsStrExt};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration};
+
+    const MASK: u32 = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MODIFY | libc::IN_CLOSE_WRITE | libc::IN_ATTRIB |
+        libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_DELETE_SELF | libc::IN_ONLYDIR;
+
+    /// Size of the fixed part of `struct inotify_event`
+    const EVENT_SIZE: usize = 16;
+
+    pub struct Events {
+        fd: i32,
+        /// watched folders by watch descriptor
+        folders: HashMap<i32, PathBuf>,
+    }
+
+    impl Events {
+        pub fn new() -> Result<Events, Error> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(Error::last_os_error());
+            }
+
+            Ok(Events { fd, folders: HashMap::new() })
+        }
+
+        /// Watches the folder and all folders in it that aren't ignored.
+        pub fn watch_tree(&mut self, folder: &Path, ignored: &dyn Fn(&Path) -> bool) -> Result<(), Error> {
+            if ignored(folder) {
+                return Ok(());
+            }
+
+            let path = CString::new(folder.as_os_str().as_bytes()).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+            let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), MASK) };
+            if wd < 0 {
+                return Err(Error::last_os_error());
+            }
+            self.folders.insert(wd, folder.to_path_buf());
+
+            for entry in std::fs::read_dir(folder)? {
+                let entry = entry?;
+                // folders removed in the meantime are simply left out
+                if entry.file_type()?.is_dir() {
+                    match self.watch_tree(&entry.path(), ignored) {
+                        Err(ref e) if e.kind() == ErrorKind::NotFound => {},
+                        result => result?,
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Waits up to `timeout` for events and returns the paths they are about. `None` stands
+        /// for events that were lost because too many happened at once.
+        pub fn wait(&mut self, timeout: Duration) -> Result<Vec<Option<PathBuf>>, Error> {
+            let mut poll = libc::pollfd { fd: self.fd, events: libc::POLLIN, revents: 0 };
+            let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+            if unsafe { libc::poll(&mut poll, 1, timeout) } < 0 {
+                let error = Error::last_os_error();
+                if error.kind() != ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+
+            self.read()
+        }
+
+        /// Returns the paths of all events that happened so far, without waiting.
+        pub fn read(&mut self) -> Result<Vec<Option<PathBuf>>, Error> {
+            let mut paths: Vec<Option<PathBuf>> = Vec::new();
+            let mut buffer = [0u8; 64 * 1024];
+
+            loop {
+                let length = unsafe { libc::read(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+                if length < 0 {
+                    let error = Error::last_os_error();
+                    match error.kind() {
+                        ErrorKind::WouldBlock => { break; },
+                        ErrorKind::Interrupted => { continue; },
+                        _ => { return Err(error); }
+                    }
+                }
+                if length == 0 { break; }
+
+                let mut offset = 0;
+                while offset + EVENT_SIZE <= length as usize {
+                    let field = |i: usize| u32::from_ne_bytes([buffer[offset + i], buffer[offset + i + 1], buffer[offset + i + 2], buffer[offset + i + 3]]);
+                    let (wd, mask, name_length) = (field(0) as i32, field(4), field(12) as usize);
+                    let name = &buffer[offset + EVENT_SIZE..offset + EVENT_SIZE + name_length];
+                    let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name_length)];
+                    offset += EVENT_SIZE + name_length;
+
+                    if mask & libc::IN_Q_OVERFLOW != 0 {
+                        paths.push(None);
+                    } else if mask & libc::IN_IGNORED != 0 {
+                        self.folders.remove(&wd);
+                    } else if let Some(folder) = self.folders.get(&wd) {
+                        paths.push(Some(if name.is_empty() { folder.clone() } else { folder.join(OsStr::from_bytes(name)) }));
+                    }
+                }
+            }
+
+            Ok(paths)
+        }
+    }
+
+    impl Drop for Events {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.fd); }
+        }
+    }
+}
+
+/// Watches a folder for added, removed and modified files. On Linux, changes are reported by
+/// inotify. Elsewhere, or if inotify is not available, the modification times and sizes of the
+/// files are compared every time the folder is checked.
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::watch::Watcher;
+/// let dir = tempfile::tempdir().unwrap();
+/// std::fs::write(dir.path().join("config.cpp"), "class CfgPatches {};").unwrap();
+///
+/// let mut watcher = Watcher::new(dir.path().to_path_buf(), Vec::new()).unwrap();
+/// assert!(!watcher.changed().unwrap());
+///
+/// std::fs::write(dir.path().join("script.sqf"), "true").unwrap();
+/// assert!(watcher.changed().unwrap());
+/// assert!(!watcher.changed().unwrap());
+/// ```
+pub struct Watcher {
+    pub folder: PathBuf,
+    /// files and folders whose changes are ignored, like the built PBO if it is in the folder.
+    /// Files named like an ignored file with another extension appended, like the signatures of
+    /// a PBO, are ignored as well.
+    pub ignored: Vec<PathBuf>,
+    files: BTreeMap<PathBuf, (Option<SystemTime>, u64)>,
+    #[cfg(target_os = "linux")]
+    events: Option<events::Events>,
+}
+
+impl Watcher {
+    /// Creates a watcher for the folder, taking the current state of its files as unchanged.
+    pub fn new(folder: PathBuf, ignored: Vec<PathBuf>) -> Result<Watcher, Error> {
+        let mut watcher = Watcher {
+            folder,
+            ignored,
+            files: BTreeMap::new(),
+            #[cfg(target_os = "linux")]
+            events: None,
+        };
+
+        #[cfg(target_os = "linux")]
+        {
+            let ignored = &watcher.ignored;
+            let watched = events::Events::new().and_then(|mut events| {
+                events.watch_tree(&watcher.folder, &|path| is_ignored(ignored, path)).map(|_| events)
+            });
+            match watched {
+                Ok(events) => {
+                    watcher.events = Some(events);
+                    return Ok(watcher);
+                },
+                Err(e) => {
+                    warning(format!("Failed to watch {} for changes, checking it every interval instead: {}", watcher.folder.display(), e), Some("watch-polling"), (None, None));
+                }
+            }
+        }
+
+        watcher.files = watcher.snapshot()?;
+
+        Ok(watcher)
+    }
+
+    fn snapshot(&self) -> Result<BTreeMap<PathBuf, (Option<SystemTime>, u64)>, Error> {
+        let mut files: BTreeMap<PathBuf, (Option<SystemTime>, u64)> = BTreeMap::new();
+        for path in list_files(&self.folder)? {
+            if is_ignored(&self.ignored, &path) { continue; }
+
+            // files removed while listing are simply left out
+            if let Ok(metadata) = path.metadata() {
+                files.insert(path, (metadata.modified().ok(), metadata.len()));
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Returns whether any of the paths of events is not ignored, watching new folders among
+    /// them. After lost events, the whole folder is watched again.
+    #[cfg(target_os = "linux")]
+    fn handle_events(&mut self, paths: Vec<Option<PathBuf>>) -> Result<bool, Error> {
+        let (ignored, folder) = (&self.ignored, &self.folder);
+        let events = self.events.as_mut().unwrap();
+        let mut changed = false;
+
+        for path in paths {
+            match path {
+                Some(path) if is_ignored(ignored, &path) => {},
+                Some(path) => {
+                    if path != *folder && path.is_dir() {
+                        let _ = events.watch_tree(&path, &|p| is_ignored(ignored, p));
+                    }
+                    changed = true;
+                },
+                None => {
+                    events.watch_tree(folder, &|p| is_ignored(ignored, p))?;
+                    changed = true;
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Returns true if files were added, removed or modified since the last call.
+    pub fn changed(&mut self) -> Result<bool, Error> {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(ref mut events) = self.events {
+                let paths = events.read()?;
+                return self.handle_events(paths);
+            }
+        }
+
+        let files = self.snapshot()?;
+        let changed = files != self.files;
+        self.files = files;
+
+        Ok(changed)
+    }
+
+    /// Waits up to `timeout` and returns true if files were added, removed or modified since the
+    /// last call. With inotify, this returns as soon as a file changes.
+    pub fn wait(&mut self, timeout: Duration) -> Result<bool, Error> {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(ref mut events) = self.events {
+                let paths = events.wait(timeout)?;
+                return self.handle_events(paths);
+            }
+        }
+
+        sleep(timeout);
+        self.changed()
+    }
+}
+
+/// Runs `build` for the folder and again whenever files in it change, until the process is
+/// terminated. A build only starts once the files didn't change for one `interval`, so saving
+/// several files at once causes a single build. Without inotify, the folder is checked for
+/// changes every `interval`.
+///
+/// Failed builds are printed and don't stop watching. Changes to `ignored` files, like the output
+/// of `build`, don't cause builds.
+pub fn cmd_watch<F: FnMut() -> Result<(), Error>>(folder: &Path, ignored: Vec<PathBuf>, interval: Duration, mut build: F) -> Result<(), Error> {
+    if !folder.is_dir() {
+        return Err(error!("Source folder {} doesn't exist.", folder.display()));
+    }
+
+    // the watched files have absolute paths, ignored files may not exist yet
+    let ignored: Vec<PathBuf> = ignored.iter().map(|p| absolute_path(p).unwrap_or_else(|| p.clone())).collect();
+    let mut watcher = Watcher::new(folder.canonicalize()?, ignored)?;
+
+    loop {
+        let result = build();
+        if result.is_ok() {
+            println!("Build finished, watching {} for changes.", folder.display());
+        }
+        result.print_error(false);
+
+        while !watcher.wait(interval)? {}
+        while watcher.wait(interval)? {}
+    }
+}
@@ -0,0 +1,51 @@
+use std::fs::{create_dir_all, write};
+
+use armake2::archive::*;
+use armake2::pbo::PBO;
+use armake2::sign::*;
+
+#[test]
+fn test_directory_archive() {
+    let dir = tempfile::tempdir().unwrap();
+    create_dir_all(dir.path().join("functions")).unwrap();
+    write(dir.path().join("$PBOPREFIX$"), "x\\test\\addons\\main\n").unwrap();
+    write(dir.path().join("config.cpp"), "class CfgPatches {};").unwrap();
+    write(dir.path().join("functions").join("fn_test.sqf"), "true").unwrap();
+
+    let mut directory = open_archive(dir.path()).unwrap();
+    assert_eq!(vec!["config.cpp", "functions\\fn_test.sqf"], directory.entries().unwrap());
+    assert_eq!(Some("x\\test\\addons\\main"), directory.metadata().unwrap().get("prefix").map(|s| s.as_str()));
+
+    directory.write_entry("data\\script.sqf", b"false").unwrap();
+    assert_eq!(b"false".to_vec(), std::fs::read(dir.path().join("data").join("script.sqf")).unwrap());
+    directory.remove_entry("config.cpp").unwrap();
+    assert_eq!(None, directory.read_entry("config.cpp").unwrap());
+
+    // entries can't name files outside of the folder
+    for name in ["..\\outside.sqf", "data\\..\\..\\outside.sqf", "/tmp/outside.sqf", "\\outside.sqf", "C:\\outside.sqf"] {
+        assert!(directory.write_entry(name, b"true").is_err(), "{}", name);
+        assert!(directory.read_entry(name).is_err(), "{}", name);
+        assert!(directory.remove_entry(name).is_err(), "{}", name);
+    }
+    assert!(!dir.path().parent().unwrap().join("outside.sqf").exists());
+}
+
+#[test]
+fn test_archive_hashes() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path().join("B.sqf"), "b").unwrap();
+    write(dir.path().join("a.hpp"), "a").unwrap();
+    write(dir.path().join("empty.sqf"), "").unwrap();
+
+    let pbo = PBO::from_directory(dir.path().to_path_buf(), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    let mut pbo = PBO::read(&mut pbo.to_cursor().unwrap()).unwrap();
+    let directory = Directory::new(dir.path().to_path_buf());
+
+    // hashes of packed and unpacked content are the same
+    assert_eq!(&*namehash(&pbo).unwrap(), &*namehash(&directory).unwrap());
+    assert_eq!(&*filehash(&pbo, BISignVersion::V3).unwrap(), &*filehash(&directory, BISignVersion::V3).unwrap());
+
+    pbo.write_entry("a.hpp", b"changed").unwrap();
+    assert!(pbo.checksum.is_none());
+    assert_ne!(&*filehash(&pbo, BISignVersion::V3).unwrap(), &*filehash(&directory, BISignVersion::V3).unwrap());
+}
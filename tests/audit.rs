@@ -0,0 +1,55 @@
+use std::fs::{create_dir_all, rename, write};
+
+use armake2::audit::*;
+use armake2::pbo::{ContainerType, FileType};
+use armake2::run::*;
+
+#[test]
+fn test_audit() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    let addons = dir.path().join("@abc").join("addons");
+    let keys = dir.path().join("@abc").join("keys");
+    create_dir_all(&source).unwrap();
+    create_dir_all(&addons).unwrap();
+    create_dir_all(&keys).unwrap();
+    write(source.join("config.cpp"), "class CfgPatches { class abc_main { units[] = {}; }; };").unwrap();
+    write(source.join("script.sqf"), "hint \"hello\";").unwrap();
+
+    let pbo = addons.join("abc_main.pbo");
+    let key = dir.path().join("abc");
+    let response = dir.path().join("audit.txt");
+    write(&response, format!("\
+build -e hemtt=1.5.0 \"{src}\" \"{pbo}\"
+keygen \"{key}\"
+sign \"{key}.biprivatekey\" \"{pbo}\"
+", src = source.display(), pbo = pbo.display(), key = key.display())).unwrap();
+    run_response_file(&response).unwrap();
+
+    // without the key of the mod the signature can't be checked
+    let report = audit(&pbo).unwrap();
+    assert_eq!(ContainerType::PBO, report.container);
+    assert_eq!(vec!["HEMTT 1.5.0"], report.tools);
+    assert_eq!(2, report.files);
+    assert_eq!(vec![(FileType::Config, 1)], report.binarized);
+    assert_eq!(Some(true), report.checksum);
+    assert_eq!(1, report.signatures.len());
+    assert_eq!(Some("abc".to_string()), report.signatures[0].authority);
+    assert_eq!(3, report.signatures[0].version);
+    assert_eq!(None, report.signatures[0].valid);
+
+    rename(dir.path().join("abc.bikey"), keys.join("abc.bikey")).unwrap();
+    let report = audit(&pbo).unwrap();
+    assert_eq!(Some(keys.join("abc.bikey")), report.signatures[0].key);
+    assert_eq!(Some(true), report.signatures[0].valid);
+
+    // a PBO changed after signing fails its signature
+    write(&response, format!("pack -f \"{src}\" \"{pbo}\"\n", src = source.display(), pbo = pbo.display())).unwrap();
+    run_response_file(&response).unwrap();
+    let report = audit(&pbo).unwrap();
+    assert!(report.tools.is_empty());
+    assert!(report.binarized.is_empty());
+    assert_eq!(Some(false), report.signatures[0].valid);
+
+    cmd_audit(pbo).unwrap();
+}
@@ -0,0 +1,41 @@
+use std::path::{Path};
+
+use armake2::binarize::*;
+
+#[test]
+fn test_parse_log() {
+    let log = "\
+Binarize Version 1.0
+Warning: No entry 'bin\\config.bin/CfgModels.model'.
+Cannot open object a3\\structures_f\\missing.p3d
+Cannot open object a3\\structures_f\\missing.p3d
+Error: Cannot create output folder
+some unrelated progress line
+";
+    let diagnostics = parse_log(log, Path::new("addon/model.p3d"));
+
+    assert_eq!(3, diagnostics.len());
+    assert_eq!((false, "binarize"), (diagnostics[0].error, diagnostics[0].name));
+    assert_eq!("No entry 'bin\\config.bin/CfgModels.model'.", diagnostics[0].message);
+    assert_eq!("Referenced file a3\\structures_f\\missing.p3d can't be loaded.", diagnostics[1].message);
+    assert_eq!("binarize-missing-file", diagnostics[1].name);
+    assert!(diagnostics[2].error);
+    assert!(diagnostics.iter().all(|d| d.file == "addon/model.p3d" && d.line.is_none()));
+}
+
+#[test]
+fn test_dependencies() {
+    let dir = tempfile::tempdir().unwrap();
+    let folder = dir.path().join("addon").join("data");
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(dir.path().join("addon").join("model.cfg"), "#include \"skeletons.hpp\"\nclass CfgModels {};\n").unwrap();
+    std::fs::write(dir.path().join("addon").join("skeletons.hpp"), "class CfgSkeletons {};\n").unwrap();
+    std::fs::write(folder.join("model.cfg"), "class CfgModels {};\n").unwrap();
+    let model = folder.join("model.p3d");
+
+    assert_eq!(vec![folder.join("model.cfg"), dir.path().join("addon").join("model.cfg")], model_configs(&model));
+
+    let dependencies = dependencies(&model, &[]);
+    assert_eq!(3, dependencies.len());
+    assert!(dependencies.iter().any(|d| d.ends_with("skeletons.hpp")));
+}
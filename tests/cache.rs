@@ -0,0 +1,108 @@
+use std::collections::{HashMap};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener};
+use std::thread;
+
+use armake2::cache::*;
+
+#[test]
+fn test_local_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    init_cache(Some(open_cache(dir.path().to_str().unwrap()).unwrap()));
+
+    let key = cache_key("rapify", &[b"foo = 1;"]);
+    assert_ne!(key, cache_key("rapify", &[b"foo = 2;"]));
+    assert_ne!(key, cache_key("rapify", &[b"foo = ", b"1;"]));
+
+    let mut computed = 0;
+    for _i in 0..2 {
+        let data = cached(&key, || { computed += 1; Ok(b"result".to_vec()) }).unwrap();
+        assert_eq!(b"result", &data[..]);
+    }
+    assert_eq!(1, computed);
+    assert!(dir.path().join(&key).exists());
+
+    // results depending on includes are computed again when an include changes
+    let include = dir.path().join("macros.hpp");
+    std::fs::write(&include, "#define FOO 1").unwrap();
+    let key = cache_key("config", &[b"foo = FOO;"]);
+    let mut computed = 0;
+    for change in &[false, false, true, false] {
+        if *change {
+            std::fs::write(&include, "#define FOO 2").unwrap();
+        }
+        let data = cached_with_includes(&key, || { computed += 1; Ok((b"result".to_vec(), vec![include.clone()])) }).unwrap();
+        assert_eq!(b"result", &data[..]);
+    }
+    assert_eq!(2, computed);
+
+    init_cache(None);
+}
+
+#[cfg(feature = "remote-cache")]
+#[test]
+fn test_http_cache() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let server = thread::spawn(move || {
+        let mut stored: HashMap<String, Vec<u8>> = HashMap::new();
+        for _i in 0..9 {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let parts: Vec<String> = request_line.split_whitespace().map(|s| s.to_string()).collect();
+
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() { break; }
+                if line.to_lowercase().starts_with("content-length:") {
+                    length = line[15..].trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = reader.into_inner();
+            let response: Vec<u8> = match (parts[0].as_str(), stored.get(&parts[1])) {
+                ("GET", Some(data)) if parts[1].ends_with("chunked") => {
+                    let (first, second) = data.split_at(3);
+                    [format!("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x};ext=1\r\n", first.len()).into_bytes(), first.to_vec(),
+                        format!("\r\n{:x}\r\n", second.len()).into_bytes(), second.to_vec(), b"\r\n0\r\nExpires: 0\r\n\r\n".to_vec()].concat()
+                },
+                ("GET", _) if parts[1].ends_with("huge") => b"HTTP/1.1 200 OK\r\nContent-Length: 18446744073709551615\r\n\r\ndata".to_vec(),
+                ("GET", _) if parts[1].ends_with("huge-chunk") => b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffff\r\ndata".to_vec(),
+                ("GET", _) if parts[1].ends_with("short") => b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\ndata".to_vec(),
+                ("GET", _) if parts[1].ends_with("gzip") => b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\nContent-Length: 4\r\n\r\n\x1f\x8b\x08\x00".to_vec(),
+                ("GET", Some(data)) => [format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", data.len()).into_bytes(), data.clone()].concat(),
+                ("GET", None) => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                _ => {
+                    stored.insert(parts[1].clone(), body);
+                    b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n".to_vec()
+                }
+            };
+            stream.write_all(&response).unwrap();
+        }
+    });
+
+    let cache = HttpCache::new(&format!("http://127.0.0.1:{}/armake2/", port)).unwrap();
+    assert_eq!(None, cache.get("key").unwrap());
+    cache.put("key", b"rapified").unwrap();
+    assert_eq!(Some(b"rapified".to_vec()), cache.get("key").unwrap());
+
+    // chunked responses are decoded, other transfer encodings are rejected
+    cache.put("chunked", b"rapified").unwrap();
+    assert_eq!(Some(b"rapified".to_vec()), cache.get("chunked").unwrap());
+    assert!(cache.get("gzip").is_err());
+
+    // sizes the server claims are not allocated up front
+    assert!(cache.get("huge").is_err());
+    assert!(cache.get("huge-chunk").is_err());
+    assert!(cache.get("short").is_err());
+
+    server.join().unwrap();
+}
@@ -0,0 +1,72 @@
+use std::io::Cursor;
+
+use armake2::pbo::*;
+use armake2::run::*;
+
+// "Cprs"
+const PACKING_COMPRESSED: u32 = 0x4370_7273;
+
+#[test]
+fn test_compressed_pbo() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("functions")).unwrap();
+    let script = "params [\"_unit\"];\n".repeat(200);
+    std::fs::write(source.join("functions").join("fn_init.sqf"), &script).unwrap();
+    std::fs::write(source.join("texture.paa"), vec![7u8; 1000]).unwrap();
+    std::fs::write(source.join("short.hpp"), "#define X").unwrap();
+    std::fs::write(source.join("$PBOPREFIX$"), "x\\abc\\main").unwrap();
+
+    let pbo = PBO::from_directory(source.clone(), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    let plain = pbo.to_cursor().unwrap().into_inner();
+
+    init_compression(Some(COMPRESSED_EXTENSIONS.iter().map(|e| e.to_string()).collect()));
+    assert!(compresses("functions\\fn_init.SQF"));
+    assert!(!compresses("texture.paa"));
+    let packed = pbo.to_cursor().unwrap().into_inner();
+    assert!(packed.len() < plain.len());
+
+    // only files that get smaller are compressed
+    let mut reader = PBOReader::new(Cursor::new(&packed)).unwrap();
+    let compressed: Vec<&str> = reader.entries.iter().filter(|e| e.is_compressed()).map(|e| e.name.as_str()).collect();
+    assert_eq!(vec!["functions\\fn_init.sqf"], compressed);
+    let index = reader.entries.iter().position(|e| e.is_compressed()).unwrap();
+    assert_eq!(script.len() as u32, reader.entries[index].original_size);
+    assert_eq!(script.as_bytes(), &reader.read_entry_at(index).unwrap()[..]);
+    assert_eq!(script.len() as u64 + 1009, reader.data_size());
+
+    // compressed files are read decompressed and written as they were read
+    let reread = PBO::read(&mut Cursor::new(&packed)).unwrap();
+    assert_eq!(script.as_bytes(), &reread.files["functions\\fn_init.sqf"].get_ref()[..]);
+    init_compression(None);
+    assert_eq!(packed, reread.to_cursor().unwrap().into_inner());
+    assert!(roundtrip_differences(&packed).unwrap().is_empty());
+
+    // files are signed as they were read, changed ones with their new content
+    init_compression(Some(vec!["paa".to_string()]));
+    assert_eq!(PACKING_COMPRESSED, reread.stored_data("texture.paa").unwrap().0);
+    assert_eq!((0, &[7u8; 1000][..]), reread.read_data("texture.paa").unwrap());
+    init_compression(None);
+    let mut changed = PBO::read(&mut Cursor::new(&packed)).unwrap();
+    assert_eq!(PACKING_COMPRESSED, changed.read_data("functions\\fn_init.sqf").unwrap().0);
+    *changed.files.get_mut("functions\\fn_init.sqf").unwrap() = Cursor::new(b"hint \"x\";".to_vec().into_boxed_slice());
+    assert_eq!((0, &b"hint \"x\";"[..]), changed.read_data("functions\\fn_init.sqf").unwrap());
+
+    // unpacking, signing and packing with the command line
+    let target = dir.path().join("main.pbo");
+    let unpacked = dir.path().join("unpacked");
+    let key = dir.path().join("abc");
+    let response = dir.path().join("commands.txt");
+    std::fs::write(&response, format!("\
+pack --compress-ext sqf \"{src}\" \"{pbo}\"
+keygen \"{key}\"
+sign \"{key}.biprivatekey\" \"{pbo}\"
+verify \"{key}.bikey\" \"{pbo}\"
+unpack \"{pbo}\" \"{unpacked}\"
+", src = source.display(), pbo = target.display(), key = key.display(), unpacked = unpacked.display())).unwrap();
+    run_response_file(&response).unwrap();
+
+    let written = std::fs::read(&target).unwrap();
+    assert_eq!(1, PBOReader::new(Cursor::new(&written)).unwrap().entries.iter().filter(|e| e.is_compressed()).count());
+    assert_eq!(script, std::fs::read_to_string(unpacked.join("functions").join("fn_init.sqf")).unwrap());
+}
@@ -46,3 +46,398 @@ class CfgPatches {
     };
 };", output.trim());
 }
+
+#[test]
+fn config_optimize() {
+    let input = String::from("\
+class CfgVehicles {
+    class Car;
+    class MyCar: Car {
+        displayName = \" \";
+        maxSpeed = 100;
+        maxSpeed = 120;
+        hiddenSelections[] = {\"a\"};
+        hiddenSelections[] += {\"b\"};
+    };
+    class Other {
+        description = \"   \";
+        sounds[] = {};
+        sounds[] = {\"horn\"};
+    };
+};");
+    let mut config = Config::from_string(input, None, &Vec::new()).unwrap();
+    let before = config.rapified_length();
+
+    assert_eq!(3, config.optimize());
+    assert!(config.rapified_length() < before);
+    assert_eq!(config.rapified_length(), config.to_cursor().unwrap().into_inner().len());
+
+    assert_eq!("class CfgVehicles {
+    class Car;
+    class MyCar: Car {
+        displayName = \" \";
+        maxSpeed = 120;
+        hiddenSelections[] = {\"a\"};
+        hiddenSelections[] += {\"b\"};
+    };
+    class Other {
+        sounds[] = {\"horn\"};
+    };
+};", config.to_string().unwrap().trim());
+}
+
+#[test]
+fn config_compressed() {
+    let input = String::from("class CfgPatches { class test { units[] = {}; weapons[] = {}; requiredAddons[] = {\"test_main\", \"test_common\", \"test_main\"}; }; };");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    let mut compressed: Vec<u8> = Vec::new();
+    config.write_rapified_compressed(&mut compressed).unwrap();
+    assert_ne!(b"\0raP", &compressed[..4]);
+    assert!(compressed.len() < config.rapified_length());
+
+    let reread = Config::read_rapified(&mut Cursor::new(compressed)).unwrap();
+    assert_eq!(config.to_string().unwrap(), reread.to_string().unwrap());
+
+    assert!(Config::read_rapified(&mut Cursor::new(b"class foo {};".to_vec())).is_err());
+}
+
+#[test]
+fn config_strict() {
+    let strict = |input: &str| {
+        let (preprocessed, info) = armake2::preprocess::preprocess(input.to_string(), None, &Vec::new()).unwrap();
+        Config::from_preprocessed_strict(&preprocessed, &info).map_err(|e| e.to_string())
+    };
+
+    assert!(strict("class A { x = 1; y[] = {\"a\"}; y[] += {\"b\"}; class B; class B {}; };").is_ok());
+
+    let error = strict("class A {\n    x = 1;\n    x = 2;\n    name = foo bar;\n    class B { y = 3 };\n};").unwrap_err();
+    assert!(error.starts_with("3 problems in strict mode:"), "{}", error);
+    assert!(error.contains(":4: String value \"foo bar\" is not quoted properly. [unquoted-string]"), "{}", error);
+    assert!(error.contains(":5: Missing semicolon before \"}\". [missing-semicolon]"), "{}", error);
+    assert!(error.contains("A.x is defined multiple times."), "{}", error);
+}
+
+#[test]
+fn config_pragma() {
+    let input = String::from("#pragma once\nclass A {\n#pragma armake2 mute unquoted-string\n    x = foo;\n    #pragma vendor option\n};\n");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    assert_eq!("class A {\n    x = \"foo\";\n};\n", config.to_string().unwrap());
+}
+
+#[test]
+fn config_recover() {
+    let input = "\
+class CfgVehicles {
+    class Car {
+        maxSpeed = ;
+        class Wheels { count = 4; ; };
+    };
+    class Truck: Car {
+        hiddenSelections[] = {\"a\", };
+        displayName = \"Truck\";
+    };
+    = 1;
+};
+";
+    let (preprocessed, info) = armake2::preprocess::preprocess(input.to_string(), None, &Vec::new()).unwrap();
+
+    let error = Config::from_preprocessed(&preprocessed, &info).unwrap_err().to_string();
+    assert!(!error.contains("syntax errors"));
+
+    let error = Config::from_preprocessed_recover(&preprocessed, &info).unwrap_err().to_string();
+    assert!(error.starts_with("3 syntax errors:"), "{}", error);
+    assert!(error.contains(":3: Invalid entry \"maxSpeed = ;\""), "{}", error);
+    assert!(error.contains(":10: Invalid entry \"= 1;\""), "{}", error);
+
+    let valid = "class A { x = 1; };";
+    let (preprocessed, info) = armake2::preprocess::preprocess(valid.to_string(), None, &Vec::new()).unwrap();
+    assert!(Config::from_preprocessed_recover(&preprocessed, &info).is_ok());
+}
+
+#[test]
+fn config_tokenize() {
+    let input = "#define X(a) \\\n    a\n// comment\nclass A { delete B; x = -0x1F; y = \"a \"\"b\"\"\"; z = 1e; w = 3a; /* done */ };";
+    let tokens = tokenize(input);
+
+    assert_eq!(input, tokens.iter().map(|t| t.text(input)).collect::<String>());
+
+    let significant: Vec<(TokenKind, &str)> = tokens.iter()
+        .filter(|t| t.kind != TokenKind::Whitespace)
+        .map(|t| (t.kind, t.text(input)))
+        .collect();
+    assert_eq!(vec![
+        (TokenKind::Directive, "#define X(a) \\\n    a"),
+        (TokenKind::Comment, "// comment"),
+        (TokenKind::Keyword, "class"), (TokenKind::Name, "A"), (TokenKind::Punctuation, "{"),
+        (TokenKind::Keyword, "delete"), (TokenKind::Name, "B"), (TokenKind::Punctuation, ";"),
+        (TokenKind::Name, "x"), (TokenKind::Punctuation, "="), (TokenKind::Integer, "-0x1F"), (TokenKind::Punctuation, ";"),
+        (TokenKind::Name, "y"), (TokenKind::Punctuation, "="), (TokenKind::String, "\"a \"\"b\"\"\""), (TokenKind::Punctuation, ";"),
+        (TokenKind::Name, "z"), (TokenKind::Punctuation, "="), (TokenKind::Name, "1e"), (TokenKind::Punctuation, ";"),
+        (TokenKind::Name, "w"), (TokenKind::Punctuation, "="), (TokenKind::Name, "3a"), (TokenKind::Punctuation, ";"),
+        (TokenKind::Comment, "/* done */"),
+        (TokenKind::Punctuation, "}"), (TokenKind::Punctuation, ";"),
+    ], significant);
+}
+
+#[test]
+fn config_spans() {
+    let input = String::from("class A {\n    x = 1;\n    y[] = {\"a\", 2};\n    class B: A { z = 3 };\n};\n");
+    let (preprocessed, info) = armake2::preprocess::preprocess(input, Some(std::path::PathBuf::from("config.cpp")), &Vec::new()).unwrap();
+    let config = Config::from_preprocessed(&preprocessed, &info).unwrap();
+
+    let text = |path: &str| &preprocessed[config.span(path).unwrap().range.clone()];
+    assert_eq!("x = 1;", text("A.x"));
+    assert_eq!("y[] = {\"a\", 2};", text("A.y"));
+    assert_eq!("\"a\"", text("A.y[0]"));
+    assert_eq!("class B: A { z = 3 };", text("A.B"));
+    assert_eq!("z = 3 ", text("A.B.z"));
+
+    let span = config.span("a.b.z").unwrap();
+    assert_eq!(Some(std::path::Path::new("config.cpp")), span.file.as_deref());
+    assert_eq!(4, span.line);
+    // spans in the same file share its path
+    assert!(std::sync::Arc::ptr_eq(span.file.as_ref().unwrap(), config.span("A.x").unwrap().file.as_ref().unwrap()));
+
+    let rapified = Config::read_rapified(&mut Cursor::new(config.to_cursor().unwrap().into_inner())).unwrap();
+    assert!(rapified.spans().is_empty());
+}
+
+#[test]
+fn config_functions() {
+    let input = String::from("
+        class CfgFunctions {
+            class abc {
+                class common {
+                    file = \"\\x\\abc\\addons\\main\\functions\\\";
+                    class init {};
+                    class tick { ext = \".fsm\"; };
+                };
+                class debug {
+                    class log { file = \"x\\abc\\addons\\main\\log.sqf\"; };
+                };
+            };
+            class Other {
+                tag = \"ABC\";
+                class misc {
+                    class init {};
+                };
+            };
+        };");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    let functions = config.functions();
+    let paths: Vec<(String, &str)> = functions.iter().map(|f| (f.full_name(), f.path.as_str())).collect();
+    assert_eq!(vec![
+        ("abc_fnc_init".to_string(), "\\x\\abc\\addons\\main\\functions\\fn_init.sqf"),
+        ("abc_fnc_tick".to_string(), "\\x\\abc\\addons\\main\\functions\\fn_tick.fsm"),
+        ("abc_fnc_log".to_string(), "x\\abc\\addons\\main\\log.sqf"),
+        ("ABC_fnc_init".to_string(), "functions\\misc\\fn_init.sqf"),
+    ], paths);
+
+    let files = vec!["functions\\fn_init.sqf".to_string(), "functions\\FN_TICK.fsm".to_string()];
+    let problems = armake2::lint::check_functions(&functions, "\\x\\abc\\addons\\main\\", &files);
+    assert_eq!(vec![
+        ("File x\\abc\\addons\\main\\log.sqf of function abc_fnc_log doesn't exist.".to_string(), "function-missing-file"),
+        ("Tag ABC is also written as abc.".to_string(), "function-tag"),
+        ("Function ABC_fnc_init is declared more than once.".to_string(), "function-duplicate"),
+    ], problems);
+}
+
+#[test]
+fn config_derapify_formats() {
+    let input = String::from("class Base; class A: Base { delete B; text = \"a \"\"quoted\"\"\\nline\"; f = 1.5; x[] += {1, {}}; };");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    let mut json: Vec<u8> = Vec::new();
+    config.write_json(&mut json).unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(serde_json::json!(true), value["Base"]["$external"]);
+    assert_eq!(serde_json::json!({"$delete": true}), value["A"]["B"]);
+    assert_eq!(serde_json::json!("a \"quoted\"\\nline"), value["A"]["text"]);
+    assert_eq!(serde_json::json!(1.5), value["A"]["f"]);
+    assert_eq!(serde_json::json!([1, []]), value["A"]["x+="]);
+
+    let mut yaml: Vec<u8> = Vec::new();
+    config.write_yaml(&mut yaml).unwrap();
+    assert_eq!("\
+Base:
+  \"$external\": true
+A:
+  \"$parent\": \"Base\"
+  B:
+    \"$delete\": true
+  text: \"a \\\"quoted\\\"\\\\nline\"
+  f: 1.5
+  \"x+=\":
+    - 1
+    - []
+", String::from_utf8(yaml).unwrap());
+
+    let mut rapified = Cursor::new(config.to_cursor().unwrap().into_inner().into_vec());
+    let mut derapified: Vec<u8> = Vec::new();
+    cmd_derapify(&mut rapified, &mut derapified, "json").unwrap();
+    assert_eq!(serde_json::json!(1.5), serde_json::from_slice::<serde_json::Value>(&derapified).unwrap()["A"]["f"]);
+
+    rapified.seek(SeekFrom::Start(0)).unwrap();
+    assert!(cmd_derapify(&mut rapified, &mut Vec::new(), "xml").is_err());
+}
+
+#[test]
+fn config_json_roundtrip() {
+    let input = String::from("class Base; class A: Base { delete B; text = \"x\"; f = 1.5; g = 2.0; i = 3; x[] += {1, {0.25, \"y\"}}; class C {}; };");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    let mut json: Vec<u8> = Vec::new();
+    config.write_json(&mut json).unwrap();
+    let reread = Config::read_json(&mut Cursor::new(json.clone())).unwrap();
+    assert_eq!(config.to_string().unwrap(), reread.to_string().unwrap());
+
+    let mut rapified: Vec<u8> = Vec::new();
+    let options = RapifyOptions { json: true, ..RapifyOptions::default() };
+    cmd_rapify(&mut Cursor::new(json), &mut rapified, None, &Vec::new(), &Vec::new(), &options).unwrap();
+    assert_eq!(config.to_cursor().unwrap().into_inner().to_vec(), rapified);
+
+    assert!(Config::read_json(&mut Cursor::new("[1, 2]")).is_err());
+    assert!(Config::read_json(&mut Cursor::new("{\"x+=\": 1}")).is_err());
+    assert!(Config::read_json(&mut Cursor::new("{\"x\": [{}]}")).is_err());
+}
+
+#[test]
+fn config_get() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("config.cpp");
+    std::fs::write(&source, "#define VERSION 1.5\nclass CfgPatches { class my_addon { units[] = {\"a\", 1}; requiredVersion = VERSION; author = \"me\"; }; class Ext; };\nclass Empty: CfgPatches {};\n").unwrap();
+
+    let get = |source: &std::path::Path, path: &str, format: &str, full: bool| -> String {
+        let mut output: Vec<u8> = Vec::new();
+        cmd_config_get(&mut output, source.to_path_buf(), &Vec::new(), path, format, full).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    assert_eq!("1.5\n", get(&source, "CfgPatches/my_addon/requiredVersion", "cpp", false));
+    assert_eq!("me\n", get(&source, "cfgpatches.MY_ADDON.author", "cpp", false));
+    assert_eq!("{\"a\", 1}\n", get(&source, "CfgPatches/my_addon/units", "cpp", false));
+    assert_eq!("units\nrequiredVersion\nauthor\n", get(&source, "CfgPatches/my_addon", "cpp", false));
+    assert_eq!("class Empty: CfgPatches {};\n", get(&source, "Empty", "cpp", true));
+    assert_eq!("class CfgPatches {\n    class my_addon {\n        units[] = {\"a\", 1};\n        requiredVersion = 1.5;\n        author = \"me\";\n    };\n    class Ext;\n};\n",
+        get(&source, "CfgPatches", "cpp", true));
+
+    assert_eq!("[\"a\",1]\n", get(&source, "CfgPatches/my_addon/units", "json", false));
+    assert_eq!("[\"my_addon\",\"Ext\"]\n", get(&source, "CfgPatches", "json", false));
+    assert_eq!("{\"$parent\":\"CfgPatches\"}\n", get(&source, "Empty", "json", true));
+    assert_eq!("- \"a\"\n- 1\n", get(&source, "CfgPatches/my_addon/units", "yaml", false));
+
+    // rapified configs give the same results
+    let rapified = dir.path().join("config.bin");
+    let config = Config::from_string(std::fs::read_to_string(&source).unwrap(), None, &Vec::new()).unwrap();
+    config.write_rapified(&mut std::fs::File::create(&rapified).unwrap()).unwrap();
+    assert_eq!(get(&source, "CfgPatches", "yaml", true), get(&rapified, "CfgPatches", "yaml", true));
+
+    assert!(cmd_config_get(&mut Vec::new(), source.clone(), &Vec::new(), "CfgPatches/other_addon", "cpp", false).is_err());
+    assert!(cmd_config_get(&mut Vec::new(), source.clone(), &Vec::new(), "CfgPatches/my_addon/author/x", "cpp", false).is_err());
+    assert!(cmd_config_get(&mut Vec::new(), source, &Vec::new(), "CfgPatches", "xml", false).is_err());
+}
+
+#[test]
+fn config_derapify_large_class() {
+    // classes with many entries are written in parallel, the order has to be kept
+    let expected: String = (0..10000).map(|i| format!("class Item{} {{\n    id = {};\n    name = \"a \"\"b\"\" {}\";\n}};\n", i, i, i)).collect();
+    let config = Config::from_string(expected.clone(), None, &Vec::new()).unwrap();
+
+    let rapified = config.to_cursor().unwrap().into_inner();
+    let mut derapified: Vec<u8> = Vec::new();
+    cmd_derapify(&mut Cursor::new(rapified), &mut derapified, "cpp").unwrap();
+    assert_eq!(expected, String::from_utf8(derapified).unwrap());
+}
+
+fn read_cstring(data: &[u8], offset: &mut usize) -> String {
+    let end = *offset + data[*offset..].iter().position(|b| *b == 0).unwrap();
+    let s = String::from_utf8(data[*offset..end].to_vec()).unwrap();
+    *offset = end + 1;
+    s
+}
+
+fn read_compressed_int(data: &[u8], offset: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let b = data[*offset];
+        *offset += 1;
+        value |= ((b & 0x7f) as usize) << shift;
+        if b & 0x80 == 0 { return value; }
+        shift += 7;
+    }
+}
+
+/// Lists the classes and properties of the rapified class body at the offset as
+/// `path:parent` and `path=value`, following the offsets of the classes in it.
+fn walk_class(data: &[u8], mut offset: usize, path: &str, output: &mut Vec<String>) {
+    let parent = read_cstring(data, &mut offset);
+    output.push(format!("{}:{}", path, parent));
+
+    for _ in 0..read_compressed_int(data, &mut offset) {
+        let kind = data[offset];
+        offset += 1;
+        match kind {
+            0 => {
+                let name = read_cstring(data, &mut offset);
+                let body = u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+                offset += 4;
+                walk_class(data, body, &format!("{}/{}", path, name), output);
+            },
+            1 => {
+                let subtype = data[offset];
+                offset += 1;
+                let name = read_cstring(data, &mut offset);
+                let value = match subtype {
+                    0 => read_cstring(data, &mut offset),
+                    2 => { offset += 4; i32::from_le_bytes([data[offset - 4], data[offset - 3], data[offset - 2], data[offset - 1]]).to_string() },
+                    _ => panic!("unexpected subtype {}", subtype),
+                };
+                output.push(format!("{}/{}={}", path, name, value));
+            },
+            _ => panic!("unexpected entry type {}", kind),
+        }
+    }
+}
+
+#[test]
+fn config_rapify_class_offsets() {
+    let input = "\
+class A {
+    x = 1;
+    class A1 {
+        y = \"a1\";
+    };
+    class A2: A1 {
+        class A21 {
+            z = 21;
+        };
+    };
+};
+class B {
+    class B1 {};
+    class B2 {
+        w = 2;
+    };
+};
+class C: B {
+    v = \"c\";
+};
+";
+    let config = Config::read(&mut Cursor::new(input), None, &Vec::new()).unwrap();
+    let rapified = config.to_cursor().unwrap().into_inner();
+
+    let derapified = Config::read_rapified(&mut Cursor::new(&rapified[..])).unwrap();
+    assert_eq!(input, derapified.to_string().unwrap());
+
+    // every class entry points at the body of that class
+    let mut classes: Vec<String> = Vec::new();
+    walk_class(&rapified, 16, "", &mut classes);
+    assert_eq!(vec![
+        ":", "/A:", "/A/x=1", "/A/A1:", "/A/A1/y=a1", "/A/A2:A1", "/A/A2/A21:", "/A/A2/A21/z=21",
+        "/B:", "/B/B1:", "/B/B2:", "/B/B2/w=2", "/C:B", "/C/v=c",
+    ], classes);
+}
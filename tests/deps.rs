@@ -0,0 +1,42 @@
+use std::fs::{create_dir_all, write};
+use std::path::{PathBuf};
+
+use armake2::deps::*;
+use armake2::pbo::PBO;
+
+#[test]
+fn test_build_dependencies() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_path = dir.path().canonicalize().unwrap();
+    let source = dir_path.join("addon");
+    create_dir_all(source.join("data")).unwrap();
+    write(source.join("script_component.hpp"), "#define VALUE 1\n").unwrap();
+    write(source.join("config.cpp"), "#include \"script_component.hpp\"\nvalue = VALUE;\n").unwrap();
+    write(source.join("data").join("script.sqf"), "true").unwrap();
+    write(source.join("notes.txt"), "excluded").unwrap();
+
+    // the cached config still reports its includes
+    armake2::cache::init_cache(Some(armake2::cache::open_cache(dir_path.join("cache").to_str().unwrap()).unwrap()));
+    for _i in 0..2 {
+        start_tracking();
+        PBO::from_directory(source.clone(), true, &["*.txt".to_string()], &[], &[], false).unwrap();
+        let dependencies = finish_tracking();
+
+        assert_eq!(vec![source.join("config.cpp"), source.join("data").join("script.sqf"), source.join("script_component.hpp")], dependencies);
+    }
+    armake2::cache::init_cache(None);
+
+    // nothing is tracked afterwards
+    PBO::from_directory(source.clone(), true, &[], &[], &[], false).unwrap();
+    assert!(finish_tracking().is_empty());
+
+    let depfile = dir_path.join("addon.pbo.json");
+    write_file(&depfile, &PathBuf::from("addon.pbo"), &[source.join("config.cpp")]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&depfile).unwrap()).unwrap();
+    assert_eq!("addon.pbo", json["target"]);
+    assert_eq!(source.join("config.cpp").to_str().unwrap(), json["dependencies"][0]);
+
+    let depfile = dir_path.join("addon.pbo.d");
+    write_file(&depfile, &PathBuf::from("$addon.pbo"), &[]).unwrap();
+    assert_eq!("$$addon.pbo:\n", std::fs::read_to_string(&depfile).unwrap());
+}
@@ -0,0 +1,62 @@
+use armake2::error::*;
+
+#[test]
+fn test_warning_config() {
+    let config = WarningConfig::from_string("\
+# project warnings
+mute = [
+    \"redefinition-wo-undef\", # noisy
+    \"unquoted-string\",
+]
+max = 20
+
+[caps]
+\"duplicate-file\" = 2
+
+[[paths]]
+glob = \"legacy/**\"
+mute = [\"unquoted-string\"]
+max = 5
+
+[[paths]]
+glob = 'addons/*/#old/**'
+").unwrap();
+
+    assert_eq!(2, config.muted.len());
+    assert_eq!(Some(20), config.max);
+    assert_eq!(Some(&2), config.caps.get("duplicate-file"));
+    assert_eq!(2, config.paths.len());
+    assert_eq!(Some(5), config.paths[0].max);
+    assert_eq!("addons/*/#old/**", config.paths[1].glob);
+    assert!(matches_path_glob("/home/user/mod/addons/main/#old/script.sqf", &config.paths[1].glob));
+
+    assert!(WarningConfig::from_string("[warnings]\n").is_err());
+    assert!(WarningConfig::from_string("max = \"ten\"\n").is_err());
+    assert!(WarningConfig::from_string("mute = [\"a\",\n").is_err());
+    assert!(WarningConfig::from_string("[[paths]]\nmax = 1\n").is_err());
+}
+
+#[test]
+fn test_warning_summary() {
+    let config = WarningConfig::from_string("[caps]\ncapped = 1\n[[paths]]\nglob = \"legacy/**\"\nmute = [\"frequent\"]\n").unwrap();
+    init_warnings(vec!["muted".to_string()].into_iter().collect(), false, config);
+
+    warning("muted", Some("muted"), (None, None));
+    warning("capped", Some("capped"), (None, None));
+    warning("capped", Some("capped"), (None, None));
+    for _ in 0..3 {
+        warning("frequent", Some("frequent"), (Some("addons/main/config.cpp"), Some(1)));
+    }
+    warning("frequent", Some("frequent"), (Some("addons/main/legacy/macros.hpp"), Some(1)));
+    warning("unnamed", None, (None, None));
+
+    let summary = warning_summary();
+    assert_eq!(6, summary.warnings);
+    assert_eq!(1, summary.suppressed);
+    assert_eq!(vec![("frequent".to_string(), 3), ("capped".to_string(), 2)], summary.names);
+    assert_eq!(vec![("addons/main/config.cpp".to_string(), 3)], summary.files);
+
+    let mut json: Vec<u8> = Vec::new();
+    summary.write_json(&mut json).unwrap();
+    assert!(String::from_utf8(json).unwrap().contains("\"suppressed\": 1"));
+}
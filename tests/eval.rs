@@ -0,0 +1,34 @@
+use armake2::eval::*;
+
+#[test]
+fn test_evaluate_arithmetic() {
+    assert_eq!(Value::Number(14.0), evaluate("2 + 3 * 4").unwrap());
+    assert_eq!(Value::Number(20.0), evaluate("(2 + 3) * 4").unwrap());
+    assert_eq!(Value::Number(4.0), evaluate("-2 ^ 2").unwrap());
+    assert_eq!(Value::Number(1.0), evaluate("7 MOD 3").unwrap());
+    assert_eq!(Value::Number(3.0), evaluate("1 max 3 min 2 + 1").unwrap());
+    assert_eq!(Value::Number(255.0), evaluate("0xff").unwrap());
+    assert_eq!(Value::Number(2.0), evaluate("floor sqrt 8").unwrap());
+    assert!(evaluate("1 / 0").is_err());
+    assert!(evaluate("1 +").is_err());
+}
+
+#[test]
+fn test_evaluate_strings_and_comparisons() {
+    assert_eq!(Value::String("ab\"c".to_string()), evaluate("'a' + \"b\"\"c\"").unwrap());
+    assert_eq!(Value::String("\"X\"".to_string()), evaluate("str toUpper 'x'").unwrap());
+    assert_eq!(Value::Number(3.0), evaluate("count 'abc'").unwrap());
+    assert_eq!(Value::Bool(true), evaluate("'abc' == 'ABC' and !(1 > 2)").unwrap());
+    assert_eq!(Value::Bool(false), evaluate("2 <= 1 || not true").unwrap());
+    assert!(evaluate("'a' < 'b'").is_err());
+    assert!(evaluate("1 == 'a'").is_err());
+}
+
+#[test]
+fn test_value_to_config() {
+    assert_eq!("3", Value::Number(3.0).to_config());
+    assert_eq!("0.5", Value::Number(0.5).to_config());
+    assert_eq!("10000000000.0", Value::Number(1e10).to_config());
+    assert_eq!("\"say \"\"hi\"\"\"", Value::String("say \"hi\"".to_string()).to_config());
+    assert_eq!("1", Value::Bool(true).to_config());
+}
@@ -0,0 +1,34 @@
+use std::fs::{write};
+use std::process::{Command};
+
+use armake2::git::*;
+
+fn git(directory: &std::path::Path, args: &[&str]) {
+    let status = Command::new("git").current_dir(directory)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args).status().unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn test_git_build_info() {
+    let dir = tempfile::tempdir().unwrap();
+    git(dir.path(), &["init", "-q"]);
+    write(dir.path().join("config.cpp"), "class CfgPatches {};").unwrap();
+    git(dir.path(), &["add", "config.cpp"]);
+    git(dir.path(), &["commit", "-q", "-m", "init"]);
+
+    let info = GitInfo::read(dir.path()).unwrap();
+    assert_eq!(40, info.hash.len());
+    assert!(!info.dirty);
+
+    let (mut defines, mut headerext) = (Vec::new(), Vec::new());
+    add_git_build_info(dir.path(), true, &mut defines, &mut headerext).unwrap();
+    assert_eq!(vec![("__GIT_HASH__".to_string(), info.hash.clone())], defines);
+    assert_eq!(vec![format!("git_hash={}", info.hash)], headerext);
+
+    write(dir.path().join("config.cpp"), "class CfgPatches { class foo {}; };").unwrap();
+    assert!(GitInfo::read(dir.path()).unwrap().dirty);
+    assert!(add_git_build_info(dir.path(), true, &mut defines, &mut headerext).is_err());
+    assert!(add_git_build_info(dir.path(), false, &mut defines, &mut headerext).is_ok());
+}
@@ -0,0 +1,57 @@
+use std::fs::{create_dir_all, write};
+use std::io::{Cursor};
+
+use armake2::hooks::*;
+use armake2::pbo::*;
+
+#[test]
+fn test_hook_parse() {
+    assert_eq!(HookStage::PostRapify, Hook::parse("post-rapify = ./obfuscate.sh").unwrap().stage);
+    assert!(Hook::parse("post-build=true").is_err());
+    assert!(Hook::parse("pre-pack").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pack_hooks() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(source.join("data")).unwrap();
+    write(source.join("data").join("keep.sqf"), "keep").unwrap();
+    write(source.join("remove.txt"), "remove").unwrap();
+
+    let hooks = Hooks::new(&[
+        "pre-preprocess=echo source > \"$ARMAKE2_SOURCE/pre.txt\"".to_string(),
+        "pre-pack=echo generated > \"$ARMAKE2_FILES/data/gen.txt\" && rm \"$ARMAKE2_FILES/remove.txt\"".to_string(),
+    ], source.clone(), None).unwrap();
+
+    let mut output: Vec<u8> = Vec::new();
+    cmd_pack(source.clone(), &mut output, &[], &[], &hooks, None).unwrap();
+
+    let pbo = PBO::read(&mut Cursor::new(output)).unwrap();
+    let mut names: Vec<&String> = pbo.files.keys().collect();
+    names.sort();
+    assert_eq!(vec!["data\\gen.txt", "data\\keep.sqf", "pre.txt"], names);
+    assert_eq!(b"generated\n", &pbo.files["data\\gen.txt"].get_ref()[..]);
+
+    let failing = Hooks::new(&["pre-pack=exit 3".to_string()], source.clone(), None).unwrap();
+    let error = cmd_pack(source, &mut Vec::new(), &[], &[], &failing, None).unwrap_err();
+    assert_eq!("pre-pack hook \"exit 3\" terminated with exit code: 3", error.to_string());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_hook_files_removed_on_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(&source).unwrap();
+    write(source.join("script.sqf"), "true").unwrap();
+    let record = dir.path().join("staging.txt");
+
+    let hooks = Hooks::new(&[format!("pre-pack=printf %s \"$ARMAKE2_FILES\" > \"{}\" && exit 1", record.display())], source.clone(), None).unwrap();
+    assert!(cmd_pack(source, &mut Vec::new(), &[], &[], &hooks, None).is_err());
+
+    let staging = std::fs::read_to_string(&record).unwrap();
+    assert!(staging.contains("hook_pre-pack_"));
+    assert!(!std::path::Path::new(&staging).exists());
+}
@@ -0,0 +1,41 @@
+use std::fs::{File, create_dir_all, read, write};
+use std::path::PathBuf;
+
+use tempfile::{tempdir};
+
+use armake2::install::*;
+use armake2::pbo::PBO;
+use armake2::sign::{BIPrivateKey, BISignVersion};
+
+#[test]
+fn test_install() {
+    let dir = tempdir().unwrap();
+    let build = dir.path().join("build");
+    let addons = dir.path().join("@mod").join("addons");
+    create_dir_all(&build).unwrap();
+    create_dir_all(&addons).unwrap();
+
+    let pbo_path = build.join("main.pbo");
+    let pbo = PBO::from_directory(PathBuf::from("src/selftest"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    pbo.write(&mut File::create(&pbo_path).unwrap()).unwrap();
+    let pbo = PBO::read(&mut File::open(&pbo_path).unwrap()).unwrap();
+
+    let key = BIPrivateKey::generate(1024, "mykey".to_string());
+    key.sign(&pbo, BISignVersion::V3).write(&mut File::create(build.join("main.pbo.mykey.bisign")).unwrap()).unwrap();
+    assert_eq!(vec![build.join("main.pbo.mykey.bisign")], pbo_signatures(&pbo_path).unwrap());
+
+    // signature of the same key under an old name, and one of another key
+    key.sign(&pbo, BISignVersion::V3).write(&mut File::create(addons.join("main.pbo.mykey_old.bisign")).unwrap()).unwrap();
+    BIPrivateKey::generate(1024, "other".to_string()).sign(&pbo, BISignVersion::V3)
+        .write(&mut File::create(addons.join("main.pbo.other.bisign")).unwrap()).unwrap();
+    write(addons.join("main.pbo"), b"old").unwrap();
+
+    let installed = install(&pbo_path, &addons).unwrap();
+    assert_eq!(vec![addons.join("main.pbo"), addons.join("main.pbo.mykey.bisign")], installed);
+    assert_eq!(read(&pbo_path).unwrap(), read(addons.join("main.pbo")).unwrap());
+    assert_eq!(vec![addons.join("main.pbo.mykey.bisign"), addons.join("main.pbo.other.bisign")], pbo_signatures(&addons.join("main.pbo")).unwrap());
+    assert_eq!(3, std::fs::read_dir(&addons).unwrap().count());
+
+    assert!(install(&pbo_path, &dir.path().join("missing")).is_err());
+    assert!(install(&build.join("missing.pbo"), &addons).is_err());
+}
@@ -0,0 +1,39 @@
+#![cfg(unix)]
+
+use std::fs::{create_dir_all, read_dir, write};
+use std::path::{Path};
+use std::process::{Command};
+use std::thread::{sleep};
+use std::time::{Duration, Instant};
+
+/// Returns whether the folder contains a temporary file of an output being written.
+fn has_temp_output(dir: &Path) -> bool {
+    read_dir(dir).unwrap().any(|e| e.unwrap().file_name().to_string_lossy().starts_with(".addon.pbo.tmp"))
+}
+
+#[test]
+fn test_interrupt_removes_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(&source).unwrap();
+    write(source.join("script.sqf"), "true").unwrap();
+    let target = dir.path().join("addon.pbo");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["pack", "--hook", "pre-pack=sleep 2", source.to_str().unwrap(), target.to_str().unwrap()])
+        .spawn().unwrap();
+
+    // the temporary output is created before the files are packed
+    let start = Instant::now();
+    while !has_temp_output(dir.path()) && start.elapsed() < Duration::from_secs(10) {
+        sleep(Duration::from_millis(10));
+    }
+    assert!(has_temp_output(dir.path()));
+
+    Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+    let status = child.wait().unwrap();
+
+    assert_eq!(Some(130), status.code());
+    assert!(!target.exists());
+    assert!(!has_temp_output(dir.path()));
+}
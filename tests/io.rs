@@ -0,0 +1,21 @@
+use armake2::io::*;
+
+#[test]
+fn test_format_size() {
+    assert_eq!("0 B", format_size(0));
+    assert_eq!("1.0 KiB", format_size(1024));
+    assert_eq!("2.5 GiB", format_size(5 * 512 * 1024 * 1024));
+}
+
+#[test]
+fn test_check_space() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // the space of missing folders is the one of their closest existing parent
+    let missing = dir.path().join("a").join("b.pbo");
+    assert!(available_space(&missing).unwrap() > 0);
+    assert!(check_space(&missing, 1).is_ok());
+
+    let error = check_space(&missing, u64::MAX).unwrap_err();
+    assert!(error.to_string().starts_with("Not enough disk space"), "{}", error);
+}
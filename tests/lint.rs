@@ -0,0 +1,365 @@
+use armake2::archive::{Archive, Directory};
+use armake2::config::*;
+use armake2::lint::*;
+
+#[test]
+fn test_procedural_texture() {
+    assert!(check_procedural_texture("#(rgb,8,8,3)color(0.5,0.5,0.5,1,co)").is_ok());
+    assert!(check_procedural_texture("#(ai,64,64,1)fresnel(1.3,7)").is_ok());
+    assert!(check_procedural_texture("#(rgb,512,512,3)text(0,0,\"Caveat\",0.1,\"#0000007f\",\"#ffffff\",\"x\")").is_ok());
+
+    assert_eq!(Err("unknown format \"rbg\"".to_string()), check_procedural_texture("#(rbg,8,8,3)color(1,1,1,1)"));
+    assert_eq!(Err("size \"10\" is not a power of two up to 4096".to_string()), check_procedural_texture("#(argb,10,8,3)color(1,1,1,1)"));
+    assert_eq!(Err("argument 2 of color is out of range 0..1".to_string()), check_procedural_texture("#(argb,8,8,3)color(1,2,1,1)"));
+    assert_eq!(Err("argument \"x\" of fresnel is not a number".to_string()), check_procedural_texture("#(ai,64,64,1)fresnel(x,7)"));
+    assert!(check_procedural_texture("#(argb,8,8,3)color(1,1,1,1").is_err());
+}
+
+#[test]
+fn test_procedural_texture_config() {
+    // invalid procedural textures only cause warnings
+    let input = String::from("texture = \"#(argb,8,8,3)color(1,0,0)\";\ntextures[] = {\"#(argb,8,8,3)colour(1,0,0,1)\"};\n");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    assert_eq!("texture = \"#(argb,8,8,3)color(1,0,0)\";\ntextures[] = {\"#(argb,8,8,3)colour(1,0,0,1)\"};\n", config.to_string().unwrap());
+}
+
+#[test]
+fn test_lint_fix() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("macros.hpp"), "#define ICON(x) \\x\\addons/x.paa\nvalue = included;\n").unwrap();
+    let path = dir.path().join("config.cpp");
+    std::fs::write(&path, "#include \"macros.hpp\"\nclass A {\n    name = My Addon; // comment\n    picture = \"/x/data/icon.paa\";\n    icon = ICON(foo);\n    list[] = {one, \"two\"};\n    x = 1\n};\n").unwrap();
+
+    armake2::lint::cmd_lint(path.clone(), &Vec::new(), false).unwrap();
+    assert!(std::fs::read_to_string(&path).unwrap().contains("My Addon;"));
+
+    armake2::lint::cmd_lint(path.clone(), &Vec::new(), true).unwrap();
+    assert_eq!("#include \"macros.hpp\"\nclass A {\n    name = \"My Addon\"; // comment\n    picture = \"\\x\\data\\icon.paa\";\n    icon = ICON(foo);\n    list[] = {\"one\", \"two\"};\n    x = 1;\n};\n",
+        std::fs::read_to_string(&path).unwrap());
+    assert_eq!("#define ICON(x) \\x\\addons/x.paa\nvalue = included;\n", std::fs::read_to_string(dir.path().join("macros.hpp")).unwrap());
+    // the fixed file replaces the source without leaving temporary files behind
+    assert_eq!(2, std::fs::read_dir(dir.path()).unwrap().count());
+
+    // applying the fixes again changes nothing
+    armake2::lint::cmd_lint(path.clone(), &Vec::new(), true).unwrap();
+    assert!(std::fs::read_to_string(&path).unwrap().contains("    x = 1;\n};\n"));
+}
+
+#[test]
+fn test_header_extension() {
+    assert!(check_header_extension("prefix", "z\\ace\\addons\\common").is_ok());
+    assert_eq!(Err("the prefix contains forward slashes".to_string()), check_header_extension("prefix", "z/ace/addons/common"));
+    assert!(check_header_extension("prefix", "\\z\\ace\\addons\\common").is_err());
+    assert!(check_header_extension("prefix", "z\\ace\\\\common").is_err());
+    assert!(check_header_extension("prefix", "").is_err());
+
+    assert!(check_header_extension("version", "3.15.2.69").is_ok());
+    assert!(check_header_extension("version", "v1.0.0-rc1").is_ok());
+    assert!(check_header_extension("version", "1..2").is_err());
+    assert!(check_header_extension("hemtt", "1.13.1").is_ok());
+    assert!(check_header_extension("Mikero", "DePbo.dll").is_err());
+    assert!(check_header_extension("git_hash", "0123abc").is_ok());
+    assert!(check_header_extension("git_hash", "HEAD").is_err());
+
+    assert!(check_header_extension("", "value").is_err());
+    assert_eq!(Err("it contains the control character '\\n'".to_string()), check_header_extension("author", "a\nb"));
+}
+
+#[test]
+fn test_scan_secrets() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut directory = Directory::new(dir.path().to_path_buf());
+
+    directory.write_entry("script.sqf", b"private _hook = \"https://discord.com/api/webhooks/123/abc-DEF\";\n").unwrap();
+    directory.write_entry("data\\keys\\release.key", b"release\0\x94\x02\0\0\x07\x02\x00\x00\x00\x24\x00\x00RSA2").unwrap();
+    directory.write_entry("config.cpp", b"class CfgPatches {};\napiKey = \"0123456789abcdef0123\";\n").unwrap();
+    directory.write_entry("readme.txt", b"Built from /home/builder/mod").unwrap();
+
+    let secrets = scan_secrets(&directory).unwrap();
+    let found: Vec<(&str, Option<usize>, &str)> = secrets.iter().map(|s| (s.entry.as_str(), s.line, s.message.as_str())).collect();
+    assert_eq!(vec![
+        ("config.cpp", Some(2), "hardcoded credential"),
+        ("data\\keys\\release.key", None, "BI private key"),
+        ("readme.txt", Some(1), "absolute path exposing the user name builder"),
+        ("script.sqf", Some(1), "Discord webhook URL"),
+    ], found);
+
+    assert!(cmd_scan_secrets(dir.path().to_path_buf()).is_err());
+    directory.write_entry("config.cpp", b"class CfgPatches {};").unwrap();
+    for entry in ["script.sqf", "data\\keys\\release.key", "readme.txt"] {
+        directory.remove_entry(entry).unwrap();
+    }
+    assert!(cmd_scan_secrets(dir.path().to_path_buf()).is_ok());
+}
+
+#[test]
+fn test_entry_path() {
+    assert!(check_entry_path("\\x\\foo\\addons\\main\\", "functions\\fn_init.sqf").is_empty());
+
+    let problems = check_entry_path("x\\foo", "Data\\Übersicht Karte.paa");
+    let names: Vec<&str> = problems.iter().map(|(_, name)| *name).collect();
+    assert_eq!(vec!["path-space", "non-ascii-path"], names);
+
+    // the separator between prefix and name counts, leading and trailing backslashes don't
+    let name = "a".repeat(255 - 6);
+    assert!(check_entry_path("\\x\\foo\\", &name).is_empty());
+    assert!(check_entry_path("\\x\\foo\\", &format!("{}a", name))[0].0.contains("256 characters"));
+}
+
+#[test]
+fn test_patches() {
+    let problems = |input: &str, prefix: &str| -> Vec<(String, &'static str)> {
+        let config = Config::from_string(input.to_string(), None, &Vec::new()).unwrap();
+        check_patches(&config, prefix)
+    };
+
+    let valid = "class CfgPatches { class abc_main { units[] = {\"abc_car\"}; weapons[] = {\"ABC_Rifle\"}; requiredAddons[] = {\"cba_main\"}; }; };\n\
+        class CfgVehicles { class abc_car {}; };\nclass CfgWeapons { class abc_rifle {}; };";
+    assert!(problems(valid, "x\\abc\\addons\\main").is_empty());
+    assert!(problems("class CfgPatches { class main {}; };", "main").is_empty());
+
+    assert_eq!("patches-missing", problems("class CfgVehicles {};", "main")[0].1);
+
+    let wrong = problems(valid, "x\\abc\\addons\\common");
+    assert_eq!(1, wrong.len());
+    assert_eq!("patches-name", wrong[0].1);
+    assert!(wrong[0].0.contains("abc_main"), "{}", wrong[0].0);
+
+    let required = problems("class CfgPatches { class main { requiredAddons[] = {\"a\", \"A\", \"main\", \"my addon\", 1}; }; };", "main");
+    let messages: Vec<&str> = required.iter().map(|(msg, _)| msg.as_str()).collect();
+    assert!(required.iter().all(|(_, name)| *name == "patches-required-addons"));
+    assert_eq!(4, required.len(), "{:?}", messages);
+}
+
+#[test]
+fn test_3den() {
+    let input = String::from("
+        class Cfg3DEN {
+            class Attributes {
+                class Default;
+                class abc_colorPicker: Default {};
+            };
+            class Object {
+                class AttributeCategories {
+                    class abc_attributes {
+                        class Attributes {
+                            class abc_color { property = \"abc_color\"; control = \"abc_colorPicker\"; expression = \"\"; defaultValue = \"'red'\"; };
+                            class abc_mode { property = \"abc_color\"; control = \"Combo\"; validate = \"numbers\"; };
+                            class abc_size { property = \"abc_size\"; control = \"Edit\"; defaultValue = 1; };
+                            class abc_missing { control = \"cba_slider\"; };
+                        };
+                    };
+                };
+            };
+            class Group {
+                class AttributeCategories {
+                    class abc_attributes {
+                        class Attributes {
+                            class abc_color { property = \"abc_color\"; control = \"Checkbox\"; typeName = \"BOOL\"; };
+                        };
+                    };
+                };
+            };
+        };");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    let problems: Vec<(String, &str)> = check_3den(&config).into_iter()
+        .map(|(path, _, name)| (path.rsplit('.').next().unwrap().to_string(), name))
+        .collect();
+    assert_eq!(vec![
+        ("abc_mode".to_string(), "3den-property"),
+        ("abc_mode".to_string(), "3den-values"),
+        ("abc_mode".to_string(), "3den-validate"),
+        ("abc_size".to_string(), "3den-attribute-type"),
+        ("abc_missing".to_string(), "3den-attribute-missing"),
+        ("abc_missing".to_string(), "3den-unknown-control"),
+    ], problems);
+}
+
+#[test]
+fn test_remote_exec() {
+    let input = String::from("
+        class CfgRemoteExec {
+            class Functions {
+                mode = 1;
+                jip = 0;
+                class abc_fnc_notify { allowedTargets = 1; };
+                class bis_fnc_execvm { allowedTargets = 2; };
+            };
+            class Commands {
+                class hint {};
+            };
+        };");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    let problems = check_remote_exec(&config);
+    let problems: Vec<(&str, &str)> = problems.iter().map(|(path, _, name)| (path.as_str(), *name)).collect();
+    assert_eq!(vec![
+        ("CfgRemoteExec.Functions.bis_fnc_execvm", "remote-exec-code"),
+        ("CfgRemoteExec.Commands", "remote-exec-mode"),
+    ], problems);
+
+    let restricted = String::from("class CfgRemoteExec { class Functions { mode = 1; }; class Commands { mode = 0; }; };");
+    assert!(check_remote_exec(&Config::from_string(restricted, None, &Vec::new()).unwrap()).is_empty());
+}
+
+#[test]
+fn test_mission_description() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("sounds")).unwrap();
+    std::fs::create_dir_all(dir.path().join("functions")).unwrap();
+    std::fs::write(dir.path().join("sounds").join("alarm.ogg"), "").unwrap();
+    std::fs::write(dir.path().join("functions").join("fn_init.sqf"), "").unwrap();
+    std::fs::write(dir.path().join("stringtable.xml"), "<Project><Package><Key ID=\"STR_mission_title\"><English>Title</English></Key></Package></Project>").unwrap();
+    std::fs::write(dir.path().join("description.ext"), "
+        respawn = 7;
+        respawnTemplates[] = {\"MenuPosition\", \"Custom\"};
+        briefingName = \"$STR_mission_title\";
+        onLoadName = \"$STR_mission_missing\";
+        class CfgSounds {
+            class alarm { sound[] = {\"sounds\\alarm.ogg\", 1, 1}; };
+            class siren { sound[] = {\"sounds\\siren.ogg\", 1, 1}; };
+            class vanilla { sound[] = {\"@a3\\sounds_f\\sfx\\alarm.wss\", 1, 1}; };
+        };
+        class CfgFunctions { class abc { class misc { file = \"functions\"; class init {}; class other {}; }; }; };
+        class CfgRoles { class Rifleman {}; };
+        class CfgRespawnInventory {
+            class rifleman { displayName = \"Rifleman\"; role = \"Rifleman\"; weapons[] = {\"arifle_MX_F\"}; };
+            class medic { role = \"Medic\"; weapons = \"arifle_MX_F\"; };
+        };
+        class Params {
+            class Daytime { title = \"Time\"; values[] = {6, 12}; texts[] = {\"Morning\"}; default = 12; };
+        };").unwrap();
+
+    let config = Config::read(&mut std::fs::File::open(dir.path().join("description.ext")).unwrap(), None, &Vec::new()).unwrap();
+    let files = Directory::new(dir.path().to_path_buf()).entries().unwrap();
+    let keys = stringtable_keys(&std::fs::read_to_string(dir.path().join("stringtable.xml")).unwrap());
+
+    let problems = check_description(&config, &files, Some(&keys));
+    let problems: Vec<(&str, &str)> = problems.iter().map(|(path, _, name)| (path.as_str(), *name)).collect();
+    assert_eq!(vec![
+        ("respawn", "mission-respawn"),
+        ("respawnTemplates[1]", "mission-respawn"),
+        ("CfgRespawnInventory.medic", "mission-loadout"),
+        ("CfgRespawnInventory.medic.weapons", "mission-loadout"),
+        ("CfgRespawnInventory.medic.role", "mission-loadout"),
+        ("Params.Daytime.texts", "mission-param"),
+        ("CfgFunctions", "function-missing-file"),
+        ("CfgSounds.siren.sound[0]", "mission-file-missing"),
+        ("onLoadName", "mission-stringtable"),
+    ], problems);
+
+    armake2::lint::cmd_mission_lint(dir.path().to_path_buf(), &Vec::new()).unwrap();
+}
+
+#[test]
+fn test_localization() {
+    let input = String::from("
+        class CfgPatches { class abc_main { name = \"ABC\"; }; };
+        class CfgVehicles {
+            class abc_car {
+                displayName = \"Car\";
+                DisplayNameShort = \"$str_abc_car_short\";
+                descriptionShort = \"$STR_abc_missing\";
+                picture = \"\\x\\abc\\addons\\main\\data\\car_ca.paa\";
+            };
+        };
+        class RscTitles { class abc_hud { class Controls { class icon { text = \"\\x\\abc\\addons\\main\\data\\icon_ca.paa\"; }; class dash { text = \"--\"; }; }; }; };");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+    let keys = stringtable_keys("<Project><Package><Key ID=\"STR_abc_car_short\"><English>Car</English></Key></Package></Project>");
+
+    let problems = check_localization(&config, Some(&keys));
+    let problems: Vec<(&str, &str)> = problems.iter().map(|(path, _, name)| (path.as_str(), *name)).collect();
+    assert_eq!(vec![
+        ("CfgVehicles.abc_car.displayName", "localization-hardcoded"),
+        ("CfgVehicles.abc_car.descriptionShort", "localization-missing-key"),
+    ], problems);
+
+    // without a stringtable every key is missing
+    let problems = check_localization(&config, None);
+    assert_eq!(3, problems.len());
+    assert_eq!("localization-missing-key", problems[1].2);
+
+    // the stringtable of the addon is found next to the config
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("$PBOPREFIX$"), "x\\abc\\addons\\main").unwrap();
+    std::fs::write(dir.path().join("Stringtable.xml"), "<Project><Package><Key ID=\"STR_abc_car\"><English>Car</English></Key></Package></Project>").unwrap();
+    std::fs::write(dir.path().join("config.cpp"), "class CfgVehicles { class abc_car { displayName = \"$STR_abc_car\"; }; };\n").unwrap();
+    armake2::lint::cmd_lint(dir.path().join("config.cpp"), &Vec::new(), false).unwrap();
+}
+
+/// Rule of a team that wants every addon to have a url
+struct AddonUrl;
+
+impl LintRule for AddonUrl {
+    fn name(&self) -> &'static str { "test-addon-url" }
+
+    fn description(&self) -> &'static str { "Addons without url." }
+
+    fn check(&self, config: &Config, _context: &LintContext) -> Vec<(String, String, &'static str)> {
+        match config.get("CfgPatches/abc_main/url") {
+            Some(_) => Vec::new(),
+            None => vec![("CfgPatches.abc_main".to_string(), "Addon has no url.".to_string(), self.name())],
+        }
+    }
+}
+
+#[test]
+fn test_rules() {
+    let input = String::from("
+        class CfgPatches { class abc_main { author = \"\"; requiredVersion = 2.0; }; };
+        class CfgVehicles {
+            class Car;
+            class abc_car: Car { scope = \"2\"; displayName = \"Car\"; mass[] = {1}; };
+            class abc_van: abc_car { displayName = \"Van\"; };
+            class abc_bus: abc_bus { scope = 2; };
+            class abc_truck: abc_tractor { scope = 1; class Turrets { class MainTurret: abc_car {}; }; };
+            class abc_tractor: Car { scope = 1; scope = 2; };
+        };");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    let problems = run_rules(&config, &LintContext::default(), true);
+    let problems: Vec<(&str, &str)> = problems.iter().map(|(path, _, name)| (path.as_str(), *name)).collect();
+    assert!(problems.starts_with(&[
+        ("CfgVehicles.abc_van", "missing-scope"),
+        ("CfgPatches.abc_main", "missing-author"),
+        ("CfgVehicles.abc_bus", "suspicious-inheritance"),
+        ("CfgVehicles.abc_truck", "suspicious-inheritance"),
+        ("CfgVehicles.abc_tractor.scope", "duplicate-property"),
+        ("CfgVehicles.abc_car.scope", "non-numeric"),
+        ("CfgVehicles.abc_car.mass", "non-numeric"),
+    ]), "{:?}", problems);
+
+    // rules of other tools are run after the built-in ones
+    register_rule(std::sync::Arc::new(AddonUrl));
+    assert!(rules().iter().any(|rule| rule.name() == "localization" && !rule.in_build()));
+    let problems = run_rules(&config, &LintContext::default(), true);
+    assert_eq!(("CfgPatches.abc_main", "test-addon-url"), (problems.last().unwrap().0.as_str(), problems.last().unwrap().2));
+}
+
+#[test]
+fn test_key_usages() {
+    let dir = tempfile::tempdir().unwrap();
+    let addon = dir.path().join("addons").join("main");
+    std::fs::create_dir_all(addon.join("functions")).unwrap();
+    std::fs::write(addon.join("stringtable.xml"), "<Project name=\"ABC\">
+    <Package name=\"Main\">
+        <Key ID=\"STR_abc_main_car\"><English>Car</English></Key>
+        <Key ID=\"STR_abc_main_hint\"><English>Hint</English></Key>
+        <Key ID=\"STR_abc_main_tooltip\"><English>Tooltip</English></Key>
+        <Key ID=\"STR_abc_main_old\"><English>Old</English></Key>
+    </Package>
+</Project>").unwrap();
+    std::fs::write(addon.join("config.cpp"), "class CfgVehicles {\n    class abc_car {\n        displayName = \"$STR_ABC_main_car\";\n        tooltip = LSTRING(tooltip);\n    };\n};\n").unwrap();
+    std::fs::write(addon.join("functions").join("fn_hint.sqf"), "hint localize \"STR_abc_main_hint\";\nhint localize \"STR_abc_main_typo\";\nhint localize \"STR_A3_Intel\";\n").unwrap();
+
+    let problems = check_key_usages(&Directory::new(dir.path().to_path_buf())).unwrap();
+    let problems: Vec<(&str, u32, &str)> = problems.iter().map(|(entry, line, _, name)| (entry.as_str(), *line, *name)).collect();
+    assert_eq!(vec![
+        ("addons\\main\\stringtable.xml", 6, "stringtable-unused-key"),
+        ("addons\\main\\functions\\fn_hint.sqf", 2, "stringtable-undefined-key"),
+    ], problems);
+
+    armake2::lint::cmd_unused_keys(dir.path().to_path_buf()).unwrap();
+}
@@ -0,0 +1,31 @@
+use std::fs::{File, create_dir_all};
+use std::io::{Write};
+
+use tempfile::{tempdir};
+
+use armake2::lock::*;
+
+#[test]
+fn test_dependency_lock() {
+    let dir = tempdir().unwrap();
+    let addons = dir.path().join("@dep").join("addons");
+    create_dir_all(&addons).unwrap();
+    File::create(addons.join("dep_main.pbo")).unwrap().write_all(b"first").unwrap();
+
+    let includefolders = vec![dir.path().join("@dep")];
+    let lock_path = dir.path().join(LOCK_FILE);
+
+    check_dependency_lock(&lock_path, &includefolders, true).unwrap_err();
+    check_dependency_lock(&lock_path, &includefolders, false).unwrap();
+    let locked = DependencyLock::read(&mut File::open(&lock_path).unwrap()).unwrap();
+    assert_eq!(vec!["dep_main.pbo"], locked.hashes.keys().collect::<Vec<_>>());
+    check_dependency_lock(&lock_path, &includefolders, true).unwrap();
+
+    File::create(addons.join("dep_main.pbo")).unwrap().write_all(b"second").unwrap();
+    File::create(addons.join("dep_extra.pbo")).unwrap().write_all(b"extra").unwrap();
+    let current = DependencyLock::from_includefolders(&includefolders).unwrap();
+    assert_eq!(vec!["dep_extra.pbo is not locked", "dep_main.pbo changed"], current.differences(&locked));
+
+    check_dependency_lock(&lock_path, &includefolders, true).unwrap_err();
+    check_dependency_lock(&lock_path, &includefolders, false).unwrap();
+}
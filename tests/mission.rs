@@ -0,0 +1,48 @@
+use std::fs::{File, create_dir_all};
+
+use tempfile::{tempdir};
+
+use armake2::mission::*;
+use armake2::pbo::PBO;
+
+#[test]
+fn test_mission_classes() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("abc_main");
+    create_dir_all(&source).unwrap();
+    std::fs::write(source.join("config.cpp"), "class CfgPatches { class abc_main { units[] = {\"abc_car\", \"abc_tank\"}; weapons[] = {}; }; };").unwrap();
+    let addons = dir.path().join("@abc").join("addons");
+    create_dir_all(&addons).unwrap();
+    PBO::from_directory(source, true, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap()
+        .write(&mut File::create(addons.join("abc_main.pbo")).unwrap()).unwrap();
+
+    let mission = dir.path().join("test.Altis");
+    create_dir_all(&mission).unwrap();
+    std::fs::write(mission.join("mission.sqm"), "version = 53;
+addons[] = {\"abc_main\"};
+class Mission {
+    class Entities {
+        items = 3;
+        class Item0 { dataType = \"Object\"; type = \"abc_car\"; };
+        class Item1 { dataType = \"Marker\"; type = \"hd_dot\"; };
+        class Item2 { dataType = \"Group\"; class Entities { items = 1; class Item0 { dataType = \"Object\"; type = \"ABC_Car\"; }; }; };
+    };
+};
+").unwrap();
+
+    let dependencies = MissionDependencies::read(&mission.join("mission.sqm")).unwrap();
+    assert_eq!(vec!["abc_main"], dependencies.addons);
+    assert_eq!(vec![("abc_car".to_string(), 2)], dependencies.classes.into_iter().collect::<Vec<_>>());
+
+    let available = AvailableAddons::from_pbos(&[addons.join("abc_main.pbo")]).unwrap();
+    assert_eq!(Some(&"abc_main".to_string()), available.units.get("abc_tank"));
+
+    let includefolders = vec![dir.path().join("@abc")];
+    cmd_mission_classes(mission.clone(), &includefolders).unwrap();
+
+    // 2D editor missions list the addons in the mission class
+    std::fs::write(mission.join("mission.sqm"), "class Mission { addOns[] = {\"abc_main\", \"xyz_main\"};
+        class Vehicles { items = 1; class Item0 { vehicle = \"abc_tank\"; }; }; };").unwrap();
+    let error = cmd_mission_classes(mission, &includefolders).err().unwrap().to_string();
+    assert!(error.ends_with("requires 1 missing addons: xyz_main"), "{}", error);
+}
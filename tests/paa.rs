@@ -0,0 +1,209 @@
+use std::io::{Cursor};
+
+use armake2::paa::*;
+
+fn dxt1_dds(width: u32, height: u32, mipmaps: u32) -> Vec<u8> {
+    let mut header = vec![0u32; 31];
+    header[0] = 124;
+    header[1] = 0x1 | 0x2 | 0x4 | 0x1000 | 0x2_0000;
+    header[2] = height;
+    header[3] = width;
+    header[6] = mipmaps;
+    header[18] = 32;
+    header[19] = 0x4;
+    header[20] = u32::from_le_bytes(*b"DXT1");
+
+    let mut dds: Vec<u8> = b"DDS ".to_vec();
+    for value in header {
+        dds.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let (mut w, mut h) = (width, height);
+    for i in 0..mipmaps {
+        for b in 0..(w.div_ceil(4) * h.div_ceil(4)) {
+            dds.extend_from_slice(&[0x1f, 0x00, 0x00, 0xf8, b as u8, i as u8, 0x55, 0xaa]);
+        }
+        w = (w / 2).max(1);
+        h = (h / 2).max(1);
+    }
+
+    dds
+}
+
+#[test]
+fn test_dds_paa_roundtrip() {
+    let dds = dxt1_dds(16, 8, 5);
+
+    let paa = PAA::read_dds(&mut Cursor::new(dds.clone())).unwrap();
+    assert_eq!(PAAType::DXT1, paa.format);
+    // mipmaps smaller than a DXT block are dropped
+    assert_eq!(2, paa.mipmaps.len());
+    assert!(paa.taggs.contains_key("AVGC"));
+
+    let mut paa_data: Vec<u8> = Vec::new();
+    paa.write(&mut paa_data).unwrap();
+    assert_eq!([0x01, 0xff], paa_data[..2]);
+
+    let reread = PAA::read(&mut Cursor::new(paa_data)).unwrap();
+    assert_eq!(2, reread.mipmaps.len());
+    for (a, b) in paa.mipmaps.iter().zip(reread.mipmaps.iter()) {
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        assert_eq!(a.data, b.data);
+    }
+
+    let mut dds_out: Vec<u8> = Vec::new();
+    reread.write_dds(&mut dds_out).unwrap();
+    let top_level = 128 + 16 / 4 * 8 / 4 * 8;
+    assert_eq!(dds[128..top_level], dds_out[128..top_level]);
+}
+
+#[test]
+fn test_paa_lzo_mipmap() {
+    let mut paa_data: Vec<u8> = vec![0x01, 0xff, 0x00, 0x00];
+    paa_data.extend_from_slice(&(4u16 | 0x8000).to_le_bytes());
+    paa_data.extend_from_slice(&4u16.to_le_bytes());
+
+    // 4 literals, then a 4 byte match at distance 4, then the end marker
+    let compressed = [21, b'a', b'b', b'c', b'd', 0x6c, 0x00, 0x11, 0x00, 0x00];
+    paa_data.extend_from_slice(&[compressed.len() as u8, 0, 0]);
+    paa_data.extend_from_slice(&compressed);
+    paa_data.extend_from_slice(&[0; 6]);
+
+    let paa = PAA::read(&mut Cursor::new(paa_data)).unwrap();
+    assert_eq!(4, paa.mipmaps[0].width);
+    assert_eq!(b"abcdabcd".to_vec(), paa.mipmaps[0].data);
+}
+
+#[test]
+fn test_paa_lzss_mipmap() {
+    let pixels: Vec<u8> = (0..32 * 32).flat_map(|i| [(i % 32) as u8, 0xf0]).collect();
+    let compressed = armake2::io::lzss_compress(&pixels);
+    assert!(compressed.len() < pixels.len());
+
+    let mut paa_data: Vec<u8> = vec![0x44, 0x44, 0x00, 0x00];
+    paa_data.extend_from_slice(&32u16.to_le_bytes());
+    paa_data.extend_from_slice(&32u16.to_le_bytes());
+    paa_data.extend_from_slice(&[(compressed.len() & 0xff) as u8, (compressed.len() >> 8) as u8, 0]);
+    paa_data.extend_from_slice(&compressed);
+    paa_data.extend_from_slice(&[0; 6]);
+
+    let paa = PAA::read(&mut Cursor::new(paa_data.clone())).unwrap();
+    assert_eq!(PAAType::ARGB4444, paa.format);
+    assert_eq!(pixels, paa.mipmaps[0].data);
+
+    // data that is neither the expected size nor valid LZSS is rejected
+    let index = paa_data.len() - 10;
+    paa_data[index] ^= 0xff;
+    assert!(PAA::read(&mut Cursor::new(paa_data)).is_err());
+}
+
+#[test]
+fn test_paa_mipmap_size_limit() {
+    let mut paa = PAA::new(PAAType::ARGB8888);
+    paa.mipmaps.push(MipMap { width: 2048, height: 2048, data: vec![0; 2048 * 2048 * 4] });
+
+    let error = paa.write(&mut Vec::new()).err().unwrap();
+    assert!(error.to_string().contains("too large"), "{}", error);
+}
+
+#[test]
+fn test_dxt_encode_decode() {
+    let mut image = Image::new(8, 8);
+    for (i, pixel) in image.data.iter_mut().enumerate() {
+        *pixel = if i % 2 == 0 { [255, 0, 0, 255] } else { [0, 0, 255, 0] };
+    }
+
+    let dxt5 = MipMap::encode(&image, PAAType::DXT5);
+    assert_eq!(PAAType::DXT5.data_size(8, 8), dxt5.data.len());
+    assert_eq!(image.data, dxt5.decode(PAAType::DXT5).data);
+
+    let dxt1 = MipMap::encode(&image, PAAType::DXT1);
+    let decoded = dxt1.decode(PAAType::DXT1);
+    assert_eq!([255, 0, 0, 255], decoded.data[0]);
+    assert_eq!(0, decoded.data[1][3]);
+}
+
+#[test]
+fn test_normalmap_conversion() {
+    let mut image = Image::new(16, 16);
+    for (i, pixel) in image.data.iter_mut().enumerate() {
+        *pixel = if (i / 16) % 2 == 0 { [218, 128, 218, 255] } else { [37, 128, 218, 255] };
+    }
+
+    let mut paa = PAA::new(PAAType::ARGB8888);
+    paa.mipmaps.push(MipMap::encode(&image, PAAType::ARGB8888));
+    let mut dds: Vec<u8> = Vec::new();
+    paa.write_dds(&mut dds).unwrap();
+
+    let mut output: Vec<u8> = Vec::new();
+    cmd_dds2paa(&mut Cursor::new(dds), &mut output, Some("data/foo_nohq.paa".into()), true, false).unwrap();
+    let converted = PAA::read(&mut Cursor::new(output)).unwrap();
+
+    assert_eq!(PAAType::DXT5, converted.format);
+    assert_eq!(&[1, 8, 2, 8], &converted.taggs["SWIZ"][..]);
+    assert_eq!(3, converted.mipmaps.len());
+
+    // the averaged normal (0, 0, 0.7) is scaled back to unit length
+    let smallest = converted.mipmaps[2].decode(PAAType::DXT5);
+    for pixel in smallest.data {
+        assert_eq!([255, 255], [pixel[0], pixel[2]]);
+        assert!((i32::from(pixel[3]) - 128).abs() <= 2);
+    }
+}
+
+#[test]
+fn test_alpha_coverage_mipmaps() {
+    // sparse grass blades: every fourth pixel column is opaque
+    let mut image = Image::new(32, 32);
+    for (i, pixel) in image.data.iter_mut().enumerate() {
+        *pixel = [0, 255, 0, if i % 4 == 0 { 255 } else { 0 }];
+    }
+
+    let mut paa = PAA::new(PAAType::ARGB8888);
+    paa.mipmaps.push(MipMap::encode(&image, PAAType::ARGB8888));
+    let coverage = image.alpha_coverage(ALPHA_REFERENCE);
+
+    paa.generate_mipmaps(MipFilter::Default).unwrap();
+    assert_eq!(0.0, paa.mipmaps[2].decode(PAAType::ARGB8888).alpha_coverage(ALPHA_REFERENCE));
+
+    paa.generate_mipmaps(MipFilter::AlphaCoverage).unwrap();
+    for mipmap in paa.mipmaps.iter().skip(1) {
+        let mip_coverage = mipmap.decode(PAAType::ARGB8888).alpha_coverage(ALPHA_REFERENCE);
+        assert!(mip_coverage >= coverage, "{} < {}", mip_coverage, coverage);
+    }
+}
+
+#[test]
+fn test_image_paa_conversion() {
+    // 8x4 bottom-up RLE TGA: a red run for the bottom rows, raw pixels for the top rows, stored
+    // bottom row first
+    let mut tga: Vec<u8> = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 0, 4, 0, 24, 0];
+    tga.extend_from_slice(&[0x80 | 15, 0, 0, 255]);
+    tga.push(15);
+    for i in 0..16u8 {
+        tga.extend_from_slice(&[i * 16, 255, 0]);
+    }
+    let image = Image::read_tga(&mut Cursor::new(&tga)).unwrap();
+    assert_eq!((8, 4), (image.width, image.height));
+    assert_eq!([0, 255, 128, 255], image.data[0]);
+    assert_eq!([255, 0, 0, 255], image.data[31]);
+
+    let mut paa_data: Vec<u8> = Vec::new();
+    cmd_img2paa(&mut Cursor::new(&tga), &mut paa_data, None, None, true, false).unwrap();
+    let paa = PAA::read(&mut Cursor::new(&paa_data)).unwrap();
+    assert_eq!(PAAType::DXT1, paa.format);
+    assert_eq!(1, paa.mipmaps.len());
+    assert!(paa.taggs.contains_key("AVGC"));
+
+    let mut transparent = Image::new(4, 4);
+    transparent.data[0][3] = 0;
+    assert_eq!(PAAType::DXT5, PAA::from_image(&transparent, None).unwrap().format);
+    assert!(PAA::from_image(&Image::new(6, 4), None).is_err());
+
+    let lossless = PAA::from_image(&image, Some(PAAType::ARGB8888)).unwrap();
+    let mut out: Vec<u8> = Vec::new();
+    lossless.write(&mut out).unwrap();
+    let mut tga_out: Vec<u8> = Vec::new();
+    cmd_paa2img(&mut Cursor::new(out), &mut tga_out).unwrap();
+    assert_eq!(image.data, Image::read_tga(&mut Cursor::new(tga_out)).unwrap().data);
+}
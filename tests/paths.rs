@@ -0,0 +1,73 @@
+use std::path::{Path};
+
+use armake2::paths::*;
+
+#[test]
+fn test_normalize() {
+    let policy = PathPolicy::default();
+    assert_eq!("x\\foo\\data", policy.normalize("\\x\\foo\\data\\"));
+    assert_eq!("x\\foo\\data", policy.normalize("x/foo//data"));
+    assert_eq!("", policy.normalize("\\"));
+}
+
+#[test]
+fn test_case_policy() {
+    let insensitive = PathPolicy::default();
+    assert!(insensitive.eq("\\X\\Foo\\Data\\Icon_CA.paa", "x/foo/data/icon_ca.paa"));
+    assert_eq!("x\\foo\\icon_ca.paa", insensitive.key("X\\Foo\\Icon_CA.paa"));
+
+    let sensitive = PathPolicy { ignore_case: false, ..PathPolicy::default() };
+    assert!(!sensitive.eq("\\X\\Foo\\Data\\Icon_CA.paa", "x/foo/data/icon_ca.paa"));
+    assert!(sensitive.eq("\\x\\foo\\icon_ca.paa", "x/foo/icon_ca.paa"));
+    assert_eq!("X\\Foo\\Icon_CA.paa", sensitive.key("X\\Foo\\Icon_CA.paa"));
+}
+
+#[test]
+fn test_prefix() {
+    let policy = PathPolicy::default();
+    assert_eq!("\\x\\foo\\data\\icon_ca.paa", policy.join_prefix("\\x\\foo", "data\\icon_ca.paa"));
+    assert_eq!(Some("data\\icon_ca.paa".to_string()), policy.strip_prefix("x\\foo\\data\\icon_ca.paa", "\\x\\foo\\"));
+    assert_eq!(Some("data\\icon_ca.paa".to_string()), policy.strip_prefix("data\\icon_ca.paa", ""));
+    assert_eq!(None, policy.strip_prefix("\\x\\foo", "x\\foo"));
+    assert_eq!(None, policy.strip_prefix("\\x\\grün\\icon_ca.paa", "x\\gr"));
+}
+
+#[test]
+fn test_file_system_paths() {
+    let path = Path::new("data").join("ui").join("icon_ca.paa");
+    assert_eq!("data\\ui\\icon_ca.paa", from_fs_path(&path));
+    assert_eq!(path, to_fs_path("data\\ui\\icon_ca.paa"));
+}
+
+#[test]
+fn test_globs() {
+    assert!(matches_glob("addons\\main\\config.cpp", "addons/*/config.cpp"));
+    assert!(matches_glob("config.cpp", "**/config.cpp"));
+    assert!(!matches_glob("addons\\main\\config.cpp", "config.cpp"));
+    assert!(matches_glob_suffix("P:\\x\\mod\\addons\\main\\config.cpp", "main/*.cpp"));
+    assert!(!matches_glob_suffix("", "**"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let addons = dir.path().join("@abc").join("addons");
+    std::fs::create_dir_all(addons.join("optional")).unwrap();
+    for name in &["main.pbo", "ui.pbo", "readme.txt", "optional/extra.pbo"] {
+        std::fs::write(addons.join(to_fs_path(&name.replace('/', "\\"))), "").unwrap();
+    }
+
+    let root = dir.path().to_string_lossy().to_string();
+    assert_eq!(vec![addons.join("main.pbo"), addons.join("ui.pbo")], glob_files(&format!("{}/@abc/addons/*.pbo", root)).unwrap());
+    assert_eq!(3, glob_files(&format!("{}/**/*.pbo", root)).unwrap().len());
+    assert_eq!(vec![addons.join("readme.txt")], glob_files(&format!("{}/@abc/addons/readme.txt", root)).unwrap());
+    assert!(glob_files(&format!("{}/@abc/keys/*.bikey", root)).unwrap().is_empty());
+}
+
+#[test]
+fn test_entry_address() {
+    let address = EntryAddress::parse("C:\\mods\\@abc\\addons\\*.pbo::**/*.hpp");
+    assert_eq!("C:\\mods\\@abc\\addons\\*.pbo", address.pbo);
+    assert_eq!("**/*.hpp", address.entry);
+    assert!(EntryAddress::is_address("main.pbo::config.cpp"));
+    assert!(!EntryAddress::is_address("main.pbo"));
+    assert!(EntryAddress::parse("main.pbo::config.cpp").matches("CONFIG.cpp"));
+    assert!(EntryAddress::parse("main.pbo").pbos().is_err());
+}
@@ -0,0 +1,592 @@
+use std::io::{Cursor};
+use std::path::{PathBuf};
+
+use armake2::pbo::*;
+
+fn header(name: &str, packing_method: u32, size: u32) -> Vec<u8> {
+    let mut data: Vec<u8> = name.as_bytes().to_vec();
+    data.push(0);
+    for value in &[packing_method, size, 0, 0, size] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data
+}
+
+fn container(files: &[(&str, u32)]) -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::new();
+    for (name, packing_method) in files {
+        data.extend(header(name, *packing_method, 4));
+    }
+    data.extend(header("", 0, 0));
+    for _ in files {
+        data.extend_from_slice(b"data");
+    }
+    data.extend_from_slice(&[0; 21]);
+    data
+}
+
+#[test]
+fn test_detect_container() {
+    let pbo = PBO::from_directory(PathBuf::from("tests"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    assert_eq!(ContainerType::PBO, detect_container(&mut pbo.to_cursor().unwrap()));
+
+    let ebo = container(&[("config.bin", 0x456e_6372)]);
+    assert_eq!(ContainerType::EBO, detect_container(&mut Cursor::new(&ebo)));
+    let error = PBO::read(&mut Cursor::new(&ebo)).err().unwrap();
+    assert!(error.to_string().contains("encrypted PBO (EBO)"), "{}", error);
+
+    let duplicate = container(&[("config.bin", 0), ("CONFIG.bin", 0)]);
+    assert_eq!(ContainerType::Obfuscated("duplicate file CONFIG.bin".to_string()), detect_container(&mut Cursor::new(&duplicate)));
+
+    let traversal = container(&[("config.bin", 0), ("..\\config.bin", 0)]);
+    assert!(matches!(detect_container(&mut Cursor::new(&traversal)), ContainerType::Obfuscated(_)));
+    for name in &["data/../../config.bin", "/etc/config.bin"] {
+        let traversal = container(&[("config.bin", 0), (name, 0)]);
+        assert!(matches!(detect_container(&mut Cursor::new(&traversal)), ContainerType::Obfuscated(_)), "{}", name);
+    }
+
+    let mut truncated = container(&[("config.bin", 0)]);
+    truncated.truncate(truncated.len() - 10);
+    assert_eq!(ContainerType::Obfuscated("file sizes exceed the PBO".to_string()), detect_container(&mut Cursor::new(&truncated)));
+
+    assert_eq!(ContainerType::Unknown, detect_container(&mut Cursor::new(b"\x89PNG\r\n\x1a\n")));
+}
+
+#[test]
+fn test_file_types() {
+    assert_eq!(FileType::ODOL, FileType::detect(b"ODOL\x46\0\0\0"));
+    assert_eq!(FileType::PAA, FileType::detect(b"\x05\xffGGATCGVA"));
+    assert_eq!(FileType::Binary, FileType::detect(b"\x05\xff\0\x01"));
+    assert_eq!(FileType::Empty, FileType::detect(b""));
+
+    // a PNG renamed to .paa is a mismatch, rapified and text materials are both fine
+    assert!(!FileType::expected("paa").unwrap().contains(&FileType::detect(b"\x89PNG\r\n\x1a\n")));
+    assert!(FileType::expected("RVMAT").unwrap().contains(&FileType::Config));
+    assert!(FileType::expected("rvmat").unwrap().contains(&FileType::Text));
+    assert!(FileType::expected("xyz").is_none());
+}
+
+#[test]
+fn test_unpack_convert_configs() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join("config.cpp"), "class CfgPatches { class test {}; };").unwrap();
+    std::fs::write(source.join("script.sqf"), "true").unwrap();
+
+    let pbo = PBO::from_directory(source, true, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    let output = dir.path().join("output");
+    cmd_unpack(&mut pbo.to_cursor().unwrap(), output.clone(), &UnpackOptions { convert: true, ..Default::default() }).unwrap();
+
+    assert!(!output.join("config.bin").exists());
+    assert_eq!("class CfgPatches {\n    class test {};\n};\n", std::fs::read_to_string(output.join("config.cpp")).unwrap());
+    assert_eq!("true", std::fs::read_to_string(output.join("script.sqf")).unwrap());
+}
+
+#[test]
+fn test_roundtrip() {
+    let pbo = PBO::from_directory(PathBuf::from("tests"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    let mut written = pbo.to_cursor().unwrap().into_inner();
+    assert!(roundtrip_differences(&written).unwrap().is_empty());
+
+    // set the timestamp of the first file, located after the prefix extension
+    let index = written.windows(5).position(|w| w == b".rs\0\0").unwrap() + 4;
+    written[index + 12] = 1;
+    let differences = roundtrip_differences(&written).unwrap();
+    assert_eq!(1, differences.len());
+    assert!(differences[0].ends_with("timestamp changed from 1 to 0"), "{}", differences[0]);
+
+    // the data of compressed files has to decompress to their original size
+    let compressed = container(&[("config.bin", 0x4370_7273)]);
+    let error = roundtrip_differences(&compressed).err().unwrap();
+    assert!(error.to_string().contains("Failed to decompress config.bin"), "{}", error);
+}
+
+#[test]
+fn test_estimate_size() {
+    let pbo = PBO::from_directory(PathBuf::from("tests"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    let written = pbo.to_cursor().unwrap().into_inner();
+    let estimate = estimate_size(&PathBuf::from("tests"), &Vec::new()).unwrap();
+    assert!(estimate >= written.len() as u64 && estimate < written.len() as u64 + 2048, "{} for {}", estimate, written.len());
+
+    let reader = PBOReader::new(Cursor::new(&written)).unwrap();
+    assert_eq!(pbo.files.values().map(|f| f.get_ref().len() as u64).sum::<u64>(), reader.data_size());
+}
+
+#[test]
+fn test_unpack_permissions() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join("start.sh"), "#!/bin/sh").unwrap();
+    std::fs::write(source.join("config.cpp"), "class A {};").unwrap();
+    let pbo = PBO::from_directory(source, false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+
+    // existing read-only files are only overwritten with force
+    let unpacked = dir.path().join("unpacked");
+    std::fs::create_dir_all(&unpacked).unwrap();
+    std::fs::write(unpacked.join("config.cpp"), "old").unwrap();
+    let mut permissions = std::fs::metadata(unpacked.join("config.cpp")).unwrap().permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(unpacked.join("config.cpp"), permissions).unwrap();
+    assert!(cmd_unpack(&mut pbo.to_cursor().unwrap(), unpacked.clone(), &UnpackOptions::default()).is_err());
+
+    let options = UnpackOptions {
+        force: true,
+        mode: Some(parse_mode("640").unwrap()),
+        executable_extensions: vec!["sh".to_string()],
+        ..Default::default()
+    };
+    cmd_unpack(&mut pbo.to_cursor().unwrap(), unpacked.clone(), &options).unwrap();
+    assert_eq!("class A {};", std::fs::read_to_string(unpacked.join("config.cpp")).unwrap());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |name: &str| std::fs::metadata(unpacked.join(name)).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(0o640, mode("config.cpp"));
+        assert_eq!(0o750, mode("start.sh"));
+    }
+}
+
+#[test]
+fn test_from_directory_keep_going() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("config.cpp"), "class CfgPatches {").unwrap();
+    std::fs::write(dir.path().join("sub").join("material.rvmat"), "ambient[] = {1,1,1,1}").unwrap();
+    std::fs::write(dir.path().join("script.sqf"), "true").unwrap();
+
+    let error = PBO::from_directory(dir.path().to_path_buf(), true, &Vec::new(), &Vec::new(), &Vec::new(), false).err().unwrap().to_string();
+    assert!(!error.contains("files failed to build"));
+
+    let error = PBO::from_directory(dir.path().to_path_buf(), true, &Vec::new(), &Vec::new(), &Vec::new(), true).err().unwrap().to_string();
+    assert!(error.starts_with("2 of 3 files failed to build:"));
+    assert!(error.contains("config.cpp:"));
+    assert!(error.contains("material.rvmat:"));
+}
+
+#[test]
+fn test_from_directory_deterministic() {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..50 {
+        let folder = dir.path().join(format!("folder_{}", i % 5));
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join(format!("material_{}.rvmat", i)), format!("ambient[] = {{{},1,1,1}};", i)).unwrap();
+        std::fs::write(folder.join(format!("script_{}.sqf", i)), format!("diag_log {};", i)).unwrap();
+    }
+    std::fs::write(dir.path().join("$PBOPREFIX$"), "prefix=x\\test\n").unwrap();
+
+    let first = PBO::from_directory(dir.path().to_path_buf(), true, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    assert_eq!(100, first.files.len());
+    assert_eq!(Some(&"x\\test".to_string()), first.header_extensions.get("prefix"));
+
+    let written = first.to_cursor().unwrap().into_inner();
+    for _ in 0..3 {
+        let pbo = PBO::from_directory(dir.path().to_path_buf(), true, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+        assert_eq!(first.files.keys().collect::<Vec<_>>(), pbo.files.keys().collect::<Vec<_>>());
+        assert_eq!(written, pbo.to_cursor().unwrap().into_inner());
+    }
+}
+
+#[test]
+fn test_roundtrip_hex_diff() {
+    let pbo = PBO::from_directory(PathBuf::from("tests"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    let mut written = pbo.to_cursor().unwrap().into_inner();
+    assert!(roundtrip_hex_diff(&written).unwrap().is_none());
+
+    let index = written.windows(5).position(|w| w == b".rs\0\0").unwrap() + 4;
+    written[index + 12] = 1;
+    let diff = roundtrip_hex_diff(&written).unwrap().unwrap();
+    assert!(diff.starts_with(&format!("First difference at offset {:#x} in the headers:", index + 12)), "{}", diff);
+}
+
+#[test]
+fn test_manifest_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("data")).unwrap();
+    std::fs::write(source.join("script.sqf"), "true").unwrap();
+    std::fs::write(source.join("data").join("a.sqf"), "false").unwrap();
+    std::fs::write(source.join("$PBOPREFIX$"), "version=1.0\nprefix=x\\y\n").unwrap();
+
+    // files in reverse order with timestamps, extensions in a non-default order
+    let pbo = PBO::from_directory(source, false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    let manifest = PBOManifest {
+        header_extensions: vec![("version".to_string(), "1.0".to_string()), ("prefix".to_string(), "x\\y".to_string())],
+        entries: vec![
+            ManifestEntry { name: "script.sqf".to_string(), packing_method: 0, original_size: 0, reserved: 0, timestamp: 1_600_000_000, data_size: 4 },
+            ManifestEntry { name: "data\\a.sqf".to_string(), packing_method: 0, original_size: 5, reserved: 7, timestamp: 1_500_000_000, data_size: 5 },
+        ],
+    };
+    let mut original: Vec<u8> = Vec::new();
+    pbo.write_with_manifest(&mut original, &manifest).unwrap();
+    assert_eq!(manifest, PBOManifest::from_pbo(&mut Cursor::new(&original)).unwrap());
+
+    let manifest_path = dir.path().join("manifest.json");
+    cmd_inspect(&mut Cursor::new(&original), false, false, Some(manifest_path.clone()), false).unwrap();
+    let unpacked = dir.path().join("unpacked");
+    cmd_unpack(&mut Cursor::new(&original), unpacked.clone(), &UnpackOptions::default()).unwrap();
+
+    let hooks = armake2::hooks::Hooks::new(&[], unpacked.clone(), None).unwrap();
+    let mut plain: Vec<u8> = Vec::new();
+    cmd_pack(unpacked.clone(), &mut plain, &[], &[], &hooks, None).unwrap();
+    assert_ne!(original, plain);
+
+    let mut repacked: Vec<u8> = Vec::new();
+    cmd_pack(unpacked.clone(), &mut repacked, &[], &[], &hooks, Some(manifest_path.clone())).unwrap();
+    assert_eq!(original, repacked);
+
+    // without a prefix in the original, the default one isn't added
+    std::fs::remove_file(unpacked.join("$PBOPREFIX$")).unwrap();
+    let mut manifest = PBOManifest::read(&mut std::fs::File::open(&manifest_path).unwrap()).unwrap();
+    manifest.header_extensions.clear();
+    manifest.write(&mut std::fs::File::create(&manifest_path).unwrap()).unwrap();
+    let mut repacked: Vec<u8> = Vec::new();
+    cmd_pack(unpacked, &mut repacked, &[], &[], &hooks, Some(manifest_path)).unwrap();
+    assert!(PBO::read(&mut Cursor::new(&repacked)).unwrap().header_extensions.is_empty());
+}
+
+#[test]
+fn test_manifest_compressed_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    let script = "params [\"_unit\"];\n".repeat(100);
+    std::fs::write(source.join("script.sqf"), &script).unwrap();
+    std::fs::write(source.join("data.bin"), vec![3u8; 500]).unwrap();
+
+    // the script is compressed and stored before the data, unlike in the default order
+    let pbo = PBO::from_directory(source, false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    let manifest = PBOManifest {
+        header_extensions: vec![("prefix".to_string(), "source".to_string())],
+        entries: vec![
+            ManifestEntry { name: "script.sqf".to_string(), packing_method: 0x4370_7273, original_size: script.len() as u32, reserved: 0, timestamp: 0,
+                data_size: armake2::io::lzss_compress(script.as_bytes()).len() as u32 },
+            ManifestEntry { name: "data.bin".to_string(), packing_method: 0, original_size: 0, reserved: 0, timestamp: 0, data_size: 500 },
+        ],
+    };
+    let mut original: Vec<u8> = Vec::new();
+    pbo.write_with_manifest(&mut original, &manifest).unwrap();
+    assert_eq!(manifest, PBOManifest::from_pbo(&mut Cursor::new(&original)).unwrap());
+
+    let manifest_path = dir.path().join("manifest.json");
+    cmd_inspect(&mut Cursor::new(&original), false, false, Some(manifest_path.clone()), false).unwrap();
+    let unpacked = dir.path().join("unpacked");
+    cmd_unpack(&mut Cursor::new(&original), unpacked.clone(), &UnpackOptions::default()).unwrap();
+
+    let hooks = armake2::hooks::Hooks::new(&[], unpacked.clone(), None).unwrap();
+    let mut repacked: Vec<u8> = Vec::new();
+    cmd_pack(unpacked, &mut repacked, &[], &[], &hooks, Some(manifest_path)).unwrap();
+    assert_eq!(original, repacked);
+}
+
+#[test]
+fn test_find_artifacts() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("sub")).unwrap();
+    std::fs::create_dir_all(source.join("cache")).unwrap();
+    std::fs::write(source.join("script.sqf"), "true").unwrap();
+    std::fs::write(source.join("key.biprivatekey"), "").unwrap();
+    std::fs::write(source.join("sub").join("old.pbo"), "").unwrap();
+    std::fs::write(source.join("sub").join("old.pbo.key.bisign"), "").unwrap();
+    std::fs::write(source.join("cache").join("0123abcd"), "").unwrap();
+    std::fs::write(source.join("main.pbo.part"), "").unwrap();
+
+    let output = source.join("main.pbo.part");
+    let artifacts = find_artifacts(&source, &["*.bisign".to_string()], Some(&output), Some(&source.join("cache"))).unwrap();
+    assert_eq!(vec![
+        ("cache\\0123abcd".to_string(), "in the build cache"),
+        ("key.biprivatekey".to_string(), "a private key"),
+        ("main.pbo.part".to_string(), "the output file"),
+        ("sub\\old.pbo".to_string(), "a PBO"),
+    ], artifacts);
+
+    std::fs::remove_file(&output).unwrap();
+    std::fs::remove_dir_all(source.join("cache")).unwrap();
+    assert_eq!(3, find_artifacts(&source, &[], Some(&output), None).unwrap().len());
+}
+
+#[test]
+fn test_mission_pack() {
+    let dir = tempfile::tempdir().unwrap();
+    let mission = dir.path().join("co10_test.Stratis");
+    std::fs::create_dir_all(&mission).unwrap();
+    std::fs::write(mission.join("init.sqf"), "hint \"hi\";").unwrap();
+    std::fs::write(mission.join("mission.sqm.bak"), "version = 52;").unwrap();
+    std::fs::write(mission.join("notes.txt~"), "todo").unwrap();
+    std::fs::write(mission.join("Thumbs.db"), "").unwrap();
+    std::fs::create_dir_all(mission.join("images")).unwrap();
+    std::fs::write(mission.join("images").join("Thumbs.db"), "").unwrap();
+    std::fs::write(mission.join("images").join("desktop.ini"), "").unwrap();
+    std::fs::write(mission.join("images").join("überblick.sqf"), "true").unwrap();
+
+    assert!(cmd_mission_pack(mission.clone(), Some(dir.path().to_path_buf()), &Vec::new(), true, false).is_err());
+
+    std::fs::write(mission.join("mission.sqm"), "version = 53;\nclass Mission { class Intel { timeOfChanges = 1800; }; };\n").unwrap();
+    let output = cmd_mission_pack(mission.clone(), Some(dir.path().to_path_buf()), &Vec::new(), true, false).unwrap();
+    assert_eq!(dir.path().join("co10_test.Stratis.pbo"), output);
+    assert!(cmd_mission_pack(mission.clone(), Some(dir.path().to_path_buf()), &Vec::new(), true, false).is_err());
+
+    let pbo = PBO::read(&mut std::fs::File::open(&output).unwrap()).unwrap();
+    let mut names: Vec<&String> = pbo.files.keys().collect();
+    names.sort();
+    assert_eq!(vec!["images\\überblick.sqf", "init.sqf", "mission.sqm"], names);
+    assert_eq!(FileType::Config, FileType::detect(pbo.files["mission.sqm"].get_ref()));
+    assert_eq!(Some(&"co10_test.Stratis".to_string()), pbo.header_extensions.get("prefix"));
+
+    let unbinarized = cmd_mission_pack(mission, Some(dir.path().join("test.Stratis.pbo")), &Vec::new(), false, false).unwrap();
+    let pbo = PBO::read(&mut std::fs::File::open(&unbinarized).unwrap()).unwrap();
+    assert_eq!(FileType::Text, FileType::detect(pbo.files["mission.sqm"].get_ref()));
+}
+
+#[test]
+fn test_pbo_reader() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("data")).unwrap();
+    std::fs::write(source.join("script.sqf"), "true").unwrap();
+    std::fs::write(source.join("data").join("big.bin"), vec![7u8; 100_000]).unwrap();
+    std::fs::write(source.join("$PBOPREFIX$"), "prefix=x\\y\nversion=1.0\n").unwrap();
+
+    let path = dir.path().join("test.pbo");
+    PBO::from_directory(source, false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap()
+        .write(&mut std::fs::File::create(&path).unwrap()).unwrap();
+
+    let mut reader = PBOReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    let names: Vec<&str> = reader.entries.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(vec!["data\\big.bin", "script.sqf"], names);
+    assert!(reader.header_extensions.contains(&("version".to_string(), "1.0".to_string())));
+
+    // entries can be read in any order
+    assert_eq!(b"true".to_vec(), reader.read_entry_at(1).unwrap());
+    assert_eq!(vec![7u8; 100_000], reader.read_entry_at(0).unwrap());
+    assert!(reader.entry("missing.sqf").unwrap().is_none());
+    let checksum = PBO::read(&mut std::fs::File::open(&path).unwrap()).unwrap().checksum;
+    assert_eq!(checksum, reader.checksum().unwrap());
+
+    let mut truncated = std::fs::read(&path).unwrap();
+    truncated.truncate(truncated.len() - 23);
+    let mut reader = PBOReader::new(Cursor::new(truncated)).unwrap();
+    assert!(reader.read_entry_at(0).is_ok());
+    assert!(reader.read_entry_at(1).is_err());
+    assert!(reader.checksum().unwrap().is_none());
+
+    let unpacked = dir.path().join("unpacked");
+    cmd_unpack(&mut std::fs::File::open(&path).unwrap(), unpacked.clone(), &UnpackOptions::default()).unwrap();
+    assert_eq!(100_000, std::fs::metadata(unpacked.join("data").join("big.bin")).unwrap().len());
+    assert_eq!("prefix=x\\y\nversion=1.0\n", std::fs::read_to_string(unpacked.join("$PBOPREFIX$")).unwrap());
+}
+
+#[test]
+fn test_checksum() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.pbo");
+    PBO::from_directory(PathBuf::from("src"), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap()
+        .write(&mut std::fs::File::create(&path).unwrap()).unwrap();
+    let original = std::fs::read(&path).unwrap();
+
+    let mut reader = PBOReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(Some(reader.compute_checksum().unwrap()), reader.checksum().unwrap());
+    assert!(cmd_checksum(path.clone(), false).is_ok());
+
+    let mut corrupt = original.clone();
+    let last = corrupt.len() - 1;
+    corrupt[last] ^= 0xff;
+    std::fs::write(&path, &corrupt).unwrap();
+    assert!(cmd_checksum(path.clone(), false).unwrap_err().to_string().contains("doesn't match the content"));
+    assert_eq!(corrupt, std::fs::read(&path).unwrap());
+    cmd_checksum(path.clone(), true).unwrap();
+    assert_eq!(original, std::fs::read(&path).unwrap());
+
+    // a missing footer is added
+    std::fs::write(&path, &original[..original.len() - 21]).unwrap();
+    assert!(cmd_checksum(path.clone(), false).unwrap_err().to_string().contains("missing"));
+    cmd_checksum(path.clone(), true).unwrap();
+    assert_eq!(original, std::fs::read(&path).unwrap());
+}
+
+#[test]
+fn test_build_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join("config.cpp"), "#include \"macros.hpp\"\nclass CfgPatches { class test { value = VALUE; }; };\n").unwrap();
+    std::fs::write(source.join("macros.hpp"), "#define VALUE 1\n").unwrap();
+    armake2::cache::init_cache(Some(armake2::cache::open_cache(dir.path().join("cache").to_str().unwrap()).unwrap()));
+
+    let value = || {
+        let pbo = PBO::from_directory(source.clone(), true, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+        let config = armake2::config::Config::read_rapified(&mut Cursor::new(pbo.files["config.bin"].get_ref().to_vec())).unwrap();
+        config.to_string().unwrap()
+    };
+    assert!(value().contains("value = 1;"));
+    assert!(value().contains("value = 1;"));
+
+    // changing an include that only defines macros rebuilds the config
+    std::fs::write(source.join("macros.hpp"), "#define VALUE 2\n").unwrap();
+    assert!(value().contains("value = 2;"));
+
+    armake2::cache::init_cache(None);
+}
+
+#[test]
+fn test_texheaders() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("data")).unwrap();
+    for name in &["b_co.paa", "a_nohq.paa"] {
+        let mut paa = armake2::paa::PAA::from_image(&armake2::paa::Image::new(8, 8), None).unwrap();
+        paa.update_taggs();
+        paa.write(&mut std::fs::File::create(source.join("data").join(name)).unwrap()).unwrap();
+    }
+
+    let packed = PBO::from_directory(source.clone(), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    assert!(!packed.files.contains_key("texHeaders.bin"));
+
+    let built = PBO::from_directory(source, true, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    let headers = built.files["texHeaders.bin"].get_ref();
+    assert_eq!(b"0DHT", &headers[..4]);
+    assert_eq!(2, u32::from_le_bytes([headers[8], headers[9], headers[10], headers[11]]));
+
+    // textures are listed sorted by their path in the PBO
+    let text = String::from_utf8_lossy(headers);
+    assert!(text.find("data\\a_nohq.paa").unwrap() < text.find("data\\b_co.paa").unwrap());
+}
+
+#[test]
+fn test_rewrite_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("data")).unwrap();
+    std::fs::write(source.join("$PBOPREFIX$"), "x\\test\\addons\\main").unwrap();
+    std::fs::write(source.join("data").join("tex_co.paa"), "").unwrap();
+    std::fs::write(source.join("data").join("model.p3d"), "").unwrap();
+    std::fs::write(source.join("config.cpp"), "class CfgVehicles { class Test {
+        model = \"data\\model.p3d\";
+        hiddenSelectionsTextures[] = {\"data/tex_co.paa\", \"\\a3\\data_f\\black_co.paa\", \"data\\missing_co.paa\"};
+        displayName = \"data\";
+    }; };").unwrap();
+    std::fs::write(source.join("data").join("material.rvmat"), "class Stage1 { texture = \"tex_co.paa\"; };").unwrap();
+
+    let mut output: Vec<u8> = Vec::new();
+    cmd_build(source, &mut output, &Vec::new(), &Vec::new(), &Vec::new(), &Vec::new(), &armake2::hooks::Hooks::default(), false, true).unwrap();
+    let pbo = PBO::read(&mut Cursor::new(output)).unwrap();
+
+    let config = armake2::config::Config::read_rapified(&mut Cursor::new(pbo.files["config.bin"].get_ref().to_vec())).unwrap().to_string().unwrap();
+    assert!(config.contains("model = \"\\x\\test\\addons\\main\\data\\model.p3d\";"));
+    assert!(config.contains("{\"\\x\\test\\addons\\main\\data\\tex_co.paa\", \"\\a3\\data_f\\black_co.paa\", \"data\\missing_co.paa\"}"));
+    assert!(config.contains("displayName = \"data\";"));
+
+    // paths relative to the config's folder
+    let material = armake2::config::Config::read_rapified(&mut Cursor::new(pbo.files["data\\material.rvmat"].get_ref().to_vec())).unwrap().to_string().unwrap();
+    assert!(material.contains("\"\\x\\test\\addons\\main\\data\\tex_co.paa\""));
+}
+
+#[test]
+fn test_read_limits() {
+    let limits = ReadLimits { max_entries: 2, max_header_extensions: 1, max_name_length: 16, max_read: 4 };
+
+    let pbo = container(&[("config.bin", 0), ("script.sqf", 0)]);
+    assert_eq!(ContainerType::PBO, detect_container_limited(&mut Cursor::new(&pbo), &limits));
+    assert_eq!(2, PBOReader::with_limits(Cursor::new(&pbo), &limits).unwrap().entries.len());
+
+    let many = container(&[("a.sqf", 0), ("b.sqf", 0), ("c.sqf", 0)]);
+    assert_eq!(ContainerType::Obfuscated("more than 2 files".to_string()), detect_container_limited(&mut Cursor::new(&many), &limits));
+    assert_eq!(ContainerType::PBO, detect_container(&mut Cursor::new(&many)));
+
+    let long = container(&[("config.bin", 0), ("a_very_long_file_name.sqf", 0)]);
+    assert_eq!(ContainerType::Obfuscated("unreadable file name".to_string()), detect_container_limited(&mut Cursor::new(&long), &limits));
+
+    let mut extensions = header("", 0x5665_7273, 0);
+    extensions.extend_from_slice(b"prefix\0x\\abc\0version\x001.0\0\0");
+    extensions.extend(container(&[("config.bin", 0)]));
+    assert_eq!(ContainerType::Obfuscated("more than 1 header extensions".to_string()), detect_container_limited(&mut Cursor::new(&extensions), &limits));
+    assert!(PBOReader::with_limits(Cursor::new(&extensions), &limits).is_err());
+
+    // the data of the files has to be in the input, the footer doesn't
+    let mut truncated = container(&[("config.bin", 0)]);
+    truncated.truncate(truncated.len() - 21);
+    assert!(PBOReader::with_limits(Cursor::new(&truncated), &limits).is_ok());
+    truncated.pop();
+    assert!(PBOReader::with_limits(Cursor::new(&truncated), &limits).is_err());
+    assert!(PBOReader::new(Cursor::new(&truncated)).is_ok());
+
+    // nothing is written for untrusted PBOs
+    let dir = tempfile::tempdir().unwrap();
+    let manifest = dir.path().join("manifest.json");
+    assert!(cmd_inspect(&mut Cursor::new(&pbo), false, true, None, true).is_ok());
+    assert!(cmd_inspect(&mut Cursor::new(&pbo), false, false, Some(manifest.clone()), true).is_err());
+    assert!(!manifest.exists());
+}
+
+#[test]
+fn test_cat_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    let addons = dir.path().join("addons");
+    std::fs::create_dir_all(source.join("ui")).unwrap();
+    std::fs::create_dir_all(&addons).unwrap();
+    std::fs::write(source.join("config.cpp"), "class A {};\n").unwrap();
+    std::fs::write(source.join("ui").join("macros.hpp"), "#define X 1\n").unwrap();
+    std::fs::write(source.join("$PBOPREFIX$"), "x\\abc\\main").unwrap();
+    for name in &["main.pbo", "other.pbo"] {
+        PBO::from_directory(source.clone(), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap()
+            .write(&mut std::fs::File::create(addons.join(name)).unwrap()).unwrap();
+    }
+    let addons = addons.to_string_lossy();
+
+    let mut output: Vec<u8> = Vec::new();
+    cmd_cat_entries(&[format!("{}/*.pbo::**/*.HPP", addons)], &mut output, None, false).unwrap();
+    assert_eq!("#define X 1\n#define X 1\n", String::from_utf8(output).unwrap());
+
+    let mut output: Vec<u8> = Vec::new();
+    cmd_cat_entries(&[format!("{}/main.pbo::config.cpp", addons), format!("{}/other.pbo::ui/*", addons)], &mut output, None, false).unwrap();
+    assert_eq!("class A {};\n#define X 1\n", String::from_utf8(output).unwrap());
+
+    // files are written to their game paths
+    let folder = dir.path().join("out");
+    cmd_cat_entries(&[format!("{}/main.pbo", addons)], &mut Vec::new(), Some(folder.clone()), false).unwrap();
+    assert!(folder.join("x").join("abc").join("main").join("ui").join("macros.hpp").exists());
+    assert!(cmd_cat_entries(&[format!("{}/main.pbo", addons)], &mut Vec::new(), Some(folder.clone()), false).is_err());
+    cmd_cat_entries(&[format!("{}/main.pbo", addons)], &mut Vec::new(), Some(folder.clone()), true).unwrap();
+
+    assert!(cmd_cat_entries(&[format!("{}/*.pbo::*.sqf", addons)], &mut Vec::new(), None, false).is_err());
+    assert!(cmd_cat_entries(&[format!("{}/*.ebo::*", addons)], &mut Vec::new(), None, false).is_err());
+}
+
+#[test]
+fn test_read_address() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("ui")).unwrap();
+    let config = armake2::config::Config::from_string("class CfgPatches { class abc { units[] = {}; }; };\n".to_string(), None, &[]).unwrap();
+    config.write_rapified(&mut std::fs::File::create(source.join("config.bin")).unwrap()).unwrap();
+    std::fs::write(source.join("ui").join("a.hpp"), "#define A\n").unwrap();
+    std::fs::write(source.join("ui").join("b.hpp"), "#define B\n").unwrap();
+    let pbo = dir.path().join("main.pbo");
+    PBO::from_directory(source.clone(), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap()
+        .write(&mut std::fs::File::create(&pbo).unwrap()).unwrap();
+    let pbo = pbo.to_string_lossy();
+
+    assert_eq!(b"#define A\n", &read_address(&format!("{}::ui/a.hpp", pbo)).unwrap()[..]);
+    assert_eq!(b"#define B\n", &read_address(&format!("{}::**/B.*", pbo)).unwrap()[..]);
+    let error = read_address(&format!("{}::ui/*.hpp", pbo)).err().unwrap();
+    assert!(error.to_string().contains("2 files match"), "{}", error);
+    assert!(read_address(&format!("{}::ui/c.hpp", pbo)).is_err());
+    assert!(read_address(&format!("{}::*", dir.path().join("missing.pbo").display())).is_err());
+
+    // commands reading a source file accept addresses
+    let target = dir.path().join("config.cpp");
+    let response = dir.path().join("commands.txt");
+    std::fs::write(&response, format!("\
+derapify \"{pbo}::config.bin\" \"{target}\"
+", pbo = pbo, target = target.display())).unwrap();
+    armake2::run::run_response_file(&response).unwrap();
+    assert!(std::fs::read_to_string(&target).unwrap().contains("class abc"));
+
+    let mut output: Vec<u8> = Vec::new();
+    armake2::config::cmd_config_get(&mut output, PathBuf::from(format!("{}::config.bin", pbo)), &[], "CfgPatches", "cpp", false).unwrap();
+    assert!(String::from_utf8(output).unwrap().contains("abc"));
+}
@@ -122,3 +122,166 @@ class test\\
     assert_eq!(5, info.line_origins.len());
     assert_eq!(8, info.line_origins[2].0);
 }
+
+#[test]
+fn test_preprocess_defines() {
+    let input = String::from("#define QUOTE(x) #x\nversion = QUOTE(__GIT_HASH__);\n#ifdef DEBUG\ndebug = 1;\n#endif\n");
+    let defines = vec![("__GIT_HASH__".to_string(), "abc123".to_string()), ("DEBUG".to_string(), String::new())];
+
+    let (output, _) = preprocess_with(input, None, &Vec::new(), &PreprocessOptions { defines, ..Default::default() }).unwrap();
+
+    assert_eq!("version = \"abc123\";\ndebug = 1;", output.trim());
+}
+
+#[test]
+fn test_preprocess_cli_defines() {
+    let input = String::from("#ifdef DEBUG\nlevel = DEBUG;\n#endif\nname = NAME;\n");
+    let defines = vec![parse_define("DEBUG").unwrap(), parse_define("NAME=\"x=y\"").unwrap()];
+
+    let (output, _) = preprocess_with(input, None, &Vec::new(), &PreprocessOptions { defines, ..Default::default() }).unwrap();
+
+    assert_eq!("level = 1;\nname = \"x=y\";", output.trim());
+    assert!(parse_define("").is_err());
+    assert!(parse_define("A-B=1").is_err());
+}
+
+#[test]
+fn test_preprocess_eval() {
+    let input = String::from("#define SIZE 4\n#define HALF(x) __EVAL((x) / 2)\nsize = __EVAL(SIZE * 2 + 1);\nhalf = HALF(SIZE + 1);\nname = __EVAL(\"v\" + str SIZE);\nbig = __EVAL(SIZE > 2);\n");
+
+    let (output, _) = preprocess(input, None, &Vec::new()).unwrap();
+
+    assert_eq!("size = 9;\nhalf = 2.5;\nname = \"v4\";\nbig = 1;", output.trim());
+
+    let error = preprocess(String::from("x = __EVAL(1 + \"a\");\n"), None, &Vec::new()).unwrap_err();
+    assert!(error.to_string().contains("__EVAL(1 + \"a\")"));
+}
+
+#[test]
+fn test_preprocess_pragma() {
+    let input = String::from("a = 1;\n#pragma armake2 mute unquoted-string\nb = x;\n#pragma once\nc = y;\n#pragma armake2 unmute unquoted-string\nd = z;\n");
+
+    let (output, info) = preprocess(input, None, &Vec::new()).unwrap();
+
+    assert_eq!("a = 1;\nb = x;\n#pragma once\nc = y;\nd = z;", output.trim());
+    assert_eq!(4, info.line_origins[2].0);
+    assert!(!info.is_muted("unquoted-string", 1));
+    assert!(info.is_muted("unquoted-string", 2));
+    assert!(info.is_muted("unquoted-string", 4));
+    assert!(!info.is_muted("unquoted-string", 5));
+    assert!(!info.is_muted("missing-semicolon", 2));
+
+    let error = preprocess(String::from("#pragma armake2 silence\n"), None, &Vec::new()).unwrap_err();
+    assert!(error.to_string().contains("Unknown pragma"));
+}
+
+#[test]
+fn test_preprocess_trace_macros() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("config.cpp");
+    File::create(&source).unwrap().write_all(b"#define QUOTE(x) #x\n#define DOUBLES(x,y) x##_##y\n\nname = QUOTE(DOUBLES(ace,frag));\n").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_armake2"))
+        .args(["preprocess", "--trace-macros", source.to_str().unwrap()])
+        .output().unwrap();
+    let trace = String::from_utf8(output.stderr).unwrap();
+
+    assert!(output.status.success());
+    assert!(trace.contains("config.cpp:4: DOUBLES(ace, frag) -> ace_frag\n"), "{}", trace);
+    assert!(trace.contains("config.cpp:4: QUOTE(DOUBLES(ace,frag)) -> \"ace_frag\"\n"), "{}", trace);
+}
+
+#[test]
+fn test_preprocess_rapified_include() {
+    let dir = tempdir().unwrap();
+    let config = armake2::config::Config::from_string(String::from("class CfgPatches { class dependency {}; };"), None, &Vec::new()).unwrap();
+    config.write_rapified(&mut File::create(dir.path().join("config.bin")).unwrap()).unwrap();
+
+    let input = String::from("#include \"config.bin\"\nclass CfgVehicles {};\n");
+    let origin = dir.path().join("config.cpp");
+    File::create(&origin).unwrap().write_all(input.as_bytes()).unwrap();
+    let (output, _) = preprocess(input, Some(origin), &Vec::new()).unwrap();
+
+    assert!(output.contains("class dependency {};"));
+    assert!(output.contains("class CfgVehicles {};"));
+}
+
+#[test]
+fn test_preprocess_pbo_include() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("main");
+    create_dir(&source).unwrap();
+    File::create(source.join("$PBOPREFIX$")).unwrap().write_all(b"x\\dep\\addons\\main").unwrap();
+    File::create(source.join("script_macros.hpp")).unwrap().write_all(b"#define DEP_VERSION 42\n").unwrap();
+
+    let addons = dir.path().join("@dep").join("addons");
+    std::fs::create_dir_all(&addons).unwrap();
+    let pbo = armake2::pbo::PBO::from_directory(source, false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    pbo.write(&mut File::create(addons.join("dep_main.pbo")).unwrap()).unwrap();
+
+    let input = String::from("#include \"\\x\\dep\\addons\\main\\script_macros.hpp\"\nversion = DEP_VERSION;\n");
+
+    let (output, _) = preprocess(input.clone(), None, &[addons.join("dep_main.pbo")]).unwrap();
+    assert!(output.contains("version = 42;"));
+
+    let (output, _) = preprocess(input, None, &[dir.path().join("@dep")]).unwrap();
+    assert!(output.contains("version = 42;"));
+
+    // the extracted files are removed at the end of the command
+    let prefix = format!("includes_{}_", std::process::id());
+    let extracted: Vec<PathBuf> = std::fs::read_dir(armake2::temp::temp_root()).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.file_name().unwrap().to_string_lossy().starts_with(&prefix))
+        .collect();
+    assert_eq!(1, extracted.len());
+    drop(ExtractedIncludes);
+    assert!(!extracted[0].exists());
+}
+
+#[test]
+fn test_preprocess_changed_include() {
+    let dir = tempdir().unwrap();
+    let header = dir.path().join("script_component.hpp");
+    let origin = dir.path().join("config.cpp");
+    let input = String::from("#include \"script_component.hpp\"\nvalue = VALUE;\n");
+    File::create(&origin).unwrap().write_all(input.as_bytes()).unwrap();
+
+    // the parsed header is reused, but read again once it changes
+    File::create(&header).unwrap().write_all(b"#define VALUE 1\n").unwrap();
+    for _i in 0..2 {
+        let (output, info) = preprocess(input.clone(), Some(origin.clone()), &Vec::new()).unwrap();
+        assert_eq!("value = 1;", output.trim());
+        assert_eq!(1, info.includes.len());
+    }
+
+    File::create(&header).unwrap().write_all(b"#define VALUE 200\n").unwrap();
+    let (output, _) = preprocess(input, Some(origin), &Vec::new()).unwrap();
+    assert_eq!("value = 200;", output.trim());
+}
+
+#[test]
+fn test_preprocess_include_paths() {
+    let dir = tempdir().unwrap();
+    let addon = dir.path().join("addon");
+    let first = dir.path().join("first");
+    let second = dir.path().join("second");
+    std::fs::create_dir_all(second.join("x").join("cba")).unwrap();
+    for folder in [&addon, &first] {
+        create_dir(folder).unwrap();
+    }
+    File::create(addon.join("local.hpp")).unwrap().write_all(b"#define LOCAL addon\n").unwrap();
+    File::create(first.join("local.hpp")).unwrap().write_all(b"#define LOCAL first\n").unwrap();
+    File::create(first.join("shared.hpp")).unwrap().write_all(b"#define SHARED first\n").unwrap();
+    File::create(second.join("shared.hpp")).unwrap().write_all(b"#define SHARED second\n").unwrap();
+    File::create(second.join("x").join("cba").join("macros.hpp")).unwrap().write_all(b"#define CBA second\n").unwrap();
+
+    // files next to the including file win, then the include paths are searched in order
+    let input = String::from("#include \"local.hpp\"\n#include \"shared.hpp\"\n#include \"\\x\\cba\\macros.hpp\"\na = LOCAL;\nb = SHARED;\nc = CBA;\n");
+    let origin = addon.join("config.cpp");
+    File::create(&origin).unwrap().write_all(input.as_bytes()).unwrap();
+    let (output, info) = preprocess_with(input.clone(), Some(origin.clone()), &Vec::new(), &PreprocessOptions { includepaths: vec![first, second], ..Default::default() }).unwrap();
+
+    assert_eq!("a = addon;\nb = first;\nc = second;", output.trim());
+    assert_eq!(3, info.includes.len());
+    assert!(preprocess(input, Some(origin), &Vec::new()).is_err());
+}
@@ -0,0 +1,174 @@
+use std::fs::{File, create_dir_all, remove_file, write};
+
+use armake2::pbo::*;
+use armake2::project::*;
+
+#[test]
+fn test_project_build() {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, config) in &[("main", "class CfgPatches {};"), ("common", "class CfgPatches {};"), ("broken", "class CfgPatches {")] {
+        let addon = dir.path().join("addons").join(name);
+        create_dir_all(&addon).unwrap();
+        write(addon.join("config.cpp"), config).unwrap();
+        write(addon.join("$PBOPREFIX$"), format!("x\\test\\addons\\{}", name)).unwrap();
+    }
+
+    let project = Project::read(dir.path().to_path_buf(), Some(dir.path().join("build"))).unwrap();
+    create_dir_all(dir.path().join("build")).unwrap();
+
+    let options = BuildOptions {
+        jobs: 2,
+        ..Default::default()
+    };
+    let failed = build_project(&project, &options);
+
+    assert_eq!(vec!["broken"], failed.iter().map(|(name, _)| name.as_str()).collect::<Vec<&str>>());
+    for name in &["main", "common"] {
+        let pbo = PBO::read(&mut File::open(dir.path().join("build").join(format!("{}.pbo", name))).unwrap()).unwrap();
+        assert!(pbo.files.contains_key("config.bin"));
+        assert_eq!(format!("x\\test\\addons\\{}", name), pbo.header_extensions["prefix"]);
+    }
+
+    // existing PBOs are only overwritten with force
+    assert_eq!(3, build_project(&project, &options).len());
+    assert_eq!(1, build_project(&project, &BuildOptions { force: true, ..options }).len());
+}
+
+#[test]
+fn test_project_graph() {
+    let dir = tempfile::tempdir().unwrap();
+    let addon = dir.path().join("addons").join("main");
+    create_dir_all(&addon).unwrap();
+    write(addon.join("config.cpp"), "#include \"macros.hpp\"\nclass CfgPatches {};\n").unwrap();
+    write(addon.join("macros.hpp"), "#define FOO 1\n").unwrap();
+
+    let project = Project::read(dir.path().to_path_buf(), None).unwrap();
+    let options = BuildOptions {
+        privatekey: Some("keys/test.biprivatekey".into()),
+        ..Default::default()
+    };
+    let graph = project.graph(&options).unwrap();
+
+    let addon_id = graph.find(NodeKind::Addon, "main").unwrap();
+    let config_id = graph.find(NodeKind::Source, &addon.join("config.cpp").display().to_string()).unwrap();
+    let output_id = graph.find(NodeKind::Output, &dir.path().join("addons").join("main.pbo").display().to_string()).unwrap();
+    let signature_id = graph.find(NodeKind::Signature, &dir.path().join("addons").join("main.pbo.test.bisign").display().to_string()).unwrap();
+    let include_id = graph.nodes.iter().find(|n| n.kind == NodeKind::Include).unwrap().id;
+
+    for edge in &[(addon_id, config_id), (config_id, output_id), (include_id, config_id), (output_id, signature_id)] {
+        assert!(graph.edges.contains(edge), "missing edge {:?}", edge);
+    }
+    assert_eq!(Some("da39a3ee5e6b4b0d3255bfef95601890afd80709".len()), graph.nodes[config_id].hash.as_ref().map(|h| h.len()));
+
+    let mut json: Vec<u8> = Vec::new();
+    graph.write_json(&mut json).unwrap();
+    assert!(String::from_utf8(json).unwrap().contains("\"kind\": \"signature\""));
+
+    let mut dot: Vec<u8> = Vec::new();
+    graph.write_dot(&mut dot).unwrap();
+    assert!(String::from_utf8(dot).unwrap().contains(&format!("    n{} -> n{};\n", output_id, signature_id)));
+}
+
+#[test]
+fn test_rebuild_reasons() {
+    let dir = tempfile::tempdir().unwrap();
+    let addon = dir.path().join("addons").join("main");
+    create_dir_all(&addon).unwrap();
+    write(addon.join("config.cpp"), "#include \"macros.hpp\"\nclass CfgPatches {};\n").unwrap();
+    write(addon.join("macros.hpp"), "#define FOO 1\n").unwrap();
+    write(addon.join("data.txt"), "foo").unwrap();
+
+    let project = Project::read(dir.path().to_path_buf(), None).unwrap();
+    let options = BuildOptions::default();
+    let output = dir.path().join("addons").join("main.pbo").display().to_string();
+    let source = |name: &str| addon.join(name).display().to_string();
+
+    let previous = project.graph(&options).unwrap();
+    assert_eq!(vec![RebuildReason::NotBuilt], previous.rebuild_reasons(&BuildGraph::default(), &output));
+
+    write(&output, "").unwrap();
+    let mut json: Vec<u8> = Vec::new();
+    previous.write_json(&mut json).unwrap();
+    let previous = BuildGraph::read(&mut json.as_slice()).unwrap();
+    assert!(project.graph(&options).unwrap().rebuild_reasons(&previous, &output).is_empty());
+
+    write(addon.join("macros.hpp"), "#define FOO 2\n").unwrap();
+    remove_file(addon.join("data.txt")).unwrap();
+    write(addon.join("new.txt"), "bar").unwrap();
+    let reasons = project.graph(&options).unwrap().rebuild_reasons(&previous, &output);
+    assert!(reasons.contains(&RebuildReason::IncludeChanged(source("macros.hpp"), source("config.cpp"))));
+    assert!(reasons.contains(&RebuildReason::SourceChanged(source("macros.hpp"))));
+    assert!(reasons.contains(&RebuildReason::SourceAdded(source("new.txt"))));
+    assert!(reasons.contains(&RebuildReason::SourceRemoved(source("data.txt"))));
+
+    remove_file(&output).unwrap();
+    assert!(project.graph(&options).unwrap().rebuild_reasons(&previous, &output).contains(&RebuildReason::Missing));
+}
+
+#[test]
+fn test_project_build_incremental() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in &["main", "common"] {
+        let addon = dir.path().join("addons").join(name);
+        create_dir_all(&addon).unwrap();
+        write(addon.join("config.cpp"), "class CfgPatches {};").unwrap();
+    }
+    let target = dir.path().join("build");
+    let options = BuildOptions { incremental: true, ..Default::default() };
+
+    cmd_project_build(dir.path().to_path_buf(), Some(target.clone()), &options).unwrap();
+    assert!(target.join(GRAPH_FILE).exists());
+
+    // only the changed addon is rebuilt, the other PBO stays untouched
+    write(target.join("common.pbo"), "stale").unwrap();
+    write(dir.path().join("addons").join("main").join("config.cpp"), "class CfgPatches { class Main {}; };").unwrap();
+    write(target.join("main.pbo"), "stale").unwrap();
+    cmd_project_build(dir.path().to_path_buf(), Some(target.clone()), &options).unwrap();
+
+    assert_eq!(b"stale".to_vec(), std::fs::read(target.join("common.pbo")).unwrap());
+    PBO::read(&mut File::open(target.join("main.pbo")).unwrap()).unwrap();
+}
+
+#[test]
+fn test_project_build_campaign() {
+    let dir = tempfile::tempdir().unwrap();
+    let addon = dir.path().join("addons").join("campaign");
+    let campaign = addon.join("campaign");
+    for mission in &["M01.Stratis", "M02.Stratis"] {
+        create_dir_all(campaign.join("missions").join(mission)).unwrap();
+        write(campaign.join("missions").join(mission).join("mission.sqm"), "version = 53;").unwrap();
+        write(campaign.join("missions").join(mission).join("description.ext"), "#include \"..\\..\\shared.hpp\"\n").unwrap();
+    }
+    write(campaign.join("shared.hpp"), "respawn = 3;\n").unwrap();
+    write(addon.join("config.cpp"), "class CfgPatches { class campaign {}; };").unwrap();
+    let description = "class Campaign {
+    name = \"Test\";
+    firstBattle = Chapter1;
+    class MissionDefault { lives = -1; lost = ; end1 = ; end2 = ; };
+    class Chapter1 {
+        firstMission = M01;
+        end1 = ;
+        class M01: MissionDefault { end1 = M02; template = M01.Stratis; };
+        class M02: MissionDefault { template = M02.Stratis; };
+    };
+};
+";
+    write(campaign.join("description.ext"), description).unwrap();
+
+    let options = BuildOptions { jobs: 1, ..BuildOptions::default() };
+    cmd_project_build(dir.path().to_path_buf(), None, &options).unwrap();
+    let pbo = PBO::read(&mut File::open(dir.path().join("addons").join("campaign.pbo")).unwrap()).unwrap();
+    assert!(pbo.files.contains_key("campaign\\missions\\M02.Stratis\\mission.sqm"));
+
+    // broken chain, missing mission folder and a shared include outside the campaign
+    write(campaign.join("description.ext"), description.replace("end1 = M02;", "end1 = M03;").replace("M02.Stratis", "M04.Stratis")).unwrap();
+    write(campaign.join("missions").join("M01.Stratis").join("description.ext"), "#include \"..\\..\\..\\outside.hpp\"\n").unwrap();
+    let options = BuildOptions { jobs: 1, force: true, ..BuildOptions::default() };
+    assert!(cmd_project_build(dir.path().to_path_buf(), None, &options).is_err());
+
+    let project = Project::read(dir.path().to_path_buf(), None).unwrap();
+    let error = project.addons[0].build(&options).err().unwrap().to_string();
+    assert!(error.contains("Campaign.Chapter1.M01.end1: Mission M03 doesn't exist in chapter Chapter1."), "{}", error);
+    assert!(error.contains("Campaign.Chapter1.M02: Mission folder missions\\M04.Stratis doesn't contain a mission.sqm."), "{}", error);
+    assert!(error.contains("Included file ..\\..\\..\\outside.hpp is outside the campaign folder"), "{}", error);
+}
@@ -0,0 +1,44 @@
+use std::collections::{HashMap};
+use std::io::{Cursor};
+use std::path::{Path};
+
+use armake2::pbo::*;
+
+fn pack(source: &Path) -> Vec<u8> {
+    let mut pbo = PBO::from_directory(source.to_path_buf(), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    // every map has its own order of keys
+    let extensions: HashMap<String, String> = ["version", "author", "hemtt", "git_hash", "build"].iter()
+        .map(|key| (key.to_string(), format!("{} value", key))).collect();
+    pbo.header_extensions.extend(extensions);
+    pbo.to_cursor().unwrap().into_inner()
+}
+
+#[test]
+fn test_reproducible_pbo() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("data")).unwrap();
+    std::fs::write(source.join("script.sqf"), "true").unwrap();
+    std::fs::write(source.join("data").join("texture.paa"), "data").unwrap();
+    std::fs::write(source.join("$PBOPREFIX$"), "x\\abc\\main").unwrap();
+
+    let first = pack(&source);
+    for _ in 0..5 {
+        assert_eq!(first, pack(&source));
+    }
+    let reader = PBOReader::new(Cursor::new(&first)).unwrap();
+    let keys: Vec<&str> = reader.header_extensions.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(vec!["prefix", "author", "build", "git_hash", "hemtt", "version"], keys);
+    assert!(reader.entries.iter().all(|e| e.timestamp == 0));
+
+    std::env::set_var("SOURCE_DATE_EPOCH", "1700000000");
+    let dated = pack(&source);
+    assert_eq!(dated, pack(&source));
+    let reader = PBOReader::new(Cursor::new(&dated)).unwrap();
+    assert!(reader.entries.iter().all(|e| e.timestamp == 1_700_000_000));
+    assert_eq!(Some(1_700_000_000), source_date_epoch());
+
+    std::env::set_var("SOURCE_DATE_EPOCH", "yesterday");
+    assert_eq!(None, source_date_epoch());
+    assert_eq!(first, pack(&source));
+}
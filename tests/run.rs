@@ -0,0 +1,67 @@
+use std::fs::{File, create_dir_all, read, read_dir, write};
+
+use armake2::pbo::*;
+use armake2::run::*;
+
+#[test]
+fn test_response_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("addon source");
+    create_dir_all(&source).unwrap();
+    write(source.join("script.sqf"), "hint \"hello\";").unwrap();
+
+    let first = dir.path().join("first.pbo");
+    let second = dir.path().join("second.pbo");
+    let response = dir.path().join("build.txt");
+    write(&response, format!("# two pbos from one source\npack \"{}\" \"{}\"\n\npack \"{}\" \"{}\"\n",
+        source.display(), first.display(), source.display(), second.display())).unwrap();
+
+    run_response_file(&response).unwrap();
+
+    for path in &[first, second] {
+        let pbo = PBO::read(&mut File::open(path).unwrap()).unwrap();
+        assert!(pbo.files.contains_key("script.sqf"));
+    }
+
+    // the pbos exist now and -f is not given
+    let error = run_response_file(&response).unwrap_err();
+    assert!(error.to_string().starts_with("Command in line 2 failed:"));
+}
+
+#[test]
+fn test_failed_build_keeps_target() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("addon");
+    create_dir_all(&source).unwrap();
+    write(source.join("config.cpp"), "class CfgPatches {").unwrap();
+
+    let target = dir.path().join("addon.pbo");
+    write(&target, "previous build").unwrap();
+    let response = dir.path().join("build.txt");
+    write(&response, format!("build -f \"{}\" \"{}\"\n", source.display(), target.display())).unwrap();
+
+    assert!(run_response_file(&response).is_err());
+
+    // the previous output is untouched and the temporary file is removed
+    assert_eq!(b"previous build", &read(&target).unwrap()[..]);
+    assert_eq!(3, read_dir(dir.path()).unwrap().count());
+}
+
+#[test]
+fn test_translate_v1_arguments() {
+    let translate = |line: &str| {
+        let argv: Vec<String> = line.split(' ').map(String::from).collect();
+        translate_v1_arguments(&argv).map(|a| a.join(" "))
+    };
+
+    assert_eq!("armake2 build -f -w unquoted-string -i include -x *.psd addons/main main.pbo",
+        translate("armake build -f -w unquoted-string -i include -x *.psd addons/main main.pbo").unwrap());
+    assert_eq!("armake2 pack -k key.biprivatekey addons/main main.pbo",
+        translate("armake build --packonly -i include -k key.biprivatekey addons/main main.pbo").unwrap());
+    assert_eq!("armake2 sign -f key.biprivatekey main.pbo main.pbo.key.bisign",
+        translate("armake sign -s main.pbo.key.bisign -f key.biprivatekey main.pbo").unwrap());
+    assert!(translate("armake unpack -i *.sqf main.pbo main").is_err());
+    assert!(translate("armake img2paa texture.png texture.paa").is_err());
+    assert_eq!("armake2 img2paa -t DXT5 texture.tga texture.paa", translate("armake img2paa -z -t DXT5 texture.tga texture.paa").unwrap());
+    assert!(translate("armake build -k").is_err());
+}
@@ -0,0 +1,9 @@
+use armake2::selftest::*;
+
+#[test]
+fn test_selftest() {
+    for (name, result) in run_checks() {
+        assert!(result.is_ok(), "{} failed: {}", name, result.unwrap_err());
+    }
+    assert!(capabilities().iter().any(|(name, _)| *name == "Signatures"));
+}
@@ -0,0 +1,59 @@
+use std::fs::{File, create_dir_all, write};
+use std::io::{Read, Write, Cursor};
+
+use armake2::pbo::PBO;
+use armake2::serve::*;
+
+struct Connection {
+    request: Cursor<Vec<u8>>,
+    response: Vec<u8>,
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> { self.request.read(buf) }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.response.write(buf) }
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+fn request(server: &Server, request: &str) -> String {
+    let mut connection = Connection { request: Cursor::new(request.as_bytes().to_vec()), response: Vec::new() };
+    server.handle(&mut connection).unwrap();
+    String::from_utf8(connection.response).unwrap()
+}
+
+#[test]
+fn test_serve() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("main");
+    create_dir_all(source.join("data")).unwrap();
+    write(source.join("$PBOPREFIX$"), "x\\test\\addons\\main").unwrap();
+    write(source.join("config.cpp"), "class CfgPatches {};").unwrap();
+    write(source.join("data").join("my file.txt"), "hello").unwrap();
+
+    let pbo = PBO::from_directory(source.clone(), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    pbo.write(&mut File::create(dir.path().join("main.pbo")).unwrap()).unwrap();
+    let folder = dir.path().join("other");
+    create_dir_all(&folder).unwrap();
+    write(folder.join("readme.txt"), "other").unwrap();
+
+    let server = Server::new(&[dir.path().join("main.pbo"), folder]).unwrap();
+
+    let response = request(&server, "GET /x/test/addons/main/data/my%20file.txt HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nhello"));
+
+    assert!(request(&server, "GET /other/readme.txt HTTP/1.1\r\n\r\n").ends_with("other"));
+    assert!(request(&server, "GET /x/missing HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+    assert!(request(&server, "PUT /other/readme.txt HTTP/1.1\r\nContent-Length: 3\r\n\r\nnew").starts_with("HTTP/1.1 405"));
+    assert!(request(&server, "PROPFIND /other HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n").starts_with("HTTP/1.1 413"));
+    assert!(request(&server, "GET /x/test/addons/main HTTP/1.1\r\n\r\n").contains("<a href=\"/x/test/addons/main/data/\">data/</a>"));
+
+    let response = request(&server, "PROPFIND /x/test/addons/main/ HTTP/1.1\r\nDepth: 1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 207 Multi-Status\r\n"));
+    assert!(response.contains("<D:href>/x/test/addons/main/data/</D:href><D:propstat><D:prop><D:resourcetype><D:collection/>"));
+    assert!(response.contains("<D:href>/x/test/addons/main/config.cpp</D:href><D:propstat><D:prop><D:resourcetype/><D:getcontentlength>20</D:getcontentlength>"));
+    assert!(request(&server, "PROPFIND /other HTTP/1.1\r\nDepth: 1\r\n\r\n").contains("<D:href>/other/readme.txt</D:href><D:propstat><D:prop><D:resourcetype/><D:getcontentlength>5</D:getcontentlength>"));
+}
@@ -0,0 +1,207 @@
+use std::convert::TryInto;
+use std::fs::{create_dir_all, read, remove_file, write};
+use std::process::{Command};
+
+use armake2::run::*;
+use openssl::bn::{BigNum};
+use openssl::rsa::{Rsa};
+
+#[test]
+fn test_sign_multiple_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    create_dir_all(&source).unwrap();
+    write(source.join("script.sqf"), "hint \"hello\";").unwrap();
+
+    let pbo = dir.path().join("main.pbo");
+    let packed = dir.path().join("packed.pbo");
+    let team = dir.path().join("team");
+    let hub = dir.path().join("hub");
+
+    let response = dir.path().join("sign.txt");
+    write(&response, format!("\
+pack \"{src}\" \"{pbo}\"
+keygen \"{team}\"
+keygen \"{hub}\"
+sign -k \"{hub}.biprivatekey\" \"{team}.biprivatekey\" \"{pbo}\"
+verify \"{team}.bikey\" \"{pbo}\"
+verify --warn-old-keys 30 \"{hub}.bikey\" \"{pbo}\"
+pack -k \"{team}.biprivatekey\" -k \"{hub}.biprivatekey\" \"{src}\" \"{packed}\"
+verify \"{team}.bikey\" \"{packed}\"
+verify \"{hub}.bikey\" \"{packed}\"
+", src = source.display(), pbo = pbo.display(), team = team.display(), hub = hub.display(), packed = packed.display())).unwrap();
+    run_response_file(&response).unwrap();
+
+    assert!(dir.path().join("main.pbo.team.bisign").exists());
+    assert!(dir.path().join("main.pbo.hub.bisign").exists());
+
+    // one signature path for several keys is ambiguous
+    write(&response, format!("sign -f -k \"{hub}.biprivatekey\" \"{team}.biprivatekey\" \"{pbo}\" \"{sig}\"\n",
+        pbo = pbo.display(), team = team.display(), hub = hub.display(), sig = dir.path().join("main.bisign").display())).unwrap();
+    assert!(run_response_file(&response).is_err());
+    assert!(!dir.path().join("main.bisign").exists());
+}
+
+#[test]
+fn test_key_metadata() {
+    use armake2::sign::*;
+
+    let dir = tempfile::tempdir().unwrap();
+    let key = dir.path().join("team");
+
+    cmd_keygen(key.clone(), false, Some("Team".to_string()), None).unwrap();
+    let metadata = KeyMetadata::read(&dir.path().join("team.bikey")).unwrap().unwrap();
+    assert_eq!(Some("Team".to_string()), metadata.owner);
+    assert_eq!(None, metadata.comment);
+    assert_eq!(0, metadata.age_days());
+    assert_eq!(metadata, KeyMetadata::read(&dir.path().join("team.biprivatekey")).unwrap().unwrap());
+
+    cmd_key_info(dir.path().join("team.bikey")).unwrap();
+    cmd_key_info(dir.path().join("team.biprivatekey")).unwrap();
+
+    let old = KeyMetadata { created: 0, owner: None, comment: Some("rotate me".to_string()) };
+    old.write(&key).unwrap();
+    assert!(KeyMetadata::read(&key).unwrap().unwrap().age_days() > 365);
+    assert_eq!("1970-01-01", old.created_date());
+
+    assert!(KeyMetadata::read(&dir.path().join("other.bikey")).unwrap().is_none());
+}
+
+#[test]
+fn test_keygen_errors() {
+    use armake2::sign::*;
+
+    let dir = tempfile::tempdir().unwrap();
+    assert!(cmd_keygen(dir.path().join("missing").join("team"), false, None, None).is_err());
+    assert!(cmd_keygen(dir.path().join(".."), false, None, None).is_err());
+    assert_eq!(0, std::fs::read_dir(dir.path()).unwrap().count());
+}
+
+#[test]
+fn test_verify_errors() {
+    use armake2::sign::*;
+
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    create_dir_all(&source).unwrap();
+    write(source.join("script.sqf"), "true").unwrap();
+    let pbo = dir.path().join("main.pbo");
+
+    let response = dir.path().join("pack.txt");
+    write(&response, format!("pack \"{}\" \"{}\"\n", source.display(), pbo.display())).unwrap();
+    run_response_file(&response).unwrap();
+
+    cmd_keygen(dir.path().join("team"), false, None, None).unwrap();
+    cmd_keygen(dir.path().join("other"), false, None, None).unwrap();
+    cmd_sign(&[dir.path().join("team.biprivatekey")], pbo.clone(), None, BISignVersion::V3, false).unwrap();
+    let signature = dir.path().join("main.pbo.team.bisign");
+
+    cmd_verify(dir.path().join("team.bikey"), pbo.clone(), None, None).unwrap();
+
+    let error = cmd_verify(dir.path().join("other.bikey"), pbo.clone(), Some(signature.clone()), None).unwrap_err();
+    assert!(error.to_string().contains("created with key \"team\", not \"other\""), "{}", error);
+
+    // a different key with the same name
+    create_dir_all(dir.path().join("sub")).unwrap();
+    cmd_keygen(dir.path().join("sub").join("team"), false, None, None).unwrap();
+    let error = cmd_verify(dir.path().join("sub").join("team.bikey"), pbo.clone(), Some(signature), None).unwrap_err();
+    assert!(error.to_string().contains("different key"), "{}", error);
+
+    let error = cmd_verify(dir.path().join("team.bikey"), pbo, Some(dir.path().join("missing.bisign")), None).unwrap_err();
+    assert!(error.to_string().contains("Failed to open signature"), "{}", error);
+}
+
+#[test]
+fn test_sign_mod() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    let modfolder = dir.path().join("@abc");
+    let addons = modfolder.join("addons");
+    create_dir_all(&source).unwrap();
+    create_dir_all(&addons).unwrap();
+    write(source.join("script.sqf"), "hint \"hello\";").unwrap();
+
+    let key = dir.path().join("abc");
+    let response = dir.path().join("sign.txt");
+    write(&response, format!("\
+pack \"{src}\" \"{addons}/abc_main.pbo\"
+pack \"{src}\" \"{addons}/abc_ui.PBO\"
+keygen \"{key}\"
+sign-mod --copy-key \"{key}.biprivatekey\" \"{modfolder}\"
+verify \"{modfolder}/keys/abc.bikey\" \"{addons}/abc_main.pbo\"
+verify \"{modfolder}/keys/abc.bikey\" \"{addons}/abc_ui.PBO\"
+", src = source.display(), addons = addons.display(), key = key.display(), modfolder = modfolder.display())).unwrap();
+    run_response_file(&response).unwrap();
+
+    assert!(addons.join("abc_main.pbo.abc.bisign").exists());
+    assert!(addons.join("abc_ui.pbo.abc.bisign").exists());
+
+    // existing signatures are only replaced with --force
+    write(&response, format!("sign-mod \"{key}.biprivatekey\" \"{modfolder}\"\n", key = key.display(), modfolder = modfolder.display())).unwrap();
+    assert!(run_response_file(&response).is_err());
+    write(&response, format!("sign-mod -f \"{key}.biprivatekey\" \"{modfolder}\"\n", key = key.display(), modfolder = modfolder.display())).unwrap();
+    run_response_file(&response).unwrap();
+
+    // a folder without addons isn't a mod
+    write(&response, format!("sign-mod \"{key}.biprivatekey\" \"{src}\"\n", key = key.display(), src = source.display())).unwrap();
+    assert!(run_response_file(&response).is_err());
+}
+
+/// Converts a .biprivatekey to PEM for a signer command using the openssl CLI.
+fn private_key_to_pem(data: &[u8]) -> Vec<u8> {
+    let name_end = data.iter().position(|b| *b == 0).unwrap() + 1;
+    let length = u32::from_le_bytes(data[name_end + 16..name_end + 20].try_into().unwrap()) as usize;
+    let exponent = u32::from_le_bytes(data[name_end + 20..name_end + 24].try_into().unwrap());
+
+    let mut offset = name_end + 24;
+    let mut next = |size: usize| {
+        let bytes: Vec<u8> = data[offset..offset + size].iter().rev().cloned().collect();
+        offset += size;
+        BigNum::from_slice(&bytes).unwrap()
+    };
+    let n = next(length / 8);
+    let (p, q, dmp1, dmq1, iqmp) = (next(length / 16), next(length / 16), next(length / 16), next(length / 16), next(length / 16));
+    let d = next(length / 8);
+
+    Rsa::from_private_components(n, BigNum::from_u32(exponent).unwrap(), d, p, q, dmp1, dmq1, iqmp).unwrap().private_key_to_pem().unwrap()
+}
+
+#[test]
+#[cfg(unix)]
+fn test_external_signer() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    create_dir_all(&source).unwrap();
+    write(source.join("script.sqf"), "hint \"hello\";").unwrap();
+
+    let pbo = dir.path().join("main.pbo");
+    let key = dir.path().join("abc");
+    let response = dir.path().join("sign.txt");
+    write(&response, format!("pack \"{src}\" \"{pbo}\"\nkeygen \"{key}\"\n", src = source.display(), pbo = pbo.display(), key = key.display())).unwrap();
+    run_response_file(&response).unwrap();
+
+    // the private key is only read by the signer command
+    let pem = dir.path().join("abc.pem");
+    write(&pem, private_key_to_pem(&read(dir.path().join("abc.biprivatekey")).unwrap())).unwrap();
+    remove_file(dir.path().join("abc.biprivatekey")).unwrap();
+
+    if Command::new("openssl").arg("version").output().is_ok() {
+        // raw RSA with the private key, -sign only takes digests
+        let signer = format!("openssl pkeyutl -decrypt -inkey '{}' -pkeyopt rsa_padding_mode:none", pem.display());
+        write(&response, format!("\
+sign --signer \"{signer}\" \"{key}.biprivatekey\" \"{pbo}\"
+verify \"{key}.bikey\" \"{pbo}\"
+", signer = signer, key = key.display(), pbo = pbo.display())).unwrap();
+        run_response_file(&response).unwrap();
+        assert!(dir.path().join("main.pbo.abc.bisign").exists());
+    }
+
+    // commands that don't sign with the key or fail don't write signatures
+    let sig = dir.path().join("other.bisign");
+    for signer in &["cat", "exit 1", "echo"] {
+        write(&response, format!("sign --signer \"{signer}\" \"{key}.bikey\" \"{pbo}\" \"{sig}\"\n",
+            signer = signer, key = key.display(), pbo = pbo.display(), sig = sig.display())).unwrap();
+        assert!(run_response_file(&response).is_err());
+        assert!(!sig.exists());
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::{Cursor};
+
+use armake2::stringtable::*;
+
+const XML: &str = "\u{feff}<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<!-- translated by hand -->
+<Project name=\"ABC\">
+    <Package name=\"Main\">
+        <Key ID=\"STR_abc_title\">
+            <Original>Title</Original>
+            <German>Titel &lt;1&gt;</German>
+        </Key>
+        <Container name=\"Hints\">
+            <Key ID=\"STR_abc_hint\">
+                <English>Line 1\nLine \"2\", done</English>
+                <French><![CDATA[Astuce <b>]]></French>
+            </Key>
+        </Container>
+        <Key ID=\"STR_abc_empty\"><English /></Key>
+    </Package>
+</Project>
+";
+
+#[test]
+fn test_stringtable_parse() {
+    let stringtable = Stringtable::parse(XML).unwrap();
+
+    assert_eq!("ABC", stringtable.project);
+    let ids: Vec<&str> = stringtable.keys.iter().map(|k| k.id.as_str()).collect();
+    assert_eq!(vec!["STR_abc_title", "STR_abc_hint", "STR_abc_empty"], ids);
+    assert_eq!(5, stringtable.keys[0].line);
+    assert_eq!(Some("Titel <1>"), stringtable.keys[0].text("German"));
+    assert_eq!(Some("Hints"), stringtable.keys[1].container.as_deref());
+    assert_eq!(Some("Astuce <b>"), stringtable.keys[1].text("french"));
+    assert_eq!(None, stringtable.keys[2].container);
+    assert_eq!(Some(""), stringtable.keys[2].text("English"));
+    assert_eq!(vec!["Original", "German", "English", "French"], stringtable.languages());
+    assert!(stringtable.check().is_empty());
+
+    for broken in &[
+        "<Project><Package><Key ID=\"STR_a\"><English>A</Key></Package></Project>",
+        "<Project><Package><Key ID=\"STR_a\"><English>A</English></Key></Package>",
+        "<Project><Package><Key ID=STR_a><English>A</English></Key></Package></Project>",
+        "<Project><Package><Key ID=\"STR_a\"><English>A &nbsp;</English></Key></Package></Project>",
+        "<Project><Package>text<Key ID=\"STR_a\"></Key></Package></Project>",
+    ] {
+        assert!(Stringtable::parse(broken).is_err(), "{}", broken);
+    }
+}
+
+#[test]
+fn test_stringtable_check() {
+    let xml = "<Project name=\"ABC\"><Package name=\"Main\">
+<Key ID=\"abc_title\"><English>A</English></Key>
+<Key ID=\"STR_b\"><English>B</English><english>B</english></Key>
+<Key ID=\"\"><English>C</English></Key>
+</Package></Project>";
+    let problems: Vec<(u32, &str)> = Stringtable::parse(xml).unwrap().check().iter().map(|p| (p.0, p.2)).collect();
+
+    assert_eq!(vec![(2, "stringtable-key"), (3, "stringtable-duplicate"), (4, "stringtable-key")], problems);
+}
+
+#[test]
+fn test_stringtable_csv() {
+    let stringtable = Stringtable::parse(XML).unwrap();
+
+    let mut csv: Vec<u8> = Vec::new();
+    cmd_stringtable_export(&mut Cursor::new(XML), &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert!(csv.starts_with("ID,Package,Container,Original,German,English,French\r\nSTR_abc_title,Main,,Title,Titel <1>,,\r\n"));
+    assert!(csv.contains("\"Line 1\nLine \"\"2\"\", done\""));
+
+    let mut xml: Vec<u8> = Vec::new();
+    cmd_stringtable_import(&mut Cursor::new(csv.clone()), &mut xml, None).unwrap();
+    let reread = Stringtable::parse(&String::from_utf8(xml).unwrap()).unwrap();
+    assert_eq!("Main", reread.project);
+    assert_eq!(3, reread.keys.len());
+    // keys are grouped by container in the XML
+    for key in &reread.keys {
+        let original = stringtable.keys.iter().find(|k| k.id == key.id).unwrap();
+        assert_eq!((&original.id, &original.package, &original.container), (&key.id, &key.package, &key.container));
+        // empty translations are left out of the CSV
+        let texts: Vec<&(String, String)> = original.texts.iter().filter(|(_, t)| !t.is_empty()).collect();
+        assert_eq!(texts, key.texts.iter().collect::<Vec<_>>());
+    }
+
+    assert!(Stringtable::read_csv("Key,English\r\nSTR_a,A\r\n", "ABC").is_err());
+    assert!(Stringtable::read_csv("ID,Package,Container,English\r\nSTR_a,Main,,\"A\r\n", "ABC").is_err());
+    assert!(Stringtable::read_csv("ID,Package,Container,English\r\nSTR_a,Main,,A,B\r\n", "ABC").is_err());
+}
+
+#[test]
+fn test_stringtable_build() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("stringtable.xml"), "<Project name=\"ABC\"><Package name=\"Main\"><Key ID=\"STR_a\"><English>A</English></Package></Project>").unwrap();
+
+    // broken stringtables fail builds, but are packed as they are
+    let error = armake2::pbo::PBO::from_directory(dir.path().to_path_buf(), true, &Vec::new(), &Vec::new(), &Vec::new(), false).err().unwrap();
+    assert!(error.to_string().contains("stringtable.xml"));
+    assert!(armake2::pbo::PBO::from_directory(dir.path().to_path_buf(), false, &Vec::new(), &Vec::new(), &Vec::new(), false).is_ok());
+
+    std::fs::write(dir.path().join("stringtable.xml"), XML).unwrap();
+    assert!(armake2::pbo::PBO::from_directory(dir.path().to_path_buf(), true, &Vec::new(), &Vec::new(), &Vec::new(), false).is_ok());
+}
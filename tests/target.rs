@@ -0,0 +1,41 @@
+use armake2::archive::Directory;
+use armake2::pbo::*;
+use armake2::sign::{filehash, BISignVersion};
+use armake2::target::*;
+
+#[test]
+fn test_target() {
+    assert!(Target::from_name("arma4").is_err());
+    assert!(Target::from_name("A2").unwrap().supports_header_extensions());
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("script.sqf"), "true").unwrap();
+    let pbo = PBO::from_directory(dir.path().to_path_buf(), false, &Vec::new(), &Vec::new(), &Vec::new(), false).unwrap();
+    let written = pbo.to_cursor().unwrap().into_inner();
+
+    init_target(Target::OFP);
+    assert_eq!(Target::OFP, target());
+    let written_ofp = pbo.to_cursor().unwrap().into_inner();
+    init_target(Target::A3);
+
+    // the prefix is left out, so the PBO starts with the first file header
+    assert!(written.windows(6).any(|w| w == b"prefix"));
+    assert!(!written_ofp.windows(6).any(|w| w == b"prefix"));
+    let reread = PBO::read(&mut std::io::Cursor::new(written_ofp)).unwrap();
+    assert!(reread.header_extensions.is_empty());
+    assert_eq!(pbo.files.len(), reread.files.len());
+
+    // targets are global, so DayZ is tested in the same test
+    std::fs::write(dir.path().join("init.c"), "void main() {}").unwrap();
+    let directory = Directory::new(dir.path().to_path_buf());
+
+    let target = Target::from_name("dayz").unwrap();
+    assert!(target.signs_enforce_scripts());
+
+    let hash = filehash(&directory, BISignVersion::V3).unwrap().to_vec();
+    init_target(target);
+    let hash_dayz = filehash(&directory, BISignVersion::V3).unwrap().to_vec();
+    init_target(Target::A3);
+
+    assert_ne!(hash, hash_dayz);
+}
@@ -0,0 +1,24 @@
+use std::fs::{write};
+
+use armake2::temp::*;
+
+#[test]
+fn test_temp_dir() {
+    let first = TempDir::new("model.p3d").unwrap();
+    let second = TempDir::new("model.p3d").unwrap();
+    assert_ne!(first.path(), second.path());
+    assert!(first.path().starts_with(temp_root()));
+    assert!(first.path().file_name().unwrap().to_str().unwrap().starts_with(&format!("model.p3d_{}_", std::process::id())));
+
+    let path = first.path().to_path_buf();
+    write(path.join("file.txt"), "content").unwrap();
+    drop(first);
+    assert!(!path.exists());
+
+    init_keep_temp(true);
+    let kept = second.path().to_path_buf();
+    drop(second);
+    init_keep_temp(false);
+    assert!(kept.exists());
+    std::fs::remove_dir_all(kept).unwrap();
+}
@@ -0,0 +1,40 @@
+use armake2::config::Config;
+use armake2::testing::*;
+
+#[test]
+fn test_generated_roundtrip() {
+    check_generated(0, 100, |_, config| check_roundtrip(&config)).unwrap();
+}
+
+#[test]
+fn test_generated_optimized_roundtrip() {
+    let mut generator = ConfigGenerator::new(7);
+    generator.max_depth = 2;
+    generator.max_entries = 20;
+
+    for _ in 0..100 {
+        let mut config = generator.config();
+        config.optimize();
+        check_roundtrip(&config).unwrap();
+    }
+}
+
+#[test]
+fn test_roundtrip_deletion() {
+    check_source_roundtrip("class A; delete B; class C: A { x[] += {1, 2.5, \"a\"\"b\"}; };").unwrap();
+}
+
+#[test]
+fn test_check_generated_reports_case() {
+    let error = check_generated(3, 10, |source, config: Config| {
+        if source.contains("delete") {
+            Err(std::io::Error::other("deletion"))
+        } else {
+            check_roundtrip(&config)
+        }
+    }).unwrap_err().to_string();
+
+    assert!(error.starts_with("Case "));
+    assert!(error.contains("of seed 3 failed for config:\n"));
+    assert!(error.ends_with("deletion"));
+}
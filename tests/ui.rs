@@ -0,0 +1,37 @@
+use armake2::paa::*;
+use armake2::ui::*;
+
+#[test]
+fn test_atlas_pages() {
+    let mut red = Image::new(30, 20);
+    for pixel in red.data.iter_mut() {
+        *pixel = [255, 0, 0, 255];
+    }
+
+    let images = vec![
+        ("a".to_string(), red.clone()),
+        ("b".to_string(), red.clone()),
+        ("c".to_string(), Image::new(8, 8)),
+    ];
+    let atlas = Atlas::pack(images, 32).unwrap();
+
+    assert_eq!(2, atlas.pages.len());
+    assert_eq!(vec![0, 1, 0], atlas.entries.iter().map(|e| e.page).collect::<Vec<usize>>());
+    assert_eq!([255, 0, 0, 255], atlas.pages[1].data[32 + 1]);
+    assert_eq!([0, 0, 0, 0], atlas.pages[1].data[0]);
+
+    assert!(Atlas::pack(vec![("big".to_string(), Image::new(40, 8))], 32).is_err());
+}
+
+#[test]
+fn test_atlas_header() {
+    let images = vec![("ui_icon-1".to_string(), Image::new(16, 16))];
+    let atlas = Atlas::pack(images, 64).unwrap();
+
+    let mut header: Vec<u8> = Vec::new();
+    atlas.write_header(&mut header, &["\\x\\mod\\ui\\atlas_0_ca.paa".to_string()]).unwrap();
+    let header = String::from_utf8(header).unwrap();
+
+    assert!(header.contains("#define UI_ICON_1_TEXTURE \"\\x\\mod\\ui\\atlas_0_ca.paa\"\n"));
+    assert!(header.contains("#define UI_ICON_1_UV 0.015625, 0.015625, 0.250000, 0.250000\n"));
+}
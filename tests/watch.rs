@@ -0,0 +1,51 @@
+use armake2::watch::*;
+
+#[test]
+fn test_watcher_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let folder = dir.path().canonicalize().unwrap();
+    std::fs::write(folder.join("config.cpp"), "class CfgPatches {};").unwrap();
+
+    let mut watcher = Watcher::new(folder.clone(), vec![folder.join("addon.pbo")]).unwrap();
+
+    // the built PBO and its signatures don't trigger builds
+    std::fs::write(folder.join("addon.pbo"), "pbo").unwrap();
+    std::fs::write(folder.join("addon.pbo.key.bisign"), "signature").unwrap();
+    assert!(!watcher.changed().unwrap());
+
+    std::fs::write(folder.join("addon.sqf"), "true").unwrap();
+    assert!(watcher.changed().unwrap());
+
+    std::fs::remove_file(folder.join("addon.sqf")).unwrap();
+    assert!(watcher.changed().unwrap());
+}
+
+#[test]
+fn test_watcher_events() {
+    let dir = tempfile::tempdir().unwrap();
+    let folder = dir.path().canonicalize().unwrap();
+    std::fs::write(folder.join("config.cpp"), "class CfgPatches {};").unwrap();
+
+    let mut watcher = Watcher::new(folder.clone(), vec![folder.join("addon.pbo")]).unwrap();
+
+    // temporary files the PBO is written to first are ignored as well
+    std::fs::write(folder.join(".addon.pbo.tmp1_0"), "pbo").unwrap();
+    std::fs::remove_file(folder.join(".addon.pbo.tmp1_0")).unwrap();
+    assert!(!watcher.changed().unwrap());
+
+    // files in new folders are watched
+    std::fs::create_dir(folder.join("functions")).unwrap();
+    assert!(watcher.changed().unwrap());
+    std::fs::write(folder.join("functions").join("fn_init.sqf"), "true").unwrap();
+    assert!(watcher.changed().unwrap());
+    assert!(!watcher.changed().unwrap());
+
+    // waiting returns as soon as a file changes, or after the timeout without changes
+    let writer = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        std::fs::write(folder.join("functions").join("fn_init.sqf"), "false").unwrap();
+    });
+    assert!(watcher.wait(std::time::Duration::from_secs(5)).unwrap());
+    writer.join().unwrap();
+    assert!(!watcher.wait(std::time::Duration::from_millis(50)).unwrap());
+}